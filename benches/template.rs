@@ -0,0 +1,277 @@
+/*++
+
+Licensed under the Apache-2.0 license.
+
+File Name:
+
+    template.rs
+
+Abstract:
+
+    Benchmarks of the template generation stages
+
+--*/
+
+use const_oid::ObjectIdentifier;
+use criterion::measurement::{Measurement, ValueFormatter};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use ml_dsa::{KeyGen, KeyPair, MlDsa87};
+use rand::{rngs::StdRng, SeedableRng};
+use signature::Signer;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::borrow::Cow;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use test_rustcrypto_mldsa::keys::SlhDsaSha2_128fKeyPair;
+use test_rustcrypto_mldsa::tbs::init_param;
+use test_rustcrypto_mldsa::{
+    BuilderKeys, CertTemplateBuilder, CodeGen, CodeGenOptions, CsrTemplateBuilder, Fwid, FwidParam,
+    TbsParam, TbsTemplate,
+};
+
+/// System allocator counting the allocated bytes, see `AllocatedBytes`
+struct CountingAlloc;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Criterion measurement of the bytes allocated by the routine, as criterion only measures
+/// time by default
+struct AllocatedBytes;
+
+impl Measurement for AllocatedBytes {
+    type Intermediate = usize;
+    type Value = usize;
+
+    fn start(&self) -> usize {
+        ALLOCATED.load(Ordering::Relaxed)
+    }
+
+    fn end(&self, start: usize) -> usize {
+        ALLOCATED.load(Ordering::Relaxed) - start
+    }
+
+    fn add(&self, v1: &usize, v2: &usize) -> usize {
+        v1 + v2
+    }
+
+    fn zero(&self) -> usize {
+        0
+    }
+
+    fn to_f64(&self, value: &usize) -> f64 {
+        *value as f64
+    }
+
+    fn formatter(&self) -> &dyn ValueFormatter {
+        &BytesFormatter
+    }
+}
+
+struct BytesFormatter;
+
+impl ValueFormatter for BytesFormatter {
+    fn scale_values(&self, _typical_value: f64, _values: &mut [f64]) -> &'static str {
+        "B"
+    }
+
+    fn scale_throughputs(
+        &self,
+        _typical_value: f64,
+        _throughput: &Throughput,
+        _values: &mut [f64],
+    ) -> &'static str {
+        "B"
+    }
+
+    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
+        "B"
+    }
+}
+
+const SHA384_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.2");
+
+static UEID: [u8; 17] = [0xFF; 17];
+
+static DEVICE_FWIDS: [FwidParam<'static>; 1] = [FwidParam {
+    name: "TCB_INFO_DEVICE_INFO_HASH",
+    fwid: Fwid {
+        hash_alg: SHA384_OID,
+        digest: Cow::Borrowed(&[0xEF; 48]),
+    },
+}];
+
+static FMC_FWIDS: [FwidParam<'static>; 1] = [FwidParam {
+    name: "TCB_INFO_FMC_TCI",
+    fwid: Fwid {
+        hash_alg: SHA384_OID,
+        digest: Cow::Borrowed(&[0xCD; 48]),
+    },
+}];
+
+/// Key generated from a fixed seed, so the non-keygen benches sign the same TBS every run
+fn fixed_key(seed: u64) -> KeyPair<MlDsa87> {
+    <MlDsa87 as KeyGen>::key_gen(&mut StdRng::seed_from_u64(seed))
+}
+
+fn fmc_alias_cert(subject_key: KeyPair<MlDsa87>, issuer_key: KeyPair<MlDsa87>) -> TbsTemplate {
+    CertTemplateBuilder::<KeyPair<MlDsa87>>::fmc_alias_profile(&UEID, &DEVICE_FWIDS, &FMC_FWIDS)
+        .set_subject_key(subject_key)
+        .set_issuer_key(issuer_key)
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+            "Caliptra 2.0 MlDsa87 LDevID",
+        )
+}
+
+/// Copy of `template` that can be consumed by `CodeGen`
+fn template_copy(template: &TbsTemplate) -> TbsTemplate {
+    TbsTemplate::new(template.tbs().to_vec(), template.params().to_vec())
+        .with_tbs_offset(template.tbs_offset())
+}
+
+fn bench_keygen(c: &mut Criterion) {
+    c.bench_function("keygen/mldsa87", |b| {
+        b.iter(<KeyPair<MlDsa87> as BuilderKeys>::key_gen_os)
+    });
+}
+
+fn bench_sign(c: &mut Criterion) {
+    let key = fixed_key(0);
+    let template = fmc_alias_cert(fixed_key(1), fixed_key(0));
+    let tbs = template.tbs();
+
+    c.bench_function("sign/mldsa87", |b| {
+        b.iter(|| -> ml_dsa::Signature<MlDsa87> { key.sign(black_box(tbs)) })
+    });
+}
+
+fn bench_build(c: &mut Criterion) {
+    c.bench_function("build/idevid_csr", |b| {
+        b.iter_batched(
+            || fixed_key(0),
+            |key| {
+                CsrTemplateBuilder::<KeyPair<MlDsa87>>::idevid_profile(&UEID)
+                    .set_key(key)
+                    .tbs_template("Caliptra 2.0 MlDsa87 IDevID")
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    c.bench_function("build/fmc_alias_cert", |b| {
+        b.iter_batched(
+            || (fixed_key(1), fixed_key(0)),
+            |(subject_key, issuer_key)| fmc_alias_cert(subject_key, issuer_key),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn bench_init_param(c: &mut Criterion) {
+    // Search the subject public key in the whole DER of the FMC alias certificate
+    let template = fmc_alias_cert(fixed_key(1), fixed_key(0));
+    let der = template.der();
+    let public_key = template
+        .params_in_der()
+        .unwrap()
+        .into_iter()
+        .find(|p| p.name == "PUBLIC_KEY")
+        .unwrap();
+    let needle = &der[public_key.offset..public_key.offset + public_key.len];
+
+    c.bench_function("init_param/public_key", |b| {
+        b.iter(|| {
+            init_param(
+                black_box(needle),
+                black_box(der),
+                TbsParam::new("PUBLIC_KEY", 0, needle.len()),
+            )
+        })
+    });
+}
+
+fn bench_allocations(c: &mut Criterion<AllocatedBytes>) {
+    // Bytes allocated by a single build, for comparing the param pipeline across changes
+    c.bench_function("alloc/fmc_alias_cert", |b| {
+        b.iter_batched(
+            || (fixed_key(1), fixed_key(0)),
+            |(subject_key, issuer_key)| fmc_alias_cert(subject_key, issuer_key),
+            BatchSize::SmallInput,
+        )
+    });
+
+    // SLH-DSA signatures make the DER much larger than the TBS the pipeline copies
+    c.bench_function("alloc/slh_dsa_ldevid_cert", |b| {
+        b.iter_batched(
+            || {
+                (
+                    SlhDsaSha2_128fKeyPair::key_gen(&mut StdRng::seed_from_u64(1)),
+                    SlhDsaSha2_128fKeyPair::key_gen(&mut StdRng::seed_from_u64(0)),
+                )
+            },
+            |(subject_key, issuer_key)| {
+                CertTemplateBuilder::<SlhDsaSha2_128fKeyPair>::ldevid_profile(&UEID)
+                    .set_subject_key(subject_key)
+                    .set_issuer_key(issuer_key)
+                    .tbs_template("Caliptra 2.0 SlhDsa LDevID", "Caliptra 2.0 SlhDsa IDevID")
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn bench_codegen(c: &mut Criterion) {
+    let template = fmc_alias_cert(fixed_key(1), fixed_key(0));
+    let options = CodeGenOptions::default();
+
+    c.bench_function("codegen/fmc_alias_cert", |b| {
+        b.iter_batched(
+            || template_copy(&template),
+            |template| CodeGen::code("FmcAliasCertTbsMlDsa87", template, &options),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn bench_end_to_end(c: &mut Criterion) {
+    let options = CodeGenOptions::default();
+
+    c.bench_function("e2e/fmc_alias_cert", |b| {
+        b.iter(|| {
+            let subject_key = <KeyPair<MlDsa87> as BuilderKeys>::key_gen_os();
+            let issuer_key = <KeyPair<MlDsa87> as BuilderKeys>::key_gen_os();
+            let template = fmc_alias_cert(subject_key, issuer_key);
+            CodeGen::code("FmcAliasCertTbsMlDsa87", template, &options)
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_keygen,
+    bench_sign,
+    bench_build,
+    bench_init_param,
+    bench_codegen,
+    bench_end_to_end
+);
+criterion_group!(
+    name = allocations;
+    config = Criterion::default().with_measurement(AllocatedBytes);
+    targets = bench_allocations
+);
+criterion_main!(benches, allocations);
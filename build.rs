@@ -0,0 +1,85 @@
+/*++
+
+Licensed under the Apache-2.0 license.
+
+File Name:
+
+    build.rs
+
+Abstract:
+
+    Build script exposing the versions of the template dependencies for the provenance
+    record of the generated templates.
+
+--*/
+
+use std::path::Path;
+
+/// Dependencies whose versions are recorded, with the environment variable holding each
+const DEPENDENCIES: [(&str, &str); 3] = [
+    ("ml-dsa", "PROVENANCE_ML_DSA_VERSION"),
+    ("x509-cert", "PROVENANCE_X509_CERT_VERSION"),
+    ("der", "PROVENANCE_DER_VERSION"),
+];
+
+fn main() {
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let manifest_path = Path::new(&manifest_dir).join("Cargo.toml");
+    println!("cargo:rerun-if-changed={}", manifest_path.display());
+    println!("cargo:rerun-if-changed=build.rs");
+
+    // The lock file is untracked and sits at the workspace root, a fresh checkout or a
+    // packaged crate has none
+    let lock_path = Path::new(&manifest_dir)
+        .ancestors()
+        .map(|dir| dir.join("Cargo.lock"))
+        .find(|path| path.exists());
+    let lock = match &lock_path {
+        Some(path) => {
+            println!("cargo:rerun-if-changed={}", path.display());
+            std::fs::read_to_string(path).unwrap_or_default()
+        }
+        None => String::new(),
+    };
+    let manifest = std::fs::read_to_string(&manifest_path).unwrap_or_default();
+    for (package, var) in DEPENDENCIES {
+        let version = match locked_version(&lock, package) {
+            Some(version) => version.to_string(),
+            None => manifest_version(&manifest, package).unwrap_or_else(|| "unknown".into()),
+        };
+        println!("cargo:rustc-env={}={}", var, version);
+    }
+}
+
+/// Version of the first `package` of the Cargo.lock `lock`
+fn locked_version<'a>(lock: &'a str, package: &str) -> Option<&'a str> {
+    let name = format!("name = \"{}\"", package);
+    let mut lines = lock.lines();
+    lines.find(|line| *line == name)?;
+    lines
+        .next()?
+        .strip_prefix("version = \"")?
+        .strip_suffix('"')
+}
+
+/// Dependency of `package` in the Cargo.toml `manifest`, the pinned revision of a git
+/// dependency or the version requirement, e.g. "git e7c698a" or "req 0.8.0-rc.1"
+fn manifest_version(manifest: &str, package: &str) -> Option<String> {
+    let prefix = format!("{} = ", package);
+    let spec = manifest
+        .lines()
+        .find_map(|line| line.strip_prefix(&prefix))?
+        .trim();
+    if let Some(version) = spec.strip_prefix('"') {
+        return Some(format!("req {}", version.strip_suffix('"')?));
+    }
+    let field = |key: &str| {
+        let value = spec.split(&format!("{} = \"", key)).nth(1)?;
+        value.split('"').next()
+    };
+    match (field("rev"), field("version")) {
+        (Some(rev), _) => Some(format!("git {}", rev)),
+        (None, Some(version)) => Some(format!("req {}", version)),
+        (None, None) => None,
+    }
+}
@@ -0,0 +1,70 @@
+/*++
+
+Licensed under the Apache-2.0 license.
+
+File Name:
+
+    async_signer.rs
+
+Abstract:
+
+    File contains the signer interface of remote issuer keys, e.g. held by a KMS, for template
+    generation services. Built with --features async.
+
+--*/
+
+use core::marker::PhantomData;
+use der::Document;
+use signature::Keypair;
+use spki::{AlgorithmIdentifier, EncodePublicKey, SignatureAlgorithmIdentifier};
+
+/// Issuer key signing templates asynchronously, e.g. the client of a KMS
+#[async_trait::async_trait]
+pub trait AsyncTemplateSigner: Sync {
+    /// Sign `msg`, returning the content of the signature BIT STRING
+    async fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, signature::Error>;
+
+    /// Retrieve the DER SubjectPublicKeyInfo of the issuer key
+    fn verifying_key_der(&self) -> Vec<u8>;
+}
+
+/// Public part of a remote issuer key signing with the algorithm of `Issuer`, standing in for
+/// the issuer key while the TBS is built
+pub(crate) struct RemoteIssuer<Issuer> {
+    spki: SpkiDer,
+    issuer: PhantomData<Issuer>,
+}
+
+impl<Issuer> RemoteIssuer<Issuer> {
+    pub(crate) fn new(signer: &dyn AsyncTemplateSigner) -> Self {
+        Self {
+            spki: SpkiDer(signer.verifying_key_der()),
+            issuer: PhantomData,
+        }
+    }
+}
+
+impl<Issuer> Keypair for RemoteIssuer<Issuer> {
+    type VerifyingKey = SpkiDer;
+
+    fn verifying_key(&self) -> SpkiDer {
+        self.spki.clone()
+    }
+}
+
+impl<Issuer: SignatureAlgorithmIdentifier> SignatureAlgorithmIdentifier for RemoteIssuer<Issuer> {
+    type Params = Issuer::Params;
+
+    const SIGNATURE_ALGORITHM_IDENTIFIER: AlgorithmIdentifier<Self::Params> =
+        Issuer::SIGNATURE_ALGORITHM_IDENTIFIER;
+}
+
+/// DER SubjectPublicKeyInfo of a remote key
+#[derive(Clone)]
+pub(crate) struct SpkiDer(Vec<u8>);
+
+impl EncodePublicKey for SpkiDer {
+    fn to_public_key_der(&self) -> spki::Result<Document> {
+        Ok(Document::try_from(self.0.as_slice())?)
+    }
+}
@@ -12,65 +12,216 @@ Abstract:
     template using RustCrypto that can be substituted at firmware runtime.
 
 --*/
-use std::str::FromStr;
-
-use crate::tbs::{get_tbs, init_param, sanitize, TbsParam, TbsTemplate};
+#[cfg(feature = "async")]
+use crate::async_signer::{AsyncTemplateSigner, RemoteIssuer};
+use crate::keys::{check_algorithm_parameters, seed_from_mnemonic, sign, BuilderKeys, SigningMode};
+use crate::keystore::{KeyStore, KeyStoreError};
+use crate::measurement::SHA384_OID;
+use crate::name::{
+    cn_placeholder, cn_sn_name, format_name, key_bytes_hash_with, name_common_name,
+    name_serial_number, reserved_name, subject_format, template_subject, SnHash,
+    CN_ONLY_SUBJECT_FORMAT, DEFAULT_SUBJECT_FORMAT, ISSUER_CN_SENTINEL, SUBJECT_CN_SENTINEL,
+};
+use crate::placeholder::{alphanumeric, PlaceholderAllocator};
+use crate::profile::{
+    check_dice_profile, check_key_usage_encoding, order_extension_oids, order_extensions,
+    sort_extensions_by_oid, ueid_int_bytes, DiceCertKind, CALIPTRA_EXTENSION_ORDER,
+    MULTI_TCB_INFO_OID, TCB_INFO_OID, TCG_DICE_KP_ATTEST_LOC_OID, UEID_OID,
+};
+use crate::provenance::Provenance;
+use crate::tbs::{
+    artifact_name, check_der_reencoding, check_serial_number_param, check_signature_algorithms,
+    check_tbs_size, encode_serial_number, fwid_digest_ranges, get_tbs_offset,
+    init_repeated_param_in, locate_element, locate_extension, locate_public_key, param_name,
+    sanitize_with, tbs_range, tcb_info_svn_ranges, PlaceholderFill, TbsParam, TbsTemplate,
+    TemplateError, TemplateKind, BASIC_CONSTRAINTS_PATH_LEN_PARAM, SERIAL_NUMBER_PARAM,
+};
+use crate::validate::{
+    check_cn_len, check_cn_reservation, check_extended_key_usage, check_fwid,
+    check_hash_alg_candidates, check_opaque_subject_len, check_path_len, check_serial_number_len,
+    check_ueid, BuilderError, MAX_PATCHABLE_PATH_LEN,
+};
 use const_oid::{AssociatedOid, ObjectIdentifier};
 use core::marker::PhantomData;
-use der::asn1::UtcTime;
+use der::asn1::{Any, BitString, GeneralizedTime, Ia5String, Null, OctetString, UtcTime};
 use der::DateTime;
 use der::Decode;
 use der::Sequence;
-use ml_dsa::{KeyGen, MlDsa87};
-use sha2::{Digest, Sha256};
+use num_bigint::BigUint;
+use rand_core::CryptoRngCore;
+use sha2::{Digest, Sha256, Sha384};
 use signature::Keypair;
-use spki::EncodePublicKey;
+use spki::{
+    DynSignatureAlgorithmIdentifier, EncodePublicKey, SignatureBitStringEncoding,
+    SubjectPublicKeyInfoRef,
+};
+use std::borrow::Cow;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 use x509_cert::builder::profile::devid::DevId;
+use x509_cert::builder::profile::BuilderProfile;
 use x509_cert::builder::{Builder, CertificateBuilder};
+use x509_cert::certificate::Version;
 use x509_cert::der::Encode;
 use x509_cert::ext::{
-    pkix::{BasicConstraints, KeyUsage, SubjectKeyIdentifier},
+    pkix::{
+        constraints::name::GeneralSubtree,
+        name::{GeneralName, OtherName},
+        AuthorityKeyIdentifier, BasicConstraints, ExtendedKeyUsage, KeyUsage, KeyUsages,
+        NameConstraints, OcspNoCheck, SubjectAltName, SubjectKeyIdentifier,
+    },
     AsExtension, Extension,
 };
 use x509_cert::name::Name;
 use x509_cert::serial_number::SerialNumber;
 use x509_cert::time::Time;
 use x509_cert::time::Validity;
+use x509_cert::TbsCertificate;
 
-/// CSR Template Param
-struct CertTemplateParam {
+/// Certificate Template Param
+///
+/// Needles borrowed from the builder inputs, like UEIDs and FWID digests, aren't copied.
+#[derive(Clone)]
+struct CertTemplateParam<'a> {
     tbs_param: TbsParam,
-    needle: Vec<u8>,
+    needle: Cow<'a, [u8]>,
 }
 
-#[derive(Sequence, Default, Debug)]
-struct TcgUeid<'a> {
-    #[asn1(type = "OCTET STRING")]
-    ueid: &'a [u8],
+/// TBSCertificate of a template before it is signed, with the inputs of its params needed
+/// once signed
+struct UnsignedCert {
+    tbs_certificate: TbsCertificate,
+    subject_cn: String,
+    subject_pk_bytes: Vec<u8>,
 }
 
-impl<'a> AssociatedOid for TcgUeid<'a> {
+#[derive(Sequence, Debug)]
+struct TcgUeid {
+    ueid: OctetString,
+}
+
+impl AssociatedOid for TcgUeid {
     const OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.23.133.5.4.4");
 }
 
-impl<'a> AsExtension for TcgUeid<'a> {
+impl AsExtension for TcgUeid {
     fn critical(&self, _subject: &Name, _extensions: &[Extension]) -> bool {
         true
     }
 }
 
-#[derive(Sequence, Debug)]
+/// Extension value pre-encoded by `add_encoded_ext`, keeping the OID of its ASN.1 type
+pub struct EncodedExt<T> {
+    value: Any,
+    critical: bool,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+impl<T: Encode + AssociatedOid> EncodedExt<T> {
+    pub fn new(value: &T, critical: bool) -> Self {
+        Self {
+            value: Any::from_der(&value.to_der().unwrap()).unwrap(),
+            critical,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: AssociatedOid> AssociatedOid for EncodedExt<T> {
+    const OID: ObjectIdentifier = T::OID;
+}
+
+impl<T> Encode for EncodedExt<T> {
+    fn encoded_len(&self) -> der::Result<der::Length> {
+        self.value.encoded_len()
+    }
+
+    fn encode(&self, encoder: &mut impl der::Writer) -> der::Result<()> {
+        self.value.encode(encoder)
+    }
+}
+
+impl<T: AssociatedOid> AsExtension for EncodedExt<T> {
+    fn critical(&self, _subject: &Name, _extensions: &[Extension]) -> bool {
+        self.critical
+    }
+}
+
+/// Domain of the digests of `Fwid::placeholder_sha384`
+const FWID_PLACEHOLDER_DOMAIN: &[u8] = b"Caliptra FWID placeholder";
+
+/// FWID of a DiceTcbInfo, with a borrowed or owned digest
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Fwid<'a> {
     pub hash_alg: ObjectIdentifier,
-    #[asn1(type = "BIT STRING")]
-    pub digest: &'a [u8],
+    pub digest: Cow<'a, [u8]>,
 }
 
+impl<'a> Fwid<'a> {
+    pub fn new(hash_alg: ObjectIdentifier, digest: impl Into<Cow<'a, [u8]>>) -> Self {
+        Self {
+            hash_alg,
+            digest: digest.into(),
+        }
+    }
+
+    /// FWID owning the SHA-384 `digest`
+    pub fn sha384(digest: [u8; 48]) -> Fwid<'static> {
+        Fwid::new(SHA384_OID, digest.to_vec())
+    }
+
+    /// SHA-384 FWID with a placeholder digest distinct for each `index`
+    ///
+    /// The digest is the SHA-384 of a fixed domain and `index`, so the placeholders of a
+    /// TcbInfo don't repeat each other nor the fill bytes of typical test digests.
+    pub fn placeholder_sha384(index: u8) -> Fwid<'static> {
+        let digest = Sha384::new()
+            .chain_update(FWID_PLACEHOLDER_DOMAIN)
+            .chain_update([index])
+            .finalize();
+        Fwid::new(SHA384_OID, digest.to_vec())
+    }
+
+    /// Copy the digest if borrowed, so the FWID outlives it
+    pub fn into_owned(self) -> Fwid<'static> {
+        Fwid::new(self.hash_alg, self.digest.into_owned())
+    }
+}
+
+/// FWID encoded in the fwids of a DiceTcbInfo
+#[derive(Sequence, Debug)]
+struct EncodedFwid {
+    hash_alg: ObjectIdentifier,
+    digest: BitString,
+}
+
+impl From<&Fwid<'_>> for EncodedFwid {
+    fn from(fwid: &Fwid<'_>) -> Self {
+        Self {
+            hash_alg: fwid.hash_alg,
+            digest: BitString::from_bytes(&fwid.digest).unwrap(),
+        }
+    }
+}
+
+/// FWID registered as the param `name`
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct FwidParam<'a> {
     pub name: &'static str,
     pub fwid: Fwid<'a>,
 }
 
+impl<'a> FwidParam<'a> {
+    pub fn new(name: &'static str, fwid: Fwid<'a>) -> Self {
+        Self { name, fwid }
+    }
+
+    /// Copy the digest if borrowed, so the param outlives it
+    pub fn into_owned(self) -> FwidParam<'static> {
+        FwidParam::new(self.name, self.fwid.into_owned())
+    }
+}
+
 const TCG_MULTI_TCB_INFO_OID: &str = "2.23.133.5.4.5";
 
 // DICE flag bits
@@ -79,13 +230,27 @@ const FLAG_BIT_NOT_SECURE: u32 = 1 << 1;
 const FLAG_BIT_DEBUG: u32 = 1 << 3;
 const FLAG_BIT_FIXED_WIDTH: u32 = 1 << 31;
 
-const FLAG_MASK: u32 = FLAG_BIT_NOT_CONFIGURED 
-    | FLAG_BIT_NOT_SECURE 
-    | FLAG_BIT_DEBUG 
-    | FLAG_BIT_FIXED_WIDTH;
+const FLAG_MASK: u32 =
+    FLAG_BIT_NOT_CONFIGURED | FLAG_BIT_NOT_SECURE | FLAG_BIT_DEBUG | FLAG_BIT_FIXED_WIDTH;
 
-fn fixed_width_svn(svn: u8) -> u16 {
-    (1_u16 << 8) | svn as u16
+/// Width in bytes of the "tcb_info_fw_svn" param of the RT TcbInfo
+///
+/// This is a breaking change from the former 1 byte RT SVN param, consumers of its length
+/// need to be regenerated. The FMC SVN params keep their width of `FMC_SVN_PARAM_LEN`.
+pub const SVN_PARAM_LEN: usize = std::mem::size_of::<u32>();
+
+/// Width in bytes of the SVN params of the FMC MultiTcbInfo
+pub const FMC_SVN_PARAM_LEN: usize = std::mem::size_of::<u8>();
+
+/// Names of the SVN params, located in the SVNs of the TcbInfos
+const SVN_PARAMS: [&str; 2] = ["tcb_info_fw_svn", "tcb_info_fw_svn_fuses"];
+
+/// Prefix the `len` byte SVN with a 0x01 marker byte
+///
+/// The INTEGER always encodes to `len + 1` content bytes, so the SVN param keeps its
+/// offset and length for any SVN, including values with the top bit set.
+fn fixed_width_svn(svn: u32, len: usize) -> u64 {
+    (1_u64 << (8 * len)) | svn as u64
 }
 
 #[derive(Sequence, Debug)]
@@ -97,12 +262,6 @@ impl<'a> AssociatedOid for MultiTcbInfo<'a> {
     const OID: ObjectIdentifier = ObjectIdentifier::new_unwrap(TCG_MULTI_TCB_INFO_OID);
 }
 
-impl<'a> AsExtension for MultiTcbInfo<'a> {
-    fn critical(&self, _subject: &Name, _extensions: &[Extension]) -> bool {
-        true
-    }
-}
-
 #[derive(Sequence, Debug)]
 #[asn1(tag_mode = "IMPLICIT")]
 struct TcbInfo<'a> {
@@ -116,7 +275,7 @@ struct TcbInfo<'a> {
     version: Option<String>,
 
     #[asn1(context_specific = "3", optional = "true", tag_mode = "IMPLICIT")]
-    svn: Option<u32>,
+    svn: Option<u64>,
 
     #[asn1(context_specific = "4", optional = "true", tag_mode = "IMPLICIT")]
     layer: Option<u64>,
@@ -125,7 +284,7 @@ struct TcbInfo<'a> {
     index: Option<u64>,
 
     #[asn1(context_specific = "6", optional = "true", tag_mode = "IMPLICIT")]
-    fwids: Option<Vec<Fwid<'a>>>,
+    fwids: Option<Vec<EncodedFwid>>,
 
     #[asn1(
         context_specific = "7",
@@ -160,114 +319,1243 @@ struct TcbInfo<'a> {
     flags_mask: Option<&'a [u8]>,
 }
 
-impl<'a> AssociatedOid for TcbInfo<'a> {
-    const OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.23.133.5.4.1");
-}
+impl<'a> AssociatedOid for TcbInfo<'a> {
+    const OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.23.133.5.4.1");
+}
+
+/// id-on-permanentIdentifier
+pub const PERMANENT_IDENTIFIER_OID: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.8.3");
+
+/// Sentinel filling the PermanentIdentifier identifierValue placeholder
+const PERMANENT_ID_SENTINEL: char = '#';
+
+/// Range of the Ueid extension stripped from the template without UEID
+const UEID_VARIANT_RANGE: &str = "EXT_UEID_RANGE";
+
+/// Sentinel filling the RT DiceTcbInfo version placeholder, distinct from the CN sentinels
+const TCB_INFO_VERSION_SENTINEL: char = '~';
+
+/// Sentinel byte filling the issuerUniqueID and subjectUniqueID placeholders
+const UNIQUE_ID_SENTINEL: u8 = 0xA5;
+
+/// Sentinel byte filling the authorityCertSerialNumber placeholder, whose first byte is
+/// encoded by `encode_serial_number`
+const AUTHORITY_CERT_SERIAL_SENTINEL: u8 = 0x5C;
+
+/// Name of the param of the authorityCertSerialNumber content
+pub const AUTHORITY_CERT_SERIAL_PARAM: &str = "AUTHORITY_CERT_SERIAL";
+
+/// Name of the param of the issuerUniqueID content
+pub const ISSUER_UNIQUE_ID_PARAM: &str = "ISSUER_UNIQUE_ID";
+
+/// Name of the param of the subjectUniqueID content
+pub const SUBJECT_UNIQUE_ID_PARAM: &str = "SUBJECT_UNIQUE_ID";
+
+/// UniqueIdentifier BIT STRING of `len` sentinel bytes without unused bits
+fn unique_id_placeholder(len: usize) -> BitString {
+    BitString::from_bytes(&vec![UNIQUE_ID_SENTINEL; len]).unwrap()
+}
+
+/// PermanentIdentifier of RFC 4043
+#[derive(Sequence, Debug)]
+struct PermanentIdentifier {
+    #[asn1(optional = "true")]
+    identifier_value: Option<String>,
+
+    #[asn1(optional = "true")]
+    assigner: Option<ObjectIdentifier>,
+}
+
+/// Digest used to derive the certificate serial number from the subject public key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SerialNumberDigest {
+    Sha256,
+    Sha384,
+}
+
+/// Derive a `len` byte certificate serial number from the raw subject public key bytes
+///
+/// The digest is truncated to `len` bytes and follows the convention of
+/// `encode_serial_number`, so the INTEGER is positive and encodes to exactly `len` bytes.
+/// Firmware recomputing the serial must truncate and mask the digest the same way.
+pub fn derive_serial_number(digest: SerialNumberDigest, public_key: &[u8], len: usize) -> Vec<u8> {
+    let mut serial_number = match digest {
+        SerialNumberDigest::Sha256 => Sha256::digest(public_key).to_vec(),
+        SerialNumberDigest::Sha384 => Sha384::digest(public_key).to_vec(),
+    };
+    if len == 0 || len > 20 || len > serial_number.len() {
+        panic!("Unsupported serial number length {} for {:?}", len, digest);
+    }
+    serial_number.truncate(len);
+    encode_serial_number(&mut serial_number);
+    serial_number
+}
+
+/// Builder profile of the DICE certificates
+///
+/// The issuer, subject and extensions of the DevId profile are followed by the DICE
+/// extensions, and all of them are sorted by `order` so the builder signs the final TBS.
+/// An explicit `authority_key_id` replaces the keyIdentifier derived from the issuer key,
+/// an `authority_cert` adds the issuer and serial of the issuer certificate to it, and a
+/// `key_usage` replaces the bits of the KeyUsage.
+struct DiceProfile {
+    devid: DevId,
+    extensions: Vec<Extension>,
+    order: Vec<ObjectIdentifier>,
+    canonical: bool,
+    authority_key_id: Option<Vec<u8>>,
+    authority_cert: Option<(Vec<GeneralName>, SerialNumber)>,
+    key_usage: Option<KeyUsage>,
+}
+
+impl BuilderProfile for DiceProfile {
+    fn get_issuer(&self, subject: &Name) -> Name {
+        self.devid.get_issuer(subject)
+    }
+
+    fn get_subject(&self) -> Name {
+        self.devid.get_subject()
+    }
+
+    fn build_extensions(
+        &self,
+        spk: SubjectPublicKeyInfoRef<'_>,
+        issuer_spk: SubjectPublicKeyInfoRef<'_>,
+        tbs: &TbsCertificate,
+    ) -> x509_cert::builder::Result<Vec<Extension>> {
+        let mut extensions = self.devid.build_extensions(spk, issuer_spk, tbs)?;
+        if self.authority_key_id.is_some() || self.authority_cert.is_some() {
+            for ext in extensions
+                .iter_mut()
+                .filter(|ext| ext.extn_id == AuthorityKeyIdentifier::OID)
+            {
+                let mut aki = AuthorityKeyIdentifier::from_der(ext.extn_value.as_bytes())?;
+                if let Some(key_id) = &self.authority_key_id {
+                    aki.key_identifier = Some(OctetString::new(key_id.clone())?);
+                }
+                if let Some((issuer, serial)) = &self.authority_cert {
+                    aki.authority_cert_issuer = Some(issuer.clone());
+                    aki.authority_cert_serial_number = Some(serial.clone());
+                }
+                ext.extn_value = OctetString::new(aki.to_der()?)?;
+            }
+        }
+        if let Some(key_usage) = self.key_usage {
+            let extn_value = OctetString::new(key_usage.to_der()?)?;
+            match extensions
+                .iter_mut()
+                .find(|ext| ext.extn_id == KeyUsage::OID)
+            {
+                Some(ext) => ext.extn_value = extn_value,
+                None => extensions.push(Extension {
+                    extn_id: KeyUsage::OID,
+                    critical: true,
+                    extn_value,
+                }),
+            }
+        }
+        extensions.extend(self.extensions.iter().cloned());
+        if self.canonical {
+            sort_extensions_by_oid(&mut extensions);
+        } else {
+            order_extensions(&mut extensions, &self.order);
+        }
+        Ok(extensions)
+    }
+}
+
+/// Append the `ext` of the certificate of `subject` to `extensions`
+fn push_extension(extensions: &mut Vec<Extension>, subject: &Name, ext: &impl AsExtension) {
+    let ext = ext.to_extension(subject, extensions).unwrap();
+    extensions.push(ext);
+}
+
+/// pathLenConstraint of a BasicConstraints extension
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathLen {
+    /// Value fixed at generation time
+    Fixed(u32),
+    /// "BASIC_CONSTRAINTS_PATH_LEN" param of at most `max`
+    ///
+    /// The INTEGER content is a single byte, so runtime values are 0 to 127 and the DER
+    /// length stays stable. Values 128 to 255 would need a leading zero byte, which isn't
+    /// minimal for smaller values, so `max` is limited to `validate::MAX_PATCHABLE_PATH_LEN`.
+    Patchable { max: u8 },
+}
+
+/// Derivation of the SubjectKeyIdentifier from the subject public key
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SkiDerivation {
+    /// SHA-1 of the key, RFC 5280 section 4.2.1.2 method 1
+    #[default]
+    Sha1,
+    /// Leftmost 160 bits of the SHA-256 of the key, RFC 7093 section 2 method 1
+    Sha256Truncated160,
+    /// Full SHA-256 of the key
+    Sha256,
+}
+
+/// Derive the SubjectKeyIdentifier from the raw subject public key bytes
+///
+/// The hash covers the BIT STRING content of subjectPublicKey without the unused bits
+/// byte, i.e. the PUBLIC_KEY param.
+pub fn derive_subject_key_id(derivation: SkiDerivation, public_key: &[u8]) -> Vec<u8> {
+    match derivation {
+        SkiDerivation::Sha1 => sha1::Sha1::digest(public_key).to_vec(),
+        SkiDerivation::Sha256Truncated160 => Sha256::digest(public_key)[..20].to_vec(),
+        SkiDerivation::Sha256 => Sha256::digest(public_key).to_vec(),
+    }
+}
+
+/// Retrieve the keyIdentifier of the SubjectKeyIdentifier of a certificate template, `None`
+/// for a CSR or a certificate without it
+fn template_subject_key_id(template: &TbsTemplate) -> Option<Vec<u8>> {
+    let cert = x509_cert::Certificate::from_der(template.der()).ok()?;
+    let ext = cert
+        .tbs_certificate
+        .extensions?
+        .into_iter()
+        .find(|ext| ext.extn_id == SubjectKeyIdentifier::OID)?;
+    let key_id = SubjectKeyIdentifier::from_der(ext.extn_value.as_bytes()).ok()?;
+    Some(key_id.0.as_bytes().to_vec())
+}
+
+/// Encoding of the Validity placeholders
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimeEncoding {
+    /// UTCTime for dates before 2050 and GeneralizedTime after, as mandated by RFC 5280
+    #[default]
+    Rfc5280,
+    /// UTCTime for all dates, giving 13 byte NOT_BEFORE/NOT_AFTER params
+    UtcTime,
+    /// GeneralizedTime for all dates, giving 15 byte NOT_BEFORE/NOT_AFTER params
+    GeneralizedTime,
+}
+
+/// SubjectAltName entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SanEntry {
+    Dns(String),
+    Uri(String),
+    /// iPAddress, encoded as a 4 or 16 byte OCTET STRING
+    Ip(IpAddr),
+    /// otherName of `type_id` with the DER encoded `value`
+    ///
+    /// The content of the value is registered as the `param` template param if set, for
+    /// values only known at runtime such as hardware identifiers.
+    OtherName {
+        type_id: ObjectIdentifier,
+        value: Vec<u8>,
+        param: Option<&'static str>,
+    },
+}
+
+impl SanEntry {
+    fn general_name(&self) -> GeneralName {
+        match self {
+            Self::Dns(name) => GeneralName::DnsName(Ia5String::new(name).unwrap()),
+            Self::Uri(uri) => GeneralName::UniformResourceIdentifier(Ia5String::new(uri).unwrap()),
+            Self::Ip(IpAddr::V4(ip)) => {
+                GeneralName::IpAddress(OctetString::new(ip.octets()).unwrap())
+            }
+            Self::Ip(IpAddr::V6(ip)) => {
+                GeneralName::IpAddress(OctetString::new(ip.octets()).unwrap())
+            }
+            Self::OtherName { type_id, value, .. } => GeneralName::OtherName(OtherName {
+                type_id: *type_id,
+                value: Any::from_der(value).unwrap(),
+            }),
+        }
+    }
+}
+
+impl TimeEncoding {
+    fn encode(self, date_time: DateTime) -> Time {
+        match self {
+            Self::UtcTime => match UtcTime::from_date_time(date_time) {
+                Ok(time) => Time::UtcTime(time),
+                Err(_) => panic!("{} can't be encoded as UTCTime", date_time),
+            },
+            Self::GeneralizedTime => Time::GeneralTime(GeneralizedTime::from_date_time(date_time)),
+            Self::Rfc5280 if date_time.year() < 2050 => Self::UtcTime.encode(date_time),
+            Self::Rfc5280 => Self::GeneralizedTime.encode(date_time),
+        }
+    }
+}
+
+/// CSR Tempate Builder
+///
+/// The template is signed by an `Issuer` key, of the subject `Key` type unless the builder
+/// is created with `cross_signed`.
+pub struct CertTemplateBuilder<'a, Key, Issuer = Key> {
+    basic_constraints: Option<BasicConstraints>,
+    basic_constraints_critical: Option<bool>,
+    key_usage: Option<KeyUsage>,
+    path_len_max: Option<u8>,
+    name_constraints: Option<NameConstraints>,
+    subject_alt_name: Option<SubjectAltName>,
+    extension_ranges: Vec<(ObjectIdentifier, &'static str)>,
+    tcg_ueid: Option<TcgUeid>,
+    multi_tcb_info: Option<MultiTcbInfo<'a>>,
+    multi_tcb_info_critical: bool,
+    single_tcb_info: Option<TcbInfo<'a>>,
+    single_tcb_info_critical: bool,
+    fwids: Vec<(&'static str, ObjectIdentifier)>,
+    fwid_hash_alg_params: Vec<(&'static str, Vec<ObjectIdentifier>)>,
+    encoded_exts: Vec<(
+        ObjectIdentifier,
+        Box<dyn Fn(&Name, &[Extension]) -> Extension + Send + Sync + 'a>,
+    )>,
+    params: Vec<CertTemplateParam<'a>>,
+    spki_algorithm_param: bool,
+    subject_param_len: Option<usize>,
+    opaque_subject: bool,
+    subject_format: &'a str,
+    serial_number_rdn: bool,
+    sn_hash: SnHash,
+    subject_cn_len: Option<usize>,
+    issuer_cn_len: Option<usize>,
+    parent_name: Option<Name>,
+    parent_key_id: Option<Vec<u8>>,
+    self_signed: bool,
+    subject_key: Option<Key>,
+    issuer_key: Option<Issuer>,
+    rng: Option<Box<dyn CryptoRngCore + Send + 'a>>,
+    signing_mode: SigningMode,
+    serial_number_derivation: Option<(SerialNumberDigest, usize)>,
+    ski_derivation: SkiDerivation,
+    authority_key_id: Option<&'a [u8]>,
+    authority_cert: Option<(Vec<GeneralName>, SerialNumber)>,
+    time_encoding: TimeEncoding,
+    not_before: DateTime,
+    not_after: DateTime,
+    dice_profile: Option<DiceCertKind>,
+    placeholder_signature: bool,
+    placeholder_fill: PlaceholderFill,
+    placeholder_allocator: Option<PlaceholderAllocator>,
+    version: Option<Version>,
+    issuer_unique_id_len: Option<usize>,
+    subject_unique_id_len: Option<usize>,
+    max_tbs_size: Option<usize>,
+    der_round_trip_check: bool,
+    debug_artifacts: Option<PathBuf>,
+    key_seed: Option<Vec<u8>>,
+    #[cfg(feature = "fault-injection")]
+    post_build_mutation: Option<fn(&mut Vec<u8>)>,
+    extension_order: Vec<ObjectIdentifier>,
+    canonical_extension_order: bool,
+    error: Option<BuilderError>,
+    _phantom: PhantomData<(Key, Issuer)>,
+}
+
+impl<'a, Key> Default for CertTemplateBuilder<'a, Key>
+where
+    Key: BuilderKeys
+        + spki::SignatureAlgorithmIdentifier
+        + Keypair
+        + signature::Signer<<Key as BuilderKeys>::Signature>,
+    Key::VerifyingKey: EncodePublicKey,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, Key> CertTemplateBuilder<'a, Key>
+where
+    Key: BuilderKeys
+        + spki::SignatureAlgorithmIdentifier
+        + Keypair
+        + signature::Signer<<Key as BuilderKeys>::Signature>,
+    Key::VerifyingKey: EncodePublicKey,
+{
+    pub fn new() -> Self {
+        Self::init()
+    }
+
+    /// Caliptra 2.0 LDevID certificate: UEID, BasicConstraints(CA, pathLen 3) and keyCertSign
+    pub fn ldevid_profile(ueid: &'a [u8]) -> Self {
+        Self::new()
+            .add_basic_constraints_ext(true, 3)
+            .add_key_usage_ext(KeyUsage(KeyUsages::KeyCertSign.into()))
+            .add_ueid_ext(ueid)
+    }
+
+    /// Caliptra 2.0 FMC alias certificate: the LDevID profile with the MultiTcbInfo of the
+    /// device and FMC measurements
+    pub fn fmc_alias_profile(
+        ueid: &'a [u8],
+        device_fwids: &'a [FwidParam<'a>],
+        fmc_fwids: &'a [FwidParam<'a>],
+    ) -> Self {
+        Self::ldevid_profile(ueid).add_fmc_dice_tcb_info_ext(device_fwids, fmc_fwids, true)
+    }
+
+    /// Caliptra 2.0 RT alias certificate: UEID, BasicConstraints(CA, pathLen 2),
+    /// keyCertSign and digitalSignature, and the TcbInfo of the RT measurements
+    pub fn rt_alias_profile(ueid: &'a [u8], svn: u32, rt_fwids: &'a [FwidParam<'a>]) -> Self {
+        let key_usage = KeyUsage((KeyUsages::KeyCertSign | KeyUsages::DigitalSignature).into());
+        Self::new()
+            .add_basic_constraints_ext(true, 2)
+            .add_key_usage_ext(key_usage)
+            .add_ueid_ext(ueid)
+            .add_rt_dice_tcb_info_ext(svn, rt_fwids, true)
+    }
+
+    /// Create a builder for a template issued by the subject of `parent`
+    ///
+    /// The issuer Name is copied from the parent subject and its serialNumber registered as
+    /// the "ISSUER_SN" param. The keyIdentifier of the AuthorityKeyIdentifier is the
+    /// SubjectKeyIdentifier of a parent certificate, registered as the "AUTHORITY_KEY_ID"
+    /// param, or derived from the issuer key for a parent CSR. Inject the parent key with
+    /// `set_issuer_key` to also match the signature in the generation-time values. The
+    /// issuer CN passed to `tbs_template` must be the CN of the parent subject, a differing
+    /// CN is reported as `BuilderError::IssuerCn`.
+    pub fn child_of(parent: &TbsTemplate) -> Self {
+        let mut builder = Self::new();
+        builder.parent_name = Some(template_subject(parent));
+        builder.parent_key_id = template_subject_key_id(parent);
+        builder
+    }
+
+    /// Caliptra 2.0 DPE leaf certificate: BasicConstraints(not a CA), digitalSignature, the
+    /// critical tcg-dice-kp-attestLoc ExtendedKeyUsage and the MultiTcbInfo of `tci_count`
+    /// DPE-measured TCIs
+    ///
+    /// Each TCI is a SHA-384 FWID registered as the "DPE_TCI_<i>" param, with a distinct
+    /// placeholder of a `PlaceholderAllocator`. The template is validated as a DICE leaf,
+    /// which also requires the UEID of `add_ueid_ext`. A zero `tci_count` is reported as
+    /// `BuilderError::NoDpeTci` by `try_tbs_template`.
+    pub fn dpe_leaf_profile(tci_count: usize) -> Self {
+        let mut allocator = PlaceholderAllocator::new();
+        let tcis = (0..tci_count)
+            .map(|i| allocator.fwid(param_name(format!("DPE_TCI_{}", i)), SHA384_OID))
+            .collect();
+        let mut builder = Self::new()
+            .add_key_usage_ext(KeyUsage(KeyUsages::DigitalSignature.into()))
+            .add_extended_key_usage_ext(&[TCG_DICE_KP_ATTEST_LOC_OID], true)
+            .add_dpe_tcb_info_ext(tcis)
+            .with_placeholder_allocator(allocator)
+            .validate_as(DiceCertKind::Leaf);
+        // RFC 5280 only allows a pathLenConstraint if cA is asserted
+        builder.basic_constraints = Some(BasicConstraints {
+            ca: false,
+            path_len_constraint: None,
+        });
+        if tci_count == 0 {
+            builder.reject(BuilderError::NoDpeTci);
+        }
+        builder
+    }
+
+    /// Generate the self-signed root template of the subject `cn`, panicking on invalid
+    /// builder inputs
+    pub fn self_signed(self, cn: &str) -> TbsTemplate {
+        self.try_self_signed(cn)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Generate the self-signed root template of the subject `cn`, or report the first
+    /// invalid builder input
+    ///
+    /// The issuer Name is the subject Name and the subject key signs its own TBS, the issuer
+    /// key of the builder is ignored. The "ISSUER_SN" param, patched with the value of
+    /// "SUBJECT_SN", covers the serialNumber of the issuer Name. A builder of `child_of` or
+    /// with CN params is reported as `BuilderError::Conflict`.
+    pub fn try_self_signed(mut self, cn: &str) -> Result<TbsTemplate, BuilderError> {
+        if self.parent_name.is_some() {
+            self.reject(BuilderError::Conflict {
+                first: "Self-signed template",
+                second: "a parent template",
+            });
+        }
+        if self.subject_cn_len.is_some() || self.issuer_cn_len.is_some() {
+            self.reject(BuilderError::Conflict {
+                first: "CN params",
+                second: "a self-signed template",
+            });
+        }
+        self.check_inputs(cn, cn)?;
+        self.self_signed = true;
+
+        let mut rng = self.take_rng();
+        let key = self
+            .subject_key
+            .take()
+            .unwrap_or_else(|| Key::key_gen(&mut rng));
+        self.sign_tbs_template(cn, cn, &key, &key, &mut rng)
+    }
+}
+
+impl<'a, Key, Issuer> CertTemplateBuilder<'a, Key, Issuer>
+where
+    Key: BuilderKeys + Keypair,
+    Key::VerifyingKey: EncodePublicKey,
+    Issuer: BuilderKeys
+        + spki::SignatureAlgorithmIdentifier
+        + Keypair
+        + signature::Signer<<Issuer as BuilderKeys>::Signature>,
+    Issuer::VerifyingKey: EncodePublicKey,
+{
+    /// Take the RNG of `set_rng`, or the thread-local RNG
+    fn take_rng(&mut self) -> Box<dyn CryptoRngCore + 'a> {
+        match self.rng.take() {
+            Some(rng) => rng,
+            None => Box::new(rand::thread_rng()),
+        }
+    }
+
+    /// Allocate the needle of the param `name` from the placeholder allocator, encoded by
+    /// `encode`, in place of the `value` generated by the builder
+    fn allocate_needle(
+        &mut self,
+        name: &'static str,
+        value: Vec<u8>,
+        encode: impl Fn(&mut [u8]),
+    ) -> Vec<u8> {
+        match &mut self.placeholder_allocator {
+            Some(allocator) => allocator.allocate_with(name, value.len(), encode),
+            None => value,
+        }
+    }
+
+    /// Record the needle `value` of the param `name`, which can't be a placeholder, with the
+    /// placeholder allocator
+    fn register_needle(&mut self, name: &'static str, value: &[u8]) {
+        if let Some(allocator) = &mut self.placeholder_allocator {
+            allocator.register(name, value);
+        }
+    }
+
+    /// Create a builder signing with an `Issuer` key of another algorithm than the subject
+    /// key, e.g. an ML-DSA-87 subject key certified by an ECDSA P-384 issuer during the
+    /// transition to post-quantum keys
+    ///
+    /// The signature AlgorithmIdentifiers are those of the issuer key, the
+    /// subjectPublicKeyInfo and the "PUBLIC_KEY" param those of the subject key.
+    pub fn cross_signed() -> Self {
+        Self::init()
+    }
+
+    fn init() -> Self {
+        Self {
+            params: Vec::new(),
+            spki_algorithm_param: false,
+            subject_param_len: None,
+            opaque_subject: false,
+            subject_format: DEFAULT_SUBJECT_FORMAT,
+            serial_number_rdn: true,
+            sn_hash: SnHash::default(),
+            subject_cn_len: None,
+            issuer_cn_len: None,
+            parent_name: None,
+            parent_key_id: None,
+            self_signed: false,
+            subject_key: None,
+            issuer_key: None,
+            rng: None,
+            signing_mode: SigningMode::default(),
+            serial_number_derivation: None,
+            ski_derivation: SkiDerivation::default(),
+            authority_key_id: None,
+            authority_cert: None,
+            time_encoding: TimeEncoding::default(),
+            not_before: DateTime::new(2023, 01, 01, 0, 0, 0).unwrap(),
+            not_after: DateTime::new(2049, 12, 31, 23, 59, 59).unwrap(),
+            dice_profile: None,
+            placeholder_signature: false,
+            placeholder_fill: PlaceholderFill::default(),
+            placeholder_allocator: None,
+            version: None,
+            issuer_unique_id_len: None,
+            subject_unique_id_len: None,
+            max_tbs_size: None,
+            der_round_trip_check: true,
+            debug_artifacts: None,
+            key_seed: None,
+            #[cfg(feature = "fault-injection")]
+            post_build_mutation: None,
+            extension_order: CALIPTRA_EXTENSION_ORDER.to_vec(),
+            canonical_extension_order: false,
+            error: None,
+            _phantom: PhantomData,
+            basic_constraints: None,
+            basic_constraints_critical: None,
+            key_usage: None,
+            path_len_max: None,
+            name_constraints: None,
+            subject_alt_name: None,
+            extension_ranges: Vec::new(),
+            tcg_ueid: None,
+            multi_tcb_info: None,
+            multi_tcb_info_critical: true,
+            single_tcb_info: None,
+            single_tcb_info_critical: true,
+            fwids: Vec::new(),
+            fwid_hash_alg_params: Vec::new(),
+            encoded_exts: Vec::new(),
+        }
+    }
+
+    /// Fail template generation if the result violates the DICE profile of `kind`
+    pub fn validate_as(mut self, kind: DiceCertKind) -> Self {
+        self.dice_profile = Some(kind);
+        self
+    }
+
+    /// Force the X.509 `version` of the certificate instead of the v3 picked by the builder
+    ///
+    /// v1 and v2 certificates with extensions, which require v3, are reported as
+    /// `BuilderError::Version` by `try_tbs_template`.
+    pub fn version(mut self, version: Version) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    /// Add an issuerUniqueID of `len` placeholder bytes, registered as the
+    /// "ISSUER_UNIQUE_ID" param
+    ///
+    /// The [1] BIT STRING follows the subjectPublicKeyInfo and has no unused bits, the param
+    /// covers its content. Unique identifiers require a v2 or v3 certificate. A zero `len` is
+    /// reported as `BuilderError::EmptyField` by `try_tbs_template`.
+    pub fn add_issuer_unique_id(mut self, len: usize) -> Self {
+        if len == 0 {
+            self.reject(BuilderError::EmptyField {
+                field: "issuerUniqueID",
+            });
+            return self;
+        }
+        self.issuer_unique_id_len = Some(len);
+        self
+    }
+
+    /// Add a subjectUniqueID of `len` placeholder bytes, registered as the
+    /// "SUBJECT_UNIQUE_ID" param
+    ///
+    /// The [2] BIT STRING follows the issuerUniqueID, if any, and precedes the extensions,
+    /// see `add_issuer_unique_id`.
+    pub fn add_subject_unique_id(mut self, len: usize) -> Self {
+        if len == 0 {
+            self.reject(BuilderError::EmptyField {
+                field: "subjectUniqueID",
+            });
+            return self;
+        }
+        self.subject_unique_id_len = Some(len);
+        self
+    }
+
+    /// Zero the signature of the generated DER, keeping its length, and register it as the
+    /// "SIGNATURE" param of `TbsTemplate::params_in_der`
+    ///
+    /// The DER stays a syntactically valid certificate that firmware completes by
+    /// overwriting the signature. The generation-time signature is still verified first.
+    pub fn placeholder_signature(mut self) -> Self {
+        self.placeholder_signature = true;
+        self
+    }
+
+    /// Fill the sanitized params of the template with the placeholder bytes of `fill` rather
+    /// than the 0x5F of `sanitize`, e.g. `PlaceholderFill::Byte(0xAA)` to spot a wrongly
+    /// patched region
+    ///
+    /// The params left unsanitized, e.g. "SPKI_ALGORITHM" and "SUBJECT", keep their bytes.
+    pub fn placeholder_fill(mut self, fill: PlaceholderFill) -> Self {
+        self.placeholder_fill = fill;
+        self
+    }
+
+    /// Allocate the placeholders of the params of the builder from `allocator`, e.g. the
+    /// serial number, the CNs and the key identifiers, and check that its patterns, also the
+    /// UEID and FWID digests passed to the builder, only occur in the TBS at their params
+    ///
+    /// The validity times, a derived serial number and the AKI of a child template can't be
+    /// placeholders, they are recorded with the allocator instead.
+    pub fn with_placeholder_allocator(mut self, allocator: PlaceholderAllocator) -> Self {
+        self.placeholder_allocator = Some(allocator);
+        self
+    }
+
+    /// Fail template generation if the TBS is larger than `bytes`
+    pub fn max_tbs_size(mut self, bytes: usize) -> Self {
+        self.max_tbs_size = Some(bytes);
+        self
+    }
+
+    /// Skip the check that the generated DER re-encodes to the same bytes once decoded,
+    /// e.g. to speed up the generation of many templates
+    pub fn skip_der_round_trip_check(mut self) -> Self {
+        self.der_round_trip_check = false;
+        self
+    }
+
+    /// Record the seed the key was generated from, e.g. by `keys::mldsa87_key_from_seed`, in
+    /// the provenance of the template
+    ///
+    /// The seed is only recorded for deterministic signing, whose output it reproduces.
+    pub fn record_key_seed(mut self, seed: &[u8]) -> Self {
+        self.key_seed = Some(seed.to_vec());
+        self
+    }
+
+    /// Write the intermediate DER, TBS and param offsets of the template to `dir`, see
+    /// `TbsTemplate::write_debug_artifacts`
+    ///
+    /// The file names are prefixed by the subject CN. The template is not affected.
+    pub fn debug_artifacts(mut self, dir: impl AsRef<Path>) -> Self {
+        self.debug_artifacts = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Mutate the signed DER with `mutation` before the TBS and the params are extracted
+    ///
+    /// Fault-injection hook reaching the error paths of template generation from tests.
+    #[cfg(feature = "fault-injection")]
+    pub fn with_post_build_mutation(mut self, mutation: fn(&mut Vec<u8>)) -> Self {
+        self.post_build_mutation = Some(mutation);
+        self
+    }
+
+    /// Order the extensions by OID, extensions missing from `order` follow the others in
+    /// insertion order
+    ///
+    /// Defaults to the legacy Caliptra layout of `CALIPTRA_EXTENSION_ORDER`.
+    pub fn extension_order(mut self, order: &[ObjectIdentifier]) -> Self {
+        self.extension_order = order.to_vec();
+        self
+    }
+
+    /// Sort the extensions by OID instead of `extension_order`, so templates with the same
+    /// extensions are identical whatever order they were added in
+    pub fn canonical_extension_order(mut self) -> Self {
+        self.canonical_extension_order = true;
+        self
+    }
+
+    /// OIDs of the extensions of the configured builder, in the order of the generated TBS
+    ///
+    /// The KeyUsage and AuthorityKeyIdentifier of the DevId profile and the
+    /// SubjectKeyIdentifier are always emitted. No key is generated, so tooling can check the
+    /// configuration cheaply.
+    pub fn extension_oids(&self) -> Vec<ObjectIdentifier> {
+        let configured = [
+            (self.basic_constraints.is_some(), BasicConstraints::OID),
+            (self.name_constraints.is_some(), NameConstraints::OID),
+            (self.subject_alt_name.is_some(), SubjectAltName::OID),
+            (self.tcg_ueid.is_some(), TcgUeid::OID),
+            (self.multi_tcb_info.is_some(), MultiTcbInfo::OID),
+            (self.single_tcb_info.is_some(), TcbInfo::OID),
+        ];
+        let mut oids = vec![KeyUsage::OID, AuthorityKeyIdentifier::OID];
+        for (set, oid) in configured {
+            if set {
+                oids.push(oid);
+            }
+        }
+        oids.extend(self.encoded_exts.iter().map(|(oid, _)| *oid));
+        oids.push(SubjectKeyIdentifier::OID);
+        let canonical = self.canonical_extension_order;
+        order_extension_oids(&mut oids, &self.extension_order, canonical);
+        oids
+    }
+
+    /// Generate the keys that were not set, and the randomness of hedged signatures, with
+    /// `rng` instead of the thread-local RNG
+    ///
+    /// A seeded `rng` with fixed validity and names reproduces the template byte for byte.
+    pub fn set_rng(mut self, rng: impl CryptoRngCore + Send + 'a) -> Self {
+        self.rng = Some(Box::new(rng));
+        self
+    }
+
+    /// Sign the certificate in the signing `mode`, deterministic by default
+    ///
+    /// Hedged signing draws its randomness from the RNG of `set_rng` and fails generation
+    /// for keys without a hedged variant.
+    pub fn signing_mode(mut self, mode: SigningMode) -> Self {
+        self.signing_mode = mode;
+        self
+    }
+
+    /// Use a pre-generated subject `key` instead of generating one
+    pub fn set_subject_key(mut self, key: Key) -> Self {
+        self.subject_key = Some(key);
+        self
+    }
+
+    /// Sign the template with a pre-generated issuer `key` instead of generating one
+    pub fn set_issuer_key(mut self, key: Issuer) -> Self {
+        self.issuer_key = Some(key);
+        self
+    }
+
+    /// Add a BasicConstraints extension, critical for a CA unless overridden by
+    /// `set_basic_constraints_critical`
+    ///
+    /// A `path_len` above 255 is reported as `BuilderError::PathLength` by
+    /// `try_tbs_template`.
+    pub fn add_basic_constraints_ext(self, ca: bool, path_len: u32) -> Self {
+        self.add_basic_constraints_ext_with(ca, PathLen::Fixed(path_len))
+    }
+
+    /// Add a BasicConstraints extension whose pathLenConstraint may be patchable
+    ///
+    /// A patchable pathLenConstraint holds `max` at generation time and its INTEGER content
+    /// is located structurally as the single byte "BASIC_CONSTRAINTS_PATH_LEN" param.
+    /// `TbsTemplate::apply` rejects values above `max`.
+    pub fn add_basic_constraints_ext_with(mut self, ca: bool, path_len: PathLen) -> Self {
+        let path_len = match path_len {
+            PathLen::Fixed(path_len) => check_path_len(path_len),
+            PathLen::Patchable { max } if max > MAX_PATCHABLE_PATH_LEN => {
+                Err(BuilderError::PatchablePathLength { max })
+            }
+            PathLen::Patchable { max } => {
+                self.path_len_max = Some(max);
+                Ok(max)
+            }
+        };
+        match path_len {
+            Ok(path_len) => {
+                self.basic_constraints = Some(BasicConstraints {
+                    ca,
+                    path_len_constraint: Some(path_len),
+                })
+            }
+            Err(err) => self.reject(err),
+        }
+        self
+    }
+
+    /// Mark the BasicConstraints extension critical or not, overriding the default
+    ///
+    /// By default it is critical for a CA, as RFC 5280 requires, and non-critical otherwise.
+    pub fn set_basic_constraints_critical(mut self, critical: bool) -> Self {
+        self.basic_constraints_critical = Some(critical);
+        self
+    }
+
+    /// Set the KeyUsage extension to the `usage` bits, instead of the digitalSignature of the
+    /// DevId profile
+    ///
+    /// The BIT STRING is DER-minimal, one content byte up to encipherOnly and two with
+    /// decipherOnly. Its length depends on the bits, so it is fixed at generation time.
+    pub fn add_key_usage_ext(mut self, usage: KeyUsage) -> Self {
+        self.key_usage = Some(usage);
+        self
+    }
+
+    /// Add an ExtendedKeyUsage extension with the key purposes `usages`
+    ///
+    /// `ANY_EXTENDED_KEY_USAGE_OID` must be the only key purpose, see
+    /// `check_extended_key_usage`. RFC 5280 recommends a non-critical extension with it.
+    pub fn add_extended_key_usage_ext(
+        mut self,
+        usages: &[ObjectIdentifier],
+        critical: bool,
+    ) -> Self {
+        if let Err(err) = check_extended_key_usage(usages) {
+            self.reject(err);
+            return self;
+        }
+        self.add_encoded_ext(&ExtendedKeyUsage(usages.to_vec()), critical)
+    }
+
+    /// Derive the placeholder serial number from the subject public key
+    ///
+    /// The SERIAL_NUMBER param remains patchable, so firmware can recompute the serial over
+    /// the real key with `derive_serial_number`.
+    pub fn add_derived_serial_number(mut self, digest: SerialNumberDigest, len: usize) -> Self {
+        self.serial_number_derivation = Some((digest, len));
+        self
+    }
+
+    /// Select the derivation of the SubjectKeyIdentifier, SHA-1 by default
+    ///
+    /// The SUBJECT_KEY_ID param length follows the derivation, 20 bytes except 32 for the full
+    /// SHA-256. The keyIdentifier of the AuthorityKeyIdentifier is derived from the issuer
+    /// key the same way, so it matches the SubjectKeyIdentifier of an issuer template using
+    /// the same derivation. A child template takes the key identifier of its parent instead.
+    pub fn set_subject_key_id_derivation(mut self, derivation: SkiDerivation) -> Self {
+        self.ski_derivation = derivation;
+        self
+    }
+
+    /// Set the keyIdentifier of the AuthorityKeyIdentifier
+    ///
+    /// `Some` bakes the issuer key identifier known at generation time into the template,
+    /// without a param. `None` keeps the identifier derived from the issuer key with the
+    /// `SkiDerivation` of the builder, registered as the "AUTHORITY_KEY_ID" param for
+    /// firmware to substitute at runtime.
+    pub fn add_auth_key_id_ext(mut self, key_id: Option<&'a [u8]>) -> Self {
+        self.authority_key_id = key_id;
+        self
+    }
+
+    /// Add the authorityCertIssuer `issuer` and an authorityCertSerialNumber of `serial_len`
+    /// content octets to the AuthorityKeyIdentifier, e.g. for cross-certificates
+    ///
+    /// The serial of the issuer certificate is only known once that certificate is issued,
+    /// so the template holds a sentinel serial registered as the `AUTHORITY_CERT_SERIAL_PARAM`
+    /// param, for firmware or `TbsTemplate::apply` to patch with the real serial. The param
+    /// is located at the end of the AuthorityKeyIdentifier rather than by needle search, as
+    /// a short serial may occur elsewhere in the TBS. The `issuer` names are fixed at
+    /// generation time. A length outside of 1 to `MAX_SERIAL_NUMBER_LEN` bytes is reported as
+    /// `BuilderError::SerialNumberLength` by `try_tbs_template`.
+    pub fn add_auth_key_id_cert_issuer(
+        mut self,
+        issuer: &[GeneralName],
+        serial_len: usize,
+    ) -> Self {
+        if let Err(err) = check_serial_number_len(serial_len) {
+            self.reject(err);
+            return self;
+        }
+        let mut sentinel = vec![AUTHORITY_CERT_SERIAL_SENTINEL; serial_len];
+        encode_serial_number(&mut sentinel);
+        let serial = SerialNumber::new(&sentinel).unwrap();
+        self.authority_cert = Some((issuer.to_vec(), serial));
+        self
+    }
+
+    /// Select the encoding of the Validity placeholders
+    ///
+    /// The NOT_BEFORE and NOT_AFTER param lengths follow the encoding, 13 bytes for UTCTime
+    /// and 15 bytes for GeneralizedTime.
+    pub fn set_time_encoding(mut self, encoding: TimeEncoding) -> Self {
+        self.time_encoding = encoding;
+        self
+    }
+
+    /// Set the placeholder Validity times
+    ///
+    /// Use `DateTime::INFINITY` for a certificate without a well-defined expiry, which is
+    /// encoded as 99991231235959Z and requires GeneralizedTime.
+    pub fn set_validity(mut self, not_before: DateTime, not_after: DateTime) -> Self {
+        self.not_before = not_before;
+        self.not_after = not_after;
+        self
+    }
+
+    /// Add a critical NameConstraints extension restricting the names a CA may issue for
+    ///
+    /// An empty slice omits the corresponding subtrees, but at least one of `permitted` and
+    /// `excluded` must be non-empty as required by RFC 5280.
+    pub fn add_name_constraints_ext(
+        mut self,
+        permitted: &[GeneralSubtree],
+        excluded: &[GeneralSubtree],
+    ) -> Self {
+        if permitted.is_empty() && excluded.is_empty() {
+            panic!("NameConstraints requires at least one permitted or excluded subtree");
+        }
+
+        let subtrees = |s: &[GeneralSubtree]| (!s.is_empty()).then(|| s.to_vec());
+        self.name_constraints = Some(NameConstraints {
+            permitted_subtrees: subtrees(permitted),
+            excluded_subtrees: subtrees(excluded),
+        });
+        self
+    }
+
+    /// Add a SubjectAltName extension with the `names` in order
+    ///
+    /// Names of further calls, or of `add_permanent_identifier_ext`, are appended to the same
+    /// extension.
+    pub fn add_subject_alt_name_ext(mut self, names: &[SanEntry]) -> Self {
+        for name in names.iter() {
+            if let SanEntry::OtherName {
+                value,
+                param: Some(param),
+                ..
+            } = name
+            {
+                let content = Any::from_der(value).unwrap().value().to_vec();
+                match TbsParam::try_new(param, 0, content.len()) {
+                    Ok(tbs_param) => self.params.push(CertTemplateParam {
+                        tbs_param,
+                        needle: content.into(),
+                    }),
+                    Err(err) => self.reject(BuilderError::Template(err)),
+                }
+            }
+        }
+
+        self.push_subject_alt_names(names.iter().map(SanEntry::general_name));
+        self
+    }
+
+    /// Add an id-on-permanentIdentifier otherName to the SubjectAltName extension
+    ///
+    /// The identifierValue UTF8String reserves `value_len` bytes and is registered as the
+    /// "PERMANENT_ID" param, for firmware to patch with the permanent ID of the device.
+    pub fn add_permanent_identifier_ext(
+        mut self,
+        assigner: Option<ObjectIdentifier>,
+        value_len: usize,
+    ) -> Self {
+        if value_len == 0 {
+            panic!("PermanentIdentifier identifierValue requires a non-zero length");
+        }
+
+        let placeholder: String = std::iter::repeat(PERMANENT_ID_SENTINEL)
+            .take(value_len)
+            .collect();
+        self.params.push(CertTemplateParam {
+            tbs_param: TbsParam::new("PERMANENT_ID", 0, value_len),
+            needle: placeholder.as_bytes().to_vec().into(),
+        });
+
+        let permanent_identifier = PermanentIdentifier {
+            identifier_value: Some(placeholder),
+            assigner,
+        };
+        let other_name = OtherName {
+            type_id: PERMANENT_IDENTIFIER_OID,
+            value: Any::from_der(&permanent_identifier.to_der().unwrap()).unwrap(),
+        };
+        self.push_subject_alt_names([GeneralName::OtherName(other_name)]);
+        self
+    }
+
+    fn push_subject_alt_names(&mut self, names: impl IntoIterator<Item = GeneralName>) {
+        self.subject_alt_name
+            .get_or_insert_with(|| SubjectAltName(Vec::new()))
+            .0
+            .extend(names);
+    }
+
+    /// Register the range of the whole Extension of `oid` as `name`, e.g.
+    /// "EXT_TCB_INFO_RANGE", so firmware can strip it with `TbsTemplate::strip`
+    ///
+    /// The Extension is located structurally once the template is built.
+    pub fn add_extension_range_param(mut self, oid: ObjectIdentifier, name: &'static str) -> Self {
+        self.extension_ranges.push((oid, name));
+        self
+    }
+
+    /// Register the SubjectPublicKeyInfo AlgorithmIdentifier as the "SPKI_ALGORITHM" param
+    ///
+    /// The param is located structurally and left unsanitized, so firmware can validate the
+    /// algorithm against the key it injects.
+    pub fn add_spki_algorithm_param(mut self) -> Self {
+        self.spki_algorithm_param = true;
+        self
+    }
+
+    /// Register the whole subject Name as the "SUBJECT" param instead of "SUBJECT_SN"
+    ///
+    /// The subject is padded to an encoding of exactly `len` bytes, which firmware must
+    /// preserve when substituting its own Name. A `len` the padding can't reach is reported
+    /// as `BuilderError::SubjectLength` by `try_tbs_template`, see `name::reserved_cn_sn_name`.
+    pub fn add_subject_param(mut self, len: usize) -> Self {
+        self.subject_param_len = Some(len);
+        self
+    }
 
-impl<'a> AsExtension for TcbInfo<'a> {
-    fn critical(&self, _subject: &Name, _extensions: &[Extension]) -> bool {
-        true
+    /// Reserve the whole subject Name as the "SUBJECT" param of `len` bytes, for firmware to
+    /// overwrite with an opaque Name, e.g. provided by the attested component
+    ///
+    /// The placeholder is a Name of a sentinel CN padded to `len` bytes, which must fit a
+    /// minimal Name, see `MIN_OPAQUE_SUBJECT_LEN`. It derives from neither the subject CN
+    /// nor the subject key, so the template has no "SUBJECT_SN" param. Combined with
+    /// `add_subject_cn_param`, it is reported as `BuilderError::Conflict`.
+    pub fn set_opaque_subject(mut self, len: usize) -> Self {
+        if let Err(err) = check_opaque_subject_len(len) {
+            self.reject(err);
+        }
+        self.opaque_subject = true;
+        self.add_subject_param(len)
     }
-}
 
-/// CSR Tempate Builder
-pub struct CertTemplateBuilder<'a, Key> {
-    basic_constraints: Option<BasicConstraints>,
-    key_usage: Option<KeyUsage>,
-    tcg_ueid: Option<TcgUeid<'a>>,
-    multi_tcb_info: Option<MultiTcbInfo<'a>>,
-    single_tcb_info: Option<TcbInfo<'a>>,
-    params: Vec<CertTemplateParam>,
-    _phantom: PhantomData<Key>,
-}
+    /// Set the RFC 4514 string of the subject Name, `DEFAULT_SUBJECT_FORMAT` by default
+    ///
+    /// `{cn}` and `{sn}` stand for the subject CN and the serialNumber derived from the
+    /// subject key, e.g. `OU=Caliptra,OU=RT,CN={cn},serialNumber={sn}`. Attribute types may
+    /// repeat and the serialNumber may take any position, the "SUBJECT_SN" param is located
+    /// by its value.
+    pub fn set_subject_format(mut self, format: &'a str) -> Self {
+        self.subject_format = format;
+        self
+    }
 
-pub trait BuilderKeys: Sized {
-    type Signature: spki::SignatureBitStringEncoding;
-    fn key_gen() -> Self;
-}
+    /// Select the digest of the public keys in the serialNumber attributes of the subject
+    /// and issuer Names, SHA-256 by default
+    ///
+    /// The "SUBJECT_SN" and "ISSUER_SN" params take the length of the hex digest. The issuer
+    /// Name of a child template comes from its parent and keeps the digest of the parent.
+    pub fn set_sn_hash(mut self, hash: SnHash) -> Self {
+        self.sn_hash = hash;
+        self
+    }
 
-impl BuilderKeys for ml_dsa::KeyPair<MlDsa87> {
-    type Signature = ml_dsa::Signature<MlDsa87>;
-    fn key_gen() -> Self {
-        let mut rng = rand::thread_rng();
-        <MlDsa87 as KeyGen>::key_gen(&mut rng)
+    /// Omit the serialNumber RDN of the subject Name, which then holds only the CN, or the
+    /// attributes of a `set_subject_format` without `{sn}`, and has no "SUBJECT_SN" param
+    pub fn no_serial_number_rdn(mut self) -> Self {
+        self.serial_number_rdn = false;
+        self
     }
-}
 
-impl<'a, Key> CertTemplateBuilder<'a, Key>
-where
-    Key: BuilderKeys
-        + spki::SignatureAlgorithmIdentifier
-        + Keypair
-        + signature::Signer<<Key as BuilderKeys>::Signature>,
-    Key::VerifyingKey: EncodePublicKey,
-{
-    pub fn new() -> Self {
-        Self {
-            params: Vec::new(),
-            _phantom: PhantomData,
-            basic_constraints: None,
-            key_usage: None,
-            tcg_ueid: None,
-            multi_tcb_info: None,
-            single_tcb_info: None,
+    /// Register the subject CommonName as the "SUBJECT_CN" param
+    ///
+    /// The generation-time CN is padded with a sentinel to reserve `len` bytes, so it must be
+    /// shorter than the reservation, or `try_tbs_template` reports
+    /// `BuilderError::CnReservation`. Runtime values shorter than the reservation must be
+    /// padded with trailing spaces.
+    pub fn add_subject_cn_param(mut self, len: usize) -> Self {
+        if let Err(err) = check_cn_len("Subject CN reservation", len) {
+            self.reject(err);
         }
+        self.subject_cn_len = Some(len);
+        self
     }
 
-    pub fn add_basic_constraints_ext(mut self, ca: bool, path_len: u32) -> Self {
-        self.basic_constraints = Some(BasicConstraints {
-            ca,
-            path_len_constraint: Some(path_len as u8),
-        });
+    /// Register the issuer CommonName as the "ISSUER_CN" param
+    ///
+    /// The generation-time CN is padded with a sentinel distinct from the subject's to reserve
+    /// `len` bytes, so it must be shorter than the reservation as for `add_subject_cn_param`.
+    /// Runtime values shorter than the reservation must be padded with trailing spaces.
+    pub fn add_issuer_cn_param(mut self, len: usize) -> Self {
+        if let Err(err) = check_cn_len("Issuer CN reservation", len) {
+            self.reject(err);
+        }
+        self.issuer_cn_len = Some(len);
         self
     }
 
-    pub fn add_key_usage_ext(mut self, usage: KeyUsage) -> Self {
-        self.key_usage = Some(usage);
-        self
+    /// Add the TCG DICE Ueid extension, the UEID must be 1 to 33 bytes
+    pub fn add_ueid_ext(self, ueid: &'a [u8]) -> Self {
+        self.set_ueid(Cow::Borrowed(ueid))
+    }
+
+    /// Add the TCG DICE Ueid extension holding the integer `value` as `width` big-endian bytes
+    ///
+    /// Leading zeros are kept, so the UEID param is always `width` bytes. A `value` that
+    /// doesn't fit is reported as `BuilderError::UeidValue` by `try_tbs_template`.
+    pub fn add_ueid_int(mut self, value: &BigUint, width: usize) -> Self {
+        match ueid_int_bytes(value, width) {
+            Some(ueid) => self.set_ueid(Cow::Owned(ueid)),
+            None => {
+                self.reject(BuilderError::UeidValue { width });
+                self
+            }
+        }
     }
 
-    pub fn add_ueid_ext(mut self, ueid: &'a [u8]) -> Self {
-        self.tcg_ueid = Some(TcgUeid { ueid });
+    fn set_ueid(mut self, ueid: Cow<'a, [u8]>) -> Self {
+        if let Err(err) = check_ueid(&ueid) {
+            self.reject(err);
+            return self;
+        }
+        self.tcg_ueid = Some(TcgUeid {
+            ueid: OctetString::new(ueid.to_vec()).unwrap(),
+        });
         let param = CertTemplateParam {
             tbs_param: TbsParam::new("UEID", 0, ueid.len()),
-            needle: ueid.to_vec(),
+            needle: ueid,
         };
         self.params.push(param);
 
         self
     }
 
-    pub fn add_fmc_dice_tcb_info_ext(
+    /// Add an extension of any ASN.1 type with an associated OID
+    ///
+    /// The value is encoded as the extnValue of an extension identified by `T::OID`.
+    pub fn add_encoded_ext<T: Encode + AssociatedOid + 'a>(
+        mut self,
+        value: &T,
+        critical: bool,
+    ) -> Self {
+        let ext = EncodedExt::new(value, critical);
+        self.encoded_exts.push((
+            T::OID,
+            Box::new(move |subject, extensions| ext.to_extension(subject, extensions).unwrap()),
+        ));
+        self
+    }
+
+    /// Add the non-critical id-pkix-ocsp-nocheck extension of an OCSP responder certificate
+    ///
+    /// The extnValue is an OCTET STRING wrapping a DER NULL.
+    pub fn add_ocsp_nocheck_ext(self) -> Self {
+        self.add_encoded_ext(&OcspNoCheck(Null), false)
+    }
+
+    /// Register the hashAlg OID of the FWID param `fwid_name` as the "<fwid_name>_HASH_ALG"
+    /// param, for firmware choosing the measurement hash at runtime
+    ///
+    /// The param covers the OID content bytes, 9 bytes for SHA-256, SHA-384 and SHA-512. The
+    /// digest param keeps the length of the generation-time digest, so build the template
+    /// with the longest candidate, e.g. a 64 byte SHA-512 placeholder, and patch shorter
+    /// digests with `measurement::padded_digest`. All FWIDs sharing the hash algorithm must
+    /// register the param, so their OIDs are located unambiguously.
+    pub fn add_fwid_hash_alg_param(self, fwid_name: &'static str) -> Self {
+        self.add_fwid_hash_alg_param_with(fwid_name, &[])
+    }
+
+    /// Register the hashAlg OID param of `add_fwid_hash_alg_param`, checking that the
+    /// `candidates` firmware may patch in encode to the length of the generation-time OID
+    ///
+    /// A candidate of another length would shift the offsets of the following fields, it is
+    /// reported as `BuilderError::HashAlgLength` by `try_tbs_template`.
+    pub fn add_fwid_hash_alg_param_with(
         mut self,
+        fwid_name: &'static str,
+        candidates: &[ObjectIdentifier],
+    ) -> Self {
+        self.fwid_hash_alg_params
+            .push((fwid_name, candidates.to_vec()));
+        self
+    }
+
+    /// Add the MultiTcbInfo extension of the device and FMC measurements, see
+    /// `add_fmc_dice_tcb_info_ext_owned`
+    pub fn add_fmc_dice_tcb_info_ext(
+        self,
         device_fwids: &'a [FwidParam<'a>],
         fmc_fwids: &'a [FwidParam<'a>],
+        critical: bool,
+    ) -> Self {
+        self.add_fmc_dice_tcb_info_ext_owned(device_fwids.to_vec(), fmc_fwids.to_vec(), critical)
+    }
+
+    /// Add the MultiTcbInfo extension of the device and FMC measurements, taking the FWIDs
+    /// by value so they may own their digests
+    ///
+    /// The Caliptra profiles mark the extension `critical`, some DICE profiles don't.
+    pub fn add_fmc_dice_tcb_info_ext_owned(
+        mut self,
+        device_fwids: Vec<FwidParam<'a>>,
+        fmc_fwids: Vec<FwidParam<'a>>,
+        critical: bool,
     ) -> Self {
-        // This method of finding the offsets is fragile. Especially for the 1 byte values.
-        // These may need to be updated to stay unique when the cert template is updated.
+        for fwid in device_fwids.iter().chain(fmc_fwids.iter()) {
+            if let Err(err) = check_fwid(fwid) {
+                self.reject(err);
+            }
+        }
+
+        // This method of finding the offsets is fragile. The 1 byte SVNs are only searched
+        // in the SVN INTEGERs of the TcbInfos, but the flags may need to be updated to stay
+        // unique when the cert template is updated.
         let flags: u32 = 0xC0C1C2C3;
         let svn: u8 = 0xC4;
         let svn_fuses: u8 = 0xC6;
 
-        let wide_svn = fixed_width_svn(svn);
-        let wide_svn_fuses = fixed_width_svn(svn_fuses);
+        let wide_svn = fixed_width_svn(svn.into(), FMC_SVN_PARAM_LEN);
+        let wide_svn_fuses = fixed_width_svn(svn_fuses.into(), FMC_SVN_PARAM_LEN);
 
         // No need to create a local variable for the mask
 
         // Create the device info TcbInfo
-        let device_fwids_vec: Vec<Fwid> = device_fwids
-            .iter()
-            .map(|f| Fwid {
-                hash_alg: f.fwid.hash_alg.clone(),
-                digest: f.fwid.digest,
-            })
-            .collect();
+        let device_fwids_vec = device_fwids.iter().map(|f| (&f.fwid).into()).collect();
 
         let device_info = TcbInfo {
             vendor: None,
             model: None,
             version: None,
-            svn: Some(wide_svn_fuses as u32),
+            svn: Some(wide_svn_fuses),
             layer: None,
             index: None,
             fwids: Some(device_fwids_vec),
@@ -279,19 +1567,13 @@ where
         };
 
         // Create the FMC info TcbInfo
-        let fmc_fwids_vec: Vec<Fwid> = fmc_fwids
-            .iter()
-            .map(|f| Fwid {
-                hash_alg: f.fwid.hash_alg.clone(),
-                digest: f.fwid.digest,
-            })
-            .collect();
+        let fmc_fwids_vec = fmc_fwids.iter().map(|f| (&f.fwid).into()).collect();
 
         let fmc_info = TcbInfo {
             vendor: None,
             model: None,
             version: None,
-            svn: Some(wide_svn as u32),
+            svn: Some(wide_svn),
             layer: None,
             index: None,
             fwids: Some(fmc_fwids_vec),
@@ -309,31 +1591,29 @@ where
         // Add parameters for template generation
         self.params.push(CertTemplateParam {
             tbs_param: TbsParam::new("tcb_info_flags", 0, std::mem::size_of_val(&flags)),
-            needle: flags.to_be_bytes().to_vec(),
+            needle: flags.to_be_bytes().to_vec().into(),
         });
 
         self.params.push(CertTemplateParam {
-            tbs_param: TbsParam::new("tcb_info_fw_svn", 0, std::mem::size_of_val(&svn)),
-            needle: svn.to_be_bytes().to_vec(),
+            tbs_param: TbsParam::new("tcb_info_fw_svn", 0, FMC_SVN_PARAM_LEN),
+            needle: svn.to_be_bytes().to_vec().into(),
         });
 
         self.params.push(CertTemplateParam {
-            tbs_param: TbsParam::new(
-                "tcb_info_fw_svn_fuses",
-                0,
-                std::mem::size_of_val(&svn_fuses),
-            ),
-            needle: svn_fuses.to_be_bytes().to_vec(),
+            tbs_param: TbsParam::new("tcb_info_fw_svn_fuses", 0, FMC_SVN_PARAM_LEN),
+            needle: svn_fuses.to_be_bytes().to_vec().into(),
         });
 
-        for fwid in device_fwids.iter().chain(fmc_fwids.iter()) {
+        for fwid in device_fwids.into_iter().chain(fmc_fwids) {
             self.params.push(CertTemplateParam {
                 tbs_param: TbsParam::new(fwid.name, 0, fwid.fwid.digest.len()),
-                needle: fwid.fwid.digest.to_vec(),
+                needle: fwid.fwid.digest,
             });
+            self.fwids.push((fwid.name, fwid.fwid.hash_alg));
         }
 
         self.multi_tcb_info = Some(multi_tcb_info);
+        self.multi_tcb_info_critical = critical;
 
         // TODO: Complete implementation to add the extension to the certificate builder
         // This part would require more work with the RustCrypto builder API
@@ -341,83 +1621,521 @@ where
         self
     }
 
-    pub fn add_rt_dice_tcb_info_ext(mut self, svn: u8, fwids: &'a [FwidParam<'a>]) -> Self {
-        let wide_svn = fixed_width_svn(svn);
+    /// Add the RT DiceTcbInfo extension
+    ///
+    /// The SVN is encoded as a fixed width INTEGER with a "tcb_info_fw_svn" param of
+    /// `SVN_PARAM_LEN` bytes holding its big-endian value. The param is only searched in the
+    /// SVN of the TcbInfo, so any SVN may be used, even one whose bytes occur elsewhere.
+    /// The Caliptra profiles mark the extension `critical`, some DICE profiles don't.
+    pub fn add_rt_dice_tcb_info_ext(
+        self,
+        svn: u32,
+        fwids: &'a [FwidParam<'a>],
+        critical: bool,
+    ) -> Self {
+        self.add_rt_tcb_info(svn, fwids.to_vec(), None, None, critical)
+    }
+
+    /// Add the RT DiceTcbInfo extension, taking the FWIDs by value so they may own their
+    /// digests, see `add_rt_dice_tcb_info_ext`
+    pub fn add_rt_dice_tcb_info_ext_owned(
+        self,
+        svn: u32,
+        fwids: Vec<FwidParam<'a>>,
+        critical: bool,
+    ) -> Self {
+        self.add_rt_tcb_info(svn, fwids, None, None, critical)
+    }
+
+    /// Add the RT DiceTcbInfo extension with the `index` and `tcb_type` of the layer
+    ///
+    /// Verifiers correlate the TcbInfo of the layers of a multi-layer report by these
+    /// fields. The index is omitted if `None`, and the type defaults to "RT_INFO".
+    pub fn add_indexed_rt_dice_tcb_info_ext(
+        self,
+        svn: u32,
+        fwids: &'a [FwidParam<'a>],
+        index: Option<u64>,
+        tcb_type: Option<&'a [u8]>,
+        critical: bool,
+    ) -> Self {
+        self.add_rt_tcb_info(svn, fwids.to_vec(), index, tcb_type, critical)
+    }
+
+    fn add_rt_tcb_info(
+        mut self,
+        svn: u32,
+        fwids: Vec<FwidParam<'a>>,
+        index: Option<u64>,
+        tcb_type: Option<&'a [u8]>,
+        critical: bool,
+    ) -> Self {
+        for fwid in fwids.iter() {
+            if let Err(err) = check_fwid(fwid) {
+                self.reject(err);
+            }
+        }
+
+        let wide_svn = fixed_width_svn(svn, SVN_PARAM_LEN);
 
         // Create the RT info TcbInfo
-        let rt_fwids_vec: Vec<Fwid> = fwids
-            .iter()
-            .map(|f| Fwid {
-                hash_alg: f.fwid.hash_alg.clone(),
-                digest: f.fwid.digest,
-            })
-            .collect();
+        let rt_fwids_vec = fwids.iter().map(|f| (&f.fwid).into()).collect();
 
         let rt_info = TcbInfo {
             vendor: None,
             model: None,
             version: None,
-            svn: Some(wide_svn as u32),
+            svn: Some(wide_svn),
             layer: None,
-            index: None,
+            index,
             fwids: Some(rt_fwids_vec),
             flags: None,
             vendor_info: None,
-            tcb_type: Some(b"RT_INFO"),
+            tcb_type: Some(tcb_type.unwrap_or(b"RT_INFO")),
             flags_mask: None,
         };
 
         // Add parameters for template generation
         self.params.push(CertTemplateParam {
-            tbs_param: TbsParam::new("tcb_info_fw_svn", 0, std::mem::size_of_val(&svn)),
-            needle: svn.to_be_bytes().to_vec(),
+            tbs_param: TbsParam::new("tcb_info_fw_svn", 0, SVN_PARAM_LEN),
+            needle: svn.to_be_bytes().to_vec().into(),
         });
 
-        for fwid in fwids.iter() {
+        for fwid in fwids {
             self.params.push(CertTemplateParam {
                 tbs_param: TbsParam::new(fwid.name, 0, fwid.fwid.digest.len()),
-                needle: fwid.fwid.digest.to_vec(),
+                needle: fwid.fwid.digest,
             });
+            self.fwids.push((fwid.name, fwid.fwid.hash_alg));
         }
 
         // Store the TcbInfo
         self.single_tcb_info = Some(rt_info);
+        self.single_tcb_info_critical = critical;
+
+        self
+    }
+
+    /// Add the MultiTcbInfo extension of the DPE `tcis`, a TcbInfo holding the FWID of each
+    /// TCI at its index
+    fn add_dpe_tcb_info_ext(mut self, tcis: Vec<FwidParam<'a>>) -> Self {
+        for tci in tcis.iter() {
+            if let Err(err) = check_fwid(tci) {
+                self.reject(err);
+            }
+        }
+
+        let tcb_infos = tcis
+            .iter()
+            .enumerate()
+            .map(|(index, tci)| TcbInfo {
+                vendor: None,
+                model: None,
+                version: None,
+                svn: None,
+                layer: None,
+                index: Some(index as u64),
+                fwids: Some(vec![(&tci.fwid).into()]),
+                flags: None,
+                vendor_info: None,
+                tcb_type: None,
+                flags_mask: None,
+            })
+            .collect();
+
+        for tci in tcis {
+            self.params.push(CertTemplateParam {
+                tbs_param: TbsParam::new(tci.name, 0, tci.fwid.digest.len()),
+                needle: tci.fwid.digest,
+            });
+            self.fwids.push((tci.name, tci.fwid.hash_alg));
+        }
+
+        self.multi_tcb_info = Some(MultiTcbInfo { tcb_infos });
+        self.multi_tcb_info_critical = true;
+        self
+    }
+
+    /// Reserve `version_placeholder_len` bytes for the version UTF8String of the RT
+    /// DiceTcbInfo and register it as the "TCB_INFO_RT_VERSION" param
+    ///
+    /// Must follow `add_rt_dice_tcb_info_ext` or `add_indexed_rt_dice_tcb_info_ext`. Runtime
+    /// versions shorter than the reservation must be padded with trailing spaces, which
+    /// verifiers trim, as NULs aren't printable in the UTF8String. An empty reservation or a
+    /// missing RT TcbInfo is reported by `try_tbs_template`.
+    pub fn add_rt_tcb_info_version_param(mut self, version_placeholder_len: usize) -> Self {
+        if version_placeholder_len == 0 {
+            self.reject(BuilderError::EmptyField {
+                field: "TcbInfo version",
+            });
+            return self;
+        }
+        let Some(rt_info) = self.single_tcb_info.as_mut() else {
+            self.reject(BuilderError::MissingExtension {
+                field: "TCB_INFO_RT_VERSION param",
+                extension: "RT TcbInfo",
+            });
+            return self;
+        };
+
+        let placeholder: String = std::iter::repeat(TCB_INFO_VERSION_SENTINEL)
+            .take(version_placeholder_len)
+            .collect();
+        self.params.push(CertTemplateParam {
+            tbs_param: TbsParam::new("TCB_INFO_RT_VERSION", 0, version_placeholder_len),
+            needle: placeholder.as_bytes().to_vec().into(),
+        });
+        rt_info.version = Some(placeholder);
+        self
+    }
+
+    /// Set the operationalFlags BIT STRING of the RT DiceTcbInfo
+    ///
+    /// Must follow `add_rt_dice_tcb_info_ext` or `add_indexed_rt_dice_tcb_info_ext`, a missing
+    /// RT TcbInfo is reported as `BuilderError::MissingExtension` by `try_tbs_template`.
+    pub fn add_rt_tcb_info_operational_flags(mut self, flags: &'a [u8]) -> Self {
+        let Some(rt_info) = self.single_tcb_info.as_mut() else {
+            self.reject(BuilderError::MissingExtension {
+                field: "operationalFlags",
+                extension: "RT TcbInfo",
+            });
+            return self;
+        };
+        rt_info.flags = Some(flags);
+        self
+    }
 
+    /// Set the vendorInfo OCTET STRING of the RT DiceTcbInfo
+    ///
+    /// Must follow `add_rt_dice_tcb_info_ext` or `add_indexed_rt_dice_tcb_info_ext`, see
+    /// `add_rt_tcb_info_operational_flags`.
+    pub fn add_rt_tcb_info_vendor_info(mut self, vendor_info: &'a [u8]) -> Self {
+        let Some(rt_info) = self.single_tcb_info.as_mut() else {
+            self.reject(BuilderError::MissingExtension {
+                field: "vendorInfo",
+                extension: "RT TcbInfo",
+            });
+            return self;
+        };
+        rt_info.vendor_info = Some(vendor_info);
         self
     }
 
-    pub fn tbs_template(mut self, subject_cn: &str, issuer_cn: &str) -> TbsTemplate {
-        let subject_key = Key::key_gen();
-        let issuer_key = Key::key_gen();
+    /// Set the vendorInfo of the RT DiceTcbInfo and register it as the
+    /// "TCB_INFO_RT_VENDOR_INFO" param, for firmware to patch with a runtime value such as a
+    /// per-boot nonce
+    ///
+    /// `vendor_info` is the generation-time value, which must occur once in the TBS.
+    pub fn add_rt_tcb_info_vendor_info_param(mut self, vendor_info: &'a [u8]) -> Self {
+        match TbsParam::try_new("TCB_INFO_RT_VENDOR_INFO", 0, vendor_info.len()) {
+            Ok(tbs_param) => self.params.push(CertTemplateParam {
+                tbs_param,
+                needle: Cow::Borrowed(vendor_info),
+            }),
+            Err(err) => self.reject(BuilderError::Template(err)),
+        }
+        self.add_rt_tcb_info_vendor_info(vendor_info)
+    }
+
+    /// Generate the "WithUeid" and "WithoutUeid" variants of the template, for SKUs whose
+    /// firmware selects at runtime whether the Ueid extension is present
+    ///
+    /// The variant without UEID is derived with `TbsTemplate::without_range`, so its DER
+    /// lengths are recomputed, it has no "UEID" param and its signature is a placeholder. The
+    /// other params have the same semantics in both variants, at their own offsets. Panics on
+    /// invalid builder inputs, see `try_tbs_template_variants`.
+    pub fn tbs_template_variants(
+        self,
+        subject_cn: &str,
+        issuer_cn: &str,
+    ) -> Vec<(&'static str, TbsTemplate)> {
+        self.try_tbs_template_variants(subject_cn, issuer_cn)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Generate the UEID variants of `tbs_template_variants`, or report the first invalid
+    /// builder input
+    ///
+    /// A builder without the Ueid extension is reported as `BuilderError::MissingExtension`.
+    pub fn try_tbs_template_variants(
+        mut self,
+        subject_cn: &str,
+        issuer_cn: &str,
+    ) -> Result<Vec<(&'static str, TbsTemplate)>, BuilderError> {
+        if self.tcg_ueid.is_none() {
+            self.reject(BuilderError::MissingExtension {
+                field: "UEID variants",
+                extension: "Ueid",
+            });
+        }
+        self.extension_ranges.push((UEID_OID, UEID_VARIANT_RANGE));
+        let with_ueid = self.try_tbs_template(subject_cn, issuer_cn)?;
+        let without_ueid = with_ueid.without_range(UEID_VARIANT_RANGE)?;
+
+        // The range only serves to derive the variant
+        let ranges = with_ueid
+            .ranges()
+            .iter()
+            .filter(|r| r.name != UEID_VARIANT_RANGE)
+            .copied()
+            .collect();
+        let with_ueid = with_ueid.with_ranges(ranges);
+        Ok(vec![("WithUeid", with_ueid), ("WithoutUeid", without_ueid)])
+    }
+
+    /// Generate the template, panicking on invalid builder inputs
+    pub fn tbs_template(self, subject_cn: &str, issuer_cn: &str) -> TbsTemplate {
+        self.try_tbs_template(subject_cn, issuer_cn)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Generate the template with the subject key of the seed phrase `phrase`, see
+    /// `keys::seed_from_mnemonic` and `BuilderKeys::key_gen_from_seed`
+    pub fn tbs_template_from_mnemonic(
+        self,
+        phrase: &str,
+        subject_cn: &str,
+        issuer_cn: &str,
+    ) -> TbsTemplate {
+        let key = Key::key_gen_from_seed(&seed_from_mnemonic(phrase));
+        self.set_subject_key(key)
+            .tbs_template(subject_cn, issuer_cn)
+    }
+
+    /// Generate the template, or report the first invalid builder input
+    ///
+    /// The CNs, or their reservations, must be 1 to `validate::MAX_CN_LEN` bytes, and a
+    /// reserved CN shorter than its reservation. The issuer CN of a child template is taken
+    /// from its parent and isn't checked.
+    pub fn try_tbs_template(
+        mut self,
+        subject_cn: &str,
+        issuer_cn: &str,
+    ) -> Result<TbsTemplate, BuilderError> {
+        self.check_inputs(subject_cn, issuer_cn)?;
+
+        let mut rng = self.take_rng();
+        let subject_key = self
+            .subject_key
+            .take()
+            .unwrap_or_else(|| Key::key_gen(&mut rng));
+        let issuer_key = self
+            .issuer_key
+            .take()
+            .unwrap_or_else(|| Issuer::key_gen(&mut rng));
+        self.sign_tbs_template(subject_cn, issuer_cn, &subject_key, &issuer_key, &mut rng)
+    }
+
+    /// Generate a template for each serialNumber attribute digest of `hashes`, for verifiers
+    /// migrating between digests, see `set_sn_hash`
+    ///
+    /// The templates share the keys and the inputs of the builder, so they only differ in the
+    /// serialNumber attributes and the offsets following them. Panics on invalid builder
+    /// inputs, see `try_tbs_template_sn_hash_variants`.
+    pub fn tbs_template_sn_hash_variants(
+        self,
+        subject_cn: &str,
+        issuer_cn: &str,
+        hashes: &[SnHash],
+    ) -> Vec<(SnHash, TbsTemplate)> {
+        self.try_tbs_template_sn_hash_variants(subject_cn, issuer_cn, hashes)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Generate the serialNumber digest variants of `tbs_template_sn_hash_variants`, or
+    /// report the first invalid builder input
+    pub fn try_tbs_template_sn_hash_variants(
+        mut self,
+        subject_cn: &str,
+        issuer_cn: &str,
+        hashes: &[SnHash],
+    ) -> Result<Vec<(SnHash, TbsTemplate)>, BuilderError> {
+        self.check_inputs(subject_cn, issuer_cn)?;
+
+        let mut rng = self.take_rng();
+        let subject_key = self
+            .subject_key
+            .take()
+            .unwrap_or_else(|| Key::key_gen(&mut rng));
+        let issuer_key = self
+            .issuer_key
+            .take()
+            .unwrap_or_else(|| Issuer::key_gen(&mut rng));
+
+        // Each build registers the params of its fields, allocates their placeholders and
+        // takes the parent Name
+        let params = self.params.clone();
+        let parent_name = self.parent_name.clone();
+        let placeholder_allocator = self.placeholder_allocator.clone();
+        hashes
+            .iter()
+            .map(|&hash| {
+                self.sn_hash = hash;
+                self.params = params.clone();
+                self.parent_name = parent_name.clone();
+                self.placeholder_allocator = placeholder_allocator.clone();
+                let template = self.sign_tbs_template(
+                    subject_cn,
+                    issuer_cn,
+                    &subject_key,
+                    &issuer_key,
+                    &mut rng,
+                )?;
+                Ok((hash, template))
+            })
+            .collect()
+    }
+
+    /// Build the TBSCertificate of `subject_key` issued by `issuer_key`, sign and template it
+    fn sign_tbs_template(
+        &mut self,
+        subject_cn: &str,
+        issuer_cn: &str,
+        subject_key: &Key,
+        issuer_key: &Issuer,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<TbsTemplate, BuilderError> {
+        let unsigned =
+            self.build_tbs_certificate(subject_cn, issuer_cn, subject_key, issuer_key)?;
+        let tbs = unsigned.tbs_certificate.to_der().unwrap();
+        let signature = sign(issuer_key, &tbs, self.signing_mode, rng);
+        let signature = signature.to_bitstring().unwrap();
+        self.finish_tbs_template(unsigned, signature, |tbs, signature| {
+            issuer_key.verify(tbs, signature)
+        })
+    }
+
+    /// Generate the template, signing the TBS with the remote `signer`, e.g. the client of a
+    /// KMS holding the issuer key
+    ///
+    /// The TBS is built and templated as by `try_tbs_template`, with the issuer public key of
+    /// `signer` instead of the issuer key of the builder. `signer` must sign with the
+    /// algorithm of `Issuer`, a signature failing verification with the public key DER of
+    /// `signer` is reported as `BuilderError::Signing`. The future is `Send` if the key types
+    /// are.
+    #[cfg(feature = "async")]
+    pub async fn tbs_template_with_async_signer(
+        mut self,
+        subject_cn: &str,
+        issuer_cn: &str,
+        signer: &dyn AsyncTemplateSigner,
+    ) -> Result<TbsTemplate, BuilderError> {
+        self.check_inputs(subject_cn, issuer_cn)?;
+
+        // The thread-local RNG isn't Send, so it is dropped before the signer is awaited
+        let subject_key = match self.subject_key.take() {
+            Some(key) => key,
+            None => Key::key_gen(&mut self.take_rng()),
+        };
+        let issuer = RemoteIssuer::<Issuer>::new(signer);
+
+        let unsigned = self.build_tbs_certificate(subject_cn, issuer_cn, &subject_key, &issuer)?;
+        let tbs = unsigned.tbs_certificate.to_der().unwrap();
+        let signature = signer
+            .sign(&tbs)
+            .await
+            .map_err(|err| BuilderError::Signing {
+                reason: err.to_string(),
+            })?;
+        let spki = signer.verifying_key_der();
+        if !Issuer::verify_spki(&spki, &tbs, &signature) {
+            return Err(BuilderError::Signing {
+                reason: "the signature fails verification with the signer public key".to_string(),
+            });
+        }
+        let signature = BitString::from_bytes(&signature).unwrap();
+        self.finish_tbs_template(unsigned, signature, |tbs, signature| {
+            Issuer::verify_spki(&spki, tbs, signature)
+        })
+    }
+
+    /// Report the first invalid builder input
+    fn check_inputs(&mut self, subject_cn: &str, issuer_cn: &str) -> Result<(), BuilderError> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        check_cn_len(
+            "Subject CN",
+            self.subject_cn_len.unwrap_or(subject_cn.len()),
+        )?;
+        if let Some(len) = self.subject_cn_len {
+            check_cn_reservation("Subject CN", subject_cn, len)?;
+        }
+        if self.opaque_subject && self.subject_cn_len.is_some() {
+            return Err(BuilderError::Conflict {
+                first: "SUBJECT_CN param",
+                second: "an opaque subject",
+            });
+        }
+        match &self.parent_name {
+            Some(_) if self.issuer_cn_len.is_some() => {
+                return Err(BuilderError::Conflict {
+                    first: "ISSUER_CN param",
+                    second: "a child template",
+                });
+            }
+            Some(parent_name) => {
+                let parent_cn = name_common_name(parent_name).unwrap_or_default();
+                if parent_cn != issuer_cn.as_bytes() {
+                    return Err(BuilderError::IssuerCn {
+                        cn: issuer_cn.to_string(),
+                        parent_cn: String::from_utf8_lossy(&parent_cn).into_owned(),
+                    });
+                }
+            }
+            None => {
+                check_cn_len("Issuer CN", self.issuer_cn_len.unwrap_or(issuer_cn.len()))?;
+                if let Some(len) = self.issuer_cn_len {
+                    check_cn_reservation("Issuer CN", issuer_cn, len)?;
+                }
+            }
+        }
+        for &(name, ref candidates) in self.fwid_hash_alg_params.iter() {
+            match self.fwids.iter().find(|&&(fwid, _)| fwid == name) {
+                Some(&(_, hash_alg)) => check_hash_alg_candidates(name, hash_alg, candidates)?,
+                None => return Err(BuilderError::UnknownFwid { fwid: name }),
+            }
+        }
+        Ok(())
+    }
 
+    /// Build the TBSCertificate issued by `issuer`, registering the params of its fields
+    ///
+    /// Only the public key and the signature algorithm of `issuer` are used, so a remote
+    /// signer can stand in for the issuer key. A forced version lacking the fields of the
+    /// TBS is reported as `BuilderError::Version`.
+    fn build_tbs_certificate<S>(
+        &mut self,
+        subject_cn: &str,
+        issuer_cn: &str,
+        subject_key: &Key,
+        issuer: &S,
+    ) -> Result<UnsignedCert, BuilderError>
+    where
+        S: Keypair + DynSignatureAlgorithmIdentifier,
+        S::VerifyingKey: EncodePublicKey,
+    {
         // Set the valid from time
-        let not_before_dt = DateTime::new(2023, 01, 01, 0, 0, 0).unwrap();
-        let not_before = UtcTime::from_date_time(not_before_dt).unwrap();
+        let not_before = self.time_encoding.encode(self.not_before);
+        let needle = not_before.to_der().unwrap()[2..].to_vec();
+        self.register_needle("NOT_BEFORE", &needle);
         let param = CertTemplateParam {
-            tbs_param: TbsParam::new("NOT_BEFORE", 0, not_before.to_der().unwrap().len() - 2),
-            needle: not_before.to_der().unwrap()[2..].to_vec(),
+            tbs_param: TbsParam::new("NOT_BEFORE", 0, needle.len()),
+            needle: needle.into(),
         };
         self.params.push(param);
 
         // Set the valid to time
-        let not_after_dt = DateTime::new(2049, 12, 31, 23, 59, 59).unwrap();
-        let not_after = UtcTime::from_date_time(not_after_dt).unwrap();
+        let not_after = self.time_encoding.encode(self.not_after);
+        let needle = not_after.to_der().unwrap()[2..].to_vec();
+        self.register_needle("NOT_AFTER", &needle);
         let param = CertTemplateParam {
-            tbs_param: TbsParam::new("NOT_AFTER", 0, not_after.to_der().unwrap().len() - 2),
-            needle: not_after.to_der().unwrap()[2..].to_vec(),
+            tbs_param: TbsParam::new("NOT_AFTER", 0, needle.len()),
+            needle: needle.into(),
         };
         self.params.push(param);
 
-        let validity = Validity::new(Time::UtcTime(not_before), Time::UtcTime(not_after));
-
-        // Set the serial number
-        let serial_number_bytes = [0x7fu8; 20];
-        let serial_number = SerialNumber::new(&serial_number_bytes).unwrap();
-        let param = CertTemplateParam {
-            tbs_param: TbsParam::new("SERIAL_NUMBER", 0, serial_number_bytes.len()),
-            needle: serial_number_bytes.to_vec(),
-        };
-        self.params.push(param);
+        let validity = Validity::new(not_before, not_after);
 
         // Get the subject public key and encode it
         let subject_pk_der = subject_key.verifying_key().to_public_key_der().unwrap();
@@ -427,110 +2145,551 @@ where
         let subject_pk_bytes = subject_spki.subject_public_key.as_bytes().unwrap().to_vec();
         let param = CertTemplateParam {
             tbs_param: TbsParam::new("PUBLIC_KEY", 0, subject_pk_bytes.len()),
-            needle: subject_pk_bytes.clone(),
+            needle: subject_pk_bytes.clone().into(),
         };
         self.params.push(param);
 
-        let subject_key_hash = hex::encode(Sha256::digest(&subject_pk_bytes)).to_uppercase();
-        let subject = format!("CN={},serialNumber={}", subject_cn, subject_key_hash);
-        let subject_name = Name::from_str(&subject).unwrap();
+        // Set the serial number
+        let serial_number_bytes = match self.serial_number_derivation {
+            Some((digest, len)) => {
+                let serial = derive_serial_number(digest, &subject_pk_bytes, len);
+                self.register_needle(SERIAL_NUMBER_PARAM, &serial);
+                serial
+            }
+            None => {
+                self.allocate_needle(SERIAL_NUMBER_PARAM, vec![0x7fu8; 20], encode_serial_number)
+            }
+        };
+        let serial_number = SerialNumber::new(&serial_number_bytes).unwrap();
         let param = CertTemplateParam {
-            tbs_param: TbsParam::new("SUBJECT_SN", 0, subject_key_hash.len()),
-            needle: subject_key_hash.into_bytes(),
+            tbs_param: TbsParam::new(SERIAL_NUMBER_PARAM, 0, serial_number_bytes.len()),
+            needle: serial_number_bytes.clone().into(),
         };
         self.params.push(param);
 
+        let subject_key_hash = key_bytes_hash_with(self.sn_hash, &subject_pk_bytes);
+        let subject_cn = match self.subject_cn_len {
+            Some(len) => {
+                let placeholder = cn_placeholder(subject_cn, SUBJECT_CN_SENTINEL, len);
+                let placeholder =
+                    self.allocate_needle("SUBJECT_CN", placeholder.into_bytes(), alphanumeric);
+                self.params.push(CertTemplateParam {
+                    tbs_param: TbsParam::new("SUBJECT_CN", 0, len),
+                    needle: placeholder.clone().into(),
+                });
+                String::from_utf8(placeholder).unwrap()
+            }
+            None => subject_cn.to_string(),
+        };
+        let format = subject_format(self.subject_format, self.serial_number_rdn);
+        let subject_name = match self.subject_param_len {
+            Some(len) if self.opaque_subject => {
+                let sentinel = SUBJECT_CN_SENTINEL.to_string();
+                reserved_name(CN_ONLY_SUBJECT_FORMAT, &sentinel, "", len)?
+            }
+            Some(len) => reserved_name(format, &subject_cn, &subject_key_hash, len)?,
+            None if !self.serial_number_rdn => format_name(format, &subject_cn, &subject_key_hash),
+            None => {
+                if !format.contains("{sn}") {
+                    panic!(
+                        "Subject format '{}' lacks the {{sn}} of the SUBJECT_SN param",
+                        format
+                    );
+                }
+                let subject_name = format_name(format, &subject_cn, &subject_key_hash);
+                let param = CertTemplateParam {
+                    tbs_param: TbsParam::new("SUBJECT_SN", 0, subject_key_hash.len()),
+                    needle: subject_key_hash.into_bytes().into(),
+                };
+                self.params.push(param);
+                subject_name
+            }
+        };
+
         // Get the issuer public key and encode it
-        let issuer_pk_der = issuer_key.verifying_key().to_public_key_der().unwrap();
+        let issuer_pk_der = issuer.verifying_key().to_public_key_der().unwrap();
         // Parse DER to obtain SubjectPublicKeyInfo and extract public key bytes
         let issuer_spki: spki::SubjectPublicKeyInfo<der::asn1::Any, der::asn1::BitString> =
             spki::SubjectPublicKeyInfo::from_der(issuer_pk_der.as_bytes()).unwrap();
         let issuer_pk_bytes = issuer_spki.subject_public_key.as_bytes().unwrap().to_vec();
 
-        let issuer_key_hash = hex::encode(Sha256::digest(&issuer_pk_bytes)).to_uppercase();
-        let issuer = format!("CN={},serialNumber={}", issuer_cn, issuer_key_hash);
-        let issuer_name = Name::from_str(&issuer).unwrap();
-        let param = CertTemplateParam {
-            tbs_param: TbsParam::new("ISSUER_SN", 0, issuer_key_hash.len()),
-            needle: issuer_key_hash.into_bytes(),
+        if self.self_signed {
+            self.parent_name = Some(subject_name.clone());
+        }
+        let issuer_name = match self.parent_name.take() {
+            Some(parent_name) => {
+                if let Some(issuer_sn) = name_serial_number(&parent_name) {
+                    let param = CertTemplateParam {
+                        tbs_param: TbsParam::new("ISSUER_SN", 0, issuer_sn.len()),
+                        needle: issuer_sn.into(),
+                    };
+                    // Params sharing a needle take its instances in order, and the issuer
+                    // Name of a self-signed TBS precedes its subject Name
+                    let subject_sn = self
+                        .params
+                        .iter()
+                        .position(|p| p.tbs_param.name == "SUBJECT_SN");
+                    match subject_sn {
+                        Some(index) if self.self_signed => self.params.insert(index, param),
+                        _ => self.params.push(param),
+                    }
+                }
+                parent_name
+            }
+            None => {
+                let issuer_key_hash = key_bytes_hash_with(self.sn_hash, &issuer_pk_bytes);
+                let issuer_cn = match self.issuer_cn_len {
+                    Some(len) => {
+                        let placeholder = cn_placeholder(issuer_cn, ISSUER_CN_SENTINEL, len);
+                        let placeholder = self.allocate_needle(
+                            "ISSUER_CN",
+                            placeholder.into_bytes(),
+                            alphanumeric,
+                        );
+                        self.params.push(CertTemplateParam {
+                            tbs_param: TbsParam::new("ISSUER_CN", 0, len),
+                            needle: placeholder.clone().into(),
+                        });
+                        String::from_utf8(placeholder).unwrap()
+                    }
+                    None => issuer_cn.to_string(),
+                };
+                let issuer_name = cn_sn_name(&issuer_cn, &issuer_key_hash);
+                let param = CertTemplateParam {
+                    tbs_param: TbsParam::new("ISSUER_SN", 0, issuer_key_hash.len()),
+                    needle: issuer_key_hash.into_bytes().into(),
+                };
+                self.params.push(param);
+                issuer_name
+            }
         };
-        self.params.push(param);
 
-        let profile = DevId::new(issuer_name, subject_name, None).unwrap();
-
-        // Clone subject_spki before passing it to CertificateBuilder because it's needed later
-        let mut builder = CertificateBuilder::new(profile, serial_number, validity, subject_spki.clone())
-            .expect("Create certificate");
+        let devid = DevId::new(issuer_name, subject_name, None).unwrap();
+        let subject = devid.get_subject();
 
+        // DICE extensions, appended by the profile after the DevId ones
+        let mut extensions = Vec::new();
         if let Some(basic_constraints) = self.basic_constraints {
-            builder.add_extension(&basic_constraints).unwrap();
+            let critical = self
+                .basic_constraints_critical
+                .unwrap_or(basic_constraints.ca);
+            let ext = EncodedExt::new(&basic_constraints, critical);
+            push_extension(&mut extensions, &subject, &ext);
         }
 
-        if let Some(ueid) = self.tcg_ueid {
-            builder.add_extension(&ueid).unwrap();
+        if let Some(ref name_constraints) = self.name_constraints {
+            push_extension(&mut extensions, &subject, name_constraints);
+        }
+
+        if let Some(ref subject_alt_name) = self.subject_alt_name {
+            push_extension(&mut extensions, &subject, subject_alt_name);
+        }
+
+        if let Some(ref ueid) = self.tcg_ueid {
+            push_extension(&mut extensions, &subject, ueid);
         }
 
         if let Some(ref multi_tcb_info) = self.multi_tcb_info {
-            builder.add_extension(multi_tcb_info).unwrap();
+            let ext = EncodedExt::new(multi_tcb_info, self.multi_tcb_info_critical);
+            push_extension(&mut extensions, &subject, &ext);
         }
 
         if let Some(ref single_tcb_info) = self.single_tcb_info {
-            builder.add_extension(single_tcb_info).unwrap();
+            let ext = EncodedExt::new(single_tcb_info, self.single_tcb_info_critical);
+            push_extension(&mut extensions, &subject, &ext);
+        }
+
+        for (_, encoded_ext) in self.encoded_exts.iter() {
+            let ext = encoded_ext(&subject, &extensions);
+            extensions.push(ext);
         }
 
         // Add Subject Key Identifier
         let subject_key_bytes = subject_spki.subject_public_key.as_bytes().unwrap();
-        let subject_key_hash = sha1::Sha1::digest(subject_key_bytes).as_slice().to_vec();
+        let subject_key_hash = derive_subject_key_id(self.ski_derivation, subject_key_bytes);
+        let subject_key_hash = self.allocate_needle("SUBJECT_KEY_ID", subject_key_hash, |_| {});
         let subject_key_octet = der::asn1::OctetString::new(subject_key_hash.clone()).unwrap();
         let subject_key_id = SubjectKeyIdentifier::from(subject_key_octet);
-        builder.add_extension(&subject_key_id).unwrap();
+        push_extension(&mut extensions, &subject, &subject_key_id);
 
-        // Add Authority Key Identifier
+        // The Authority Key Identifier is added by the DevId profile, its keyIdentifier is
+        // derived like the SubjectKeyIdentifier, or taken from the parent of a child template
         let issuer_key_bytes = issuer_spki.subject_public_key.as_bytes().unwrap();
-        let issuer_key_hash = sha1::Sha1::digest(issuer_key_bytes).as_slice().to_vec();
-        // Somehow this ends up twice in extensions if we do this?
-        // let authority_key_id = AuthorityKeyIdentifier {
-        //     key_identifier: Some(der::asn1::OctetString::new(issuer_key_hash.clone()).unwrap()),
-        //     authority_cert_issuer: None,
-        //     authority_cert_serial_number: None,
-        // };
-        // builder.add_extension(&authority_key_id).unwrap();
+        let issuer_key_hash = match self.parent_key_id.clone() {
+            Some(key_id) => {
+                self.register_needle("AUTHORITY_KEY_ID", &key_id);
+                key_id
+            }
+            None if self.authority_key_id.is_none() => {
+                let key_id = derive_subject_key_id(self.ski_derivation, issuer_key_bytes);
+                self.allocate_needle("AUTHORITY_KEY_ID", key_id, |_| {})
+            }
+            None => derive_subject_key_id(self.ski_derivation, issuer_key_bytes),
+        };
+
+        let profile = DiceProfile {
+            devid,
+            extensions,
+            order: self.extension_order.clone(),
+            canonical: self.canonical_extension_order,
+            authority_key_id: Some(
+                self.authority_key_id
+                    .map(<[u8]>::to_vec)
+                    .unwrap_or_else(|| issuer_key_hash.clone()),
+            ),
+            authority_cert: self.authority_cert.clone(),
+            key_usage: self.key_usage,
+        };
+
+        // Clone subject_spki before passing it to CertificateBuilder because it's needed later
+        let mut builder =
+            CertificateBuilder::new(profile, serial_number, validity, subject_spki.clone())
+                .expect("Create certificate");
 
         // Add parameters for template generation
         self.params.push(CertTemplateParam {
             tbs_param: TbsParam::new("SUBJECT_KEY_ID", 0, subject_key_hash.len()),
-            needle: subject_key_hash,
+            needle: subject_key_hash.into(),
         });
 
-        self.params.push(CertTemplateParam {
-            tbs_param: TbsParam::new("AUTHORITY_KEY_ID", 0, issuer_key_hash.len()),
-            needle: issuer_key_hash,
-        });
+        if self.authority_key_id.is_none() {
+            self.params.push(CertTemplateParam {
+                tbs_param: TbsParam::new("AUTHORITY_KEY_ID", 0, issuer_key_hash.len()),
+                needle: issuer_key_hash.into(),
+            });
+        }
+
+        let tbs = builder.finalize(issuer).unwrap();
+        let mut tbs_certificate = TbsCertificate::from_der(&tbs).unwrap();
+
+        // The builder has no unique identifiers or forced version, they are set on the
+        // finalized TBS before it is signed
+        let unique_ids = [self.issuer_unique_id_len, self.subject_unique_id_len];
+        let has_unique_ids = unique_ids.iter().any(Option::is_some);
+        tbs_certificate.issuer_unique_id = self.issuer_unique_id_len.map(unique_id_placeholder);
+        tbs_certificate.subject_unique_id = self.subject_unique_id_len.map(unique_id_placeholder);
+        if let Some(version) = self.version {
+            let has_extensions = tbs_certificate
+                .extensions
+                .as_ref()
+                .is_some_and(|extensions| !extensions.is_empty());
+            if version != Version::V3 && has_extensions {
+                return Err(BuilderError::Version {
+                    version,
+                    fields: "extensions",
+                    required: Version::V3,
+                });
+            }
+            if version == Version::V1 && has_unique_ids {
+                return Err(BuilderError::Version {
+                    version,
+                    fields: "unique identifiers",
+                    required: Version::V2,
+                });
+            }
+            tbs_certificate.version = version;
+        }
+
+        Ok(UnsignedCert {
+            tbs_certificate,
+            subject_cn,
+            subject_pk_bytes,
+        })
+    }
+
+    /// Assemble the certificate of the TBS of `unsigned` and its `signature`, and template
+    /// it
+    ///
+    /// `verify` checks the signature of the TBS in the signing mode of the issuer.
+    fn finish_tbs_template(
+        &mut self,
+        unsigned: UnsignedCert,
+        signature: BitString,
+        verify: impl FnOnce(&[u8], &[u8]) -> bool,
+    ) -> Result<TbsTemplate, BuilderError> {
+        let UnsignedCert {
+            tbs_certificate,
+            subject_cn,
+            subject_pk_bytes,
+        } = unsigned;
+        let cert = x509_cert::certificate::Certificate {
+            signature_algorithm: tbs_certificate.signature.clone(),
+            tbs_certificate,
+            signature,
+        };
+        let mut der = cert.to_der().unwrap();
+
+        #[cfg(feature = "fault-injection")]
+        if let Some(mutation) = self.post_build_mutation {
+            mutation(&mut der);
+        }
 
-        let req = builder.build(&issuer_key).unwrap();
-        let der = req.to_der().unwrap();
+        // Retrieve the To be signed portion before decoding, so malformed DER is reported
+        let tbs_offset = get_tbs_offset(&der)?;
+        let mut tbs = der[tbs_range(&der)?].to_vec();
 
         // Decode the DER data back into a CertReq to verify it worked
         let decoded = x509_cert::certificate::Certificate::from_der(&der).unwrap();
-        dbg!(decoded);
+        if self.der_round_trip_check {
+            check_der_reencoding(&der, &decoded.to_der().unwrap())?;
+        }
+        let mut provenance = Provenance::new(decoded.signature_algorithm.oid)
+            .with_config("kind", "Cert")
+            .with_config("subject_cn", &subject_cn)
+            .with_config("signing_mode", format!("{:?}", self.signing_mode));
+        if let Some(kind) = self.dice_profile {
+            provenance = provenance.with_config("dice_profile", format!("{:?}", kind));
+        }
+        if let Some(seed) = &self.key_seed {
+            if self.signing_mode == SigningMode::Deterministic {
+                provenance = provenance.with_seed(seed);
+            }
+        }
+        let parameters = Issuer::ALGORITHM_PARAMETERS;
+        check_algorithm_parameters(
+            "signatureAlgorithm",
+            &decoded.signature_algorithm,
+            parameters,
+        );
+        check_algorithm_parameters("signature", &decoded.tbs_certificate.signature, parameters);
+        if let Err(err) = check_signature_algorithms(
+            &decoded.tbs_certificate.signature,
+            &decoded.signature_algorithm,
+        ) {
+            panic!("{}", err);
+        }
+        check_algorithm_parameters(
+            "subjectPublicKeyInfo",
+            &decoded.tbs_certificate.subject_public_key_info.algorithm,
+            Key::SPKI_PARAMETERS,
+        );
+        let signature = decoded.signature.raw_bytes().to_vec();
+        log::debug!("Subject: {}", decoded.tbs_certificate.subject);
+        log::debug!("Issuer: {}", decoded.tbs_certificate.issuer);
+        log::trace!("{:?}", decoded);
+
+        if let Some(kind) = self.dice_profile {
+            check_dice_profile(&der, kind);
+        }
+        check_key_usage_encoding(&der, self.key_usage);
+        if let Some(max_tbs_size) = self.max_tbs_size {
+            check_tbs_size(&der, max_tbs_size)?;
+        }
+
+        // Verify the signature in the signing mode of the issuer key
+        assert!(
+            verify(&tbs, &signature),
+            "Certificate signature verification failed"
+        );
+
+        // The signature BIT STRING content ends the Certificate
+        let signature_param = if self.placeholder_signature {
+            let offset = der.len() - signature.len();
+            der[offset..].fill(0);
+            Some(TbsParam::new("SIGNATURE", offset, signature.len()))
+        } else {
+            None
+        };
 
+        // Register the hashAlg params in FWID order, as params sharing a needle take its
+        // instances in order
+        for &(name, hash_alg) in self.fwids.iter() {
+            if self
+                .fwid_hash_alg_params
+                .iter()
+                .any(|&(param, _)| param == name)
+            {
+                let param_name = param_name(format!("{}_HASH_ALG", name));
+                self.params.push(CertTemplateParam {
+                    tbs_param: TbsParam::new(param_name, 0, hash_alg.as_bytes().len()),
+                    needle: hash_alg.as_bytes().to_vec().into(),
+                });
+            }
+        }
 
-        // TODO move get_tbs from x509_openssl
-        // Retrieve the To be signed portion from the CSR
-        let mut tbs = get_tbs(der);
+        // A key re-encoded by the builder can't be located by needle search
+        locate_public_key(&tbs, &[6], &subject_pk_bytes)?;
 
         // Match long params first to ensure a subset is not sanitized by a short param.
         self.params
             .sort_by(|a, b| a.needle.len().cmp(&b.needle.len()).reverse());
 
-        // Calculate the offset of parameters and sanitize the TBS section
-        let params = self
-            .params
+        // Locate params that can't be found unambiguously by needle search structurally.
+        // TBSCertificate: version, serialNumber, signature, issuer, validity, subject,
+        // subjectPublicKeyInfo { algorithm, ... }
+        let mut structural_params = Vec::new();
+        if self.spki_algorithm_param {
+            let (offset, len) = locate_element(&tbs, &[6, 0]);
+            structural_params.push(TbsParam::new("SPKI_ALGORITHM", offset, len));
+        }
+        if self.subject_param_len.is_some() {
+            let (offset, len) = locate_element(&tbs, &[5]);
+            structural_params.push(TbsParam::new("SUBJECT", offset, len));
+        }
+        // issuerUniqueID [1] and subjectUniqueID [2] follow the subjectPublicKeyInfo, their
+        // content ends the element
+        let mut index = 7;
+        for (name, tag, len) in [
+            (ISSUER_UNIQUE_ID_PARAM, 0x81, self.issuer_unique_id_len),
+            (SUBJECT_UNIQUE_ID_PARAM, 0x82, self.subject_unique_id_len),
+        ] {
+            if let Some(len) = len {
+                let (offset, element_len) = locate_element(&tbs, &[index]);
+                assert_eq!(tbs[offset], tag, "{} tag", name);
+                structural_params.push(TbsParam::new(name, offset + element_len - len, len));
+                index += 1;
+            }
+        }
+        if let Some((_, serial)) = &self.authority_cert {
+            // The extnValue ends with the authorityCertSerialNumber [2] of the
+            // AuthorityKeyIdentifier
+            let len = serial.as_bytes().len();
+            let Some((offset, ext_len)) = locate_extension(&tbs, AuthorityKeyIdentifier::OID)
+            else {
+                return Err(BuilderError::MissingExtension {
+                    field: "authorityCertSerialNumber",
+                    extension: "AuthorityKeyIdentifier",
+                });
+            };
+            let offset = offset + ext_len - len;
+            if tbs[offset - 2..offset] != [0x82, len as u8] {
+                return Err(TemplateError::InvalidDer {
+                    element: "AuthorityKeyIdentifier",
+                }
+                .into());
+            }
+            let param = TbsParam::new(AUTHORITY_CERT_SERIAL_PARAM, offset, len);
+            structural_params.push(sanitize_with(param, &mut tbs, self.placeholder_fill));
+        }
+        if self.path_len_max.is_some() {
+            // The extnValue ends with the pathLenConstraint INTEGER of the BasicConstraints
+            let (offset, len) = locate_extension(&tbs, BasicConstraints::OID).unwrap();
+            let offset = offset + len - 1;
+            assert_eq!(
+                tbs[offset - 2..offset],
+                [0x02, 0x01],
+                "pathLenConstraint INTEGER"
+            );
+            let param = TbsParam::new(BASIC_CONSTRAINTS_PATH_LEN_PARAM, offset, 1);
+            structural_params.push(sanitize_with(param, &mut tbs, self.placeholder_fill));
+        }
+        let ranges: Vec<TbsParam> = self
+            .extension_ranges
             .iter()
-            .map(|p| sanitize(init_param(&p.needle, &tbs, p.tbs_param), &mut tbs))
+            .map(|&(oid, name)| match locate_extension(&tbs, oid) {
+                Some((offset, len)) => TbsParam::new(name, offset, len),
+                None => panic!("No extension {} for the range {}", oid, name),
+            })
+            .collect();
+
+        // FWID digests are only searched in the digests of the TcbInfo extensions, so bytes
+        // of other fields equal to a digest, e.g. an AlgorithmIdentifier, aren't taken for it
+        let whole_tbs = [0..tbs.len()];
+        let fwid_digests: Vec<_> = [TCB_INFO_OID, MULTI_TCB_INFO_OID]
+            .into_iter()
+            .flat_map(|oid| fwid_digest_ranges(&tbs, oid))
+            .collect();
+        // Likewise SVN needles, which may be any value, are only searched in the SVNs
+        let svns: Vec<_> = [TCB_INFO_OID, MULTI_TCB_INFO_OID]
+            .into_iter()
+            .flat_map(|oid| tcb_info_svn_ranges(&tbs, oid))
             .collect();
+
+        // Calculate the offset of parameters and sanitize the TBS section. Params sharing a
+        // needle, like FWIDs with the same placeholder digest, take its instances in order.
+        let mut params: Vec<TbsParam> = self
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let count = self.params[i..]
+                    .iter()
+                    .filter(|q| q.needle == p.needle)
+                    .count();
+                let is_fwid = self.fwids.iter().any(|(name, _)| *name == p.tbs_param.name);
+                let is_svn = SVN_PARAMS.contains(&p.tbs_param.name);
+                let regions = if is_fwid {
+                    &fwid_digests[..]
+                } else if is_svn {
+                    &svns[..]
+                } else {
+                    &whole_tbs[..]
+                };
+                let param = init_repeated_param_in(&p.needle, &tbs, p.tbs_param, count, regions)?;
+                Ok(sanitize_with(param, &mut tbs, self.placeholder_fill))
+            })
+            .collect::<Result<_, TemplateError>>()?;
+        params.extend(structural_params);
+        if let Some(param) = params.iter().find(|p| p.name == SERIAL_NUMBER_PARAM) {
+            check_serial_number_param(&tbs, *param);
+        }
         // Create the template
-        TbsTemplate::new(tbs, params)
+        let template = TbsTemplate::new(tbs, params)
+            .with_kind(TemplateKind::Cert)
+            .with_tbs_offset(tbs_offset)
+            .with_der(der)
+            .with_ranges(ranges)
+            .with_algorithm_oid(Issuer::SIGNATURE_ALGORITHM_IDENTIFIER.oid);
+        let template = match self.path_len_max {
+            Some(max) => template.with_param_max(BASIC_CONSTRAINTS_PATH_LEN_PARAM, max),
+            None => template,
+        };
+        let template = match signature_param {
+            Some(param) => template.with_signature_param(param),
+            None => template,
+        };
+        let template = template.with_provenance(provenance);
+        if let Some(allocator) = &self.placeholder_allocator {
+            allocator.check(&template)?;
+        }
+        if let Some(dir) = &self.debug_artifacts {
+            template
+                .write_debug_artifacts(dir, &artifact_name(&subject_cn))
+                .map_err(|err| BuilderError::DebugArtifacts {
+                    dir: dir.clone(),
+                    reason: err.to_string(),
+                })?;
+        }
+        Ok(template)
+    }
+
+    /// Record the invalid input `err`, reported by `try_tbs_template` unless an earlier input
+    /// was already invalid
+    fn reject(&mut self, err: BuilderError) {
+        self.error.get_or_insert(err);
+    }
+}
+
+impl<'a> CertTemplateBuilder<'a, ml_dsa::KeyPair<ml_dsa::MlDsa87>> {
+    /// Use the subject key `name` of `store`, generating and saving it if missing
+    ///
+    /// Templates of a chain share keys by storing the subject key of one certificate under
+    /// the name the next certificate loads with `with_stored_issuer_key`. A missing key is
+    /// generated with the RNG of a preceding `set_rng`, or the thread RNG.
+    pub fn with_stored_subject_key(mut self, store: &KeyStore, name: &str) -> Self {
+        match self.load_or_generate(store, name) {
+            Ok(key) => self.set_subject_key(key),
+            Err(err) => panic!("{}", err),
+        }
+    }
 
+    /// Use the issuer key `name` of `store`, generating and saving it if missing, see
+    /// `with_stored_subject_key`
+    pub fn with_stored_issuer_key(mut self, store: &KeyStore, name: &str) -> Self {
+        match self.load_or_generate(store, name) {
+            Ok(key) => self.set_issuer_key(key),
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    fn load_or_generate(
+        &mut self,
+        store: &KeyStore,
+        name: &str,
+    ) -> Result<ml_dsa::KeyPair<ml_dsa::MlDsa87>, KeyStoreError> {
+        match self.rng.as_mut() {
+            Some(rng) => store.load_or_generate(name, rng),
+            None => store.load_or_generate(name, &mut rand::thread_rng()),
+        }
     }
 }
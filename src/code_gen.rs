@@ -12,10 +12,252 @@ Abstract:
 
 --*/
 
-use crate::tbs::TbsTemplate;
+use crate::report;
+use crate::tbs::{
+    strip_segments, StripSegment, TbsDigest, TbsParam, TbsTemplate, SERIAL_NUMBER_PARAM,
+    TEMPLATE_SCHEMA_VERSION,
+};
 use convert_case::{Case, Casing};
 use quote::{__private::TokenStream, format_ident, quote};
-use std::{path::Path, process::Command};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Size of the granules compared across templates by `CodeGen::gen_code_shared`
+pub const SHARED_GRANULE: usize = 32;
+
+/// Feature enabling every template gated by `CodeGenOptions::feature_gate`
+pub const ALL_TEMPLATES_FEATURE: &str = "all_templates";
+
+/// Feature gating the template `type_name`, its snake case name
+pub fn template_feature(type_name: &str) -> String {
+    type_name.to_case(Case::Snake)
+}
+
+/// Split `tbs` into little-endian `u32` words, zero padding the last word
+pub fn tbs_words(tbs: &[u8]) -> Vec<u32> {
+    tbs.chunks(4)
+        .map(|chunk| {
+            let mut word = [0u8; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            u32::from_le_bytes(word)
+        })
+        .collect()
+}
+
+/// Outcome of `CodeGen::gen_code_shared`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedCodeReport {
+    /// Generated file
+    pub path: PathBuf,
+    /// Total length of the TBS templates
+    pub total_len: usize,
+    /// Length of the pool of granules shared by the templates
+    pub shared_len: usize,
+    /// Bytes saved over emitting each template in full
+    pub saved: usize,
+}
+
+/// Copy of `len` bytes of the shared pool or of the template's own bytes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Segment {
+    shared: bool,
+    offset: usize,
+    len: usize,
+}
+
+/// Code Generation Options
+#[derive(Default)]
+pub struct CodeGenOptions {
+    header: Option<String>,
+    imports: Vec<String>,
+    const_fn: bool,
+    tbs_len_const: bool,
+    tbs_digest_const: Option<TbsDigest>,
+    param_crc32_consts: bool,
+    link_section: Option<String>,
+    align: Option<usize>,
+    file_name: Option<String>,
+    subdir: Option<String>,
+    create_dir: bool,
+    provenance_comment: bool,
+    feature_gate: bool,
+    template_hash_const: bool,
+    tbs_words_const: bool,
+    full_der_const: bool,
+    runtime_path: Option<String>,
+    metadata: Vec<(String, String)>,
+    split_tbs: Option<usize>,
+    sorted_params: bool,
+}
+
+impl CodeGenOptions {
+    /// Create an instance of `CodeGenOptions` producing the default output
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the default license preamble with `header`
+    ///
+    /// The header is emitted verbatim at the top of the generated file, so it may carry
+    /// comments as well as module-level attributes such as `#![allow(...)]`.
+    pub fn header(mut self, header: &str) -> Self {
+        self.header = Some(header.to_string());
+        self
+    }
+
+    /// Emit a `use` declaration for the fully-qualified `path` after the header
+    pub fn import(mut self, path: &str) -> Self {
+        self.imports.push(path.to_string());
+        self
+    }
+
+    /// Emit a `get_<type_name>()` const function returning the template with its placeholder
+    /// values, so the template can live in ROM without runtime initialization
+    pub fn const_fn(mut self) -> Self {
+        self.const_fn = true;
+        self
+    }
+
+    /// Emit a `pub const <TYPE_NAME>_TBS_LEN: usize` with the size of the TBS, so firmware
+    /// can size its buffers without instantiating the template
+    pub fn tbs_len_const(mut self) -> Self {
+        self.tbs_len_const = true;
+        self
+    }
+
+    /// Emit a `TBS_TEMPLATE_<DIGEST>` const with the `digest` of the template holding the
+    /// placeholder values, so firmware can check the integrity of its copy at boot
+    pub fn tbs_digest_const(mut self, digest: TbsDigest) -> Self {
+        self.tbs_digest_const = Some(digest);
+        self
+    }
+
+    /// Emit a `<PARAM>_CRC32` const per param with the CRC-32 of its sanitized placeholder,
+    /// so firmware can check the region wasn't corrupted before patching it
+    pub fn param_crc32_consts(mut self) -> Self {
+        self.param_crc32_consts = true;
+        self
+    }
+
+    /// Place the template bytes in a `<TYPE_NAME>_TBS_TEMPLATE` static of the linker
+    /// section `name`, e.g. the flash region holding the ROM templates
+    pub fn link_section(mut self, name: &str) -> Self {
+        self.link_section = Some(name.to_string());
+        self
+    }
+
+    /// Align the `<TYPE_NAME>_TBS_TEMPLATE` static of the template bytes to `align` bytes
+    ///
+    /// The static is wrapped in an `Aligned` type, emitted once per file.
+    pub fn align(mut self, align: usize) -> Self {
+        if !align.is_power_of_two() {
+            panic!("Alignment {} is not a power of two", align);
+        }
+        self.align = Some(align);
+        self
+    }
+
+    /// Write the code to `file_name` instead of the snake case type name with a `.rs`
+    /// extension
+    pub fn file_name(mut self, file_name: &str) -> Self {
+        self.file_name = Some(file_name.to_string());
+        self
+    }
+
+    /// Write the code to the `subdir` directory of the output path, e.g. "csr" or "cert" to
+    /// separate the template families
+    pub fn subdir(mut self, subdir: &str) -> Self {
+        self.subdir = Some(subdir.to_string());
+        self
+    }
+
+    /// Create the output directory and its parents if they are missing
+    pub fn create_dir(mut self) -> Self {
+        self.create_dir = true;
+        self
+    }
+
+    /// Emit the provenance of the template, if recorded, as a comment block after the header
+    pub fn provenance_comment(mut self) -> Self {
+        self.provenance_comment = true;
+        self
+    }
+
+    /// Gate the generated module behind the `template_feature` of its type, so a firmware
+    /// SKU only compiles the templates it enables
+    ///
+    /// `CodeGen::gen_mod` applies the same gates to the aggregator module and emits the
+    /// matching `[features]`.
+    pub fn feature_gate(mut self) -> Self {
+        self.feature_gate = true;
+        self
+    }
+
+    /// Emit a `pub const <TYPE_NAME>_TEMPLATE_HASH: [u8; 32]` with the `template_hash` of
+    /// the template and its params, and the `TEMPLATE_SCHEMA_VERSION` of the generator, so
+    /// firmware can check it runs against the template it was compiled for
+    pub fn template_hash_const(mut self) -> Self {
+        self.template_hash_const = true;
+        self
+    }
+
+    /// Emit a `TBS_TEMPLATE_WORDS` const alongside the template bytes, holding the TBS as
+    /// little-endian `u32` words as Caliptra reads the ROM
+    ///
+    /// The last word is zero padded, `TBS_TEMPLATE_LEN` and the param offsets remain in
+    /// bytes.
+    pub fn tbs_words_const(mut self) -> Self {
+        self.tbs_words_const = true;
+        self
+    }
+
+    /// Emit a `FULL_DER` const alongside the template bytes, holding the signed certificate
+    /// or CSR with the template bytes as its TBS, and `FULL_DER_TBS_OFFSET`
+    ///
+    /// The signature was computed over the generation-time values, it is a placeholder for
+    /// debugging and host tools reconstructing the whole DER, not a valid signature.
+    pub fn full_der_const(mut self) -> Self {
+        self.full_der_const = true;
+        self
+    }
+
+    /// Call the helpers of the `runtime` module at the crate path `path`, e.g.
+    /// "caliptra_x509::runtime", instead of emitting a copy of them into the file
+    pub fn runtime_path(mut self, path: &str) -> Self {
+        self.runtime_path = Some(path.to_string());
+        self
+    }
+
+    /// Split the template bytes into `TBS_TEMPLATE_CHUNK_<i>` consts of at most `chunk_len`
+    /// bytes, listed in `TBS_TEMPLATE_CHUNKS`, for linkers choking on large arrays
+    ///
+    /// `TBS_TEMPLATE` is assembled from the chunks by a const fn, so the param offsets remain
+    /// relative to the whole TBS.
+    pub fn split_tbs(mut self, chunk_len: usize) -> Self {
+        if chunk_len == 0 {
+            panic!("TBS chunks can't be empty");
+        }
+        self.split_tbs = Some(chunk_len);
+        self
+    }
+
+    /// Record the generation input `key` with its `value`, e.g. the algorithm or the scenario
+    /// of the template, so build systems can correlate the code with its source config
+    ///
+    /// Each entry is emitted as a `METADATA_<KEY>: &str` const, and all of them in order as
+    /// the `METADATA` table of `(key, value)` pairs. `key` must convert to an identifier.
+    pub fn metadata(mut self, key: &str, value: &str) -> Self {
+        self.metadata.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Emit the params struct fields, consts and `apply` writes in ascending offset order,
+    /// see `TbsTemplate::sorted_params`, instead of the order the builder registered them
+    pub fn sorted_params(mut self) -> Self {
+        self.sorted_params = true;
+        self
+    }
+}
 
 // Code Generator
 pub struct CodeGen {}
@@ -28,24 +270,581 @@ impl CodeGen {
     /// * `type_name` - Type Name,
     /// * `template` - To Be Signed template
     /// * `out_path` - Output Path
-    pub fn gen_code(type_name: &str, template: TbsTemplate, out_path: &str) {
-        let file_name = format!("{}.rs", type_name.to_case(Case::Snake));
-        let file_path = Path::new(out_path).join(file_name);
-        std::fs::write(&file_path, Self::code(type_name, template)).unwrap();
-        if Command::new("rustfmt")
-            .arg("--emit=files")
-            .arg("--edition=2021")
-            .arg(file_path)
-            .spawn()
-            .is_ok()
-        {}
-    }
-
-    fn code(type_name: &str, template: TbsTemplate) -> String {
+    ///
+    /// Returns the path of the generated file.
+    pub fn gen_code(type_name: &str, template: TbsTemplate, out_path: &str) -> PathBuf {
+        Self::gen_code_with_options(type_name, template, out_path, &CodeGenOptions::default())
+    }
+
+    /// Generate code using the specified options
+    ///
+    /// # Arguments
+    ///
+    /// * `type_name` - Type Name,
+    /// * `template` - To Be Signed template
+    /// * `out_path` - Output Path
+    /// * `options` - Header, imports and output file layout
+    ///
+    /// Returns the path of the generated file.
+    pub fn gen_code_with_options(
+        type_name: &str,
+        template: TbsTemplate,
+        out_path: &str,
+        options: &CodeGenOptions,
+    ) -> PathBuf {
+        let dir = Self::out_dir(out_path, options);
+        let file_name = match &options.file_name {
+            Some(file_name) => file_name.clone(),
+            None => format!("{}.rs", type_name.to_case(Case::Snake)),
+        };
+        let file_path = dir.join(file_name);
+        std::fs::write(
+            &file_path,
+            Self::formatted_code(type_name, template, options),
+        )
+        .unwrap_or_else(|err| panic!("Failed to write {}: {}", file_path.display(), err));
+        file_path
+    }
+
+    /// Generate the `mod.rs` aggregating the modules of `type_names` written by
+    /// `gen_code_with_options` with the same `options`
+    ///
+    /// Each module, named after the snake case type name, is declared along with its entry
+    /// in the `TEMPLATES` registry of the type names and TBS lengths. With
+    /// `CodeGenOptions::feature_gate`, both are gated like the module itself and a
+    /// `features.toml.example` lists the `[features]` of the templates and of
+    /// `ALL_TEMPLATES_FEATURE`, enabling them all.
+    ///
+    /// Returns the path of the generated `mod.rs`.
+    pub fn gen_mod(type_names: &[&str], out_path: &str, options: &CodeGenOptions) -> PathBuf {
+        let dir = Self::out_dir(out_path, options);
+        let gate = |type_name: &str| {
+            let feature = template_feature(type_name);
+            options
+                .feature_gate
+                .then(|| quote!(#[cfg(feature = #feature)]))
+        };
+
+        let mods = type_names.iter().map(|type_name| {
+            let gate = gate(type_name);
+            let module = format_ident!("{}", type_name.to_case(Case::Snake));
+            quote!(
+                #gate
+                pub mod #module;
+            )
+        });
+        let entries = type_names.iter().map(|type_name| {
+            let gate = gate(type_name);
+            let module = format_ident!("{}", type_name.to_case(Case::Snake));
+            let type_ident = format_ident!("{}", type_name);
+            quote!(
+                #gate
+                (#type_name, #module::#type_ident::TBS_TEMPLATE_LEN)
+            )
+        });
+        let code = quote!(
+            #(#mods)*
+
+            pub const TEMPLATES: &[(&str, usize)] = &[#(#entries,)*];
+        );
+        let header = "// Generated template modules. Do not edit.\n";
+        let file_path = dir.join("mod.rs");
+        std::fs::write(&file_path, Self::format("mod", header, code))
+            .unwrap_or_else(|err| panic!("Failed to write {}: {}", file_path.display(), err));
+
+        if options.feature_gate {
+            let features: Vec<String> = type_names.iter().map(|t| template_feature(t)).collect();
+            let mut toml = String::from(
+                "# Generated template features, copy them to the firmware crate manifest\n",
+            );
+            toml += "[features]\n";
+            toml += &format!(
+                "{} = [{}]\n",
+                ALL_TEMPLATES_FEATURE,
+                features
+                    .iter()
+                    .map(|feature| format!("\"{}\"", feature))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            for feature in features.iter() {
+                toml += &format!("{} = []\n", feature);
+            }
+            let toml_path = dir.join("features.toml.example");
+            std::fs::write(&toml_path, toml)
+                .unwrap_or_else(|err| panic!("Failed to write {}: {}", toml_path.display(), err));
+        }
+        file_path
+    }
+
+    /// Generate the `SIZES.md` report of the sizes of `templates`, see `report::generate`,
+    /// next to their code written by `gen_code_with_options` with the same `options`
+    ///
+    /// Returns the path of the generated report.
+    pub fn gen_size_report(
+        templates: &[(&str, &TbsTemplate)],
+        out_path: &str,
+        options: &CodeGenOptions,
+    ) -> PathBuf {
+        let file_path = Self::out_dir(out_path, options).join("SIZES.md");
+        std::fs::write(&file_path, report::generate(templates).to_markdown())
+            .unwrap_or_else(|err| panic!("Failed to write {}: {}", file_path.display(), err));
+        file_path
+    }
+
+    /// Generate the code of each template of `specs`, their `mod.rs` and their `SIZES.md`
+    /// report with the same `options`
+    ///
+    /// This is the batch entry point of `gen_code_with_options`, `gen_mod` and
+    /// `gen_size_report`, keeping the report in sync with the generated code.
+    ///
+    /// Returns the path of the generated `mod.rs`.
+    pub fn gen_batch(
+        specs: Vec<(&str, TbsTemplate)>,
+        out_path: &str,
+        options: &CodeGenOptions,
+    ) -> PathBuf {
+        let templates: Vec<(&str, &TbsTemplate)> = specs
+            .iter()
+            .map(|(type_name, template)| (*type_name, template))
+            .collect();
+        Self::gen_size_report(&templates, out_path, options);
+
+        let type_names: Vec<&str> = specs.iter().map(|&(type_name, _)| type_name).collect();
+        let mod_path = Self::gen_mod(&type_names, out_path, options);
+        for (type_name, template) in specs {
+            Self::gen_code_with_options(type_name, template, out_path, options);
+        }
+        mod_path
+    }
+
+    /// Render the code without writing or formatting it
+    ///
+    /// # Arguments
+    ///
+    /// * `type_name` - Type Name,
+    /// * `template` - To Be Signed template
+    /// * `options` - Header and imports to emit
+    pub fn code(type_name: &str, template: TbsTemplate, options: &CodeGenOptions) -> String {
+        let (header, code) = Self::tokens(type_name, template, options);
+        format!("{}{}", header, code)
+    }
+
+    /// Render the code formatted with `prettyplease`, as written by `gen_code`
+    ///
+    /// Formatting only changes the layout. If the code can't be parsed, it is returned
+    /// unformatted and a warning is logged.
+    pub fn formatted_code(
+        type_name: &str,
+        template: TbsTemplate,
+        options: &CodeGenOptions,
+    ) -> String {
+        let (header, code) = Self::tokens(type_name, template, options);
+        Self::format(type_name, &header, code)
+    }
+
+    /// Generate a bundle of the `csr_template` and the `cert_template` issued from it
+    ///
+    /// The file `<name>.rs` in `out_path` shares a single preamble between the
+    /// `<Name>CsrTbs` and `<Name>CertTbs` types, each namespacing its own constants and
+    /// params struct, so the params of both templates don't collide.
+    ///
+    /// Returns the path of the generated file.
+    pub fn gen_bundle(
+        name: &str,
+        csr_template: TbsTemplate,
+        cert_template: TbsTemplate,
+        out_path: &str,
+    ) -> PathBuf {
+        let options = CodeGenOptions::default();
+        let name = name.to_case(Case::Pascal);
+        let (header, preamble) = Self::preamble(&options);
+        let csr = Self::template_tokens(&format!("{}CsrTbs", name), csr_template, &options);
+        let cert = Self::template_tokens(&format!("{}CertTbs", name), cert_template, &options);
+        let code = Self::format(&name, &header, quote!(#preamble #csr #cert));
+
+        let file_path = Path::new(out_path).join(format!("{}.rs", name.to_case(Case::Snake)));
+        std::fs::write(&file_path, code)
+            .unwrap_or_else(|err| panic!("Failed to write {}: {}", file_path.display(), err));
+        file_path
+    }
+
+    /// Generate the variants of a template, e.g. of `tbs_template_variants`
+    ///
+    /// The file `<name>.rs` in `out_path` shares a single preamble between the
+    /// `<Name><Variant>` types, each namespacing its own constants and params struct.
+    ///
+    /// Returns the path of the generated file.
+    pub fn gen_variants(name: &str, variants: Vec<(&str, TbsTemplate)>, out_path: &str) -> PathBuf {
+        let options = CodeGenOptions::default();
+        let name = name.to_case(Case::Pascal);
+        let (header, preamble) = Self::preamble(&options);
+        let templates = variants.into_iter().map(|(variant, template)| {
+            let type_name = format!("{}{}", name, variant.to_case(Case::Pascal));
+            Self::template_tokens(&type_name, template, &options)
+        });
+        let code = Self::format(&name, &header, quote!(#preamble #(#templates)*));
+
+        let file_path = Path::new(out_path).join(format!("{}.rs", name.to_case(Case::Snake)));
+        std::fs::write(&file_path, code)
+            .unwrap_or_else(|err| panic!("Failed to write {}: {}", file_path.display(), err));
+        file_path
+    }
+
+    /// Generate a file to `include!` from a build script
+    ///
+    /// The file `<name>.rs` in `out_dir` holds only the `<Name>Params` and `<Name>` items and
+    /// a `pub const <NAME>_TBS_TEMPLATE` array, without imports or inner attributes that would
+    /// clash with the including module. A build script writing to `OUT_DIR` includes it with
+    ///
+    /// ```ignore
+    /// include!(concat!(env!("OUT_DIR"), "/<name>.rs"));
+    /// ```
+    ///
+    /// where `<name>` is the snake case `name`.
+    ///
+    /// Returns the path of the generated file.
+    pub fn gen_include(name: &str, template: TbsTemplate, out_dir: &str) -> PathBuf {
+        let type_name = name.to_case(Case::Pascal);
+        let tbs_len = template.tbs().len();
+        let code = Self::template_tokens(&type_name, template, &CodeGenOptions::default());
+        let const_name = format_ident!("{}_TBS_TEMPLATE", name.to_case(Case::UpperSnake));
+        let type_ident = format_ident!("{}", type_name);
+        let code = quote!(
+            #code
+            pub const #const_name: [u8; #tbs_len] = #type_ident::TBS_TEMPLATE;
+        );
+        let header = "// Generated template, include it with `include!`. Do not edit.\n";
+        let code = Self::format(&type_name, header, code);
+
+        let file_path = Path::new(out_dir).join(format!("{}.rs", name.to_case(Case::Snake)));
+        std::fs::write(&file_path, code)
+            .unwrap_or_else(|err| panic!("Failed to write {}: {}", file_path.display(), err));
+        file_path
+    }
+
+    /// Generate the verification artifact of a template, for verifiers of patched
+    /// certificates or CSRs which don't need the template bytes
+    ///
+    /// The file `<name>_verifier.rs` in `out_dir` holds the `<Name>Verifier` type with the
+    /// signature `ALGORITHM_OID`, the `TBS_LEN`, the `PARAMS` table of `(name, offset, len)`
+    /// in offset order and the `FIXED_REGION_SHA256` digest of the bytes outside of the
+    /// params, see `tbs::fixed_region_digest`. The TBS bytes themselves are not emitted.
+    ///
+    /// Returns the path of the generated file.
+    pub fn gen_verifier(name: &str, template: &TbsTemplate, out_dir: &str) -> PathBuf {
+        let type_name = format!("{}Verifier", name.to_case(Case::Pascal));
+        let type_ident = format_ident!("{}", type_name);
+        let algorithm_oid = template.algorithm_oid().to_string();
+        let tbs_len = template.tbs().len();
+        let params = template.sorted_params();
+        let param_count = params.len();
+        let param_entries = params.iter().map(|p| {
+            let (name, offset, len) = (p.name, p.offset, p.len);
+            quote!((#name, #offset, #len))
+        });
+        let digest = template.fixed_region_digest();
+        let code = quote!(
+            pub struct #type_ident {}
+
+            impl #type_ident {
+                pub const ALGORITHM_OID: &'static str = #algorithm_oid;
+                pub const TBS_LEN: usize = #tbs_len;
+                pub const PARAMS: [(&'static str, usize, usize); #param_count] = [
+                    #(#param_entries,)*
+                ];
+                pub const FIXED_REGION_SHA256: [u8; 32] = [#(#digest,)*];
+            }
+        );
+        let header = "// Generated verification artifact of a template. Do not edit.\n";
+        let code = Self::format(&type_name, header, code);
+
+        let file_name = format!("{}_verifier.rs", name.to_case(Case::Snake));
+        let file_path = Path::new(out_dir).join(file_name);
+        std::fs::write(&file_path, code)
+            .unwrap_or_else(|err| panic!("Failed to write {}: {}", file_path.display(), err));
+        file_path
+    }
+
+    /// Generate the JSON manifest of a template
+    ///
+    /// The file `<name>.json` in `out_dir` records the schema version, kind, TBS length,
+    /// params and hex content hash of the template along with its provenance, `null` if none
+    /// was recorded. The manifest holds only stable data, so regenerating it with the same
+    /// inputs yields the same file.
+    ///
+    /// Returns the path of the generated file.
+    pub fn gen_manifest(name: &str, template: &TbsTemplate, out_dir: &str) -> PathBuf {
+        let params: Vec<serde_json::Value> = template
+            .params()
+            .iter()
+            .map(|p| serde_json::json!({ "name": p.name, "offset": p.offset, "len": p.len }))
+            .collect();
+        let manifest = serde_json::json!({
+            "name": name.to_case(Case::Pascal),
+            "schema_version": TEMPLATE_SCHEMA_VERSION,
+            "kind": format!("{:?}", template.kind()),
+            "tbs_len": template.tbs().len(),
+            "params": params,
+            "template_hash": hex::encode(template.template_hash()),
+            "provenance": template.provenance().map(|p| p.to_json()),
+        });
+        let file_path = Path::new(out_dir).join(format!("{}.json", name.to_case(Case::Snake)));
+        let json = serde_json::to_string_pretty(&manifest).unwrap() + "\n";
+        std::fs::write(&file_path, json)
+            .unwrap_or_else(|err| panic!("Failed to write {}: {}", file_path.display(), err));
+        file_path
+    }
+
+    /// Generate the TBS of several templates with the granules they have in common emitted
+    /// once
+    ///
+    /// Each template is split into `SHARED_GRANULE` byte granules. The granules found more
+    /// than once across `specs` form the `SHARED` pool, the others the `<NAME>_OWN` array of
+    /// their template. `<name>_tbs(buf)` reconstructs the TBS into a caller-provided buffer of
+    /// `<NAME>_LEN` bytes, whose params are at the `<NAME>_<PARAM>_OFFSET` constants. The
+    /// generated tests check the reconstruction against the full templates.
+    ///
+    /// The file `shared_tbs.rs` is written in `out_dir`. Returns the path and the bytes saved.
+    pub fn gen_code_shared(specs: &[(&str, &TbsTemplate)], out_dir: &str) -> SharedCodeReport {
+        let mut counts: HashMap<&[u8], usize> = HashMap::new();
+        for (_, template) in specs.iter() {
+            for granule in template.tbs().chunks_exact(SHARED_GRANULE) {
+                *counts.entry(granule).or_default() += 1;
+            }
+        }
+
+        let mut pool = Vec::new();
+        let mut pool_offsets: HashMap<&[u8], usize> = HashMap::new();
+        let mut items = Vec::new();
+        let mut tests = Vec::new();
+        let mut own_len = 0;
+        for (name, template) in specs.iter() {
+            let tbs = template.tbs();
+            let mut own = Vec::new();
+            let mut segments: Vec<Segment> = Vec::new();
+            for granule in tbs.chunks(SHARED_GRANULE) {
+                let segment = if counts.get(granule).is_some_and(|&count| count > 1) {
+                    let offset = *pool_offsets.entry(granule).or_insert_with(|| {
+                        pool.extend_from_slice(granule);
+                        pool.len() - granule.len()
+                    });
+                    Segment {
+                        shared: true,
+                        offset,
+                        len: granule.len(),
+                    }
+                } else {
+                    own.extend_from_slice(granule);
+                    Segment {
+                        shared: false,
+                        offset: own.len() - granule.len(),
+                        len: granule.len(),
+                    }
+                };
+                match segments.last_mut() {
+                    Some(last)
+                        if last.shared == segment.shared
+                            && last.offset + last.len == segment.offset =>
+                    {
+                        last.len += segment.len
+                    }
+                    _ => segments.push(segment),
+                }
+            }
+            own_len += own.len();
+
+            let upper = name.to_case(Case::UpperSnake);
+            let own_name = format_ident!("{}_OWN", upper);
+            let len_name = format_ident!("{}_LEN", upper);
+            let fn_name = format_ident!("{}_tbs", name.to_case(Case::Snake));
+            let tbs_len = tbs.len();
+            let own_array_len = own.len();
+
+            let param_consts = template.params().iter().map(|p| {
+                let offset = format_ident!("{}_{}_OFFSET", upper, p.name.to_uppercase());
+                let len = format_ident!("{}_{}_LEN", upper, p.name.to_uppercase());
+                let (param_offset, param_len) = (p.offset, p.len);
+                quote!(
+                    pub const #offset: usize = #param_offset;
+                    pub const #len: usize = #param_len;
+                )
+            });
+
+            let mut dst = 0;
+            let copies = segments.iter().map(|segment| {
+                let start = dst;
+                dst += segment.len;
+                let end = dst;
+                let src = if segment.shared {
+                    quote!(SHARED)
+                } else {
+                    quote!(#own_name)
+                };
+                let (src_start, src_end) = (segment.offset, segment.offset + segment.len);
+                quote!(
+                    buf[#start..#end].copy_from_slice(&#src[#src_start..#src_end]);
+                )
+            });
+
+            items.push(quote!(
+                pub const #len_name: usize = #tbs_len;
+                pub const #own_name: [u8; #own_array_len] = [#(#own,)*];
+                #(#param_consts)*
+
+                pub fn #fn_name(buf: &mut [u8; #len_name]) {
+                    #(#copies)*
+                }
+            ));
+
+            let test_name = format_ident!("{}_matches_template", name.to_case(Case::Snake));
+            tests.push(quote!(
+                #[test]
+                fn #test_name() {
+                    let mut buf = [0u8; super::#len_name];
+                    super::#fn_name(&mut buf);
+                    assert_eq!(buf, [#(#tbs,)*]);
+                }
+            ));
+        }
+
+        let total_len: usize = specs.iter().map(|(_, t)| t.tbs().len()).sum();
+        let shared_len = pool.len();
+        let saved = total_len.saturating_sub(shared_len + own_len);
+        log::info!(
+            "Shared {} bytes of {} template bytes, saving {} bytes",
+            shared_len,
+            total_len,
+            saved
+        );
+
+        let code = quote!(
+            pub const SHARED: [u8; #shared_len] = [#(#pool,)*];
+
+            #(#items)*
+
+            #[cfg(test)]
+            mod tests {
+                #(#tests)*
+            }
+        );
+        let header = format!(
+            "// Generated templates sharing {} bytes, {} of {} template bytes saved.\n",
+            shared_len, saved, total_len
+        );
+        let code = Self::format("shared_tbs", &header, code);
+
+        let path = Path::new(out_dir).join("shared_tbs.rs");
+        std::fs::write(&path, code)
+            .unwrap_or_else(|err| panic!("Failed to write {}: {}", path.display(), err));
+        SharedCodeReport {
+            path,
+            total_len,
+            shared_len,
+            saved,
+        }
+    }
+
+    /// Directory of the generated files, created if requested by the `options`
+    fn out_dir(out_path: &str, options: &CodeGenOptions) -> PathBuf {
+        let mut dir = Path::new(out_path).to_path_buf();
+        if let Some(subdir) = &options.subdir {
+            dir.push(subdir);
+        }
+        if options.create_dir {
+            std::fs::create_dir_all(&dir)
+                .unwrap_or_else(|err| panic!("Failed to create {}: {}", dir.display(), err));
+        }
+        dir
+    }
+
+    /// Format the `code` with `prettyplease` after the verbatim `header`
+    fn format(name: &str, header: &str, code: TokenStream) -> String {
+        match syn::parse2::<syn::File>(code.clone()) {
+            Ok(file) => format!("{}{}", header, prettyplease::unparse(&file)),
+            Err(err) => {
+                log::warn!("Failed to format the code of {}: {}", name, err);
+                format!("{}{}", header, code)
+            }
+        }
+    }
+
+    /// Render the verbatim header and the tokens of the code
+    fn tokens(
+        type_name: &str,
+        template: TbsTemplate,
+        options: &CodeGenOptions,
+    ) -> (String, TokenStream) {
+        let (mut header, preamble) = Self::preamble(options);
+        if options.provenance_comment {
+            if let Some(provenance) = template.provenance() {
+                header += &provenance.comment();
+            }
+        }
+        let gate = options.feature_gate.then(|| {
+            let feature = template_feature(type_name);
+            quote!(#![cfg(feature = #feature)])
+        });
+        let code = Self::template_tokens(type_name, template, options);
+        (header, quote!(#gate #preamble #code))
+    }
+
+    /// Render the verbatim header and the tokens of the imports and license preamble
+    fn preamble(options: &CodeGenOptions) -> (String, TokenStream) {
+        let (header, preamble) = match &options.header {
+            Some(header) => (format!("{}\n", header), quote!()),
+            None => (
+                String::new(),
+                quote!(
+                    #[doc = "++
+
+Licensed under the Apache-2.0 license.
+
+Abstract:
+
+    Regenerate the template by building caliptra-x509-build with the generate-templates flag.
+
+--"]
+                ),
+            ),
+        };
+
+        let imports = options.imports.iter().map(|path| {
+            let path: TokenStream = path.parse().unwrap();
+            quote! {
+                use #path;
+            }
+        });
+
+        let aligned = match options.align {
+            Some(align) => {
+                let align: TokenStream = align.to_string().parse().unwrap();
+                quote!(
+                    #[repr(C, align(#align))]
+                    pub struct Aligned<T>(pub T);
+                )
+            }
+            None => quote!(),
+        };
+
+        (header, quote!(#(#imports)* #preamble #aligned))
+    }
+
+    /// Render the tokens of the params struct and the template type
+    fn template_tokens(
+        type_name: &str,
+        template: TbsTemplate,
+        options: &CodeGenOptions,
+    ) -> TokenStream {
         let type_name = format_ident!("{}", type_name);
         let param_name = format_ident!("{}Params", type_name);
+        let params: Vec<TbsParam> = if options.sorted_params {
+            template.sorted_params().into_iter().copied().collect()
+        } else {
+            template.params().to_vec()
+        };
 
-        let param_vars = template.params().iter().map(|p| {
+        let param_vars = params.iter().map(|p| {
             let name = format_ident!("{}", p.name.to_case(Case::Snake));
             let value = p.len;
             quote! {
@@ -53,7 +852,7 @@ impl CodeGen {
             }
         });
 
-        let offset_consts = template.params().iter().map(|p| {
+        let offset_consts = params.iter().map(|p| {
             let name = format_ident!("{}_OFFSET", p.name.to_uppercase());
             let value = p.offset;
             quote! {
@@ -61,8 +860,7 @@ impl CodeGen {
             }
         });
 
-        let len_consts: Vec<TokenStream> = template
-            .params()
+        let len_consts: Vec<TokenStream> = params
             .iter()
             .map(|p| {
                 let name = format_ident!("{}_LEN", p.name.to_uppercase());
@@ -73,33 +871,302 @@ impl CodeGen {
             })
             .collect();
 
-        let apply_calls = template.params().iter().map(|p| {
+        let crc32_consts = params.iter().filter_map(|p| {
+            if !options.param_crc32_consts {
+                return None;
+            }
+            let name = format_ident!("{}_CRC32", p.name.to_uppercase());
+            let value = template.param_crc32(p);
+            Some(quote! {
+               pub const #name: u32 = #value;
+            })
+        });
+
+        // Single byte params with a maximum value, like a patchable pathLenConstraint
+        let max_consts = params.iter().filter_map(|p| {
+            let name = format_ident!("{}_MAX", p.name.to_uppercase());
+            let value = template.param_max(p.name)?;
+            Some(quote! {
+               pub const #name: u8 = #value;
+            })
+        });
+
+        let runtime: Option<TokenStream> = options
+            .runtime_path
+            .as_ref()
+            .map(|path| path.parse().unwrap());
+        let apply_slice = match &runtime {
+            Some(runtime) => quote!(#runtime::apply_slice),
+            None => quote!(apply_slice),
+        };
+        let apply_calls = params.iter().map(|p| {
             let name = format_ident!("{}", p.name.to_case(Case::Snake));
             let len = format_ident!("{}_LEN", p.name.to_uppercase());
             let offset = format_ident!("{}_OFFSET", p.name.to_uppercase());
+            if p.name == SERIAL_NUMBER_PARAM {
+                return quote!(
+                     Self::set_serial(&mut self.tbs, params.#name);
+                );
+            }
+            let check_max = template.param_max(p.name).map(|_| {
+                let max = format_ident!("{}_MAX", p.name.to_uppercase());
+                quote!(debug_assert!(params.#name[0] <= Self::#max);)
+            });
             quote!(
-                 apply_slice::<{Self::#offset}, {Self::#len}>(&mut self.tbs, params.#name);
+                 #check_max
+                 #apply_slice::<{Self::#offset}, {Self::#len}>(&mut self.tbs, params.#name);
             )
         });
 
+        // Write the serial number with the convention of `encode_serial_number`: the top
+        // nibble of the first byte is forced to 0x4, so the INTEGER stays positive and
+        // minimally encoded in the reserved width
+        let has_serial = template
+            .params()
+            .iter()
+            .any(|p| p.name == SERIAL_NUMBER_PARAM);
+        let set_serial_fn = match (has_serial, &runtime) {
+            (true, Some(runtime)) => quote!(
+                pub fn set_serial(
+                    tbs: &mut [u8; Self::TBS_TEMPLATE_LEN],
+                    serial: &[u8; Self::SERIAL_NUMBER_LEN],
+                ) {
+                    let field = &mut tbs[Self::SERIAL_NUMBER_OFFSET..][..Self::SERIAL_NUMBER_LEN];
+                    #runtime::set_serial_number(field, serial);
+                }
+            ),
+            (true, None) => quote!(
+                pub fn set_serial(
+                    tbs: &mut [u8; Self::TBS_TEMPLATE_LEN],
+                    serial: &[u8; Self::SERIAL_NUMBER_LEN],
+                ) {
+                    let field = &mut tbs[Self::SERIAL_NUMBER_OFFSET..][..Self::SERIAL_NUMBER_LEN];
+                    field.copy_from_slice(serial);
+                    field[0] = 0x40 | (field[0] & 0x0F);
+                }
+            ),
+            (false, _) => quote!(),
+        };
+
+        // Copy the TBS without each range, with the renormalized headers computed here
+        let strip_fns = template.ranges().iter().map(|r| {
+            let offset = format_ident!("{}_OFFSET", r.name.to_uppercase());
+            let len = format_ident!("{}_LEN", r.name.to_uppercase());
+            let stripped_len = format_ident!("{}_STRIPPED_LEN", r.name.to_uppercase());
+            let fn_name = format_ident!("strip_{}", r.name.to_case(Case::Snake));
+            let (range_offset, range_len) = (r.offset, r.len);
+
+            let mut end = 0;
+            let copies: Vec<TokenStream> = strip_segments(template.tbs(), *r)
+                .into_iter()
+                .filter_map(|segment| {
+                    let start = end;
+                    match segment {
+                        StripSegment::Copy(_, 0) => None,
+                        StripSegment::Copy(offset, len) => {
+                            end += len;
+                            let src_end = offset + len;
+                            Some(quote!(
+                                tbs[#start..#end].copy_from_slice(&self.tbs[#offset..#src_end]);
+                            ))
+                        }
+                        StripSegment::Header(header) => {
+                            end += header.len();
+                            Some(quote!(
+                                tbs[#start..#end].copy_from_slice(&[#(#header,)*]);
+                            ))
+                        }
+                    }
+                })
+                .collect();
+
+            quote!(
+                pub const #offset: usize = #range_offset;
+                pub const #len: usize = #range_len;
+                pub const #stripped_len: usize = #end;
+
+                pub fn #fn_name(&self) -> [u8; Self::#stripped_len] {
+                    let mut tbs = [0u8; Self::#stripped_len];
+                    #(#copies)*
+                    tbs
+                }
+            )
+        });
+
+        let (schema_version_const, template_hash_const) = if options.template_hash_const {
+            let const_name = format_ident!(
+                "{}_TEMPLATE_HASH",
+                type_name.to_string().to_case(Case::UpperSnake)
+            );
+            let hash = template.template_hash();
+            (
+                quote!(
+                    pub const TEMPLATE_SCHEMA_VERSION: u32 = #TEMPLATE_SCHEMA_VERSION;
+                ),
+                quote!(
+                    pub const #const_name: [u8; 32] = [#(#hash,)*];
+                ),
+            )
+        } else {
+            (quote!(), quote!())
+        };
+
         let tbs_len = template.tbs().len();
+        let apply_slice_fn = match runtime {
+            Some(_) => quote!(),
+            None => quote!(
+                #[inline(always)]
+                fn apply_slice<const OFFSET: usize, const LEN: usize>(buf: &mut [u8; #tbs_len], val: &[u8; LEN]) {
+                    buf[OFFSET..OFFSET + LEN].copy_from_slice(val);
+                }
+            ),
+        };
         let tbs_len_const = quote!(
             pub const TBS_TEMPLATE_LEN: usize = #tbs_len;
         );
 
         let tbs = template.tbs();
 
-        quote!(
-            #[doc = "++
+        let tbs_digest_const = match options.tbs_digest_const {
+            Some(digest) => {
+                let const_name = format_ident!("TBS_TEMPLATE_{}", digest.name());
+                let value = digest.digest(tbs);
+                let len = value.len();
+                quote!(
+                    pub const #const_name: [u8; #len] = [#(#value,)*];
+                )
+            }
+            None => quote!(),
+        };
 
-Licensed under the Apache-2.0 license.
+        let tbs_template_const = match options.split_tbs {
+            Some(chunk_len) => {
+                let chunks: Vec<&[u8]> = tbs.chunks(chunk_len).collect();
+                let count = chunks.len();
+                let names: Vec<_> = (0..count)
+                    .map(|i| format_ident!("TBS_TEMPLATE_CHUNK_{}", i))
+                    .collect();
+                let lens = chunks.iter().map(|chunk| chunk.len());
+                let offsets = (0..count).map(|i| i * chunk_len);
+                let bytes = chunks.iter().map(|chunk| quote!([#(#chunk,)*]));
+                quote!(
+                    #(pub const #names: [u8; #lens] = #bytes;)*
+                    pub const TBS_TEMPLATE_CHUNKS: [&'static [u8]; #count] = [#(&Self::#names,)*];
+                    const TBS_TEMPLATE: [u8; Self::TBS_TEMPLATE_LEN] = Self::join_tbs_chunks();
 
-Abstract:
+                    const fn join_tbs_chunks() -> [u8; Self::TBS_TEMPLATE_LEN] {
+                        let mut tbs = [0u8; Self::TBS_TEMPLATE_LEN];
+                        #({
+                            let mut i = 0;
+                            while i < Self::#names.len() {
+                                tbs[#offsets + i] = Self::#names[i];
+                                i += 1;
+                            }
+                        })*
+                        tbs
+                    }
+                )
+            }
+            None => quote!(
+                const TBS_TEMPLATE: [u8; Self::TBS_TEMPLATE_LEN] = [#(#tbs,)*];
+            ),
+        };
 
-    Regenerate the template by building caliptra-x509-build with the generate-templates flag.
+        let tbs_words_const = if options.tbs_words_const {
+            let words = tbs_words(tbs);
+            let words_len = words.len();
+            quote!(
+                pub const TBS_TEMPLATE_WORDS_LEN: usize = #words_len;
+                pub const TBS_TEMPLATE_WORDS: [u32; Self::TBS_TEMPLATE_WORDS_LEN] = [#(#words,)*];
+            )
+        } else {
+            quote!()
+        };
 
---"]
+        let full_der_const = if options.full_der_const {
+            let der = template
+                .template_der()
+                .unwrap_or_else(|err| panic!("Template without signed DER: {}", err));
+            let der_len = der.len();
+            let tbs_offset = template.tbs_offset();
+            quote!(
+                #[doc = "Length of `FULL_DER`"]
+                pub const FULL_DER_LEN: usize = #der_len;
+                #[doc = "Signed DER of the template, its signature is a placeholder"]
+                pub const FULL_DER: [u8; Self::FULL_DER_LEN] = [#(#der,)*];
+                #[doc = "Offset of the TBS in `FULL_DER`"]
+                pub const FULL_DER_TBS_OFFSET: usize = #tbs_offset;
+            )
+        } else {
+            quote!()
+        };
+
+        let metadata_consts = if options.metadata.is_empty() {
+            quote!()
+        } else {
+            let names = options
+                .metadata
+                .iter()
+                .map(|(key, _)| format_ident!("METADATA_{}", key.to_case(Case::UpperSnake)));
+            let keys = options.metadata.iter().map(|(key, _)| key);
+            let values: Vec<&String> = options.metadata.iter().map(|(_, value)| value).collect();
+            let len = options.metadata.len();
+            quote!(
+                #(pub const #names: &'static str = #values;)*
+                pub const METADATA: [(&'static str, &'static str); #len] = [#((#keys, #values),)*];
+            )
+        };
+
+        let const_fn = if options.const_fn {
+            let fn_name = format_ident!("get_{}", type_name.to_string().to_case(Case::Snake));
+            quote!(
+                pub const fn #fn_name() -> #type_name {
+                    #type_name {
+                        tbs: #type_name::TBS_TEMPLATE,
+                    }
+                }
+            )
+        } else {
+            quote!()
+        };
+
+        let template_static = if options.link_section.is_some() || options.align.is_some() {
+            let static_name = format_ident!(
+                "{}_TBS_TEMPLATE",
+                type_name.to_string().to_case(Case::UpperSnake)
+            );
+            let link_section = options
+                .link_section
+                .as_ref()
+                .map(|section| quote!(#[link_section = #section]));
+            let (static_type, value) = match options.align {
+                Some(_) => (
+                    quote!(Aligned<[u8; #tbs_len]>),
+                    quote!(Aligned(#type_name::TBS_TEMPLATE)),
+                ),
+                None => (quote!([u8; #tbs_len]), quote!(#type_name::TBS_TEMPLATE)),
+            };
+            quote!(
+                #link_section
+                pub static #static_name: #static_type = #value;
+            )
+        } else {
+            quote!()
+        };
+
+        let pub_tbs_len_const = if options.tbs_len_const {
+            let const_name = format_ident!(
+                "{}_TBS_LEN",
+                type_name.to_string().to_case(Case::UpperSnake)
+            );
+            quote!(
+                pub const #const_name: usize = #tbs_len;
+            )
+        } else {
+            quote!()
+        };
 
+        quote!(
             pub struct #param_name<'a> {
                 #(pub #param_vars)*
             }
@@ -115,8 +1182,15 @@ Abstract:
             impl #type_name {
                 #(#offset_consts)*
                 #(#len_consts)*
+                #(#crc32_consts)*
+                #(#max_consts)*
                 #tbs_len_const
-                const TBS_TEMPLATE: [u8; Self::TBS_TEMPLATE_LEN] = [#(#tbs,)*];
+                #tbs_digest_const
+                #schema_version_const
+                #tbs_template_const
+                #tbs_words_const
+                #full_der_const
+                #metadata_consts
 
                 pub fn new(params: &#param_name) -> Self {
                     let mut template = Self {
@@ -133,20 +1207,25 @@ Abstract:
                     sign_fn(&self.tbs)
                 }
 
-                pub fn tbs(&self) -> &[u8] {
+                pub const fn tbs(&self) -> &[u8] {
                     &self.tbs
                 }
 
+                #(#strip_fns)*
+
+                #set_serial_fn
+
                 fn apply(&mut self, params: &#param_name) {
-                    #[inline(always)]
-                    fn apply_slice<const OFFSET: usize, const LEN: usize>(buf: &mut [u8; #tbs_len], val: &[u8; LEN]) {
-                        buf[OFFSET..OFFSET + LEN].copy_from_slice(val);
-                    }
+                    #apply_slice_fn
 
                     #(#apply_calls)*
                 }
             }
+
+            #const_fn
+            #pub_tbs_len_const
+            #template_hash_const
+            #template_static
         )
-        .to_string()
     }
 }
@@ -12,66 +12,204 @@ Abstract:
     template using RustCrypto that can be substituted at firmware runtime.
 
 --*/
-use std::str::FromStr;
-
-use crate::tbs::{get_tbs, init_param, sanitize, TbsParam, TbsTemplate};
+use crate::cert_rustcrypto::EncodedExt;
+use crate::keys::{check_algorithm_parameters, seed_from_mnemonic, sign, BuilderKeys, SigningMode};
+use crate::keystore::KeyStore;
+use crate::name::{
+    cn_placeholder, format_name, key_bytes_hash, reserved_name, subject_format,
+    DEFAULT_SUBJECT_FORMAT, SUBJECT_CN_SENTINEL,
+};
+use crate::placeholder::{alphanumeric, PlaceholderAllocator};
+use crate::profile::{
+    check_dice_profile, check_key_usage_encoding, order_extension_oids, order_extensions,
+    sort_extensions_by_oid, ueid_int_bytes, DiceCertKind, CALIPTRA_EXTENSION_ORDER,
+    EXTENSION_REQUEST_OID,
+};
+use crate::provenance::Provenance;
+use crate::tbs::{
+    artifact_name, check_der_reencoding, check_tbs_size, get_tbs_offset, init_param,
+    locate_element, locate_public_key, sanitize_with, tbs_range, PlaceholderFill, TbsParam,
+    TbsTemplate, TemplateError, TemplateKind,
+};
+use crate::validate::{
+    check_cn_len, check_cn_reservation, check_path_len, check_ueid, BuilderError,
+};
 use const_oid::{AssociatedOid, ObjectIdentifier};
 use core::marker::PhantomData;
+use der::asn1::{Any, OctetString, SetOfVec};
 use der::Decode;
 use der::Sequence;
-use ml_dsa::{KeyGen, MlDsa87};
-use sha2::{Digest, Sha256};
+use num_bigint::BigUint;
+use rand_core::CryptoRngCore;
 use signature::Keypair;
-use spki::EncodePublicKey;
+use spki::{EncodePublicKey, SignatureBitStringEncoding};
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use x509_cert::attr::Attribute;
 use x509_cert::builder::{Builder, RequestBuilder};
 use x509_cert::der::Encode;
 use x509_cert::ext::{
-    pkix::{BasicConstraints, KeyUsage},
+    pkix::{BasicConstraints, KeyUsage, KeyUsages},
     AsExtension, Extension,
 };
 use x509_cert::name::Name;
+use x509_cert::request::CertReq;
 
 /// CSR Template Param
-struct CsrTemplateParam {
+///
+/// Needles borrowed from the builder inputs, like UEIDs and FWID digests, aren't copied.
+struct CsrTemplateParam<'a> {
     tbs_param: TbsParam,
-    needle: Vec<u8>,
+    needle: Cow<'a, [u8]>,
 }
 
-#[derive(Sequence, Default, Debug)]
-struct TcgUeid<'a> {
-    #[asn1(type = "OCTET STRING")]
-    ueid: &'a [u8],
+#[derive(Sequence, Debug)]
+struct TcgUeid {
+    ueid: OctetString,
 }
 
-impl<'a> AssociatedOid for TcgUeid<'a> {
+impl AssociatedOid for TcgUeid {
     const OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.23.133.5.4.4");
 }
 
-impl<'a> AsExtension for TcgUeid<'a> {
+impl AsExtension for TcgUeid {
     fn critical(&self, _subject: &Name, _extensions: &[Extension]) -> bool {
         true
     }
 }
 
+/// Order the extensions of the extensionRequest attribute of `req`, see `order_extensions`,
+/// or sort them by OID if `canonical`
+///
+/// Returns whether the order of the extensions changed.
+fn order_requested_extensions(
+    req: &mut CertReq,
+    order: &[ObjectIdentifier],
+    canonical: bool,
+) -> bool {
+    let mut changed = false;
+    let attributes: Vec<Attribute> = req
+        .info
+        .attributes
+        .iter()
+        .map(|attr| {
+            if attr.oid != EXTENSION_REQUEST_OID {
+                return attr.clone();
+            }
+            let values: Vec<Any> = attr
+                .values
+                .iter()
+                .map(|value| {
+                    let mut extensions = value.decode_as::<x509_cert::ext::Extensions>().unwrap();
+                    changed |= if canonical {
+                        sort_extensions_by_oid(&mut extensions)
+                    } else {
+                        order_extensions(&mut extensions, order)
+                    };
+                    Any::encode_from(&extensions).unwrap()
+                })
+                .collect();
+            Attribute {
+                oid: attr.oid,
+                values: SetOfVec::try_from(values).unwrap(),
+            }
+        })
+        .collect();
+    req.info.attributes = SetOfVec::try_from(attributes).unwrap();
+    changed
+}
+
+/// Log the decoded CSR
+fn log_decoded(decoded: &x509_cert::request::CertReq) {
+    // Log subject names
+    log::debug!("Subject: {}", decoded.info.subject);
+    for name in decoded.info.subject.iter_rdn() {
+        let attr = name.iter().next().unwrap();
+        log::trace!("Subject name {}: {:?}", attr.oid, attr.value);
+    }
+
+    // Log public key info
+    log::debug!(
+        "Public key algorithm: {}, parameters: {:?}",
+        decoded.info.public_key.algorithm.oid,
+        decoded.info.public_key.algorithm.parameters
+    );
+    log::trace!(
+        "Public key: {:?}",
+        decoded.info.public_key.subject_public_key
+    );
+
+    // Log attributes and extensions
+    for attr in decoded.info.attributes.iter() {
+        log::debug!("Attribute: {}", attr.oid);
+        for value in attr.values.iter() {
+            if attr.oid == EXTENSION_REQUEST_OID {
+                if let Ok(extensions) = value.decode_as::<x509_cert::ext::Extensions>() {
+                    for ext in extensions.iter() {
+                        log::debug!("Extension: {}, critical: {}", ext.extn_id, ext.critical);
+                        log::trace!("Extension {} value: {:?}", ext.extn_id, ext.extn_value);
+                    }
+                }
+            } else {
+                log::trace!("Attribute {} value: {:?}", attr.oid, value);
+            }
+        }
+    }
+}
+
+/// File the `debug-output` feature writes the DER of each generated CSR to, relative to the
+/// current directory
+pub const DEBUG_OUTPUT_FILE: &str = "cert.der";
+
+/// Write the DER of the CSR to `DEBUG_OUTPUT_FILE`
+#[cfg(feature = "debug-output")]
+fn debug_output(der: &[u8]) {
+    std::fs::write(DEBUG_OUTPUT_FILE, der).unwrap();
+}
+
 /// CSR Tempate Builder
 pub struct CsrTemplateBuilder<'a, Key> {
     basic_constraints: Option<BasicConstraints>,
+    basic_constraints_critical: Option<bool>,
     key_usage: Option<KeyUsage>,
-    tcg_ueid: Option<TcgUeid<'a>>,
-    params: Vec<CsrTemplateParam>,
+    tcg_ueid: Option<TcgUeid>,
+    encoded_exts: Vec<(ObjectIdentifier, Box<dyn Fn(&mut RequestBuilder) + 'a>)>,
+    attributes: Vec<Attribute>,
+    params: Vec<CsrTemplateParam<'a>>,
+    spki_algorithm_param: bool,
+    subject_param_len: Option<usize>,
+    subject_format: &'a str,
+    serial_number_rdn: bool,
+    subject_cn_len: Option<usize>,
+    key: Option<Key>,
+    rng: Option<Box<dyn CryptoRngCore + 'a>>,
+    signing_mode: SigningMode,
+    dice_profile: Option<DiceCertKind>,
+    placeholder_signature: bool,
+    placeholder_fill: PlaceholderFill,
+    placeholder_allocator: Option<PlaceholderAllocator>,
+    max_tbs_size: Option<usize>,
+    der_round_trip_check: bool,
+    debug_artifacts: Option<PathBuf>,
+    key_seed: Option<Vec<u8>>,
+    #[cfg(feature = "fault-injection")]
+    post_build_mutation: Option<fn(&mut Vec<u8>)>,
+    extension_order: Vec<ObjectIdentifier>,
+    canonical_extension_order: bool,
+    error: Option<BuilderError>,
     _phantom: PhantomData<Key>,
 }
 
-pub trait BuilderKeys: Sized {
-    type Signature: spki::SignatureBitStringEncoding;
-    fn key_gen() -> Self;
-}
-
-impl BuilderKeys for ml_dsa::KeyPair<MlDsa87> {
-    type Signature = ml_dsa::Signature<MlDsa87>;
-    fn key_gen() -> Self {
-        let mut rng = rand::thread_rng();
-        <MlDsa87 as KeyGen>::key_gen(&mut rng)
+impl<'a, Key> Default for CsrTemplateBuilder<'a, Key>
+where
+    Key: BuilderKeys
+        + spki::SignatureAlgorithmIdentifier
+        + Keypair
+        + signature::Signer<<Key as BuilderKeys>::Signature>,
+    Key::VerifyingKey: EncodePublicKey,
+{
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -86,39 +224,370 @@ where
     pub fn new() -> Self {
         Self {
             params: Vec::new(),
+            spki_algorithm_param: false,
+            subject_param_len: None,
+            subject_format: DEFAULT_SUBJECT_FORMAT,
+            serial_number_rdn: true,
+            subject_cn_len: None,
+            key: None,
+            rng: None,
+            signing_mode: SigningMode::default(),
+            dice_profile: None,
+            placeholder_signature: false,
+            placeholder_fill: PlaceholderFill::default(),
+            placeholder_allocator: None,
+            max_tbs_size: None,
+            der_round_trip_check: true,
+            debug_artifacts: None,
+            key_seed: None,
+            #[cfg(feature = "fault-injection")]
+            post_build_mutation: None,
+            extension_order: CALIPTRA_EXTENSION_ORDER.to_vec(),
+            canonical_extension_order: false,
+            error: None,
             _phantom: PhantomData,
             basic_constraints: None,
+            basic_constraints_critical: None,
             key_usage: None,
             tcg_ueid: None,
+            encoded_exts: Vec::new(),
+            attributes: Vec::new(),
         }
     }
 
+    /// Caliptra 2.0 IDevID CSR: UEID, BasicConstraints(CA, pathLen 5) and keyCertSign
+    pub fn idevid_profile(ueid: &'a [u8]) -> Self {
+        Self::new()
+            .add_ueid_ext(ueid)
+            .add_basic_constraints_ext(true, 5)
+            .add_key_usage_ext(KeyUsage(KeyUsages::KeyCertSign.into()))
+    }
+
+    /// Fail template generation if the result violates the DICE profile of `kind`
+    pub fn validate_as(mut self, kind: DiceCertKind) -> Self {
+        self.dice_profile = Some(kind);
+        self
+    }
+
+    /// Zero the signature of the generated DER, keeping its length, and register it as the
+    /// "SIGNATURE" param of `TbsTemplate::params_in_der`
+    ///
+    /// The DER stays a syntactically valid CertificationRequest, e.g. with the 4627 byte
+    /// signature of ML-DSA-87, that firmware completes by signing the
+    /// CertificationRequestInfo and overwriting the signature. The generation-time signature
+    /// is still verified first.
+    pub fn placeholder_signature(mut self) -> Self {
+        self.placeholder_signature = true;
+        self
+    }
+
+    /// Fill the sanitized params of the template with the placeholder bytes of `fill` rather
+    /// than the 0x5F of `sanitize`, e.g. `PlaceholderFill::Byte(0xAA)` to spot a wrongly
+    /// patched region
+    ///
+    /// The params left unsanitized, e.g. "SPKI_ALGORITHM" and "SUBJECT", keep their bytes.
+    pub fn placeholder_fill(mut self, fill: PlaceholderFill) -> Self {
+        self.placeholder_fill = fill;
+        self
+    }
+
+    /// Allocate the placeholder of the "SUBJECT_CN" param from `allocator`, and check that
+    /// its patterns, also the UEID passed to the builder, only occur in the TBS at their
+    /// params
+    pub fn with_placeholder_allocator(mut self, allocator: PlaceholderAllocator) -> Self {
+        self.placeholder_allocator = Some(allocator);
+        self
+    }
+
+    /// Fail template generation if the TBS is larger than `bytes`
+    pub fn max_tbs_size(mut self, bytes: usize) -> Self {
+        self.max_tbs_size = Some(bytes);
+        self
+    }
+
+    /// Skip the check that the generated DER re-encodes to the same bytes once decoded,
+    /// e.g. to speed up the generation of many templates
+    pub fn skip_der_round_trip_check(mut self) -> Self {
+        self.der_round_trip_check = false;
+        self
+    }
+
+    /// Record the seed the key was generated from, e.g. by `keys::mldsa87_key_from_seed`, in
+    /// the provenance of the template
+    ///
+    /// The seed is only recorded for deterministic signing, whose output it reproduces.
+    pub fn record_key_seed(mut self, seed: &[u8]) -> Self {
+        self.key_seed = Some(seed.to_vec());
+        self
+    }
+
+    /// Write the intermediate DER, TBS and param offsets of the template to `dir`, see
+    /// `TbsTemplate::write_debug_artifacts`
+    ///
+    /// The file names are prefixed by the subject CN. The template is not affected.
+    pub fn debug_artifacts(mut self, dir: impl AsRef<Path>) -> Self {
+        self.debug_artifacts = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Mutate the signed DER with `mutation` before the TBS and the params are extracted
+    ///
+    /// Fault-injection hook reaching the error paths of template generation from tests.
+    #[cfg(feature = "fault-injection")]
+    pub fn with_post_build_mutation(mut self, mutation: fn(&mut Vec<u8>)) -> Self {
+        self.post_build_mutation = Some(mutation);
+        self
+    }
+
+    /// Order the requested extensions by OID, extensions missing from `order` follow the
+    /// others in insertion order
+    ///
+    /// Defaults to the legacy Caliptra layout of `CALIPTRA_EXTENSION_ORDER`.
+    pub fn extension_order(mut self, order: &[ObjectIdentifier]) -> Self {
+        self.extension_order = order.to_vec();
+        self
+    }
+
+    /// Sort the extensions by OID instead of `extension_order`, so templates with the same
+    /// extensions are identical whatever order they were added in
+    pub fn canonical_extension_order(mut self) -> Self {
+        self.canonical_extension_order = true;
+        self
+    }
+
+    /// OIDs of the requested extensions of the configured builder, in the order of the
+    /// generated CertificationRequestInfo
+    ///
+    /// No key is generated, so tooling can check the configuration cheaply.
+    pub fn extension_oids(&self) -> Vec<ObjectIdentifier> {
+        let configured = [
+            (self.basic_constraints.is_some(), BasicConstraints::OID),
+            (self.key_usage.is_some(), KeyUsage::OID),
+            (self.tcg_ueid.is_some(), TcgUeid::OID),
+        ];
+        let mut oids = Vec::new();
+        for (set, oid) in configured {
+            if set {
+                oids.push(oid);
+            }
+        }
+        oids.extend(self.encoded_exts.iter().map(|(oid, _)| *oid));
+        let canonical = self.canonical_extension_order;
+        order_extension_oids(&mut oids, &self.extension_order, canonical);
+        oids
+    }
+
+    /// Generate the key, unless one is set, and the randomness of hedged signatures with
+    /// `rng` instead of the thread-local RNG
+    pub fn set_rng(mut self, rng: impl CryptoRngCore + 'a) -> Self {
+        self.rng = Some(Box::new(rng));
+        self
+    }
+
+    /// Sign the CSR in the signing `mode`, see `CertTemplateBuilder::signing_mode`
+    pub fn signing_mode(mut self, mode: SigningMode) -> Self {
+        self.signing_mode = mode;
+        self
+    }
+
+    /// Sign the template with a pre-generated `key` instead of generating one
+    pub fn set_key(mut self, key: Key) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// Add a BasicConstraints extension, critical for a CA unless overridden by
+    /// `set_basic_constraints_critical`
+    ///
+    /// A `path_len` above 255 is reported as `BuilderError::PathLength` by
+    /// `try_tbs_template`.
     pub fn add_basic_constraints_ext(mut self, ca: bool, path_len: u32) -> Self {
-        self.basic_constraints = Some(BasicConstraints {
-            ca,
-            path_len_constraint: Some(path_len as u8),
-        });
+        match check_path_len(path_len) {
+            Ok(path_len) => {
+                self.basic_constraints = Some(BasicConstraints {
+                    ca,
+                    path_len_constraint: Some(path_len),
+                })
+            }
+            Err(err) => self.reject(err),
+        }
+        self
+    }
+
+    /// Mark the BasicConstraints extension critical or not, overriding the default
+    ///
+    /// By default it is critical for a CA, as RFC 5280 requires, and non-critical otherwise.
+    pub fn set_basic_constraints_critical(mut self, critical: bool) -> Self {
+        self.basic_constraints_critical = Some(critical);
         self
     }
 
+    /// Add a KeyUsage extension with the `usage` bits
+    ///
+    /// Unlike the certificate DevId profile, a CSR has no default KeyUsage, the extension is
+    /// only requested once this is called, e.g. by `idevid_profile`. The BIT STRING is
+    /// DER-minimal, one content byte up to encipherOnly and two with decipherOnly. Its length
+    /// depends on the bits, so it is fixed at generation time.
     pub fn add_key_usage_ext(mut self, usage: KeyUsage) -> Self {
         self.key_usage = Some(usage);
         self
     }
 
-    pub fn add_ueid_ext(mut self, ueid: &'a [u8]) -> Self {
-        self.tcg_ueid = Some(TcgUeid { ueid });
+    /// Register the SubjectPublicKeyInfo AlgorithmIdentifier as the "SPKI_ALGORITHM" param
+    ///
+    /// The param is located structurally and left unsanitized, so firmware can validate the
+    /// algorithm against the key it injects.
+    pub fn add_spki_algorithm_param(mut self) -> Self {
+        self.spki_algorithm_param = true;
+        self
+    }
+
+    /// Register the whole subject Name as the "SUBJECT" param instead of "SUBJECT_SN"
+    ///
+    /// The subject is padded to an encoding of exactly `len` bytes, which firmware must
+    /// preserve when substituting its own Name. A `len` the padding can't reach is reported
+    /// as `BuilderError::SubjectLength` by `try_tbs_template`, see `name::reserved_cn_sn_name`.
+    pub fn add_subject_param(mut self, len: usize) -> Self {
+        self.subject_param_len = Some(len);
+        self
+    }
+
+    /// Set the RFC 4514 string of the subject Name, `DEFAULT_SUBJECT_FORMAT` by default
+    ///
+    /// `{cn}` and `{sn}` stand for the subject CN and the serialNumber derived from the
+    /// subject key, e.g. `OU=Caliptra,OU=RT,CN={cn},serialNumber={sn}`. Attribute types may
+    /// repeat and the serialNumber may take any position, the "SUBJECT_SN" param is located
+    /// by its value.
+    pub fn set_subject_format(mut self, format: &'a str) -> Self {
+        self.subject_format = format;
+        self
+    }
+
+    /// Omit the serialNumber RDN of the subject Name, which then holds only the CN, or the
+    /// attributes of a `set_subject_format` without `{sn}`, and has no "SUBJECT_SN" param
+    pub fn no_serial_number_rdn(mut self) -> Self {
+        self.serial_number_rdn = false;
+        self
+    }
+
+    /// Register the subject CommonName as the "SUBJECT_CN" param
+    ///
+    /// The generation-time CN is padded with a sentinel to reserve `len` bytes, so it must be
+    /// shorter than the reservation, or `try_tbs_template` reports
+    /// `BuilderError::CnReservation`. Runtime values shorter than the reservation must be
+    /// padded with trailing spaces.
+    pub fn add_subject_cn_param(mut self, len: usize) -> Self {
+        if let Err(err) = check_cn_len("Subject CN reservation", len) {
+            self.reject(err);
+        }
+        self.subject_cn_len = Some(len);
+        self
+    }
+
+    /// Add the TCG DICE Ueid extension, the UEID must be 1 to 33 bytes
+    pub fn add_ueid_ext(self, ueid: &'a [u8]) -> Self {
+        self.set_ueid(Cow::Borrowed(ueid))
+    }
+
+    /// Add the TCG DICE Ueid extension holding the integer `value` as `width` big-endian bytes
+    ///
+    /// Leading zeros are kept, so the UEID param is always `width` bytes. A `value` that
+    /// doesn't fit is reported as `BuilderError::UeidValue` by `try_tbs_template`.
+    pub fn add_ueid_int(mut self, value: &BigUint, width: usize) -> Self {
+        match ueid_int_bytes(value, width) {
+            Some(ueid) => self.set_ueid(Cow::Owned(ueid)),
+            None => {
+                self.reject(BuilderError::UeidValue { width });
+                self
+            }
+        }
+    }
+
+    fn set_ueid(mut self, ueid: Cow<'a, [u8]>) -> Self {
+        if let Err(err) = check_ueid(&ueid) {
+            self.reject(err);
+            return self;
+        }
+        self.tcg_ueid = Some(TcgUeid {
+            ueid: OctetString::new(ueid.to_vec()).unwrap(),
+        });
         let param = CsrTemplateParam {
             tbs_param: TbsParam::new("UEID", 0, ueid.len()),
-            needle: ueid.to_vec(),
+            needle: ueid,
         };
         self.params.push(param);
 
         self
     }
 
-    pub fn tbs_template(mut self, subject_cn: &str) -> TbsTemplate {
-        let key = Key::key_gen();
+    /// Add an extension of any ASN.1 type with an associated OID
+    ///
+    /// The value is encoded as the extnValue of an extension identified by `T::OID`.
+    pub fn add_encoded_ext<T: Encode + AssociatedOid + 'a>(
+        mut self,
+        value: &T,
+        critical: bool,
+    ) -> Self {
+        let ext = EncodedExt::new(value, critical);
+        self.encoded_exts.push((
+            T::OID,
+            Box::new(move |builder| builder.add_extension(&ext).unwrap()),
+        ));
+        self
+    }
+
+    /// Add a single-valued attribute of type `oid` to the CertificationRequestInfo
+    ///
+    /// Extensions are carried by the extensionRequest attribute, add them with the extension
+    /// methods instead.
+    pub fn add_attribute(mut self, oid: ObjectIdentifier, value: &impl Encode) -> Self {
+        if oid == EXTENSION_REQUEST_OID {
+            panic!("extensionRequest attribute is built from the added extensions");
+        }
+        self.attributes.push(Attribute {
+            oid,
+            values: SetOfVec::try_from(vec![Any::encode_from(value).unwrap()]).unwrap(),
+        });
+        self
+    }
+
+    /// Generate the template, panicking on invalid builder inputs
+    pub fn tbs_template(self, subject_cn: &str) -> TbsTemplate {
+        self.try_tbs_template(subject_cn)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Generate the template with the key of the seed phrase `phrase`, see
+    /// `keys::seed_from_mnemonic` and `BuilderKeys::key_gen_from_seed`
+    pub fn tbs_template_from_mnemonic(self, phrase: &str, subject_cn: &str) -> TbsTemplate {
+        let key = Key::key_gen_from_seed(&seed_from_mnemonic(phrase));
+        self.set_key(key).tbs_template(subject_cn)
+    }
+
+    /// Generate the template, or report the first invalid builder input
+    ///
+    /// The subject CN, or its reservation, must be 1 to `validate::MAX_CN_LEN` bytes, and a
+    /// reserved CN shorter than its reservation.
+    pub fn try_tbs_template(mut self, subject_cn: &str) -> Result<TbsTemplate, BuilderError> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        check_cn_len(
+            "Subject CN",
+            self.subject_cn_len.unwrap_or(subject_cn.len()),
+        )?;
+        if let Some(len) = self.subject_cn_len {
+            check_cn_reservation("Subject CN", subject_cn, len)?;
+        }
+        self.build_tbs_template(subject_cn)
+    }
+
+    fn build_tbs_template(mut self, subject_cn: &str) -> Result<TbsTemplate, BuilderError> {
+        let mut rng = self
+            .rng
+            .take()
+            .unwrap_or_else(|| Box::new(rand::thread_rng()));
+        let key = self.key.take().unwrap_or_else(|| Key::key_gen(&mut rng));
 
         // Get the public key and encode it
         let pk_der = key.verifying_key().to_public_key_der().unwrap();
@@ -128,85 +597,225 @@ where
         let pk_bytes = spki.subject_public_key.as_bytes().unwrap().to_vec();
         let param = CsrTemplateParam {
             tbs_param: TbsParam::new("PUBLIC_KEY", 0, pk_bytes.len()),
-            needle: pk_bytes.clone(),
+            needle: pk_bytes.clone().into(),
         };
         self.params.push(param);
 
         // Format the subject name with CN and serialNumber
-        let key_hash = hex::encode(Sha256::digest(&pk_bytes)).to_uppercase();
-        let subject = format!("CN={},serialNumber={}", subject_cn, key_hash);
-        let name = Name::from_str(&subject).unwrap();
-        let param = CsrTemplateParam {
-            tbs_param: TbsParam::new("SUBJECT_SN", 0, key_hash.len()),
-            needle: key_hash.into_bytes(),
+        let key_hash = key_bytes_hash(&pk_bytes);
+        let subject_cn = match self.subject_cn_len {
+            Some(len) => {
+                let placeholder = cn_placeholder(subject_cn, SUBJECT_CN_SENTINEL, len);
+                let placeholder = match &mut self.placeholder_allocator {
+                    Some(allocator) => allocator.allocate_with("SUBJECT_CN", len, alphanumeric),
+                    None => placeholder.into_bytes(),
+                };
+                self.params.push(CsrTemplateParam {
+                    tbs_param: TbsParam::new("SUBJECT_CN", 0, len),
+                    needle: placeholder.clone().into(),
+                });
+                String::from_utf8(placeholder).unwrap()
+            }
+            None => subject_cn.to_string(),
+        };
+        let format = subject_format(self.subject_format, self.serial_number_rdn);
+        let name = match self.subject_param_len {
+            Some(len) => reserved_name(format, &subject_cn, &key_hash, len)?,
+            None if !self.serial_number_rdn => format_name(format, &subject_cn, &key_hash),
+            None => {
+                if !format.contains("{sn}") {
+                    panic!(
+                        "Subject format '{}' lacks the {{sn}} of the SUBJECT_SN param",
+                        format
+                    );
+                }
+                let name = format_name(format, &subject_cn, &key_hash);
+                let param = CsrTemplateParam {
+                    tbs_param: TbsParam::new("SUBJECT_SN", 0, key_hash.len()),
+                    needle: key_hash.into_bytes().into(),
+                };
+                self.params.push(param);
+                name
+            }
         };
-        self.params.push(param);
 
         let mut builder = RequestBuilder::new(name).unwrap();
 
         if let Some(basic_constraints) = self.basic_constraints {
-            builder.add_extension(&basic_constraints).unwrap();
+            let critical = self
+                .basic_constraints_critical
+                .unwrap_or(basic_constraints.ca);
+            let ext = EncodedExt::new(&basic_constraints, critical);
+            builder.add_extension(&ext).unwrap();
         }
-        if let Some(ueid) = self.tcg_ueid {
-            builder.add_extension(&ueid).unwrap();
+        if let Some(key_usage) = self.key_usage {
+            builder.add_extension(&key_usage).unwrap();
         }
-        let req = builder.build(&key).unwrap();
-        let der = req.to_der().unwrap();
+        if let Some(ref ueid) = self.tcg_ueid {
+            builder.add_extension(ueid).unwrap();
+        }
+        for (_, add_encoded_ext) in self.encoded_exts.iter() {
+            add_encoded_ext(&mut builder);
+        }
+        let mut req = builder.build(&key).unwrap();
+
+        // Order the extensions once built like the certificate builder, add the attributes
+        // and sign the modified CertificationRequestInfo again
+        let mut modified = order_requested_extensions(
+            &mut req,
+            &self.extension_order,
+            self.canonical_extension_order,
+        );
+        if !self.attributes.is_empty() {
+            let mut attributes: Vec<Attribute> = req.info.attributes.iter().cloned().collect();
+            attributes.append(&mut self.attributes);
+            req.info.attributes = SetOfVec::try_from(attributes).unwrap();
+            modified = true;
+        }
+        if modified || self.signing_mode == SigningMode::Hedged {
+            let info = req.info.to_der().unwrap();
+            let signature = sign(&key, &info, self.signing_mode, &mut rng);
+            req.signature = signature.to_bitstring().unwrap();
+        }
+        let mut der = req.to_der().unwrap();
+
+        #[cfg(feature = "fault-injection")]
+        if let Some(mutation) = self.post_build_mutation {
+            mutation(&mut der);
+        }
+
+        // Retrieve the To be signed portion before decoding, so malformed DER is reported
+        let tbs_offset = get_tbs_offset(&der)?;
+        let mut tbs = der[tbs_range(&der)?].to_vec();
 
         // Decode the DER data back into a CertReq to verify it worked
         let decoded = x509_cert::request::CertReq::from_der(&der).unwrap();
+        if self.der_round_trip_check {
+            check_der_reencoding(&der, &decoded.to_der().unwrap())?;
+        }
+        let mut provenance = Provenance::new(decoded.algorithm.oid)
+            .with_config("kind", "Csr")
+            .with_config("subject_cn", subject_cn)
+            .with_config("signing_mode", format!("{:?}", self.signing_mode));
+        if let Some(kind) = self.dice_profile {
+            provenance = provenance.with_config("dice_profile", format!("{:?}", kind));
+        }
+        if let Some(seed) = &self.key_seed {
+            if self.signing_mode == SigningMode::Deterministic {
+                provenance = provenance.with_seed(seed);
+            }
+        }
+        check_algorithm_parameters(
+            "signatureAlgorithm",
+            &decoded.algorithm,
+            Key::ALGORITHM_PARAMETERS,
+        );
+        check_algorithm_parameters(
+            "subjectPublicKeyInfo",
+            &decoded.info.public_key.algorithm,
+            Key::SPKI_PARAMETERS,
+        );
 
-        // Print subject names
-        println!("\nSubject names:");
-        for name in decoded.info.subject.iter_rdn() {
-            let attr = name.iter().next().unwrap();
-            println!("  {}: {:?}", attr.oid, attr.value);
+        log_decoded(&decoded);
+        #[cfg(feature = "debug-output")]
+        debug_output(&der);
+
+        if let Some(kind) = self.dice_profile {
+            check_dice_profile(&der, kind);
+        }
+        check_key_usage_encoding(&der, self.key_usage);
+        if let Some(max_tbs_size) = self.max_tbs_size {
+            check_tbs_size(&der, max_tbs_size)?;
         }
 
-        // Print public key info
-        println!("\nPublic key:");
-        println!("  Algorithm: {}", decoded.info.public_key.algorithm.oid);
-        println!(
-            "  Parameters: {:?}",
-            decoded.info.public_key.algorithm.parameters
+        // Verify the signature in the signing mode of the key
+        assert!(
+            key.verify(&tbs, decoded.signature.raw_bytes()),
+            "CSR signature verification failed"
         );
-        println!("  Key: {:?}", decoded.info.public_key.subject_public_key);
-
-        // Print attributes and extensions
-        println!("\nAttributes:");
-        for attr in decoded.info.attributes.iter() {
-            println!("  OID: {}", attr.oid);
-            for value in attr.values.iter() {
-                if attr.oid.to_string() == "1.2.840.113549.1.9.14" {
-                    // This is extensionRequest
-                    if let Ok(extensions) = value.decode_as::<x509_cert::ext::Extensions>() {
-                        println!("  Extensions:");
-                        for ext in extensions.iter() {
-                            println!("    ID: {}", ext.extn_id);
-                            println!("    Critical: {}", ext.critical);
-                            println!("    Value: {:?}", ext.extn_value);
-                        }
-                    }
-                } else {
-                    println!("    Value: {:?}", value);
-                }
-            }
-        }
 
-        // Write both DER and PEM formats
-        std::fs::write("cert.der", &der).unwrap();
+        // The signature BIT STRING content ends the CertificationRequest
+        let signature_len = decoded.signature.raw_bytes().len();
+        let signature_param = if self.placeholder_signature {
+            let offset = der.len() - signature_len;
+            der[offset..].fill(0);
+            Some(TbsParam::new("SIGNATURE", offset, signature_len))
+        } else {
+            None
+        };
+
+        // Locate params that can't be found unambiguously by needle search structurally.
+        // CertificationRequestInfo: version, subject, subjectPKInfo { algorithm, ... }
+        let mut structural_params = Vec::new();
+        if self.spki_algorithm_param {
+            let (offset, len) = locate_element(&tbs, &[2, 0]);
+            structural_params.push(TbsParam::new("SPKI_ALGORITHM", offset, len));
+        }
+        if self.subject_param_len.is_some() {
+            let (offset, len) = locate_element(&tbs, &[1]);
+            structural_params.push(TbsParam::new("SUBJECT", offset, len));
+        }
 
-        // TODO move get_tbs from x509_openssl
-        // Retrieve the To be signed portion from the CSR
-        let mut tbs = get_tbs(der);
+        // A key re-encoded by the builder can't be located by needle search
+        locate_public_key(&tbs, &[2], &pk_bytes)?;
 
         // Calculate the offset of parameters and sanitize the TBS section
-        let params = self
+        let mut params: Vec<TbsParam> = self
             .params
             .iter()
-            .map(|p| sanitize(init_param(&p.needle, &tbs, p.tbs_param), &mut tbs))
-            .collect();
+            .map(|p| {
+                Ok(sanitize_with(
+                    init_param(&p.needle, &tbs, p.tbs_param)?,
+                    &mut tbs,
+                    self.placeholder_fill,
+                ))
+            })
+            .collect::<Result<_, TemplateError>>()?;
+        params.extend(structural_params);
         // Create the template
-        TbsTemplate::new(tbs, params)
+        let template = TbsTemplate::new(tbs, params)
+            .with_kind(TemplateKind::Csr)
+            .with_tbs_offset(tbs_offset)
+            .with_der(der)
+            .with_algorithm_oid(Key::SIGNATURE_ALGORITHM_IDENTIFIER.oid);
+        let template = match signature_param {
+            Some(param) => template.with_signature_param(param),
+            None => template,
+        };
+        let template = template.with_provenance(provenance);
+        if let Some(allocator) = &self.placeholder_allocator {
+            allocator.check(&template)?;
+        }
+        if let Some(dir) = &self.debug_artifacts {
+            template
+                .write_debug_artifacts(dir, &artifact_name(subject_cn))
+                .map_err(|err| BuilderError::DebugArtifacts {
+                    dir: dir.clone(),
+                    reason: err.to_string(),
+                })?;
+        }
+        Ok(template)
+    }
+
+    /// Record the invalid input `err`, reported by `try_tbs_template` unless an earlier input
+    /// was already invalid
+    fn reject(&mut self, err: BuilderError) {
+        self.error.get_or_insert(err);
+    }
+}
+
+impl<'a> CsrTemplateBuilder<'a, ml_dsa::KeyPair<ml_dsa::MlDsa87>> {
+    /// Sign the CSR with the key `name` of `store`, generating and saving it if missing
+    ///
+    /// A missing key is generated with the RNG of a preceding `set_rng`, or the thread RNG.
+    pub fn with_stored_key(mut self, store: &KeyStore, name: &str) -> Self {
+        let key = match self.rng.as_mut() {
+            Some(rng) => store.load_or_generate(name, rng),
+            None => store.load_or_generate(name, &mut rand::thread_rng()),
+        };
+        match key {
+            Ok(key) => self.set_key(key),
+            Err(err) => panic!("{}", err),
+        }
     }
 }
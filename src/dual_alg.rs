@@ -0,0 +1,387 @@
+/*++
+
+Licensed under the Apache-2.0 license.
+
+File Name:
+
+    dual_alg.rs
+
+Abstract:
+
+    File contains the builder of matched ECC and ML-DSA certificate template pairs.
+
+--*/
+
+use crate::cert_rustcrypto::{
+    CertTemplateBuilder, FwidParam, PathLen, SerialNumberDigest, SkiDerivation, TimeEncoding,
+};
+use crate::keys::BuilderKeys;
+use crate::profile::DiceCertKind;
+use crate::tbs::{byte_ranges, element_children, TbsTemplate};
+use const_oid::ObjectIdentifier;
+use der::{DateTime, Decode};
+use signature::Keypair;
+use spki::EncodePublicKey;
+use std::ops::Range;
+use x509_cert::ext::pkix::KeyUsage;
+
+/// Names of the TBSCertificate elements
+const TBS_ELEMENTS: [&str; 8] = [
+    "version",
+    "serialNumber",
+    "signature",
+    "issuer",
+    "validity",
+    "subject",
+    "subjectPublicKeyInfo",
+    "extensions",
+];
+
+/// TBS element whose bytes differ between the two templates of a pair
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateDifference {
+    /// TBSCertificate element name, or "extension <OID>" for a single extension
+    pub element: String,
+    /// Offset and length of the element in the ECC template TBS
+    pub ecc: (usize, usize),
+    /// Offset and length of the element in the ML-DSA template TBS
+    pub pqc: (usize, usize),
+    /// Ranges of the differing bytes of the element in the ECC template TBS
+    pub ecc_ranges: Vec<Range<usize>>,
+    /// Ranges of the differing bytes of the element in the ML-DSA template TBS
+    pub pqc_ranges: Vec<Range<usize>>,
+}
+
+impl TemplateDifference {
+    /// Compare the `ecc` and `pqc` elements at the same offsets from their start, the tail
+    /// of the longer element is a range of its template only
+    fn new(
+        element: String,
+        ecc_tbs: &[u8],
+        ecc: (usize, usize),
+        pqc_tbs: &[u8],
+        pqc: (usize, usize),
+    ) -> Self {
+        let bytes = |tbs: &[u8], (offset, len): (usize, usize)| &tbs[offset..offset + len];
+        let ranges = byte_ranges(bytes(ecc_tbs, ecc), bytes(pqc_tbs, pqc));
+        let in_element = |(offset, len): (usize, usize)| {
+            ranges
+                .iter()
+                .filter(|range| range.start < len)
+                .map(|range| offset + range.start..offset + range.end.min(len))
+                .collect()
+        };
+        Self {
+            element,
+            ecc_ranges: in_element(ecc),
+            pqc_ranges: in_element(pqc),
+            ecc,
+            pqc,
+        }
+    }
+}
+
+/// ECC and ML-DSA certificate templates generated from the same configuration
+pub struct DualAlgTemplates {
+    pub ecc: TbsTemplate,
+    pub pqc: TbsTemplate,
+    /// Elements that differ between the templates, which only depend on the keys and
+    /// signature algorithms when the pair is consistent
+    pub differences: Vec<TemplateDifference>,
+}
+
+/// Builder of a certificate template pair for the ECC and ML-DSA keys of a DICE layer
+///
+/// Every setting is applied to both templates, so the pair shares the subject and issuer
+/// CNs, UEID, TCB info, validity and serial number derivation.
+pub struct DualAlgTemplateBuilder<'a, EccKey, PqcKey> {
+    ecc: CertTemplateBuilder<'a, EccKey>,
+    pqc: CertTemplateBuilder<'a, PqcKey>,
+}
+
+impl<'a, EccKey, PqcKey> Default for DualAlgTemplateBuilder<'a, EccKey, PqcKey>
+where
+    EccKey: BuilderKeys
+        + spki::SignatureAlgorithmIdentifier
+        + Keypair
+        + signature::Signer<<EccKey as BuilderKeys>::Signature>,
+    EccKey::VerifyingKey: EncodePublicKey,
+    PqcKey: BuilderKeys
+        + spki::SignatureAlgorithmIdentifier
+        + Keypair
+        + signature::Signer<<PqcKey as BuilderKeys>::Signature>,
+    PqcKey::VerifyingKey: EncodePublicKey,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, EccKey, PqcKey> DualAlgTemplateBuilder<'a, EccKey, PqcKey>
+where
+    EccKey: BuilderKeys
+        + spki::SignatureAlgorithmIdentifier
+        + Keypair
+        + signature::Signer<<EccKey as BuilderKeys>::Signature>,
+    EccKey::VerifyingKey: EncodePublicKey,
+    PqcKey: BuilderKeys
+        + spki::SignatureAlgorithmIdentifier
+        + Keypair
+        + signature::Signer<<PqcKey as BuilderKeys>::Signature>,
+    PqcKey::VerifyingKey: EncodePublicKey,
+{
+    pub fn new() -> Self {
+        Self {
+            ecc: CertTemplateBuilder::new(),
+            pqc: CertTemplateBuilder::new(),
+        }
+    }
+
+    /// Caliptra 2.0 LDevID certificate pair, see `CertTemplateBuilder::ldevid_profile`
+    pub fn ldevid_profile(ueid: &'a [u8]) -> Self {
+        Self {
+            ecc: CertTemplateBuilder::ldevid_profile(ueid),
+            pqc: CertTemplateBuilder::ldevid_profile(ueid),
+        }
+    }
+
+    /// Caliptra 2.0 FMC alias certificate pair, see `CertTemplateBuilder::fmc_alias_profile`
+    pub fn fmc_alias_profile(
+        ueid: &'a [u8],
+        device_fwids: &'a [FwidParam<'a>],
+        fmc_fwids: &'a [FwidParam<'a>],
+    ) -> Self {
+        Self {
+            ecc: CertTemplateBuilder::fmc_alias_profile(ueid, device_fwids, fmc_fwids),
+            pqc: CertTemplateBuilder::fmc_alias_profile(ueid, device_fwids, fmc_fwids),
+        }
+    }
+
+    /// Caliptra 2.0 RT alias certificate pair, see `CertTemplateBuilder::rt_alias_profile`
+    pub fn rt_alias_profile(ueid: &'a [u8], svn: u32, rt_fwids: &'a [FwidParam<'a>]) -> Self {
+        Self {
+            ecc: CertTemplateBuilder::rt_alias_profile(ueid, svn, rt_fwids),
+            pqc: CertTemplateBuilder::rt_alias_profile(ueid, svn, rt_fwids),
+        }
+    }
+
+    /// Fail template generation if either template violates the DICE profile of `kind`
+    pub fn validate_as(self, kind: DiceCertKind) -> Self {
+        Self {
+            ecc: self.ecc.validate_as(kind),
+            pqc: self.pqc.validate_as(kind),
+        }
+    }
+
+    /// Use pre-generated ECC subject and issuer keys instead of generating them
+    pub fn set_ecc_keys(self, subject_key: EccKey, issuer_key: EccKey) -> Self {
+        Self {
+            ecc: self
+                .ecc
+                .set_subject_key(subject_key)
+                .set_issuer_key(issuer_key),
+            ..self
+        }
+    }
+
+    /// Use pre-generated ML-DSA subject and issuer keys instead of generating them
+    pub fn set_pqc_keys(self, subject_key: PqcKey, issuer_key: PqcKey) -> Self {
+        Self {
+            pqc: self
+                .pqc
+                .set_subject_key(subject_key)
+                .set_issuer_key(issuer_key),
+            ..self
+        }
+    }
+
+    pub fn add_basic_constraints_ext(self, ca: bool, path_len: u32) -> Self {
+        Self {
+            ecc: self.ecc.add_basic_constraints_ext(ca, path_len),
+            pqc: self.pqc.add_basic_constraints_ext(ca, path_len),
+        }
+    }
+
+    pub fn add_basic_constraints_ext_with(self, ca: bool, path_len: PathLen) -> Self {
+        Self {
+            ecc: self.ecc.add_basic_constraints_ext_with(ca, path_len),
+            pqc: self.pqc.add_basic_constraints_ext_with(ca, path_len),
+        }
+    }
+
+    pub fn set_basic_constraints_critical(self, critical: bool) -> Self {
+        Self {
+            ecc: self.ecc.set_basic_constraints_critical(critical),
+            pqc: self.pqc.set_basic_constraints_critical(critical),
+        }
+    }
+
+    pub fn add_key_usage_ext(self, usage: KeyUsage) -> Self {
+        Self {
+            ecc: self.ecc.add_key_usage_ext(usage),
+            pqc: self.pqc.add_key_usage_ext(usage),
+        }
+    }
+
+    /// Derive both placeholder serial numbers from the subject public keys with the same
+    /// scheme, see `CertTemplateBuilder::add_derived_serial_number`
+    pub fn add_derived_serial_number(self, digest: SerialNumberDigest, len: usize) -> Self {
+        Self {
+            ecc: self.ecc.add_derived_serial_number(digest, len),
+            pqc: self.pqc.add_derived_serial_number(digest, len),
+        }
+    }
+
+    /// Derive both SubjectKeyIdentifiers with the same scheme, see
+    /// `CertTemplateBuilder::set_subject_key_id_derivation`
+    pub fn set_subject_key_id_derivation(self, derivation: SkiDerivation) -> Self {
+        Self {
+            ecc: self.ecc.set_subject_key_id_derivation(derivation),
+            pqc: self.pqc.set_subject_key_id_derivation(derivation),
+        }
+    }
+
+    pub fn set_time_encoding(self, encoding: TimeEncoding) -> Self {
+        Self {
+            ecc: self.ecc.set_time_encoding(encoding),
+            pqc: self.pqc.set_time_encoding(encoding),
+        }
+    }
+
+    pub fn set_validity(self, not_before: DateTime, not_after: DateTime) -> Self {
+        Self {
+            ecc: self.ecc.set_validity(not_before, not_after),
+            pqc: self.pqc.set_validity(not_before, not_after),
+        }
+    }
+
+    pub fn add_ueid_ext(self, ueid: &'a [u8]) -> Self {
+        Self {
+            ecc: self.ecc.add_ueid_ext(ueid),
+            pqc: self.pqc.add_ueid_ext(ueid),
+        }
+    }
+
+    pub fn add_fmc_dice_tcb_info_ext(
+        self,
+        device_fwids: &'a [FwidParam<'a>],
+        fmc_fwids: &'a [FwidParam<'a>],
+        critical: bool,
+    ) -> Self {
+        Self {
+            ecc: self
+                .ecc
+                .add_fmc_dice_tcb_info_ext(device_fwids, fmc_fwids, critical),
+            pqc: self
+                .pqc
+                .add_fmc_dice_tcb_info_ext(device_fwids, fmc_fwids, critical),
+        }
+    }
+
+    pub fn add_rt_dice_tcb_info_ext(
+        self,
+        svn: u32,
+        fwids: &'a [FwidParam<'a>],
+        critical: bool,
+    ) -> Self {
+        Self {
+            ecc: self.ecc.add_rt_dice_tcb_info_ext(svn, fwids, critical),
+            pqc: self.pqc.add_rt_dice_tcb_info_ext(svn, fwids, critical),
+        }
+    }
+
+    pub fn add_indexed_rt_dice_tcb_info_ext(
+        self,
+        svn: u32,
+        fwids: &'a [FwidParam<'a>],
+        index: Option<u64>,
+        tcb_type: Option<&'a [u8]>,
+        critical: bool,
+    ) -> Self {
+        Self {
+            ecc: self
+                .ecc
+                .add_indexed_rt_dice_tcb_info_ext(svn, fwids, index, tcb_type, critical),
+            pqc: self
+                .pqc
+                .add_indexed_rt_dice_tcb_info_ext(svn, fwids, index, tcb_type, critical),
+        }
+    }
+
+    pub fn add_rt_tcb_info_version_param(self, version_placeholder_len: usize) -> Self {
+        Self {
+            ecc: self
+                .ecc
+                .add_rt_tcb_info_version_param(version_placeholder_len),
+            pqc: self
+                .pqc
+                .add_rt_tcb_info_version_param(version_placeholder_len),
+        }
+    }
+
+    pub fn tbs_templates(self, subject_cn: &str, issuer_cn: &str) -> DualAlgTemplates {
+        let ecc = self.ecc.tbs_template(subject_cn, issuer_cn);
+        let pqc = self.pqc.tbs_template(subject_cn, issuer_cn);
+        let differences = template_differences(ecc.tbs(), pqc.tbs());
+        DualAlgTemplates {
+            ecc,
+            pqc,
+            differences,
+        }
+    }
+}
+
+/// Compare the sanitized TBSCertificates of a pair element by element, and the extensions
+/// one by one when both templates have the same extensions in the same order, reporting
+/// the differing bytes of each differing element
+fn template_differences(ecc: &[u8], pqc: &[u8]) -> Vec<TemplateDifference> {
+    let bytes = |tbs: &[u8], (offset, len): (usize, usize)| tbs[offset..offset + len].to_vec();
+    let extension_oid = |tbs: &[u8], extension: (usize, usize)| {
+        let (offset, len) = element_children(tbs, extension.0)[0];
+        ObjectIdentifier::from_der(&tbs[offset..offset + len]).unwrap()
+    };
+
+    let mut differences = Vec::new();
+    let ecc_elements = element_children(ecc, 0);
+    let pqc_elements = element_children(pqc, 0);
+    for (i, (&ecc_element, &pqc_element)) in ecc_elements.iter().zip(&pqc_elements).enumerate() {
+        if bytes(ecc, ecc_element) == bytes(pqc, pqc_element) {
+            continue;
+        }
+
+        // Extensions: [3] EXPLICIT SEQUENCE OF Extension
+        if TBS_ELEMENTS[i] == "extensions" {
+            let ecc_extensions = element_children(ecc, element_children(ecc, ecc_element.0)[0].0);
+            let pqc_extensions = element_children(pqc, element_children(pqc, pqc_element.0)[0].0);
+            let ecc_oids: Vec<_> = ecc_extensions
+                .iter()
+                .map(|e| extension_oid(ecc, *e))
+                .collect();
+            let pqc_oids: Vec<_> = pqc_extensions
+                .iter()
+                .map(|e| extension_oid(pqc, *e))
+                .collect();
+            if ecc_oids == pqc_oids {
+                for ((oid, &ecc_ext), &pqc_ext) in
+                    ecc_oids.iter().zip(&ecc_extensions).zip(&pqc_extensions)
+                {
+                    if bytes(ecc, ecc_ext) != bytes(pqc, pqc_ext) {
+                        let element = format!("extension {}", oid);
+                        differences
+                            .push(TemplateDifference::new(element, ecc, ecc_ext, pqc, pqc_ext));
+                    }
+                }
+                continue;
+            }
+        }
+
+        differences.push(TemplateDifference::new(
+            TBS_ELEMENTS[i].to_string(),
+            ecc,
+            ecc_element,
+            pqc,
+            pqc_element,
+        ));
+    }
+    differences
+}
@@ -0,0 +1,337 @@
+/*++
+
+Licensed under the Apache-2.0 license.
+
+File Name:
+
+    inspect.rs
+
+Abstract:
+
+    File contains the inspection of TBS templates, listing the DER fields of a
+    TBSCertificate or CertificationRequestInfo with the params they hold.
+
+--*/
+
+use crate::keys::{ED25519_OID, HASH_ML_DSA_87_WITH_SHA512_OID};
+use crate::profile::{EXTENSION_REQUEST_OID, MULTI_TCB_INFO_OID, TCB_INFO_OID, UEID_OID};
+use crate::tbs::{der_lengths, TbsParam};
+use const_oid::ObjectIdentifier;
+use der::Decode;
+use std::fmt;
+
+/// Friendly names of the OIDs found in the templates
+const OID_NAMES: [(ObjectIdentifier, &str); 20] = [
+    (TCB_INFO_OID, "tcg-dice-TcbInfo"),
+    (UEID_OID, "tcg-dice-Ueid"),
+    (MULTI_TCB_INFO_OID, "tcg-dice-MultiTcbInfo"),
+    (EXTENSION_REQUEST_OID, "extensionRequest"),
+    (
+        ObjectIdentifier::new_unwrap("2.5.29.14"),
+        "subjectKeyIdentifier",
+    ),
+    (ObjectIdentifier::new_unwrap("2.5.29.15"), "keyUsage"),
+    (ObjectIdentifier::new_unwrap("2.5.29.17"), "subjectAltName"),
+    (
+        ObjectIdentifier::new_unwrap("2.5.29.19"),
+        "basicConstraints",
+    ),
+    (ObjectIdentifier::new_unwrap("2.5.29.30"), "nameConstraints"),
+    (
+        ObjectIdentifier::new_unwrap("2.5.29.35"),
+        "authorityKeyIdentifier",
+    ),
+    (ObjectIdentifier::new_unwrap("2.5.29.37"), "extKeyUsage"),
+    (
+        ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.48.1.5"),
+        "ocspNoCheck",
+    ),
+    (
+        ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.3.19"),
+        "id-ml-dsa-87",
+    ),
+    (
+        HASH_ML_DSA_87_WITH_SHA512_OID,
+        "id-hash-ml-dsa-87-with-sha512",
+    ),
+    (ED25519_OID, "id-Ed25519"),
+    (
+        ObjectIdentifier::new_unwrap("1.2.840.10045.2.1"),
+        "id-ecPublicKey",
+    ),
+    (
+        ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.2"),
+        "ecdsa-with-SHA256",
+    ),
+    (
+        ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.3"),
+        "ecdsa-with-SHA384",
+    ),
+    (
+        ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.1"),
+        "rsaEncryption",
+    ),
+    (
+        ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.11"),
+        "sha256WithRSAEncryption",
+    ),
+];
+
+/// Friendly name of `oid` followed by the OID, or the OID alone if it is unknown
+pub fn describe_oid(oid: ObjectIdentifier) -> String {
+    match OID_NAMES.iter().find(|(known, _)| *known == oid) {
+        Some((_, name)) => format!("{} ({})", name, oid),
+        None => oid.to_string(),
+    }
+}
+
+/// DER field of an inspected TBS
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InspectedField {
+    /// Field name, e.g. "validity.notBefore" or "extension tcg-dice-Ueid (2.23.133.5.4.4)"
+    pub name: String,
+    /// Nesting depth below the TBS
+    pub depth: usize,
+    /// Offset and length of the full TLV in the TBS
+    pub offset: usize,
+    pub len: usize,
+    /// Params inside the field but not inside any of its subfields
+    pub params: Vec<&'static str>,
+}
+
+/// Fields of a TBS with the params they hold
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InspectionReport {
+    /// "TBSCertificate" or "CertificationRequestInfo"
+    pub kind: &'static str,
+    pub len: usize,
+    /// Fields in DER order, each followed by its subfields
+    pub fields: Vec<InspectedField>,
+    /// Params outside the TBS or straddling several fields
+    pub unassigned: Vec<&'static str>,
+}
+
+impl InspectionReport {
+    /// Retrieve the field `name`
+    pub fn field(&self, name: &str) -> Option<&InspectedField> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    /// Retrieve the field holding the param `name`
+    pub fn field_of_param(&self, name: &str) -> Option<&InspectedField> {
+        self.fields.iter().find(|f| f.params.contains(&name))
+    }
+}
+
+impl fmt::Display for InspectionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} of {} bytes", self.kind, self.len)?;
+        writeln!(f, "{:>6} {:>6}  field", "offset", "len")?;
+        for field in self.fields.iter() {
+            write!(
+                f,
+                "{:>6} {:>6}  {:indent$}{}",
+                field.offset,
+                field.len,
+                "",
+                field.name,
+                indent = 2 * field.depth
+            )?;
+            if !field.params.is_empty() {
+                write!(f, " <- {}", field.params.join(", "))?;
+            }
+            writeln!(f)?;
+        }
+        if !self.unassigned.is_empty() {
+            writeln!(f, "Unassigned params: {}", self.unassigned.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Walk the DER structure of the sanitized `tbs` and annotate its fields with the `params`
+/// falling inside them
+///
+/// Each param is associated with the innermost field containing it. OIDs without a
+/// friendly name are shown numerically. Panics if `tbs` is not DER.
+pub fn inspect_tbs(tbs: &[u8], params: &[TbsParam]) -> InspectionReport {
+    let walker = Walker { tbs, params };
+    let mut fields = Vec::new();
+    let children = walker.children(0);
+    let kind = match children.first() {
+        Some(&(offset, _)) if tbs[offset] == 0xA0 => {
+            walker.certificate(&children, &mut fields);
+            "TBSCertificate"
+        }
+        _ => {
+            walker.request(&children, &mut fields);
+            "CertificationRequestInfo"
+        }
+    };
+
+    let mut unassigned = Vec::new();
+    for param in params.iter() {
+        let field = fields
+            .iter_mut()
+            .filter(|f| f.offset <= param.offset && param.offset + param.len <= f.offset + f.len)
+            .min_by_key(|f| f.len);
+        match field {
+            Some(field) => field.params.push(param.name),
+            None => unassigned.push(param.name),
+        }
+    }
+
+    InspectionReport {
+        kind,
+        len: tbs.len(),
+        fields,
+        unassigned,
+    }
+}
+
+/// DER walk of a sanitized TBS
+struct Walker<'a> {
+    tbs: &'a [u8],
+    params: &'a [TbsParam],
+}
+
+impl Walker<'_> {
+    /// Length of the full TLV at `offset`
+    ///
+    /// A param starting at the TLV covers all of it, e.g. "SUBJECT", and its sanitized
+    /// header can't be decoded.
+    fn tlv_len(&self, offset: usize) -> usize {
+        if let Some(param) = self.params.iter().find(|p| p.offset == offset) {
+            return param.len;
+        }
+        let (header_len, len) = der_lengths(self.tbs, offset);
+        header_len + len
+    }
+
+    /// Offset and length of the full TLV of each child of the element at `offset`
+    fn children(&self, offset: usize) -> Vec<(usize, usize)> {
+        let (header_len, len) = der_lengths(self.tbs, offset);
+        let end = offset + header_len + len;
+        let mut children = Vec::new();
+        let mut child = offset + header_len;
+        while child < end {
+            let len = self.tlv_len(child);
+            children.push((child, len));
+            child += len;
+        }
+        children
+    }
+
+    /// OID of the first child of the SEQUENCE at `offset`, e.g. an AlgorithmIdentifier
+    fn first_oid(&self, offset: usize) -> Option<ObjectIdentifier> {
+        if self.tbs[offset] != 0x30 {
+            return None;
+        }
+        let &(child, len) = self.children(offset).first()?;
+        ObjectIdentifier::from_der(&self.tbs[child..child + len]).ok()
+    }
+
+    /// Name the field at `element` after the OID it starts with
+    fn oid_field(&self, prefix: &str, depth: usize, element: (usize, usize)) -> InspectedField {
+        let name = match self.first_oid(element.0) {
+            Some(oid) => format!("{} {}", prefix, describe_oid(oid)),
+            None => prefix.to_string(),
+        };
+        field(name, depth, element)
+    }
+
+    /// TBSCertificate: version, serialNumber, signature, issuer, validity, subject,
+    /// subjectPublicKeyInfo, issuerUniqueID, subjectUniqueID, extensions
+    fn certificate(&self, children: &[(usize, usize)], fields: &mut Vec<InspectedField>) {
+        const ELEMENTS: [&str; 7] = [
+            "version",
+            "serialNumber",
+            "signature",
+            "issuer",
+            "validity",
+            "subject",
+            "subjectPublicKeyInfo",
+        ];
+        for (&element, name) in children.iter().zip(ELEMENTS) {
+            match name {
+                "signature" => fields.push(self.oid_field(name, 0, element)),
+                "validity" => {
+                    fields.push(field(name, 0, element));
+                    let times = self.children(element.0);
+                    for (&time, name) in times.iter().zip(["notBefore", "notAfter"]) {
+                        fields.push(field(format!("validity.{}", name), 1, time));
+                    }
+                }
+                "subjectPublicKeyInfo" => self.spki(element, fields),
+                _ => fields.push(field(name, 0, element)),
+            }
+        }
+        for &element in children.iter().skip(ELEMENTS.len()) {
+            match self.tbs[element.0] {
+                0xA1 => fields.push(field("issuerUniqueID", 0, element)),
+                0xA2 => fields.push(field("subjectUniqueID", 0, element)),
+                0xA3 => {
+                    fields.push(field("extensions", 0, element));
+                    // [3] EXPLICIT SEQUENCE OF Extension
+                    let (extensions, _) = self.children(element.0)[0];
+                    self.extensions(extensions, 1, fields);
+                }
+                tag => fields.push(field(format!("[tag 0x{:02X}]", tag), 0, element)),
+            }
+        }
+    }
+
+    /// CertificationRequestInfo: version, subject, subjectPKInfo, attributes
+    fn request(&self, children: &[(usize, usize)], fields: &mut Vec<InspectedField>) {
+        let elements = ["version", "subject", "subjectPublicKeyInfo"];
+        for (&element, name) in children.iter().zip(elements) {
+            match name {
+                "subjectPublicKeyInfo" => self.spki(element, fields),
+                _ => fields.push(field(name, 0, element)),
+            }
+        }
+        let Some(&attributes) = children.get(3) else {
+            return;
+        };
+        fields.push(field("attributes", 0, attributes));
+        for attribute in self.children(attributes.0) {
+            fields.push(self.oid_field("attribute", 1, attribute));
+            // extensionRequest: SET OF Extensions
+            if self.first_oid(attribute.0) == Some(EXTENSION_REQUEST_OID) {
+                for (values, _) in self.children(attribute.0).into_iter().skip(1) {
+                    for (extensions, _) in self.children(values) {
+                        self.extensions(extensions, 2, fields);
+                    }
+                }
+            }
+        }
+    }
+
+    fn spki(&self, element: (usize, usize), fields: &mut Vec<InspectedField>) {
+        fields.push(field("subjectPublicKeyInfo", 0, element));
+        let children = self.children(element.0);
+        if let Some(&algorithm) = children.first() {
+            fields.push(self.oid_field("subjectPublicKeyInfo.algorithm", 1, algorithm));
+        }
+        if let Some(&key) = children.get(1) {
+            fields.push(field("subjectPublicKeyInfo.subjectPublicKey", 1, key));
+        }
+    }
+
+    /// Each Extension of the SEQUENCE at `offset`, named after its extnID
+    fn extensions(&self, offset: usize, depth: usize, fields: &mut Vec<InspectedField>) {
+        for extension in self.children(offset) {
+            fields.push(self.oid_field("extension", depth, extension));
+        }
+    }
+}
+
+fn field(name: impl Into<String>, depth: usize, (offset, len): (usize, usize)) -> InspectedField {
+    InspectedField {
+        name: name.into(),
+        depth,
+        offset,
+        len,
+        params: Vec::new(),
+    }
+}
@@ -0,0 +1,657 @@
+/*++
+
+Licensed under the Apache-2.0 license.
+
+File Name:
+
+    keys.rs
+
+Abstract:
+
+    File contains the key types used to generate and sign X509 templates.
+
+--*/
+
+use const_oid::ObjectIdentifier;
+use ml_dsa::{KeyGen, MlDsa87, B32};
+use rand_core::CryptoRngCore;
+use sha2::{Digest, Sha256, Sha512};
+use signature::{Keypair, Verifier};
+use slh_dsa::Sha2_128f;
+use spki::{
+    AlgorithmIdentifier, AlgorithmIdentifierOwned, DecodePublicKey, SignatureAlgorithmIdentifier,
+};
+use std::marker::PhantomData;
+
+/// id-hash-ml-dsa-87-with-sha512
+pub const HASH_ML_DSA_87_WITH_SHA512_OID: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.3.34");
+
+/// id-Ed25519
+pub const ED25519_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.101.112");
+
+/// id-slh-dsa-sha2-128f
+pub const SLH_DSA_SHA2_128F_OID: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.3.21");
+
+/// secp256r1
+pub const SECP256R1_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.3.1.7");
+
+/// secp384r1
+pub const SECP384R1_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.132.0.34");
+
+/// Encoding of the AlgorithmIdentifier parameters required by an algorithm
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlgorithmParameters {
+    /// The parameters field is omitted, as required by ML-DSA, SLH-DSA, EdDSA and ECDSA
+    /// signatures
+    Absent,
+    /// The parameters field is an ASN.1 NULL, as required by RSA
+    Null,
+    /// The parameters field is the OID of a named curve, as required by EC public keys
+    NamedCurve(ObjectIdentifier),
+}
+
+/// Encoding of the signature value carried in the signature BIT STRING
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignatureEncoding {
+    /// Signature of a fixed length for a given key, as for ML-DSA, SLH-DSA, EdDSA and RSA
+    Fixed,
+    /// DER encoded Ecdsa-Sig-Value of at most `max_len` bytes, as required by RFC 5758
+    ///
+    /// The INTEGERs r and s drop their leading zero bytes, so the signature and the lengths
+    /// of the enclosing BIT STRING and Certificate vary between signatures. Firmware signing
+    /// a template must compute these lengths from the actual signature rather than reserve a
+    /// fixed region.
+    Der { max_len: usize },
+}
+
+/// Signing variant of the template signatures
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SigningMode {
+    /// Signatures only depend on the key and the message, so fixtures signed from seeded keys
+    /// are byte-stable. ML-DSA signs with the all-zero randomizer of the deterministic
+    /// variant of FIPS 204.
+    #[default]
+    Deterministic,
+    /// ML-DSA signatures with fresh randomness, as recommended by FIPS 204 §3.4
+    Hedged,
+}
+
+/// Sign `msg` with `key` in the signing `mode`, drawing the hedged randomness from `rng`
+///
+/// The signature is brought to the canonical form of `BuilderKeys::canonical_signature`.
+/// Fails for `SigningMode::Hedged` if the key type has no hedged variant.
+pub fn sign<Key>(
+    key: &Key,
+    msg: &[u8],
+    mode: SigningMode,
+    rng: &mut impl CryptoRngCore,
+) -> Key::Signature
+where
+    Key: BuilderKeys + SignatureAlgorithmIdentifier + signature::Signer<Key::Signature>,
+{
+    let signature = match mode {
+        SigningMode::Deterministic => key.sign(msg),
+        SigningMode::Hedged => key.sign_hedged(msg, rng).unwrap_or_else(|| {
+            panic!(
+                "{} keys have no hedged signing mode",
+                Key::SIGNATURE_ALGORITHM_IDENTIFIER.oid
+            )
+        }),
+    };
+    Key::canonical_signature(signature)
+}
+
+/// Check that the AlgorithmIdentifier of `field` has the `expected` parameters
+///
+/// The ML-DSA certificate profile requires the parameters field to be absent rather than an
+/// ASN.1 NULL, so template generation fails if the encoding doesn't match the algorithm.
+pub fn check_algorithm_parameters(
+    field: &str,
+    algorithm: &AlgorithmIdentifierOwned,
+    expected: AlgorithmParameters,
+) {
+    let matches = match (&algorithm.parameters, expected) {
+        (None, AlgorithmParameters::Absent) => true,
+        (Some(parameters), AlgorithmParameters::Null) => parameters.is_null(),
+        (Some(parameters), AlgorithmParameters::NamedCurve(curve)) => {
+            parameters.decode_as::<ObjectIdentifier>().ok() == Some(curve)
+        }
+        _ => false,
+    };
+    if !matches {
+        panic!(
+            "{} AlgorithmIdentifier {} has parameters {:?}, expected {:?}",
+            field, algorithm.oid, algorithm.parameters, expected
+        );
+    }
+}
+
+/// Keys used to generate and sign templates
+pub trait BuilderKeys: Sized {
+    type Signature: spki::SignatureBitStringEncoding;
+
+    /// Parameters of the signature AlgorithmIdentifiers
+    const ALGORITHM_PARAMETERS: AlgorithmParameters = AlgorithmParameters::Absent;
+
+    /// Parameters of the subjectPublicKeyInfo AlgorithmIdentifier, those of the signature
+    /// unless the key names its curve
+    const SPKI_PARAMETERS: AlgorithmParameters = Self::ALGORITHM_PARAMETERS;
+
+    /// Encoding of the signatures of the key
+    const SIGNATURE_ENCODING: SignatureEncoding = SignatureEncoding::Fixed;
+
+    /// Generate a key pair with the randomness of `rng`
+    fn key_gen(rng: &mut impl CryptoRngCore) -> Self;
+
+    /// Generate a key pair deterministically from the 32 byte `seed`
+    ///
+    /// By default the seed drives `rand::rngs::StdRng`, whose algorithm may change between
+    /// `rand` releases. The ML-DSA, Ed25519 and ECDSA keys override it with a documented
+    /// derivation, so their keys are reproducible by other tools.
+    fn key_gen_from_seed(seed: &[u8; 32]) -> Self {
+        Self::key_gen(&mut <rand::rngs::StdRng as rand::SeedableRng>::from_seed(
+            *seed,
+        ))
+    }
+
+    /// Generate a key pair with the randomness of the operating system
+    fn key_gen_os() -> Self {
+        Self::key_gen(&mut rand::rngs::OsRng)
+    }
+
+    /// Sign `msg` with the randomness of `rng`, `None` if the key only signs deterministically
+    fn sign_hedged(&self, _msg: &[u8], _rng: &mut impl CryptoRngCore) -> Option<Self::Signature> {
+        None
+    }
+
+    /// Bring `signature` to the canonical form of the algorithm, unchanged by default
+    fn canonical_signature(signature: Self::Signature) -> Self::Signature {
+        signature
+    }
+
+    /// Verify the encoded `signature` over `msg` in the signing mode of the key
+    fn verify(&self, msg: &[u8], signature: &[u8]) -> bool;
+
+    /// Verify the encoded `signature` over `msg` with the public key of the DER
+    /// SubjectPublicKeyInfo `spki`, e.g. of a remote signer, as `verify` does
+    fn verify_spki(spki: &[u8], msg: &[u8], signature: &[u8]) -> bool;
+}
+
+/// Random 32 byte `rnd` of hedged ML-DSA signing
+fn hedged_rnd(rng: &mut impl CryptoRngCore) -> B32 {
+    let mut rnd = B32::default();
+    rng.fill_bytes(&mut rnd);
+    rnd
+}
+
+/// Generate the ML-DSA-87 key pair of the 32 byte `seed`
+///
+/// Together with the deterministic signing of `ml_dsa::KeyPair`, which uses an empty
+/// randomizer rather than hedged signing, keys injected with `set_key`, `set_subject_key`
+/// and `set_issuer_key` make the whole generated DER reproducible.
+pub fn mldsa87_key_from_seed(seed: &[u8; 32]) -> ml_dsa::KeyPair<MlDsa87> {
+    <MlDsa87 as KeyGen>::key_gen_internal(&B32::from(*seed))
+}
+
+/// HKDF info of the key generation seed of a seed phrase, see `seed_from_mnemonic`
+pub const MNEMONIC_SEED_INFO: &[u8] = b"x509 template key seed";
+
+/// Derive the 32 byte key generation seed of the seed phrase `phrase`, for lab provisioning
+/// reproducible from a human-memorable phrase rather than a hex seed
+///
+/// The seed is the 32 byte output of HKDF-SHA256 (RFC 5869) with an empty salt, the
+/// `MNEMONIC_SEED_INFO` info and the UTF-8 bytes of the phrase as input keying material.
+/// The words of the phrase are first joined by single spaces, so leading, trailing and
+/// repeated whitespace doesn't change the seed, while case does.
+pub fn seed_from_mnemonic(phrase: &str) -> [u8; 32] {
+    let phrase = phrase.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut seed = [0; 32];
+    hkdf::Hkdf::<Sha256>::new(None, phrase.as_bytes())
+        .expand(MNEMONIC_SEED_INFO, &mut seed)
+        .unwrap();
+    seed
+}
+
+/// HKDF info prefix of the ECDSA private key candidates of a seed, see `ecdsa_key_from_seed`
+pub const ECDSA_KEY_SEED_INFO: &[u8] = b"x509 template ECDSA key";
+
+/// Derive the ECDSA private key of the 32 byte `seed` on a curve with `len` byte scalars,
+/// `from_slice` accepting the candidates that are nonzero and below the curve order
+///
+/// Candidate i is the `len` byte output of HKDF-SHA256 (RFC 5869) with an empty salt, the
+/// seed as input keying material and `ECDSA_KEY_SEED_INFO` followed by the byte i as info.
+/// The private key d is the first candidate c, read as a big-endian integer, with
+/// 0 < c < n for the curve order n, taken unchanged. This differs from FIPS 186-5 A.2.2,
+/// which rejects c > n - 2 and returns c + 1, so other tools must follow this description
+/// to derive the same key.
+fn ecdsa_key_from_seed<Key>(
+    seed: &[u8; 32],
+    len: usize,
+    from_slice: impl Fn(&[u8]) -> Option<Key>,
+) -> Key {
+    let hkdf = hkdf::Hkdf::<Sha256>::new(None, seed);
+    (0..=u8::MAX)
+        .find_map(|i| {
+            let mut candidate = vec![0; len];
+            hkdf.expand_multi_info(&[ECDSA_KEY_SEED_INFO, &[i]], &mut candidate)
+                .unwrap();
+            from_slice(&candidate)
+        })
+        .expect("no ECDSA private key candidate within the curve order")
+}
+
+/// Signs with the deterministic variant of ML-DSA and an empty context, unless hedged
+impl BuilderKeys for ml_dsa::KeyPair<MlDsa87> {
+    type Signature = ml_dsa::Signature<MlDsa87>;
+    fn key_gen(rng: &mut impl CryptoRngCore) -> Self {
+        <MlDsa87 as KeyGen>::key_gen(rng)
+    }
+
+    /// ML-DSA.KeyGen_internal of FIPS 204 with the seed as ξ, see `mldsa87_key_from_seed`
+    fn key_gen_from_seed(seed: &[u8; 32]) -> Self {
+        mldsa87_key_from_seed(seed)
+    }
+
+    fn sign_hedged(&self, msg: &[u8], rng: &mut impl CryptoRngCore) -> Option<Self::Signature> {
+        // M' = 0 || |ctx| || ctx || M with an empty context, FIPS 204 Algorithm 2
+        let rnd = hedged_rnd(rng);
+        Some(self.signing_key().sign_internal(&[&[0], &[0], msg], &rnd))
+    }
+
+    fn verify(&self, msg: &[u8], signature: &[u8]) -> bool {
+        match ml_dsa::Signature::<MlDsa87>::try_from(signature) {
+            Ok(signature) => Keypair::verifying_key(self).verify(msg, &signature).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn verify_spki(spki: &[u8], msg: &[u8], signature: &[u8]) -> bool {
+        match (
+            ml_dsa::VerifyingKey::<MlDsa87>::from_public_key_der(spki),
+            ml_dsa::Signature::<MlDsa87>::try_from(signature),
+        ) {
+            (Ok(key), Ok(signature)) => key.verify(msg, &signature).is_ok(),
+            _ => false,
+        }
+    }
+}
+
+/// Hash function pre-hashing the messages signed with HashML-DSA-87
+pub trait PreHash {
+    /// DER encoded OID of the hash function, bound to the signature by FIPS 204 §5.4
+    const OID_DER: &'static [u8];
+
+    /// Signature algorithm OID of HashML-DSA-87 with the hash function
+    const SIGNATURE_OID: ObjectIdentifier;
+
+    /// Digest of `msg`
+    fn prehash(msg: &[u8]) -> Vec<u8>;
+}
+
+/// SHA-512, the only pre-hash function with a registered HashML-DSA-87 signature OID
+impl PreHash for Sha512 {
+    const OID_DER: &'static [u8] = &[
+        0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03,
+    ];
+    const SIGNATURE_OID: ObjectIdentifier = HASH_ML_DSA_87_WITH_SHA512_OID;
+
+    fn prehash(msg: &[u8]) -> Vec<u8> {
+        Sha512::digest(msg).to_vec()
+    }
+}
+
+/// HashML-DSA-87 key pair, pre-hashing with SHA-512 by default
+///
+/// Signs the `H` digest of the message using the pre-hash domain separator described in
+/// FIPS 204 §5.4, with an empty context and deterministic signing unless hedged. The
+/// signature AlgorithmIdentifier is `H::SIGNATURE_OID`.
+pub struct HashMlDsa87KeyPair<H = Sha512>(ml_dsa::KeyPair<MlDsa87>, PhantomData<H>);
+
+impl HashMlDsa87KeyPair {
+    /// Wrap an ML-DSA-87 key pair to sign in pre-hash mode with SHA-512
+    pub fn new(key: ml_dsa::KeyPair<MlDsa87>) -> Self {
+        Self::with_prehash(key)
+    }
+}
+
+impl<H: PreHash> HashMlDsa87KeyPair<H> {
+    /// Wrap an ML-DSA-87 key pair to sign in pre-hash mode with `H`
+    pub fn with_prehash(key: ml_dsa::KeyPair<MlDsa87>) -> Self {
+        Self(key, PhantomData)
+    }
+
+    /// Digest of `msg` signed by `sign_prehashed`, e.g. computed on the host for an HSM
+    pub fn prehash(msg: &[u8]) -> Vec<u8> {
+        H::prehash(msg)
+    }
+
+    /// Sign the pre-hashed message `digest` deterministically
+    ///
+    /// Signing the `prehash` of a TBS gives the same signature as signing the TBS itself.
+    pub fn sign_prehashed(&self, digest: &[u8]) -> ml_dsa::Signature<MlDsa87> {
+        self.sign_digest(digest, &B32::default())
+    }
+
+    /// Verify `signature` over the pre-hashed message `digest`
+    pub fn verify_prehashed(&self, digest: &[u8], signature: &[u8]) -> bool {
+        match ml_dsa::Signature::<MlDsa87>::try_from(signature) {
+            Ok(signature) => Keypair::verifying_key(&self.0)
+                .verify_internal(&[&[1], &[0], H::OID_DER, digest], &signature),
+            Err(_) => false,
+        }
+    }
+
+    /// M' = 1 || |ctx| || ctx || OID || PH(M) with an empty context, FIPS 204 Algorithm 4
+    fn sign_digest(&self, digest: &[u8], rnd: &B32) -> ml_dsa::Signature<MlDsa87> {
+        self.0
+            .signing_key()
+            .sign_internal(&[&[1], &[0], H::OID_DER, digest], rnd)
+    }
+}
+
+impl<H: PreHash> BuilderKeys for HashMlDsa87KeyPair<H> {
+    type Signature = ml_dsa::Signature<MlDsa87>;
+    fn key_gen(rng: &mut impl CryptoRngCore) -> Self {
+        Self::with_prehash(<ml_dsa::KeyPair<MlDsa87> as BuilderKeys>::key_gen(rng))
+    }
+
+    fn key_gen_from_seed(seed: &[u8; 32]) -> Self {
+        Self::with_prehash(mldsa87_key_from_seed(seed))
+    }
+
+    fn sign_hedged(&self, msg: &[u8], rng: &mut impl CryptoRngCore) -> Option<Self::Signature> {
+        let rnd = hedged_rnd(rng);
+        Some(self.sign_digest(&H::prehash(msg), &rnd))
+    }
+
+    fn verify(&self, msg: &[u8], signature: &[u8]) -> bool {
+        self.verify_prehashed(&H::prehash(msg), signature)
+    }
+
+    fn verify_spki(spki: &[u8], msg: &[u8], signature: &[u8]) -> bool {
+        match (
+            ml_dsa::VerifyingKey::<MlDsa87>::from_public_key_der(spki),
+            ml_dsa::Signature::<MlDsa87>::try_from(signature),
+        ) {
+            (Ok(key), Ok(signature)) => {
+                key.verify_internal(&[&[1], &[0], H::OID_DER, &H::prehash(msg)], &signature)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<H> Keypair for HashMlDsa87KeyPair<H> {
+    type VerifyingKey = ml_dsa::VerifyingKey<MlDsa87>;
+    fn verifying_key(&self) -> Self::VerifyingKey {
+        Keypair::verifying_key(&self.0)
+    }
+}
+
+impl<H: PreHash> SignatureAlgorithmIdentifier for HashMlDsa87KeyPair<H> {
+    type Params = der::AnyRef<'static>;
+    const SIGNATURE_ALGORITHM_IDENTIFIER: AlgorithmIdentifier<Self::Params> = AlgorithmIdentifier {
+        oid: H::SIGNATURE_OID,
+        parameters: None,
+    };
+}
+
+impl<H: PreHash> signature::Signer<ml_dsa::Signature<MlDsa87>> for HashMlDsa87KeyPair<H> {
+    fn try_sign(&self, msg: &[u8]) -> Result<ml_dsa::Signature<MlDsa87>, signature::Error> {
+        Ok(self.sign_prehashed(&H::prehash(msg)))
+    }
+}
+
+/// Ed25519 key pair
+///
+/// The 32 byte public key is a short PUBLIC_KEY needle, so collisions with other bytes of
+/// the TBS are rejected by the ambiguity check in `init_param`.
+pub struct Ed25519KeyPair(ed25519_dalek::SigningKey);
+
+impl Ed25519KeyPair {
+    /// Wrap an Ed25519 signing key
+    pub fn new(key: ed25519_dalek::SigningKey) -> Self {
+        Self(key)
+    }
+}
+
+impl BuilderKeys for Ed25519KeyPair {
+    type Signature = ed25519::Signature;
+    fn key_gen(rng: &mut impl CryptoRngCore) -> Self {
+        let mut seed = ed25519_dalek::SecretKey::default();
+        rng.fill_bytes(&mut seed);
+        Self(ed25519_dalek::SigningKey::from_bytes(&seed))
+    }
+
+    /// The seed is the RFC 8032 private key
+    fn key_gen_from_seed(seed: &[u8; 32]) -> Self {
+        Self(ed25519_dalek::SigningKey::from_bytes(seed))
+    }
+
+    fn verify(&self, msg: &[u8], signature: &[u8]) -> bool {
+        match ed25519::Signature::from_slice(signature) {
+            Ok(signature) => self
+                .0
+                .verifying_key()
+                .verify_strict(msg, &signature)
+                .is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn verify_spki(spki: &[u8], msg: &[u8], signature: &[u8]) -> bool {
+        match (
+            ed25519_dalek::VerifyingKey::from_public_key_der(spki),
+            ed25519::Signature::from_slice(signature),
+        ) {
+            (Ok(key), Ok(signature)) => key.verify_strict(msg, &signature).is_ok(),
+            _ => false,
+        }
+    }
+}
+
+impl Keypair for Ed25519KeyPair {
+    type VerifyingKey = ed25519_dalek::VerifyingKey;
+    fn verifying_key(&self) -> Self::VerifyingKey {
+        self.0.verifying_key()
+    }
+}
+
+impl SignatureAlgorithmIdentifier for Ed25519KeyPair {
+    type Params = der::AnyRef<'static>;
+    const SIGNATURE_ALGORITHM_IDENTIFIER: AlgorithmIdentifier<Self::Params> = AlgorithmIdentifier {
+        oid: ED25519_OID,
+        parameters: None,
+    };
+}
+
+impl signature::Signer<ed25519::Signature> for Ed25519KeyPair {
+    fn try_sign(&self, msg: &[u8]) -> Result<ed25519::Signature, signature::Error> {
+        signature::Signer::try_sign(&self.0, msg)
+    }
+}
+
+/// SLH-DSA-SHA2-128f key pair
+///
+/// The 17088 byte signatures make the signed DER several times larger than its TBS, so the
+/// builders copy the TBS out of the DER rather than the DER itself, see `tbs::tbs_range`.
+pub struct SlhDsaSha2_128fKeyPair(slh_dsa::SigningKey<Sha2_128f>);
+
+impl SlhDsaSha2_128fKeyPair {
+    /// Wrap an SLH-DSA-SHA2-128f signing key
+    pub fn new(key: slh_dsa::SigningKey<Sha2_128f>) -> Self {
+        Self(key)
+    }
+}
+
+/// Signs with the deterministic variant of SLH-DSA and an empty context, unless hedged
+impl BuilderKeys for SlhDsaSha2_128fKeyPair {
+    type Signature = slh_dsa::Signature<Sha2_128f>;
+    fn key_gen(rng: &mut impl CryptoRngCore) -> Self {
+        Self(slh_dsa::SigningKey::new(rng))
+    }
+
+    fn sign_hedged(&self, msg: &[u8], rng: &mut impl CryptoRngCore) -> Option<Self::Signature> {
+        Some(signature::RandomizedSigner::sign_with_rng(
+            &self.0, rng, msg,
+        ))
+    }
+
+    fn verify(&self, msg: &[u8], signature: &[u8]) -> bool {
+        match slh_dsa::Signature::<Sha2_128f>::try_from(signature) {
+            Ok(signature) => self.0.verifying_key().verify(msg, &signature).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn verify_spki(spki: &[u8], msg: &[u8], signature: &[u8]) -> bool {
+        match (
+            slh_dsa::VerifyingKey::<Sha2_128f>::from_public_key_der(spki),
+            slh_dsa::Signature::<Sha2_128f>::try_from(signature),
+        ) {
+            (Ok(key), Ok(signature)) => key.verify(msg, &signature).is_ok(),
+            _ => false,
+        }
+    }
+}
+
+impl Keypair for SlhDsaSha2_128fKeyPair {
+    type VerifyingKey = slh_dsa::VerifyingKey<Sha2_128f>;
+    fn verifying_key(&self) -> Self::VerifyingKey {
+        self.0.verifying_key()
+    }
+}
+
+impl SignatureAlgorithmIdentifier for SlhDsaSha2_128fKeyPair {
+    type Params = der::AnyRef<'static>;
+    const SIGNATURE_ALGORITHM_IDENTIFIER: AlgorithmIdentifier<Self::Params> = AlgorithmIdentifier {
+        oid: SLH_DSA_SHA2_128F_OID,
+        parameters: None,
+    };
+}
+
+impl signature::Signer<slh_dsa::Signature<Sha2_128f>> for SlhDsaSha2_128fKeyPair {
+    fn try_sign(&self, msg: &[u8]) -> Result<slh_dsa::Signature<Sha2_128f>, signature::Error> {
+        signature::Signer::try_sign(&self.0, msg)
+    }
+}
+
+/// Signs deterministically as specified by RFC 6979, in low-S form
+impl BuilderKeys for p256::ecdsa::SigningKey {
+    type Signature = p256::ecdsa::DerSignature;
+    const SPKI_PARAMETERS: AlgorithmParameters = AlgorithmParameters::NamedCurve(SECP256R1_OID);
+    const SIGNATURE_ENCODING: SignatureEncoding = SignatureEncoding::Der { max_len: 72 };
+
+    fn key_gen(rng: &mut impl CryptoRngCore) -> Self {
+        p256::ecdsa::SigningKey::random(rng)
+    }
+
+    /// HKDF-SHA256 derivation of `ecdsa_key_from_seed`
+    fn key_gen_from_seed(seed: &[u8; 32]) -> Self {
+        ecdsa_key_from_seed(seed, 32, |bytes| {
+            p256::ecdsa::SigningKey::from_slice(bytes).ok()
+        })
+    }
+
+    /// Low-S form of BIP 62, s <= n/2, which the curve doesn't normalize to when signing
+    fn canonical_signature(signature: Self::Signature) -> Self::Signature {
+        p256::ecdsa::Signature::from_der(signature.as_bytes())
+            .expect("ECDSA signature")
+            .normalize_s()
+            .to_der()
+    }
+
+    fn verify(&self, msg: &[u8], signature: &[u8]) -> bool {
+        match p256::ecdsa::DerSignature::try_from(signature) {
+            Ok(signature) => self.verifying_key().verify(msg, &signature).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn verify_spki(spki: &[u8], msg: &[u8], signature: &[u8]) -> bool {
+        match (
+            p256::ecdsa::VerifyingKey::from_public_key_der(spki),
+            p256::ecdsa::DerSignature::try_from(signature),
+        ) {
+            (Ok(key), Ok(signature)) => key.verify(msg, &signature).is_ok(),
+            _ => false,
+        }
+    }
+}
+
+/// Signs deterministically as specified by RFC 6979, in low-S form
+impl BuilderKeys for p384::ecdsa::SigningKey {
+    type Signature = p384::ecdsa::DerSignature;
+    const SPKI_PARAMETERS: AlgorithmParameters = AlgorithmParameters::NamedCurve(SECP384R1_OID);
+    const SIGNATURE_ENCODING: SignatureEncoding = SignatureEncoding::Der { max_len: 104 };
+
+    fn key_gen(rng: &mut impl CryptoRngCore) -> Self {
+        p384::ecdsa::SigningKey::random(rng)
+    }
+
+    /// HKDF-SHA256 derivation of `ecdsa_key_from_seed`
+    fn key_gen_from_seed(seed: &[u8; 32]) -> Self {
+        ecdsa_key_from_seed(seed, 48, |bytes| {
+            p384::ecdsa::SigningKey::from_slice(bytes).ok()
+        })
+    }
+
+    /// Low-S form of BIP 62, s <= n/2, which the curve doesn't normalize to when signing
+    fn canonical_signature(signature: Self::Signature) -> Self::Signature {
+        p384::ecdsa::Signature::from_der(signature.as_bytes())
+            .expect("ECDSA signature")
+            .normalize_s()
+            .to_der()
+    }
+
+    fn verify(&self, msg: &[u8], signature: &[u8]) -> bool {
+        match p384::ecdsa::DerSignature::try_from(signature) {
+            Ok(signature) => self.verifying_key().verify(msg, &signature).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn verify_spki(spki: &[u8], msg: &[u8], signature: &[u8]) -> bool {
+        match (
+            p384::ecdsa::VerifyingKey::from_public_key_der(spki),
+            p384::ecdsa::DerSignature::try_from(signature),
+        ) {
+            (Ok(key), Ok(signature)) => key.verify(msg, &signature).is_ok(),
+            _ => false,
+        }
+    }
+}
+
+/// Size of generated RSA keys, larger keys can be injected into the builders
+#[cfg(feature = "rsa")]
+pub const RSA_KEY_BITS: usize = 3072;
+
+#[cfg(feature = "rsa")]
+impl BuilderKeys for rsa::pkcs1v15::SigningKey<sha2::Sha384> {
+    type Signature = rsa::pkcs1v15::Signature;
+    const ALGORITHM_PARAMETERS: AlgorithmParameters = AlgorithmParameters::Null;
+
+    fn key_gen(rng: &mut impl CryptoRngCore) -> Self {
+        let key = rsa::RsaPrivateKey::new(rng, RSA_KEY_BITS).unwrap();
+        Self::new(key)
+    }
+
+    fn verify(&self, msg: &[u8], signature: &[u8]) -> bool {
+        match rsa::pkcs1v15::Signature::try_from(signature) {
+            Ok(signature) => Keypair::verifying_key(self).verify(msg, &signature).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn verify_spki(spki: &[u8], msg: &[u8], signature: &[u8]) -> bool {
+        match (
+            rsa::pkcs1v15::VerifyingKey::<sha2::Sha384>::from_public_key_der(spki),
+            rsa::pkcs1v15::Signature::try_from(signature),
+        ) {
+            (Ok(key), Ok(signature)) => key.verify(msg, &signature).is_ok(),
+            _ => false,
+        }
+    }
+}
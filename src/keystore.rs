@@ -0,0 +1,140 @@
+/*++
+
+Licensed under the Apache-2.0 license.
+
+File Name:
+
+    keystore.rs
+
+Abstract:
+
+    File contains the store of the ML-DSA keys shared by chained templates.
+
+--*/
+
+use crate::keys::mldsa87_key_from_seed;
+use ml_dsa::{KeyGen, MlDsa87};
+use rand_core::CryptoRngCore;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Magic of the key files
+const MAGIC: [u8; 4] = *b"MLDK";
+
+/// Length of the ML-DSA-87 key generation seed
+const SEED_LEN: usize = 32;
+
+/// Length of the SHA-256 checksum of the seed
+const CHECKSUM_LEN: usize = 32;
+
+/// Key store error
+#[derive(Debug)]
+pub enum KeyStoreError {
+    /// Key file that couldn't be read or written
+    Io { path: PathBuf, err: std::io::Error },
+    /// Key file whose content is not a valid key container
+    Corrupted { path: PathBuf, reason: &'static str },
+}
+
+impl fmt::Display for KeyStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, err } => write!(f, "Key file {}: {}", path.display(), err),
+            Self::Corrupted { path, reason } => {
+                write!(f, "Corrupted key file {}: {}", path.display(), reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for KeyStoreError {}
+
+/// Directory of named ML-DSA-87 keys
+///
+/// The store keeps the 32 byte key generation seeds of the key pairs and derives them again
+/// with `mldsa87_key_from_seed`. Each key is a `<name>.key` file holding the "MLDK" magic,
+/// the big-endian u32 length of the seed, the seed and its SHA-256 checksum.
+pub struct KeyStore {
+    dir: PathBuf,
+}
+
+impl KeyStore {
+    /// Create a store of the keys in `dir`, which is created on the first save
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Path of the key file of `name`
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.key", name))
+    }
+
+    /// Persist the key pair `key` as `name`, replacing any previous key
+    pub fn save(&self, name: &str, key: &ml_dsa::KeyPair<MlDsa87>) -> Result<(), KeyStoreError> {
+        let seed = key.to_seed();
+        let path = self.path(name);
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&(SEED_LEN as u32).to_be_bytes());
+        data.extend_from_slice(&seed);
+        data.extend_from_slice(&Sha256::digest(seed));
+
+        std::fs::create_dir_all(&self.dir)
+            .and_then(|_| std::fs::write(&path, data))
+            .map_err(|err| KeyStoreError::Io { path, err })
+    }
+
+    /// Load the key pair of `name`
+    pub fn load(&self, name: &str) -> Result<ml_dsa::KeyPair<MlDsa87>, KeyStoreError> {
+        let seed = self.load_seed(name)?;
+        Ok(mldsa87_key_from_seed(&seed))
+    }
+
+    /// Load the key pair of `name`, or generate and save it with the randomness of `rng` if
+    /// the store doesn't have it yet
+    pub fn load_or_generate(
+        &self,
+        name: &str,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<ml_dsa::KeyPair<MlDsa87>, KeyStoreError> {
+        if !self.path(name).exists() {
+            let key = <MlDsa87 as KeyGen>::key_gen(rng);
+            self.save(name, &key)?;
+            return Ok(key);
+        }
+        self.load(name)
+    }
+
+    fn load_seed(&self, name: &str) -> Result<[u8; SEED_LEN], KeyStoreError> {
+        let path = self.path(name);
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(err) => return Err(KeyStoreError::Io { path, err }),
+        };
+        let corrupted = |reason| KeyStoreError::Corrupted {
+            path: path.clone(),
+            reason,
+        };
+
+        let Some(data) = data.strip_prefix(&MAGIC) else {
+            return Err(corrupted("bad magic"));
+        };
+        if data.len() < 4 {
+            return Err(corrupted("truncated length"));
+        }
+        let (len, data) = data.split_at(4);
+        if u32::from_be_bytes(len.try_into().unwrap()) as usize != SEED_LEN {
+            return Err(corrupted("unexpected seed length"));
+        }
+        if data.len() != SEED_LEN + CHECKSUM_LEN {
+            return Err(corrupted("unexpected file length"));
+        }
+        let (seed, checksum) = data.split_at(SEED_LEN);
+        if Sha256::digest(seed).as_slice() != checksum {
+            return Err(corrupted("checksum mismatch"));
+        }
+        Ok(seed.try_into().unwrap())
+    }
+}
@@ -0,0 +1,46 @@
+/*++
+
+Licensed under the Apache-2.0 license.
+
+File Name:
+
+    lib.rs
+
+Abstract:
+
+    X509 template generator library
+
+--*/
+
+#[cfg(feature = "async")]
+pub mod async_signer;
+pub mod cert_rustcrypto;
+pub mod code_gen;
+pub mod csr_rustcrypto;
+pub mod dual_alg;
+pub mod inspect;
+pub mod keys;
+pub mod keystore;
+pub mod measurement;
+pub mod name;
+pub mod pkcs7;
+pub mod placeholder;
+pub mod profile;
+pub mod provenance;
+pub mod report;
+pub mod runtime;
+pub mod tbs;
+pub mod validate;
+
+pub use cert_rustcrypto::{CertTemplateBuilder, Fwid, FwidParam, SanEntry};
+pub use code_gen::{CodeGen, CodeGenOptions, SharedCodeReport};
+pub use csr_rustcrypto::CsrTemplateBuilder;
+pub use dual_alg::DualAlgTemplateBuilder;
+pub use inspect::{inspect_tbs, InspectionReport};
+pub use keys::BuilderKeys;
+pub use keystore::KeyStore;
+pub use measurement::FwidDigest;
+pub use name::key_hash;
+pub use provenance::Provenance;
+pub use tbs::{TbsParam, TbsTemplate, TemplateKind};
+pub use validate::BuilderError;
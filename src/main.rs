@@ -1,12 +1,77 @@
-mod cert_rustcrypto;
-mod code_gen;
-mod csr_rustcrypto;
-mod tbs;
+#[cfg(all(test, feature = "async"))]
+use test_rustcrypto_mldsa::async_signer;
+#[cfg(test)]
+use test_rustcrypto_mldsa::{
+    cert_rustcrypto, code_gen, csr_rustcrypto, dual_alg, inspect, keys, keystore, measurement,
+    name, pkcs7, placeholder, profile, provenance, report, runtime, tbs, validate,
+};
+use test_rustcrypto_mldsa::{inspect_tbs, TbsParam};
+
+const USAGE: &str = "Usage: test-rustcrypto-mldsa inspect <file.bin> [--params file.json]
+
+  file.bin     Sanitized TBS of a template
+  file.json    Params of the template, [{\"name\": ..., \"offset\": ..., \"len\": ...}, ...]";
 
 fn main() {
-    // Call the test function directly
-    //    test_gen_mldsa87_cert_template();
-    //    test_gen_fmc_alias_cert_template();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["inspect", tbs] => inspect_file(tbs, None),
+        ["inspect", tbs, "--params", params] => inspect_file(tbs, Some(*params)),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Print the inspection report of the TBS file `tbs` with the params of the JSON file `params`
+fn inspect_file(tbs: &str, params: Option<&str>) {
+    let read = |path: &str| {
+        std::fs::read(path).unwrap_or_else(|err| {
+            eprintln!("Failed to read {}: {}", path, err);
+            std::process::exit(1);
+        })
+    };
+    let tbs = read(tbs);
+    let params = match params {
+        Some(path) => parse_params(path, &read(path)),
+        None => Vec::new(),
+    };
+    print!("{}", inspect_tbs(&tbs, &params));
+}
+
+/// Parse the JSON array of params, whose names are leaked to make them `&'static str`
+fn parse_params(path: &str, data: &[u8]) -> Vec<TbsParam> {
+    let value: serde_json::Value =
+        serde_json::from_slice(data).unwrap_or_else(|err| invalid_params(path, &err.to_string()));
+    let Some(entries) = value.as_array() else {
+        invalid_params(path, "expected an array of params");
+    };
+    entries
+        .iter()
+        .map(|entry| {
+            let Some(name) = entry["name"].as_str() else {
+                invalid_params(path, "param without a name");
+            };
+            let number = |key: &str| match entry[key].as_u64() {
+                Some(value) => value as usize,
+                None => invalid_params(path, &format!("param {} without {}", name, key)),
+            };
+            let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+            TbsParam::try_new(name, number("offset"), number("len"))
+                .unwrap_or_else(|err| invalid_params(path, &err.to_string()))
+        })
+        .collect()
+}
+
+fn invalid_params(path: &str, reason: &str) -> ! {
+    eprintln!("Invalid params file {}: {}", path, reason);
+    std::process::exit(1);
 }
 
 #[test]
@@ -14,23 +79,15 @@ fn test_gen_init_devid_csr_mldsa87() {
     use crate::code_gen::CodeGen;
     use crate::csr_rustcrypto::CsrTemplateBuilder;
     use ml_dsa::MlDsa87;
-    use x509_cert::ext::pkix::{KeyUsage, KeyUsages};
 
     // Create a temporary directory for output
     let temp_dir = std::env::temp_dir();
     let out_dir = temp_dir.to_str().unwrap();
 
-    // Set up key usage for certificate signing
-    let key_usage = KeyUsage(KeyUsages::KeyCertSign.into());
-
-    // Create the CSR template builder with ML-DSA-87
-    let bldr = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
-        .add_ueid_ext(&[0xFF; 17])
-        .add_basic_constraints_ext(true, 5)
-        .add_key_usage_ext(key_usage);
-
-    // Generate the template with a subject name
-    let template = bldr.tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    // Generate the IDevID CSR template with ML-DSA-87
+    let template = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::idevid_profile(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    assert_extensions(&template, &[BC, KU, UEID]);
 
     // Generate code from the template
     CodeGen::gen_code("InitDevIdCsrTbsMlDsa87", template, out_dir);
@@ -41,23 +98,15 @@ fn test_gen_fmc_alias_csr_mldsa87() {
     use crate::code_gen::CodeGen;
     use crate::csr_rustcrypto::CsrTemplateBuilder;
     use ml_dsa::MlDsa87;
-    use x509_cert::ext::pkix::{KeyUsage, KeyUsages};
 
     // Create a temporary directory for output
     let temp_dir = std::env::temp_dir();
     let out_dir = temp_dir.to_str().unwrap();
 
-    // Set up key usage for certificate signing
-    let key_usage = KeyUsage(KeyUsages::KeyCertSign.into());
-
-    // Create the CSR template builder with ML-DSA-87
-    let bldr = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
-        .add_ueid_ext(&[0xFF; 17])
-        .add_basic_constraints_ext(true, 5)
-        .add_key_usage_ext(key_usage);
-
-    // Generate the template with a subject name
-    let template = bldr.tbs_template("Caliptra 2.0 MlDsa87 FMC Alias");
+    // The FMC alias CSR shares the IDevID configuration
+    let template = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::idevid_profile(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 FMC Alias");
+    assert_extensions(&template, &[BC, KU, UEID]);
 
     // Generate code from the template
     CodeGen::gen_code("FmcAliasTbsMlDsa87", template, out_dir);
@@ -68,23 +117,15 @@ fn test_gen_local_devid_cert_mldsa87() {
     use crate::cert_rustcrypto::CertTemplateBuilder;
     use crate::code_gen::CodeGen;
     use ml_dsa::MlDsa87;
-    use x509_cert::ext::pkix::{KeyUsage, KeyUsages};
 
     // Create a temporary directory for output
     let temp_dir = std::env::temp_dir();
     let out_dir = temp_dir.to_str().unwrap();
 
-    // Create KeyUsage with key_cert_sign set to true
-    let key_usage = KeyUsage(KeyUsages::KeyCertSign.into());
-
-    // Build the FMC Alias certificate template
-    let bldr = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
-        .add_basic_constraints_ext(true, 3)
-        .add_key_usage_ext(key_usage)
-        .add_ueid_ext(&[0xFF; 17]);
-
-    // Generate the template with subject and issuer CN
-    let template = bldr.tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    // Generate the LDevID certificate template with subject and issuer CN
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    assert_extensions(&template, &[BC, KU, UEID, SKI, AKI]);
 
     // Generate the code
     CodeGen::gen_code("LocalDevIdCertTbsMlDsa87", template, out_dir);
@@ -96,15 +137,12 @@ fn test_gen_fmc_alias_cert_mldsa87() {
     use crate::code_gen::CodeGen;
     use const_oid::ObjectIdentifier;
     use ml_dsa::MlDsa87;
-    use x509_cert::ext::pkix::{KeyUsage, KeyUsages};
+    use std::borrow::Cow;
 
     // Create a temporary directory for output
     let temp_dir = std::env::temp_dir();
     let out_dir = temp_dir.to_str().unwrap();
 
-    // Create KeyUsage with key_cert_sign set to true
-    let key_usage = KeyUsage(KeyUsages::KeyCertSign.into());
-
     // SHA-384 OID
     let sha384_oid = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.2");
 
@@ -113,7 +151,7 @@ fn test_gen_fmc_alias_cert_mldsa87() {
         name: "TCB_INFO_DEVICE_INFO_HASH",
         fwid: Fwid {
             hash_alg: sha384_oid.clone(),
-            digest: &[0xEF; 48],
+            digest: Cow::Borrowed(&[0xEF; 48]),
         },
     }];
 
@@ -121,24 +159,23 @@ fn test_gen_fmc_alias_cert_mldsa87() {
         name: "TCB_INFO_FMC_TCI",
         fwid: Fwid {
             hash_alg: sha384_oid,
-            digest: &[0xCD; 48],
+            digest: Cow::Borrowed(&[0xCD; 48]),
         },
     }];
 
     // Build the FMC Alias certificate template with TCB info
-    let bldr = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
-        .add_basic_constraints_ext(true, 3)
-        .add_key_usage_ext(key_usage)
-        .add_ueid_ext(&[0xFF; 17])
-        .add_fmc_dice_tcb_info_ext(
-            /*device_fwids=*/
-            &device_fwids,
-            /*fmc_fwids=*/
-            &fmc_fwids,
-        );
+    let bldr = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::fmc_alias_profile(
+        &[0xFF; 17],
+        &device_fwids,
+        &fmc_fwids,
+    );
 
     // Generate the template with subject and issuer CN
-    let template = bldr.tbs_template("Caliptra 2.0 MlDsa87 FMC Alias", "Caliptra 2.0 MlDsa87 LDevID");
+    let template = bldr.tbs_template(
+        "Caliptra 2.0 MlDsa87 FMC Alias",
+        "Caliptra 2.0 MlDsa87 LDevID",
+    );
+    assert_extensions(&template, &[BC, KU, UEID, MULTI_TCB_INFO, SKI, AKI]);
 
     // Generate the code
     CodeGen::gen_code("FmcAliasCertTbsMlDsa87", template, out_dir);
@@ -150,15 +187,12 @@ fn test_gen_rt_alias_cert_mldsa87() {
     use crate::code_gen::CodeGen;
     use const_oid::ObjectIdentifier;
     use ml_dsa::MlDsa87;
-    use x509_cert::ext::pkix::{KeyUsage, KeyUsages};
+    use std::borrow::Cow;
 
     // Create a temporary directory for output
     let temp_dir = std::env::temp_dir();
     let out_dir = temp_dir.to_str().unwrap();
 
-    // Create KeyUsage with key_cert_sign set to true and digital_signature set to true
-    let key_usage = KeyUsage((KeyUsages::KeyCertSign | KeyUsages::DigitalSignature).into());
-
     // SHA-384 OID
     let sha384_oid = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.2");
 
@@ -167,21 +201,6535 @@ fn test_gen_rt_alias_cert_mldsa87() {
         name: "TCB_INFO_RT_TCI",
         fwid: Fwid {
             hash_alg: sha384_oid,
-            digest: &[0xCD; 48],
+            digest: Cow::Borrowed(&[0xCD; 48]),
         },
     }];
 
     // Build the RT Alias certificate template with TCB info
-    let bldr = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
-        .add_basic_constraints_ext(true, 2)
-        .add_key_usage_ext(key_usage)
-        .add_ueid_ext(&[0xFF; 17])
-        .add_rt_dice_tcb_info_ext(0xC4, &rt_fwids);
+    let bldr = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::rt_alias_profile(
+        &[0xFF; 17],
+        0xC4,
+        &rt_fwids,
+    );
 
     // Generate the template with subject and issuer CN
-    let template = bldr.tbs_template("Caliptra 2.0 MlDsa87 RT Alias", "Caliptra 2.0 MlDsa87 FMC Alias");
+    let template = bldr.tbs_template(
+        "Caliptra 2.0 MlDsa87 RT Alias",
+        "Caliptra 2.0 MlDsa87 FMC Alias",
+    );
+    assert_extensions(&template, &[BC, KU, UEID, TCB_INFO, SKI, AKI]);
 
     // Generate the code
     CodeGen::gen_code("RtAliasCertTbsMlDsa87", template, out_dir);
 }
 
+#[test]
+fn test_gen_dpe_leaf_cert() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::keys::mldsa87_key_from_seed;
+    use crate::profile::{validate_dice_profile, DiceCertKind, TCG_DICE_KP_ATTEST_LOC_OID};
+    use crate::validate::BuilderError;
+    use const_oid::AssociatedOid;
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+    use x509_cert::ext::pkix::{BasicConstraints, ExtendedKeyUsage, KeyUsage};
+
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::dpe_leaf_profile(4)
+        .add_ueid_ext(&[0xFF; 17])
+        .set_subject_key(mldsa87_key_from_seed(&[0x01; 32]))
+        .set_issuer_key(mldsa87_key_from_seed(&[0x02; 32]))
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 DPE Leaf",
+            "Caliptra 2.0 MlDsa87 RT Alias",
+        );
+    assert_extensions(&template, &[BC, KU, UEID, MULTI_TCB_INFO, EKU, SKI, AKI]);
+    assert!(validate_dice_profile(template.der(), DiceCertKind::Leaf).is_empty());
+
+    let mut names: Vec<&str> = template.params().iter().map(|p| p.name).collect();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            "AUTHORITY_KEY_ID",
+            "DPE_TCI_0",
+            "DPE_TCI_1",
+            "DPE_TCI_2",
+            "DPE_TCI_3",
+            "ISSUER_SN",
+            "NOT_AFTER",
+            "NOT_BEFORE",
+            "PUBLIC_KEY",
+            "SERIAL_NUMBER",
+            "SUBJECT_KEY_ID",
+            "SUBJECT_SN",
+            "UEID",
+        ]
+    );
+    for param in template.params() {
+        if param.name.starts_with("DPE_TCI_") {
+            assert_eq!(param.len, 48);
+        }
+    }
+
+    let extensions = profile::der_extensions(template.der());
+    let ext = |oid| extensions.iter().find(|e| e.extn_id == oid).unwrap();
+    let eku = ext(ExtendedKeyUsage::OID);
+    assert!(eku.critical);
+    let eku = ExtendedKeyUsage::from_der(eku.extn_value.as_bytes()).unwrap();
+    assert_eq!(eku.0, [TCG_DICE_KP_ATTEST_LOC_OID]);
+    let bc = BasicConstraints::from_der(ext(BasicConstraints::OID).extn_value.as_bytes());
+    let bc = bc.unwrap();
+    assert!(!bc.ca);
+    assert!(bc.path_len_constraint.is_none());
+    let key_usage = KeyUsage::from_der(ext(KeyUsage::OID).extn_value.as_bytes()).unwrap();
+    assert!(key_usage.digital_signature());
+    assert!(!key_usage.key_cert_sign());
+
+    let err = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::dpe_leaf_profile(0)
+        .add_ueid_ext(&[0xFF; 17])
+        .try_tbs_template(
+            "Caliptra 2.0 MlDsa87 DPE Leaf",
+            "Caliptra 2.0 MlDsa87 RT Alias",
+        )
+        .unwrap_err();
+    assert_eq!(err, BuilderError::NoDpeTci);
+}
+
+#[test]
+fn test_gen_extended_key_usage() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::profile::ANY_EXTENDED_KEY_USAGE_OID;
+    use crate::validate::BuilderError;
+    use const_oid::{AssociatedOid, ObjectIdentifier};
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+    use x509_cert::ext::pkix::ExtendedKeyUsage;
+    use x509_cert::Certificate;
+
+    const SERVER_AUTH: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.3.1");
+    const CLIENT_AUTH: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.3.2");
+
+    let gen = |usages: &[ObjectIdentifier], critical: bool| {
+        CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+            .add_ueid_ext(&[0xFF; 17])
+            .add_extended_key_usage_ext(usages, critical)
+            .try_tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID")
+    };
+    let eku = |template: &tbs::TbsTemplate| {
+        let cert = Certificate::from_der(template.der()).unwrap();
+        let ext = cert
+            .tbs_certificate
+            .extensions
+            .unwrap()
+            .into_iter()
+            .find(|ext| ext.extn_id == ExtendedKeyUsage::OID)
+            .unwrap();
+        let usages = ExtendedKeyUsage::from_der(ext.extn_value.as_bytes()).unwrap();
+        (usages.0, ext.critical)
+    };
+
+    // Non-critical list of key purposes
+    let template = gen(&[SERVER_AUTH, CLIENT_AUTH], false).unwrap();
+    assert_eq!(eku(&template), (vec![SERVER_AUTH, CLIENT_AUTH], false));
+
+    // Critical
+    let template = gen(&[CLIENT_AUTH], true).unwrap();
+    assert_eq!(eku(&template), (vec![CLIENT_AUTH], true));
+
+    // anyExtendedKeyUsage alone
+    let template = gen(&[ANY_EXTENDED_KEY_USAGE_OID], false).unwrap();
+    assert_eq!(eku(&template), (vec![ANY_EXTENDED_KEY_USAGE_OID], false));
+
+    // anyExtendedKeyUsage must not be combined with other key purposes
+    assert!(matches!(
+        gen(&[SERVER_AUTH, ANY_EXTENDED_KEY_USAGE_OID], false),
+        Err(BuilderError::AnyExtendedKeyUsage { others: 1 })
+    ));
+    assert!(matches!(
+        gen(&[], false),
+        Err(BuilderError::EmptyExtendedKeyUsage)
+    ));
+}
+
+#[test]
+fn test_gen_cert_decodes_every_extension() {
+    use crate::cert_rustcrypto::{CertTemplateBuilder, Fwid, FwidParam};
+    use crate::keys::mldsa87_key_from_seed;
+    use crate::profile::{order_extensions, CALIPTRA_EXTENSION_ORDER};
+    use const_oid::{AssociatedOid, ObjectIdentifier};
+    use der::{Decode, Encode};
+    use ml_dsa::MlDsa87;
+    use sha2::Digest;
+    use std::borrow::Cow;
+    use x509_cert::ext::pkix::{
+        AuthorityKeyIdentifier, BasicConstraints, KeyUsage, SubjectKeyIdentifier,
+    };
+    use x509_cert::Certificate;
+
+    type Builder<'a> = CertTemplateBuilder<'a, ml_dsa::KeyPair<MlDsa87>>;
+
+    let fwids = [FwidParam {
+        name: "TCB_INFO_FMC_TCI",
+        fwid: Fwid {
+            hash_alg: ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.2"),
+            digest: Cow::Borrowed(&[0xCD; 48]),
+        },
+    }];
+    let builders = [
+        Builder::ldevid_profile(&[0xFF; 17]),
+        Builder::fmc_alias_profile(&[0xFF; 17], &[], &fwids),
+        Builder::rt_alias_profile(&[0xFF; 17], 0xC4, &fwids),
+    ];
+    for builder in builders {
+        let issuer_key = mldsa87_key_from_seed(&[0x02; 32]);
+        let issuer_spki =
+            spki::EncodePublicKey::to_public_key_der(issuer_key.verifying_key()).unwrap();
+        let template = builder
+            .set_subject_key(mldsa87_key_from_seed(&[0x01; 32]))
+            .set_issuer_key(issuer_key)
+            .tbs_template(
+                "Caliptra 2.0 MlDsa87 Subject",
+                "Caliptra 2.0 MlDsa87 Issuer",
+            );
+
+        // The certificate decodes and encodes back to the same DER
+        let cert = Certificate::from_der(template.der()).unwrap();
+        assert_eq!(cert.to_der().unwrap(), template.der());
+        let tbs = cert.tbs_certificate;
+        let mut extensions = tbs.extensions.clone().unwrap();
+
+        // Each extension appears once, in the Caliptra order
+        for (i, ext) in extensions.iter().enumerate() {
+            assert!(
+                extensions[..i].iter().all(|e| e.extn_id != ext.extn_id),
+                "Duplicate extension {}",
+                ext.extn_id
+            );
+        }
+        assert!(!order_extensions(&mut extensions, CALIPTRA_EXTENSION_ORDER));
+
+        // Each extnValue decodes as the type of its extnID
+        for ext in extensions.iter() {
+            let value = ext.extn_value.as_bytes();
+            match ext.extn_id {
+                oid if oid == BasicConstraints::OID => {
+                    let bc = BasicConstraints::from_der(value).unwrap();
+                    assert!(bc.ca);
+                }
+                oid if oid == KeyUsage::OID => {
+                    KeyUsage::from_der(value).unwrap();
+                }
+                oid if oid == SubjectKeyIdentifier::OID => {
+                    let ski = SubjectKeyIdentifier::from_der(value).unwrap();
+                    let public_key = tbs.subject_public_key_info.subject_public_key.raw_bytes();
+                    assert_eq!(ski.0.as_bytes(), sha1::Sha1::digest(public_key).as_slice());
+                }
+                oid if oid == AuthorityKeyIdentifier::OID => {
+                    let aki = AuthorityKeyIdentifier::from_der(value).unwrap();
+                    let issuer_spki =
+                        spki::SubjectPublicKeyInfoRef::from_der(issuer_spki.as_bytes()).unwrap();
+                    let issuer_key = issuer_spki.subject_public_key.raw_bytes();
+                    assert_eq!(
+                        aki.key_identifier.unwrap().as_bytes(),
+                        sha1::Sha1::digest(issuer_key).as_slice()
+                    );
+                }
+                oid => {
+                    let value = der::asn1::Any::from_der(value).unwrap();
+                    assert_eq!(
+                        value.to_der().unwrap(),
+                        ext.extn_value.as_bytes(),
+                        "{}",
+                        oid
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_gen_code_custom_header() {
+    use crate::code_gen::{CodeGen, CodeGenOptions};
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use ml_dsa::MlDsa87;
+
+    let out_dir = std::env::temp_dir().join("custom_header");
+    std::fs::create_dir_all(&out_dir).unwrap();
+
+    let template = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+
+    // Emit the template with a firmware specific header and import
+    let header = "// Custom header\n#![allow(clippy::all)]\n";
+    let options = CodeGenOptions::new()
+        .header(header)
+        .import("crate::x509::TbsParam");
+    CodeGen::gen_code_with_options(
+        "CustomHeaderCsrTbsMlDsa87",
+        template,
+        out_dir.to_str().unwrap(),
+        &options,
+    );
+
+    let code = std::fs::read_to_string(out_dir.join("custom_header_csr_tbs_ml_dsa87.rs")).unwrap();
+    assert!(code.starts_with(header));
+    assert!(code.contains("use crate::x509::TbsParam;"));
+    assert!(!code.contains("Regenerate the template"));
+}
+
+#[test]
+fn test_gen_code_output_layout() {
+    use crate::code_gen::{CodeGen, CodeGenOptions};
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use ml_dsa::MlDsa87;
+
+    // Start from a missing directory to check that the parents are created
+    let out_dir = std::env::temp_dir().join("output_layout").join("nested");
+    let _ = std::fs::remove_dir_all(&out_dir);
+
+    let template = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::idevid_profile(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    let options = CodeGenOptions::new()
+        .file_name("idevid.rs")
+        .subdir("csr")
+        .create_dir();
+    let path = CodeGen::gen_code_with_options(
+        "InitDevIdCsrTbsMlDsa87",
+        template,
+        out_dir.to_str().unwrap(),
+        &options,
+    );
+
+    assert_eq!(path, out_dir.join("csr").join("idevid.rs"));
+    let code = std::fs::read_to_string(&path).unwrap();
+    assert!(code.contains("pub struct InitDevIdCsrTbsMlDsa87"));
+}
+
+#[test]
+fn test_gen_code_feature_gate() {
+    use crate::code_gen::{template_feature, CodeGen, CodeGenOptions, ALL_TEMPLATES_FEATURE};
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use ml_dsa::MlDsa87;
+
+    let out_dir = std::env::temp_dir().join("feature_gate");
+    let _ = std::fs::remove_dir_all(&out_dir);
+
+    let options = CodeGenOptions::new().create_dir().feature_gate();
+    let type_names = ["InitDevIdCsrTbsMlDsa87", "UeidCsrTbsMlDsa87"];
+    let templates = [
+        CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::idevid_profile(&[0xFF; 17])
+            .tbs_template("Caliptra 2.0 MlDsa87 IDevID"),
+        CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+            .add_ueid_ext(&[0xFF; 17])
+            .tbs_template("Caliptra 2.0 MlDsa87 IDevID"),
+    ];
+    for (type_name, template) in type_names.iter().zip(templates) {
+        CodeGen::gen_code_with_options(type_name, template, out_dir.to_str().unwrap(), &options);
+    }
+    let path = CodeGen::gen_mod(&type_names, out_dir.to_str().unwrap(), &options);
+    assert_eq!(path, out_dir.join("mod.rs"));
+
+    // The module, its declaration and its registry entry share the gate
+    let aggregator = std::fs::read_to_string(&path).unwrap();
+    let features = std::fs::read_to_string(out_dir.join("features.toml.example")).unwrap();
+    for type_name in type_names {
+        let feature = template_feature(type_name);
+        let gate = format!("#[cfg(feature = \"{}\")]", feature);
+        let code = std::fs::read_to_string(out_dir.join(format!("{}.rs", feature))).unwrap();
+        assert!(code.starts_with(&format!("#![cfg(feature = \"{}\")]", feature)));
+        assert!(aggregator.contains(&format!("{}\npub mod {};", gate, feature)));
+        assert_eq!(aggregator.matches(&gate).count(), 2);
+        assert!(features.contains(&format!("\n{} = []\n", feature)));
+    }
+    assert!(features.contains(&format!(
+        "{} = [\"init_dev_id_csr_tbs_ml_dsa87\", \"ueid_csr_tbs_ml_dsa87\"]",
+        ALL_TEMPLATES_FEATURE
+    )));
+
+    // Without the gates, the aggregator only declares the modules
+    let path = CodeGen::gen_mod(
+        &type_names,
+        out_dir.to_str().unwrap(),
+        &CodeGenOptions::new(),
+    );
+    assert!(!std::fs::read_to_string(path).unwrap().contains("#[cfg("));
+}
+
+/// Extension OIDs expected in the Caliptra templates
+#[cfg(test)]
+const BC: &str = "2.5.29.19";
+#[cfg(test)]
+const KU: &str = "2.5.29.15";
+#[cfg(test)]
+const SKI: &str = "2.5.29.14";
+#[cfg(test)]
+const AKI: &str = "2.5.29.35";
+#[cfg(test)]
+const UEID: &str = "2.23.133.5.4.4";
+#[cfg(test)]
+const EKU: &str = "2.5.29.37";
+#[cfg(test)]
+const MULTI_TCB_INFO: &str = "2.23.133.5.4.5";
+#[cfg(test)]
+const TCB_INFO: &str = "2.23.133.5.4.1";
+
+/// Check that a certificate or CSR template has exactly the `expected` extensions
+#[cfg(test)]
+fn assert_extensions(template: &tbs::TbsTemplate, expected: &[&str]) {
+    let extensions = profile::der_extensions(template.der());
+    let mut oids: Vec<String> = extensions.iter().map(|e| e.extn_id.to_string()).collect();
+    let mut expected: Vec<String> = expected.iter().map(|oid| oid.to_string()).collect();
+    oids.sort();
+    expected.sort();
+    assert_eq!(oids, expected);
+}
+
+/// Decode a certificate template, substituting valid times for the validity placeholders
+#[cfg(test)]
+fn decode_cert_tbs(template: &tbs::TbsTemplate) -> x509_cert::certificate::TbsCertificate {
+    use der::Decode;
+
+    let mut tbs = template.tbs().to_vec();
+    for param in template.params() {
+        if param.name == "NOT_BEFORE" || param.name == "NOT_AFTER" {
+            let time: &[u8] = match param.len {
+                13 => b"230101000000Z",
+                _ => b"20230101000000Z",
+            };
+            tbs[param.offset..param.offset + param.len].copy_from_slice(time);
+        }
+    }
+    x509_cert::certificate::TbsCertificate::from_der(&tbs).unwrap()
+}
+
+/// Signature algorithms of the parameterized template tests
+#[cfg(test)]
+#[derive(Clone, Copy, Debug)]
+enum TestAlg {
+    MlDsa87,
+    HashMlDsa87,
+    Ed25519,
+    EcdsaP256,
+    EcdsaP384,
+    #[cfg(feature = "rsa")]
+    RsaSha384,
+}
+
+#[cfg(test)]
+impl TestAlg {
+    /// OID of the signature AlgorithmIdentifier
+    fn signature_oid(self) -> const_oid::ObjectIdentifier {
+        use const_oid::ObjectIdentifier;
+
+        match self {
+            TestAlg::MlDsa87 => ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.3.19"),
+            TestAlg::HashMlDsa87 => keys::HASH_ML_DSA_87_WITH_SHA512_OID,
+            TestAlg::Ed25519 => keys::ED25519_OID,
+            TestAlg::EcdsaP256 => ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.2"),
+            TestAlg::EcdsaP384 => ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.3"),
+            #[cfg(feature = "rsa")]
+            TestAlg::RsaSha384 => ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.12"),
+        }
+    }
+
+    /// Length of the PUBLIC_KEY param, the content of the subjectPublicKey BIT STRING
+    fn public_key_len(self) -> usize {
+        match self {
+            TestAlg::MlDsa87 | TestAlg::HashMlDsa87 => 2592,
+            TestAlg::Ed25519 => 32,
+            TestAlg::EcdsaP256 => 65,
+            TestAlg::EcdsaP384 => 97,
+            // RSAPublicKey of a 3072-bit modulus and the exponent 65537
+            #[cfg(feature = "rsa")]
+            TestAlg::RsaSha384 => 398,
+        }
+    }
+}
+
+/// Templates of the parameterized template tests
+#[cfg(test)]
+#[derive(Clone, Copy, Debug)]
+enum TestScenario {
+    IdevidCsr,
+    LdevidCert,
+    RtAliasCert,
+}
+
+#[cfg(test)]
+impl TestScenario {
+    /// Names of the params of the template, in any order
+    fn param_names(self) -> &'static [&'static str] {
+        match self {
+            TestScenario::IdevidCsr => &["UEID", "PUBLIC_KEY", "SUBJECT_SN"],
+            TestScenario::LdevidCert => &[
+                "UEID",
+                "NOT_BEFORE",
+                "NOT_AFTER",
+                "PUBLIC_KEY",
+                "SERIAL_NUMBER",
+                "SUBJECT_SN",
+                "ISSUER_SN",
+                "SUBJECT_KEY_ID",
+                "AUTHORITY_KEY_ID",
+            ],
+            TestScenario::RtAliasCert => &[
+                "UEID",
+                "tcb_info_fw_svn",
+                "TCB_INFO_RT_TCI",
+                "NOT_BEFORE",
+                "NOT_AFTER",
+                "PUBLIC_KEY",
+                "SERIAL_NUMBER",
+                "SUBJECT_SN",
+                "ISSUER_SN",
+                "SUBJECT_KEY_ID",
+                "AUTHORITY_KEY_ID",
+            ],
+        }
+    }
+}
+
+/// Generate the `scenario` template signed with `alg`
+///
+/// The keys are drawn from a ChaCha20 RNG seeded with `seed` and the validity is the fixed
+/// default of the builder, so the same arguments reproduce the template byte for byte.
+#[cfg(test)]
+fn generate_test_template(alg: TestAlg, scenario: TestScenario, seed: u64) -> tbs::TbsTemplate {
+    match alg {
+        TestAlg::MlDsa87 => {
+            generate_test_template_with::<ml_dsa::KeyPair<ml_dsa::MlDsa87>>(scenario, seed)
+        }
+        TestAlg::HashMlDsa87 => {
+            generate_test_template_with::<keys::HashMlDsa87KeyPair>(scenario, seed)
+        }
+        TestAlg::Ed25519 => generate_test_template_with::<keys::Ed25519KeyPair>(scenario, seed),
+        TestAlg::EcdsaP256 => {
+            generate_test_template_with::<p256::ecdsa::SigningKey>(scenario, seed)
+        }
+        TestAlg::EcdsaP384 => {
+            generate_test_template_with::<p384::ecdsa::SigningKey>(scenario, seed)
+        }
+        #[cfg(feature = "rsa")]
+        TestAlg::RsaSha384 => {
+            generate_test_template_with::<rsa::pkcs1v15::SigningKey<sha2::Sha384>>(scenario, seed)
+        }
+    }
+}
+
+#[cfg(test)]
+fn generate_test_template_with<Key>(scenario: TestScenario, seed: u64) -> tbs::TbsTemplate
+where
+    Key: keys::BuilderKeys
+        + spki::SignatureAlgorithmIdentifier
+        + signature::Keypair
+        + signature::Signer<<Key as keys::BuilderKeys>::Signature>,
+    Key::VerifyingKey: spki::EncodePublicKey,
+{
+    use crate::cert_rustcrypto::{CertTemplateBuilder, Fwid, FwidParam};
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use rand_chacha::rand_core::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    let rng = ChaCha20Rng::seed_from_u64(seed);
+    let rt_fwids = [FwidParam::new(
+        "TCB_INFO_RT_TCI",
+        Fwid::placeholder_sha384(0),
+    )];
+    match scenario {
+        TestScenario::IdevidCsr => CsrTemplateBuilder::<Key>::idevid_profile(&[0xFF; 17])
+            .set_rng(rng)
+            .tbs_template("Caliptra 2.0 Test IDevID"),
+        TestScenario::LdevidCert => CertTemplateBuilder::<Key>::ldevid_profile(&[0xFF; 17])
+            .set_rng(rng)
+            .tbs_template("Caliptra 2.0 Test LDevID", "Caliptra 2.0 Test IDevID"),
+        TestScenario::RtAliasCert => {
+            CertTemplateBuilder::<Key>::rt_alias_profile(&[0xFF; 17], 0xC4, &rt_fwids)
+                .set_rng(rng)
+                .tbs_template("Caliptra 2.0 Test RT Alias", "Caliptra 2.0 Test FMC Alias")
+        }
+    }
+}
+
+#[test]
+fn test_gen_hash_mldsa87_cert() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::keys::{HashMlDsa87KeyPair, HASH_ML_DSA_87_WITH_SHA512_OID};
+    use const_oid::ObjectIdentifier;
+    use ml_dsa::MlDsa87;
+
+    // Pure ML-DSA-87
+    let pure = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+
+    // HashML-DSA-87 with SHA-512
+    let prehash = CertTemplateBuilder::<HashMlDsa87KeyPair>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+
+    let pure_alg = decode_cert_tbs(&pure).signature;
+    let prehash_alg = decode_cert_tbs(&prehash).signature;
+    assert_eq!(
+        pure_alg.oid,
+        ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.3.19")
+    );
+    assert_eq!(prehash_alg.oid, HASH_ML_DSA_87_WITH_SHA512_OID);
+    assert_ne!(pure_alg.oid, prehash_alg.oid);
+    assert!(prehash_alg.parameters.is_none());
+}
+
+#[test]
+fn test_gen_prehash_signed_cert() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::keys::{mldsa87_key_from_seed, HashMlDsa87KeyPair, HASH_ML_DSA_87_WITH_SHA512_OID};
+    use der::Decode;
+    use sha2::{Digest, Sha512};
+
+    let issuer_key = || HashMlDsa87KeyPair::new(mldsa87_key_from_seed(&[0x02; 32]));
+    let template = CertTemplateBuilder::<HashMlDsa87KeyPair>::ldevid_profile(&[0xFF; 17])
+        .set_subject_key(HashMlDsa87KeyPair::new(mldsa87_key_from_seed(&[0x01; 32])))
+        .set_issuer_key(issuer_key())
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    let cert = x509_cert::Certificate::from_der(template.der()).unwrap();
+    assert_eq!(cert.signature_algorithm.oid, HASH_ML_DSA_87_WITH_SHA512_OID);
+    assert_eq!(
+        cert.tbs_certificate.signature.oid,
+        HASH_ML_DSA_87_WITH_SHA512_OID
+    );
+
+    // An HSM signing the SHA-512 digest of the TBS produces the template signature
+    let digest = HashMlDsa87KeyPair::<Sha512>::prehash(template.tbs());
+    assert_eq!(digest, Sha512::digest(template.tbs()).to_vec());
+    let signature = issuer_key().sign_prehashed(&digest);
+    assert_eq!(signature.encode().as_slice(), cert.signature.raw_bytes());
+    assert!(issuer_key().verify_prehashed(&digest, cert.signature.raw_bytes()));
+    assert!(!issuer_key().verify_prehashed(&[0; 64], cert.signature.raw_bytes()));
+}
+
+#[test]
+fn test_gen_name_constraints_cert_mldsa87() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use const_oid::AssociatedOid;
+    use der::{asn1::Ia5String, Decode};
+    use ml_dsa::MlDsa87;
+    use x509_cert::ext::pkix::{
+        constraints::name::GeneralSubtree, name::GeneralName, NameConstraints,
+    };
+
+    let permitted = [GeneralSubtree {
+        base: GeneralName::DnsName(Ia5String::new("caliptra.example").unwrap()),
+        minimum: 0,
+        maximum: None,
+    }];
+
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_basic_constraints_ext(true, 1)
+        .add_name_constraints_ext(&permitted, &[])
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+
+    let tbs = decode_cert_tbs(&template);
+    let ext = tbs
+        .extensions
+        .unwrap()
+        .into_iter()
+        .find(|ext| ext.extn_id == NameConstraints::OID)
+        .unwrap();
+    assert!(ext.critical);
+
+    let name_constraints = NameConstraints::from_der(ext.extn_value.as_bytes()).unwrap();
+    assert_eq!(name_constraints.permitted_subtrees.unwrap(), permitted);
+    assert!(name_constraints.excluded_subtrees.is_none());
+}
+
+#[test]
+fn test_mldsa87_algorithm_identifier_parameters_absent() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use der::Encode;
+    use ml_dsa::MlDsa87;
+
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    let tbs = decode_cert_tbs(&template);
+
+    // id-ml-dsa-87 without parameters: SEQUENCE { OBJECT IDENTIFIER }
+    let expected = [
+        0x30, 0x0B, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x03, 0x13,
+    ];
+    assert_eq!(tbs.signature.to_der().unwrap(), expected);
+    assert_eq!(
+        tbs.subject_public_key_info.algorithm.to_der().unwrap(),
+        expected
+    );
+}
+
+#[test]
+fn test_gen_spki_algorithm_param() {
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+
+    let template = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .add_spki_algorithm_param()
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+
+    let param = template
+        .params()
+        .iter()
+        .find(|p| p.name == "SPKI_ALGORITHM")
+        .unwrap();
+    let algorithm = spki::AlgorithmIdentifierOwned::from_der(
+        &template.tbs()[param.offset..param.offset + param.len],
+    )
+    .unwrap();
+    assert_eq!(
+        algorithm.oid,
+        const_oid::ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.3.19")
+    );
+    assert!(algorithm.parameters.is_none());
+
+    // Templates without the flag don't grow the param
+    let template = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    assert!(template.params().iter().all(|p| p.name != "SPKI_ALGORITHM"));
+}
+
+#[test]
+fn test_gen_subject_param() {
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+    use x509_cert::name::Name;
+
+    let template = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .add_subject_param(160)
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+
+    // The whole subject replaces the serialNumber param
+    assert!(template.params().iter().all(|p| p.name != "SUBJECT_SN"));
+    let param = template
+        .params()
+        .iter()
+        .find(|p| p.name == "SUBJECT")
+        .unwrap();
+    assert_eq!(param.len, 160);
+
+    let subject = Name::from_der(&template.tbs()[param.offset..param.offset + param.len]).unwrap();
+    assert!(subject
+        .to_string()
+        .contains("CN=Caliptra 2.0 MlDsa87 IDevID"));
+}
+
+#[test]
+fn test_gen_cert_subject_param() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::validate::BuilderError;
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+    use x509_cert::name::Name;
+
+    let builder = |len: usize| {
+        CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+            .add_ueid_ext(&[0xFF; 17])
+            .add_subject_param(len)
+            .try_tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID")
+    };
+
+    // The RDNs of the 27 byte CN and 64 byte serialNumber take 113 bytes, which the padding
+    // grows to 127 bytes under a 2 byte Name header
+    let template = builder(129).unwrap();
+    assert!(template.params().iter().all(|p| p.name != "SUBJECT_SN"));
+    let param = template
+        .params()
+        .iter()
+        .find(|p| p.name == "SUBJECT")
+        .unwrap();
+    assert_eq!(param.len, 129);
+    let subject = Name::from_der(&template.tbs()[param.offset..param.offset + param.len]).unwrap();
+    assert!(subject
+        .to_string()
+        .contains("CN=Caliptra 2.0 MlDsa87 LDevID"));
+
+    // The next padding step moves the Name length to its long form, skipping 130 bytes
+    match builder(130).unwrap_err() {
+        BuilderError::SubjectLength {
+            len: 130,
+            below: Some(129),
+            above,
+        } => assert!(above > 130),
+        err => panic!("Unexpected error {:?}", err),
+    }
+    match builder(20).unwrap_err() {
+        BuilderError::SubjectLength {
+            len: 20,
+            below: None,
+            above: 115,
+        } => {}
+        err => panic!("Unexpected error {:?}", err),
+    }
+}
+
+#[test]
+fn test_gen_opaque_subject() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::tbs::locate_element;
+    use crate::validate::{BuilderError, MIN_OPAQUE_SUBJECT_LEN};
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+    use x509_cert::name::Name;
+
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .set_opaque_subject(64)
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 RT Alias",
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+        );
+    let tbs = template.tbs();
+
+    // The SUBJECT param spans the whole subject Name of the TBSCertificate
+    let param = template
+        .params()
+        .iter()
+        .find(|p| p.name == "SUBJECT")
+        .unwrap();
+    assert_eq!((param.offset, param.len), locate_element(tbs, &[5]));
+    assert_eq!(param.len, 64);
+    assert!(template.params().iter().all(|p| p.name != "SUBJECT_SN"));
+
+    // The placeholder carries neither the subject CN nor a serialNumber
+    let subject = Name::from_der(&tbs[param.offset..param.offset + param.len]).unwrap();
+    assert!(!subject.to_string().contains("RT Alias"));
+    assert!(crate::name::name_serial_number(&subject).is_none());
+
+    // The minimal Name fits, anything shorter is rejected
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .set_opaque_subject(MIN_OPAQUE_SUBJECT_LEN)
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 RT Alias",
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+        );
+    let (_, len) = locate_element(template.tbs(), &[5]);
+    assert_eq!(len, MIN_OPAQUE_SUBJECT_LEN);
+
+    let err = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .set_opaque_subject(MIN_OPAQUE_SUBJECT_LEN - 1)
+        .try_tbs_template(
+            "Caliptra 2.0 MlDsa87 RT Alias",
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+        )
+        .unwrap_err();
+    assert_eq!(
+        err,
+        BuilderError::OpaqueSubjectLength {
+            len: MIN_OPAQUE_SUBJECT_LEN - 1
+        }
+    );
+
+    // The opaque subject has no CN to patch
+    let err = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .set_opaque_subject(64)
+        .add_subject_cn_param(32)
+        .try_tbs_template(
+            "Caliptra 2.0 MlDsa87 RT Alias",
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+        )
+        .unwrap_err();
+    assert_eq!(
+        err,
+        BuilderError::Conflict {
+            first: "SUBJECT_CN param",
+            second: "an opaque subject",
+        }
+    );
+}
+
+#[test]
+fn test_gen_subject_format_repeated_rdns() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::tbs::element_children;
+    use ml_dsa::MlDsa87;
+
+    const OU_OID: [u8; 3] = [0x55, 0x04, 0x0B];
+    const SERIAL_NUMBER_OID: [u8; 3] = [0x55, 0x04, 0x05];
+
+    // The serialNumber is between the OUs whichever the RDN order of the encoding
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .set_subject_format("OU=Caliptra,serialNumber={sn},OU=RT,CN={cn}")
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 RT Alias",
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+        );
+    let tbs = template.tbs();
+
+    // TBSCertificate: version, serialNumber, signature, issuer, validity, subject
+    let (subject, _) = element_children(tbs, 0)[5];
+    // Each RDN is a SET of one AttributeTypeAndValue SEQUENCE of the OID and value
+    let attributes: Vec<_> = element_children(tbs, subject)
+        .into_iter()
+        .map(|(set, _)| {
+            let (sequence, _) = element_children(tbs, set)[0];
+            let children = element_children(tbs, sequence);
+            let (oid, oid_len) = children[0];
+            (tbs[oid + 2..oid + oid_len].to_vec(), children[1])
+        })
+        .collect();
+    assert_eq!(attributes.len(), 4);
+    assert_eq!(
+        attributes.iter().filter(|(oid, _)| oid == &OU_OID).count(),
+        2
+    );
+    let sn_index = attributes
+        .iter()
+        .position(|(oid, _)| oid == &SERIAL_NUMBER_OID)
+        .unwrap();
+    assert_ne!(sn_index, attributes.len() - 1);
+
+    let param = template
+        .params()
+        .iter()
+        .find(|p| p.name == "SUBJECT_SN")
+        .unwrap();
+    let (value, value_len) = attributes[sn_index].1;
+    assert_eq!(param.offset, value + 2);
+    assert_eq!(param.len, value_len - 2);
+}
+
+#[test]
+fn test_placeholder_allocator() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::keys::mldsa87_key_from_seed;
+    use crate::measurement::SHA384_OID;
+    use crate::placeholder::PlaceholderAllocator;
+    use crate::tbs::{TbsTemplate, TemplateError};
+    use ml_dsa::MlDsa87;
+
+    let mut allocator = PlaceholderAllocator::new();
+    let ueid = allocator.allocate("UEID", 17);
+    // FWIDs of identical lengths, which couldn't share a fixed placeholder
+    let device_fwids: Vec<_> = [
+        "DEVICE_FWID_0",
+        "DEVICE_FWID_1",
+        "DEVICE_FWID_2",
+        "DEVICE_FWID_3",
+    ]
+    .into_iter()
+    .map(|name| allocator.fwid(name, SHA384_OID))
+    .collect();
+    let fmc_fwids: Vec<_> = [
+        "FMC_FWID_0",
+        "FMC_FWID_1",
+        "FMC_FWID_2",
+        "FMC_FWID_3",
+        "FMC_FWID_4",
+        "FMC_FWID_5",
+        "FMC_FWID_6",
+    ]
+    .into_iter()
+    .map(|name| allocator.fwid(name, SHA384_OID))
+    .collect();
+    assert_eq!(allocator.placeholders().len(), 12);
+    for (i, (_, a)) in allocator.placeholders().iter().enumerate() {
+        for (_, b) in allocator.placeholders()[i + 1..].iter() {
+            assert_ne!(a, b);
+        }
+    }
+
+    let builder = || {
+        CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::fmc_alias_profile(
+            &ueid,
+            &device_fwids,
+            &fmc_fwids,
+        )
+        .set_subject_key(mldsa87_key_from_seed(&[0x03; 32]))
+        .set_issuer_key(mldsa87_key_from_seed(&[0x02; 32]))
+    };
+    let template = builder()
+        .with_placeholder_allocator(allocator.clone())
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+            "Caliptra 2.0 MlDsa87 LDevID",
+        );
+    let der_region = |template: &TbsTemplate, name: &str| {
+        let param = template.params().iter().find(|p| p.name == name).unwrap();
+        let offset = template.tbs_offset() + param.offset;
+        template.der()[offset..offset + param.len].to_vec()
+    };
+
+    // Each pattern resolves to its own param
+    for &(name, ref placeholder) in allocator.placeholders() {
+        assert_eq!(&der_region(&template, name), placeholder, "{}", name);
+    }
+
+    // The needles of the params of the builder are allocated too, distinct from the patterns
+    // above, instead of the fixed serial number and the key identifiers derived from the keys
+    let plain = builder().tbs_template(
+        "Caliptra 2.0 MlDsa87 FMC Alias",
+        "Caliptra 2.0 MlDsa87 LDevID",
+    );
+    assert_eq!(der_region(&plain, "SERIAL_NUMBER"), [0x7F; 20]);
+    for name in ["SERIAL_NUMBER", "SUBJECT_KEY_ID", "AUTHORITY_KEY_ID"] {
+        let needle = der_region(&template, name);
+        assert_ne!(needle, der_region(&plain, name), "{}", name);
+        assert!(allocator.placeholders().iter().all(|(_, p)| *p != needle));
+    }
+    assert_eq!(der_region(&template, "SERIAL_NUMBER")[0] >> 4, 0x4);
+
+    // Patterns outside of a param are reported
+    let unparameterized = TbsTemplate::from_cert_der(template.der(), &[]).unwrap();
+    let ueid_param = template.params().iter().find(|p| p.name == "UEID").unwrap();
+    assert_eq!(
+        allocator.check(&unparameterized),
+        Err(TemplateError::PlaceholderCollision {
+            name: "UEID",
+            offset: ueid_param.offset
+        })
+    );
+}
+
+#[test]
+fn test_gen_no_serial_number_rdn() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::name::template_subject;
+    use ml_dsa::MlDsa87;
+
+    type Key = ml_dsa::KeyPair<MlDsa87>;
+
+    let ueid = [0xFF; 17];
+    let cert = CertTemplateBuilder::<Key>::ldevid_profile(&ueid)
+        .no_serial_number_rdn()
+        .tbs_template("Caliptra 2.0 MlDsa87 Test", "Caliptra 2.0 MlDsa87 IDevID");
+    let csr = CsrTemplateBuilder::<Key>::idevid_profile(&ueid)
+        .no_serial_number_rdn()
+        .tbs_template("Caliptra 2.0 MlDsa87 Test");
+
+    for template in [&cert, &csr] {
+        let subject = template_subject(template);
+        assert_eq!(subject.to_string(), "CN=Caliptra 2.0 MlDsa87 Test");
+        assert!(template.params().iter().all(|p| p.name != "SUBJECT_SN"));
+
+        // The other params are still located
+        let param = template.params().iter().find(|p| p.name == "UEID").unwrap();
+        let offset = template.tbs_offset() + param.offset;
+        assert_eq!(template.der()[offset..offset + param.len], ueid);
+    }
+    // The issuer keeps its serialNumber
+    assert!(cert.params().iter().any(|p| p.name == "ISSUER_SN"));
+}
+
+#[test]
+#[cfg(not(feature = "debug-output"))]
+fn test_gen_without_debug_output() {
+    use crate::csr_rustcrypto::{CsrTemplateBuilder, DEBUG_OUTPUT_FILE};
+    use ml_dsa::MlDsa87;
+
+    // Compare the content of the only file the feature writes rather than directory
+    // listings or timestamps, which other tests running in parallel may change. The CSR of a
+    // fresh key differs from any previous content.
+    let path = std::env::current_dir().unwrap().join(DEBUG_OUTPUT_FILE);
+    let before = std::fs::read(&path).ok();
+    let template = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+
+    // The CSR is not written
+    let after = std::fs::read(&path).ok();
+    assert_eq!(before, after);
+    assert_ne!(after.as_deref(), Some(template.der()));
+}
+
+#[test]
+fn test_gen_subject_cn_param() {
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use const_oid::ObjectIdentifier;
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+    use x509_cert::request::CertReqInfo;
+
+    let template = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .add_subject_cn_param(48)
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+
+    let param = template
+        .params()
+        .iter()
+        .find(|p| p.name == "SUBJECT_CN")
+        .unwrap();
+    assert_eq!(param.len, 48);
+
+    // Patch a space padded product name into the reserved CN
+    let cn = format!("{:<48}", "Caliptra 2.1 MlDsa87 Product IDevID");
+    let mut tbs = template.tbs().to_vec();
+    tbs[param.offset..param.offset + param.len].copy_from_slice(cn.as_bytes());
+
+    let info = CertReqInfo::from_der(&tbs).unwrap();
+    let attr = info
+        .subject
+        .iter_rdn()
+        .flat_map(|rdn| rdn.iter())
+        .find(|attr| attr.oid == ObjectIdentifier::new_unwrap("2.5.4.3"))
+        .unwrap();
+    assert_eq!(attr.value.value(), cn.as_bytes());
+}
+
+#[test]
+fn test_gen_subject_cn_placeholder() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::name::{cn_placeholder, SUBJECT_CN_SENTINEL};
+    use const_oid::ObjectIdentifier;
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+    use x509_cert::Certificate;
+
+    let subject_cn = "Caliptra 2.0 MlDsa87 LDevID";
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .add_subject_cn_param(48)
+        .tbs_template(subject_cn, "Caliptra 2.0 MlDsa87 IDevID");
+
+    let param = template
+        .params()
+        .iter()
+        .find(|p| p.name == "SUBJECT_CN")
+        .unwrap();
+    assert_eq!(param.len, 48);
+
+    // The generation-time CN, padded to the reservation, sits at the param offset
+    let placeholder = cn_placeholder(subject_cn, SUBJECT_CN_SENTINEL, 48);
+    let offset = template.tbs_offset() + param.offset;
+    let value = &template.der()[offset..offset + param.len];
+    assert_eq!(std::str::from_utf8(value).unwrap(), placeholder);
+
+    let cert = Certificate::from_der(template.der()).unwrap();
+    let attr = cert
+        .tbs_certificate
+        .subject
+        .iter_rdn()
+        .flat_map(|rdn| rdn.iter())
+        .find(|attr| attr.oid == ObjectIdentifier::new_unwrap("2.5.4.3"))
+        .unwrap();
+    assert_eq!(attr.value.value(), placeholder.as_bytes());
+}
+
+#[test]
+fn test_gen_issuer_and_subject_cn_params() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::tbs::TbsTemplate;
+    use crate::validate::BuilderError;
+    use const_oid::ObjectIdentifier;
+    use ml_dsa::MlDsa87;
+    use x509_cert::name::Name;
+
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .add_subject_cn_param(40)
+        .add_issuer_cn_param(40)
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+
+    // Patch distinct runtime values into both CNs
+    let subject_cn = format!("{:<40}", "Vendor MlDsa87 LDevID");
+    let issuer_cn = format!("{:<40}", "Vendor MlDsa87 IDevID");
+    let mut tbs = template.tbs().to_vec();
+    for (name, value) in [("SUBJECT_CN", &subject_cn), ("ISSUER_CN", &issuer_cn)] {
+        let param = template.params().iter().find(|p| p.name == name).unwrap();
+        tbs[param.offset..param.offset + param.len].copy_from_slice(value.as_bytes());
+    }
+
+    let cn = |name: &Name| {
+        name.iter_rdn()
+            .flat_map(|rdn| rdn.iter())
+            .find(|attr| attr.oid == ObjectIdentifier::new_unwrap("2.5.4.3"))
+            .unwrap()
+            .value
+            .value()
+            .to_vec()
+    };
+    let tbs = decode_cert_tbs(&TbsTemplate::new(tbs, template.params().to_vec()));
+    assert_eq!(cn(&tbs.subject), subject_cn.as_bytes());
+    assert_eq!(cn(&tbs.issuer), issuer_cn.as_bytes());
+
+    // Equal CNs get distinct placeholders through their sentinels
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .add_subject_cn_param(28)
+        .add_issuer_cn_param(28)
+        .tbs_template("Caliptra 2.0 MlDsa87 Device", "Caliptra 2.0 MlDsa87 Device");
+    let offset = |name: &str| {
+        template
+            .params()
+            .iter()
+            .find(|p| p.name == name)
+            .unwrap()
+            .offset
+    };
+    assert_ne!(offset("SUBJECT_CN"), offset("ISSUER_CN"));
+
+    // A CN filling its reservation would leave no sentinel to tell the placeholders apart
+    for (subject_len, issuer_len, name) in [(27, 28, "Subject CN"), (28, 27, "Issuer CN")] {
+        let err = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+            .add_subject_cn_param(subject_len)
+            .add_issuer_cn_param(issuer_len)
+            .try_tbs_template("Caliptra 2.0 MlDsa87 Device", "Caliptra 2.0 MlDsa87 Device")
+            .unwrap_err();
+        assert_eq!(
+            err,
+            BuilderError::CnReservation {
+                name,
+                cn_len: 27,
+                len: 27
+            }
+        );
+    }
+}
+
+#[test]
+fn test_params_in_der() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use ml_dsa::MlDsa87;
+
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+
+    // The certificate SEQUENCE header uses a two byte length
+    assert_eq!(template.tbs_offset(), 4);
+
+    let ueid = |params: &[crate::tbs::TbsParam]| *params.iter().find(|p| p.name == "UEID").unwrap();
+    let tbs_param = ueid(template.params());
+    let der_param = ueid(&template.params_in_der().unwrap());
+    assert_eq!(der_param.offset, tbs_param.offset + 4);
+    assert_eq!(der_param.len, tbs_param.len);
+
+    // The TBS offset of a template without its DER is unknown
+    let tbs = crate::tbs::TbsTemplate::new(template.tbs().to_vec(), template.params().to_vec());
+    assert!(tbs.params_in_der().is_none());
+}
+
+#[test]
+fn test_gen_ed25519_csr() {
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::keys::{Ed25519KeyPair, ED25519_OID};
+    use der::Decode;
+
+    let template = CsrTemplateBuilder::<Ed25519KeyPair>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .add_spki_algorithm_param()
+        .tbs_template("Caliptra 2.0 Ed25519 IDevID");
+
+    let param = |name: &str| *template.params().iter().find(|p| p.name == name).unwrap();
+    let spki_algorithm = param("SPKI_ALGORITHM");
+    let algorithm = spki::AlgorithmIdentifierOwned::from_der(
+        &template.tbs()[spki_algorithm.offset..spki_algorithm.offset + spki_algorithm.len],
+    )
+    .unwrap();
+    assert_eq!(algorithm.oid, ED25519_OID);
+    assert!(algorithm.parameters.is_none());
+    assert_eq!(param("PUBLIC_KEY").len, 32);
+}
+
+#[test]
+fn test_gen_slh_dsa_cert() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::keys::{SlhDsaSha2_128fKeyPair, SLH_DSA_SHA2_128F_OID};
+    use der::Decode;
+
+    // FIPS 205 Table 2
+    const SLH_DSA_SHA2_128F_SIGNATURE_LEN: usize = 17088;
+
+    let template = CertTemplateBuilder::<SlhDsaSha2_128fKeyPair>::ldevid_profile(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 SlhDsa LDevID", "Caliptra 2.0 SlhDsa IDevID");
+
+    let cert = x509_cert::Certificate::from_der(template.der()).unwrap();
+    assert_eq!(cert.signature_algorithm.oid, SLH_DSA_SHA2_128F_OID);
+    assert!(cert.signature_algorithm.parameters.is_none());
+    let spki = &cert.tbs_certificate.subject_public_key_info;
+    assert_eq!(spki.algorithm.oid, SLH_DSA_SHA2_128F_OID);
+    assert!(spki.algorithm.parameters.is_none());
+    let signature = cert.signature.raw_bytes();
+    assert_eq!(signature.len(), SLH_DSA_SHA2_128F_SIGNATURE_LEN);
+
+    // The signature makes up most of the DER
+    assert!(template.der().len() > 4 * template.tbs().len());
+    let tbs = der::Encode::to_der(&cert.tbs_certificate).unwrap();
+    let key =
+        slh_dsa::VerifyingKey::<slh_dsa::Sha2_128f>::try_from(spki.subject_public_key.raw_bytes())
+            .unwrap();
+    let signature = slh_dsa::Signature::<slh_dsa::Sha2_128f>::try_from(signature).unwrap();
+    assert!(signature::Verifier::verify(&key, &tbs, &signature).is_ok());
+
+    let param = *template
+        .params()
+        .iter()
+        .find(|p| p.name == "PUBLIC_KEY")
+        .unwrap();
+    assert_eq!(param.len, 32);
+}
+
+#[test]
+fn test_gen_validity_time_encoding() {
+    use crate::cert_rustcrypto::{CertTemplateBuilder, TimeEncoding};
+    use der::{DateTime, Tag};
+    use ml_dsa::MlDsa87;
+
+    let not_before = DateTime::new(2023, 1, 1, 0, 0, 0).unwrap();
+    let before_2050 = DateTime::new(2049, 12, 31, 23, 59, 59).unwrap();
+    let after_2050 = DateTime::new(2051, 1, 1, 0, 0, 0).unwrap();
+    let infinity = DateTime::INFINITY;
+    let (utc, gen) = (Tag::UtcTime, Tag::GeneralizedTime);
+    let cases = [
+        (TimeEncoding::UtcTime, before_2050, [utc, utc]),
+        (TimeEncoding::GeneralizedTime, after_2050, [gen, gen]),
+        (TimeEncoding::GeneralizedTime, infinity, [gen, gen]),
+        (TimeEncoding::Rfc5280, after_2050, [utc, gen]),
+    ];
+    for (encoding, not_after, tags) in cases {
+        let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+            .add_ueid_ext(&[0xFF; 17])
+            .set_time_encoding(encoding)
+            .set_validity(not_before, not_after)
+            .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+
+        for (name, tag) in ["NOT_BEFORE", "NOT_AFTER"].into_iter().zip(tags) {
+            let param = template.params().iter().find(|p| p.name == name).unwrap();
+            let len = match tag {
+                Tag::UtcTime => 13,
+                _ => 15,
+            };
+            // The tag and short form length precede the time
+            let header = &template.tbs()[param.offset - 2..param.offset];
+            assert_eq!(header, [tag.into(), len as u8], "{:?} {}", encoding, name);
+            assert_eq!(param.len, len, "{:?} {}", encoding, name);
+        }
+    }
+}
+
+#[test]
+fn test_gen_derived_serial_number() {
+    use crate::cert_rustcrypto::{derive_serial_number, CertTemplateBuilder, SerialNumberDigest};
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+    use x509_cert::Certificate;
+
+    let cases = [
+        (SerialNumberDigest::Sha256, 20),
+        (SerialNumberDigest::Sha384, 16),
+    ];
+    for (digest, len) in cases {
+        let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+            .add_ueid_ext(&[0xFF; 17])
+            .add_derived_serial_number(digest, len)
+            .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+
+        // Recompute the serial over the generation-time subject key
+        let cert = Certificate::from_der(template.der()).unwrap();
+        let tbs = cert.tbs_certificate;
+        let public_key = tbs.subject_public_key_info.subject_public_key.raw_bytes();
+        let serial_number = derive_serial_number(digest, public_key, len);
+        assert_eq!(serial_number[0] >> 4, 0x4);
+        assert_eq!(tbs.serial_number.as_bytes(), serial_number);
+
+        let param = template
+            .params()
+            .iter()
+            .find(|p| p.name == "SERIAL_NUMBER")
+            .unwrap();
+        assert_eq!(param.len, len);
+    }
+}
+
+#[test]
+fn test_gen_sn_hash_variants() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::keys::mldsa87_key_from_seed;
+    use crate::name::{key_bytes_hash_with, SnHash};
+    use crate::validate::BuilderError;
+    use ml_dsa::MlDsa87;
+
+    let templates = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .set_subject_key(mldsa87_key_from_seed(&[0x01; 32]))
+        .set_issuer_key(mldsa87_key_from_seed(&[0x02; 32]))
+        .tbs_template_sn_hash_variants(
+            "Caliptra 2.0 MlDsa87 LDevID",
+            "Caliptra 2.0 MlDsa87 IDevID",
+            &[SnHash::Sha256, SnHash::Sha384],
+        );
+    assert_eq!(templates.len(), 2);
+
+    let mut public_keys = Vec::new();
+    for ((hash, template), len) in templates.iter().zip([64, 96]) {
+        let param = |name: &str| {
+            let param = template
+                .params_in_der()
+                .unwrap()
+                .into_iter()
+                .find(|p| p.name == name)
+                .unwrap();
+            template.der()[param.offset..param.offset + param.len].to_vec()
+        };
+        let public_key = param("PUBLIC_KEY");
+        let subject_sn = param("SUBJECT_SN");
+        assert_eq!(subject_sn.len(), len);
+        assert_eq!(param("ISSUER_SN").len(), len);
+        assert_eq!(
+            subject_sn,
+            key_bytes_hash_with(*hash, &public_key).into_bytes()
+        );
+        public_keys.push(public_key);
+    }
+
+    // Both variants share the subject key
+    assert_eq!(public_keys[0], public_keys[1]);
+
+    let err = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[])
+        .try_tbs_template_sn_hash_variants(
+            "Caliptra 2.0 MlDsa87 LDevID",
+            "Caliptra 2.0 MlDsa87 IDevID",
+            &[SnHash::Sha256, SnHash::Sha384],
+        )
+        .unwrap_err();
+    assert_eq!(err, BuilderError::UeidLength { len: 0 });
+}
+
+#[test]
+fn test_gen_subject_key_id_derivation() {
+    use crate::cert_rustcrypto::{derive_subject_key_id, CertTemplateBuilder, SkiDerivation};
+    use crate::keys::mldsa87_key_from_seed;
+    use ml_dsa::MlDsa87;
+    use sha2::{Digest, Sha256};
+    use signature::Keypair;
+
+    let issuer_key = mldsa87_key_from_seed(&[0x02; 32]);
+    let issuer_public_key = issuer_key.verifying_key().encode();
+
+    let cases = [
+        (SkiDerivation::Sha1, 20),
+        (SkiDerivation::Sha256Truncated160, 20),
+        (SkiDerivation::Sha256, 32),
+    ];
+    for (derivation, len) in cases {
+        let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+            .add_ueid_ext(&[0xFF; 17])
+            .set_issuer_key(mldsa87_key_from_seed(&[0x02; 32]))
+            .set_subject_key_id_derivation(derivation)
+            .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+
+        let param = |name: &str| {
+            let param = template
+                .params_in_der()
+                .unwrap()
+                .into_iter()
+                .find(|p| p.name == name)
+                .unwrap();
+            template.der()[param.offset..param.offset + param.len].to_vec()
+        };
+        let public_key = param("PUBLIC_KEY");
+        let subject_key_id = param("SUBJECT_KEY_ID");
+        assert_eq!(subject_key_id.len(), len);
+        assert_eq!(
+            subject_key_id,
+            derive_subject_key_id(derivation, &public_key)
+        );
+        if derivation == SkiDerivation::Sha256Truncated160 {
+            assert_eq!(subject_key_id, Sha256::digest(&public_key)[..20]);
+        }
+
+        // The issuer key identifier is derived the same way
+        assert_eq!(
+            param("AUTHORITY_KEY_ID"),
+            derive_subject_key_id(derivation, &issuer_public_key)
+        );
+    }
+}
+
+#[test]
+fn test_gen_auth_key_id() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::keys::mldsa87_key_from_seed;
+    use const_oid::AssociatedOid;
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+    use sha2::Digest;
+    use signature::Keypair;
+    use x509_cert::ext::pkix::AuthorityKeyIdentifier;
+    use x509_cert::Certificate;
+
+    let gen = |key_id: Option<&[u8]>| {
+        CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+            .add_ueid_ext(&[0xFF; 17])
+            .set_issuer_key(mldsa87_key_from_seed(&[0x02; 32]))
+            .add_auth_key_id_ext(key_id)
+            .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID")
+    };
+    let key_id = |template: &tbs::TbsTemplate| {
+        let cert = Certificate::from_der(template.der()).unwrap();
+        let ext = cert
+            .tbs_certificate
+            .extensions
+            .unwrap()
+            .into_iter()
+            .find(|ext| ext.extn_id == AuthorityKeyIdentifier::OID)
+            .unwrap();
+        let aki = AuthorityKeyIdentifier::from_der(ext.extn_value.as_bytes()).unwrap();
+        aki.key_identifier.unwrap().as_bytes().to_vec()
+    };
+    let param = |template: &tbs::TbsTemplate| {
+        template
+            .params()
+            .iter()
+            .find(|p| p.name == "AUTHORITY_KEY_ID")
+            .copied()
+    };
+
+    // Baked in
+    let template = gen(Some(&[0xA5; 20]));
+    assert!(param(&template).is_none());
+    assert_eq!(key_id(&template), [0xA5; 20]);
+    let tbs = template.tbs();
+    assert!(tbs.windows(20).any(|w| w == [0xA5; 20]));
+
+    // Substituted at runtime, with the SHA-1 of the issuer key as generation-time value
+    let template = gen(None);
+    let param = param(&template).unwrap();
+    assert_eq!(param.len, 20);
+    let issuer_key = mldsa87_key_from_seed(&[0x02; 32]).verifying_key().encode();
+    assert_eq!(
+        key_id(&template),
+        sha1::Sha1::digest(issuer_key.as_slice()).as_slice()
+    );
+    assert!(template.tbs()[param.offset..param.offset + param.len]
+        .iter()
+        .all(|&b| b == 0x5F));
+}
+
+#[test]
+fn test_gen_auth_key_id_cert_issuer() {
+    use crate::cert_rustcrypto::{CertTemplateBuilder, AUTHORITY_CERT_SERIAL_PARAM};
+    use crate::tbs::{get_tbs, TbsTemplate};
+    use crate::validate::BuilderError;
+    use const_oid::AssociatedOid;
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+    use std::str::FromStr;
+    use x509_cert::ext::pkix::name::GeneralName;
+    use x509_cert::ext::pkix::AuthorityKeyIdentifier;
+    use x509_cert::name::Name;
+    use x509_cert::TbsCertificate;
+
+    let aki = |tbs: TbsCertificate| {
+        let ext = tbs
+            .extensions
+            .unwrap()
+            .into_iter()
+            .find(|ext| ext.extn_id == AuthorityKeyIdentifier::OID)
+            .unwrap();
+        AuthorityKeyIdentifier::from_der(ext.extn_value.as_bytes()).unwrap()
+    };
+
+    let issuer = [GeneralName::DirectoryName(
+        Name::from_str("CN=Caliptra 2.0 MlDsa87 Cross Root").unwrap(),
+    )];
+    // A one byte serial occurs elsewhere in the TBS, so the param isn't found by its value
+    let serial = [0x01];
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .add_auth_key_id_ext(Some(&[0xA5; 20]))
+        .add_auth_key_id_cert_issuer(&issuer, serial.len())
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+
+    // The serial is a param at the end of the AuthorityKeyIdentifier, the issuer names are
+    // fixed
+    let param = template
+        .params()
+        .iter()
+        .find(|p| p.name == AUTHORITY_CERT_SERIAL_PARAM)
+        .copied()
+        .unwrap();
+    assert_eq!(param.len, serial.len());
+    assert_eq!(template.tbs()[param.offset - 2..param.offset], [0x82, 0x01]);
+
+    // The keyIdentifier, authorityCertIssuer and patched authorityCertSerialNumber decode
+    // back
+    let patched = template
+        .apply(&[(AUTHORITY_CERT_SERIAL_PARAM, &serial)])
+        .unwrap();
+    let patched = TbsTemplate::new(patched, template.params().to_vec());
+    let patched_aki = aki(decode_cert_tbs(&patched));
+    assert_eq!(patched_aki.key_identifier.unwrap().as_bytes(), [0xA5; 20]);
+    assert_eq!(patched_aki.authority_cert_issuer.unwrap(), issuer);
+    assert_eq!(
+        patched_aki.authority_cert_serial_number.unwrap().as_bytes(),
+        serial
+    );
+
+    // The generated DER holds a sentinel serial of the same length
+    let tbs = get_tbs(template.der()).unwrap();
+    let sentinel = aki(TbsCertificate::from_der(tbs).unwrap())
+        .authority_cert_serial_number
+        .unwrap();
+    assert_eq!(sentinel.as_bytes().len(), serial.len());
+    assert_ne!(sentinel.as_bytes(), serial);
+
+    let err = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_auth_key_id_cert_issuer(&issuer, 21)
+        .try_tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID")
+        .unwrap_err();
+    assert_eq!(err, BuilderError::SerialNumberLength { len: 21 });
+}
+
+#[test]
+fn test_apply_serial_number_convention() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::code_gen::{CodeGen, CodeGenOptions};
+    use ml_dsa::MlDsa87;
+
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+
+    let mut leading_zeros = [0x5A; 20];
+    leading_zeros[..3].fill(0);
+    let cases = [[0x80; 20], leading_zeros, [0xFF; 20]];
+    for serial in cases {
+        let tbs = template.apply(&[("SERIAL_NUMBER", &serial)]).unwrap();
+        let patched = tbs::TbsTemplate::new(tbs, template.params().to_vec());
+        let serial_number = decode_cert_tbs(&patched).serial_number;
+
+        // Positive and minimally encoded in the reserved width
+        let mut expected = serial;
+        expected[0] = 0x40 | (serial[0] & 0x0F);
+        assert_eq!(serial_number.as_bytes(), expected);
+        assert!(serial_number.as_bytes()[0] < 0x80);
+        assert_ne!(serial_number.as_bytes()[0], 0);
+    }
+
+    let code = CodeGen::code(
+        "LocalDevIdCertTbsMlDsa87",
+        template,
+        &CodeGenOptions::default(),
+    );
+    assert!(code.contains("fn set_serial"));
+}
+
+#[test]
+fn test_gen_rt_tcb_info_svn_width() {
+    use crate::cert_rustcrypto::{
+        CertTemplateBuilder, Fwid, FwidParam, FMC_SVN_PARAM_LEN, SVN_PARAM_LEN,
+    };
+    use const_oid::ObjectIdentifier;
+    use ml_dsa::MlDsa87;
+    use std::borrow::Cow;
+
+    let rt_fwids = [FwidParam {
+        name: "TCB_INFO_RT_TCI",
+        fwid: Fwid {
+            hash_alg: ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.2"),
+            digest: Cow::Borrowed(&[0xCD; 48]),
+        },
+    }];
+
+    // Small SVNs and SVNs repeating the marker byte occur elsewhere in the TBS
+    for svn in [0xC4_u32, 0x1_0000, 0x01, 0x0101_0101] {
+        let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+            .add_ueid_ext(&[0xFF; 17])
+            .add_rt_dice_tcb_info_ext(svn, &rt_fwids, true)
+            .tbs_template(
+                "Caliptra 2.0 MlDsa87 RT Alias",
+                "Caliptra 2.0 MlDsa87 FMC Alias",
+            );
+
+        let params = template.params_in_der().unwrap();
+        let param = params.iter().find(|p| p.name == "tcb_info_fw_svn").unwrap();
+        assert_eq!(param.len, SVN_PARAM_LEN);
+
+        // [3] IMPLICIT INTEGER with the marker byte and the big-endian SVN
+        let der = template.der();
+        assert_eq!(der[param.offset - 3..param.offset], [0x83, 0x05, 0x01]);
+        assert_eq!(
+            der[param.offset..param.offset + param.len],
+            svn.to_be_bytes()
+        );
+    }
+
+    // The FMC SVN params keep their 1 byte width
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .add_fmc_dice_tcb_info_ext(&rt_fwids[..0], &rt_fwids, true)
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+            "Caliptra 2.0 MlDsa87 LDevID",
+        );
+    for name in ["tcb_info_fw_svn", "tcb_info_fw_svn_fuses"] {
+        let params = template.params_in_der().unwrap();
+        let param = params.iter().find(|p| p.name == name).unwrap();
+        assert_eq!(param.len, FMC_SVN_PARAM_LEN);
+        assert_eq!(
+            template.der()[param.offset - 3..param.offset],
+            [0x83, 0x02, 0x01]
+        );
+    }
+}
+
+#[cfg(feature = "rsa")]
+#[test]
+fn test_gen_rsa_cert() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use der::Decode;
+    use rsa::pkcs1::DecodeRsaPublicKey;
+    use rsa::traits::PublicKeyParts;
+    use sha2::Sha384;
+    use x509_cert::Certificate;
+
+    type Key = rsa::pkcs1v15::SigningKey<Sha384>;
+
+    // Inject a pre-generated 4096-bit subject key, the issuer key uses the default size
+    let mut rng = rand::thread_rng();
+    let subject_key = Key::new(rsa::RsaPrivateKey::new(&mut rng, 4096).unwrap());
+
+    let template = CertTemplateBuilder::<Key>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .set_subject_key(subject_key)
+        .tbs_template("Caliptra 2.0 Rsa LDevID", "Caliptra 2.0 Rsa IDevID");
+
+    let cert = Certificate::from_der(template.der()).unwrap();
+    // sha384WithRSAEncryption
+    assert_eq!(
+        cert.signature_algorithm.oid,
+        const_oid::ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.12")
+    );
+    assert!(cert.signature_algorithm.parameters.unwrap().is_null());
+
+    // PUBLIC_KEY covers the RSAPublicKey inside the SPKI BIT STRING
+    let rsa_public_key = cert
+        .tbs_certificate
+        .subject_public_key_info
+        .subject_public_key
+        .raw_bytes();
+    let public_key = rsa::RsaPublicKey::from_pkcs1_der(rsa_public_key).unwrap();
+    assert_eq!(public_key.size(), 512);
+    let param = template
+        .params()
+        .iter()
+        .find(|p| p.name == "PUBLIC_KEY")
+        .unwrap();
+    assert_eq!(param.len, rsa_public_key.len());
+}
+
+#[test]
+fn test_gen_self_signed_root() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::keys::{mldsa87_key_from_seed, BuilderKeys};
+    use crate::tbs::{get_tbs, TbsParam};
+    use crate::validate::BuilderError;
+    use der::{Decode, Encode};
+    use ml_dsa::MlDsa87;
+    use x509_cert::Certificate;
+
+    let key = mldsa87_key_from_seed(&[0x01; 32]);
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+        .set_subject_key(mldsa87_key_from_seed(&[0x01; 32]))
+        .self_signed("Caliptra 2.0 MlDsa87 Root");
+
+    let cert = Certificate::from_der(template.der()).unwrap();
+    let tbs = &cert.tbs_certificate;
+    assert_eq!(tbs.issuer.to_der().unwrap(), tbs.subject.to_der().unwrap());
+
+    // Signed by the subject key
+    let signature = cert.signature.raw_bytes();
+    assert!(key.verify(get_tbs(template.der()).unwrap(), signature));
+
+    // ISSUER_SN covers the issuer Name, which precedes the subject Name
+    let param = |name: &str| *template.params().iter().find(|p| p.name == name).unwrap();
+    let (issuer_sn, subject_sn) = (param("ISSUER_SN"), param("SUBJECT_SN"));
+    assert_eq!(issuer_sn.len, subject_sn.len);
+    assert!(issuer_sn.offset < subject_sn.offset);
+    let der_tbs = get_tbs(template.der()).unwrap();
+    let value = |p: TbsParam| &der_tbs[p.offset..p.offset + p.len];
+    assert_eq!(value(issuer_sn), value(subject_sn));
+
+    // The key identifiers of a self-signed certificate are equal
+    assert_eq!(
+        value(param("AUTHORITY_KEY_ID")),
+        value(param("SUBJECT_KEY_ID"))
+    );
+
+    // A self-signed template has neither a parent nor CN params
+    let err = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::child_of(&template)
+        .try_self_signed("Caliptra 2.0 MlDsa87 Root")
+        .unwrap_err();
+    assert!(matches!(err, BuilderError::Conflict { .. }));
+    let err = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_subject_cn_param(32)
+        .try_self_signed("Caliptra 2.0 MlDsa87 Root")
+        .unwrap_err();
+    assert_eq!(
+        err,
+        BuilderError::Conflict {
+            first: "CN params",
+            second: "a self-signed template",
+        }
+    );
+}
+
+#[test]
+fn test_gen_child_of_idevid() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+    use x509_cert::request::CertReq;
+    use x509_cert::Certificate;
+
+    let idevid = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    let ldevid = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::child_of(&idevid)
+        .add_ueid_ext(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+
+    // The issuer is the IDevID subject
+    let idevid_subject = CertReq::from_der(idevid.der()).unwrap().info.subject;
+    let cert = Certificate::from_der(ldevid.der()).unwrap();
+    assert_eq!(cert.tbs_certificate.issuer, idevid_subject);
+
+    for name in ["ISSUER_SN", "AUTHORITY_KEY_ID"] {
+        assert!(ldevid.params().iter().any(|p| p.name == name), "{}", name);
+    }
+}
+
+#[test]
+fn test_gen_child_of_cert() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::tbs::get_tbs;
+    use crate::validate::BuilderError;
+    use ml_dsa::MlDsa87;
+
+    let ldevid = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    let fmc_alias = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::child_of(&ldevid)
+        .add_ueid_ext(&[0xFF; 17])
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+            "Caliptra 2.0 MlDsa87 LDevID",
+        );
+
+    // The AuthorityKeyIdentifier of the child is the SubjectKeyIdentifier of the parent
+    let value = |template: &crate::tbs::TbsTemplate, name: &str| {
+        let param = *template.params().iter().find(|p| p.name == name).unwrap();
+        get_tbs(template.der()).unwrap()[param.offset..param.offset + param.len].to_vec()
+    };
+    assert_eq!(
+        value(&fmc_alias, "AUTHORITY_KEY_ID"),
+        value(&ldevid, "SUBJECT_KEY_ID")
+    );
+
+    // The issuer CN is the CN of the parent subject
+    let err = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::child_of(&ldevid)
+        .try_tbs_template("Caliptra 2.0 MlDsa87 FMC Alias", "Other")
+        .unwrap_err();
+    assert_eq!(
+        err,
+        BuilderError::IssuerCn {
+            cn: "Other".to_string(),
+            parent_cn: "Caliptra 2.0 MlDsa87 LDevID".to_string(),
+        }
+    );
+}
+
+#[test]
+fn test_tbs_param_empty_name() {
+    use crate::tbs::{TbsParam, TemplateError};
+
+    assert_eq!(
+        TbsParam::try_new("", 0, 4).unwrap_err(),
+        TemplateError::InvalidParam { name: "", len: 4 }
+    );
+}
+
+#[test]
+fn test_tbs_param_zero_length() {
+    use crate::tbs::{TbsParam, TemplateError};
+
+    assert_eq!(
+        TbsParam::try_new("UEID", 0, 0).unwrap_err(),
+        TemplateError::InvalidParam {
+            name: "UEID",
+            len: 0
+        }
+    );
+    assert!(TbsParam::try_new("UEID", 0, 17).is_ok());
+}
+
+#[test]
+fn test_tbs_param_oversized() {
+    use crate::tbs::{init_param, TbsParam, TemplateError, MAX_PARAM_LEN};
+
+    assert!(TbsParam::try_new("PUBLIC_KEY", 0, MAX_PARAM_LEN).is_ok());
+    assert_eq!(
+        TbsParam::try_new("PUBLIC_KEY", 0, MAX_PARAM_LEN + 1).unwrap_err(),
+        TemplateError::InvalidParam {
+            name: "PUBLIC_KEY",
+            len: MAX_PARAM_LEN + 1
+        }
+    );
+
+    // A needle longer than the haystack isn't found
+    let tbs = [0x30, 0x02, 0x05, 0x00];
+    let err = init_param(&[0xAA; 5], &tbs, TbsParam::new("VALUE", 0, 5));
+    assert!(matches!(
+        err,
+        Err(TemplateError::NeedleCount { found: 0, .. })
+    ));
+}
+
+#[test]
+fn test_init_param_empty_needle() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::keys::mldsa87_key_from_seed;
+    use crate::tbs::{init_param, TbsParam, TemplateError};
+    use crate::validate::BuilderError;
+    use ml_dsa::MlDsa87;
+
+    type Key = ml_dsa::KeyPair<MlDsa87>;
+
+    let tbs = [0x30, 0x02, 0x05, 0x00];
+    let param = TbsParam {
+        name: "VALUE",
+        offset: 0,
+        len: 0,
+    };
+    assert_eq!(
+        init_param(&[], &tbs, param).unwrap_err(),
+        TemplateError::InvalidParam {
+            name: "VALUE",
+            len: 0
+        }
+    );
+
+    // An empty UEID is reported instead of registering a zero-length param
+    let cert = CertTemplateBuilder::<Key>::new()
+        .set_subject_key(mldsa87_key_from_seed(&[0x01; 32]))
+        .set_issuer_key(mldsa87_key_from_seed(&[0x02; 32]))
+        .add_ueid_ext(&[])
+        .try_tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    assert_eq!(cert.err(), Some(BuilderError::UeidLength { len: 0 }));
+
+    let csr = CsrTemplateBuilder::<Key>::new()
+        .set_key(mldsa87_key_from_seed(&[0x01; 32]))
+        .add_ueid_ext(&[])
+        .try_tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    assert_eq!(csr.err(), Some(BuilderError::UeidLength { len: 0 }));
+}
+
+#[test]
+fn test_param_tail_boundary() {
+    use crate::tbs::{init_param, TbsParam, TbsTemplate, TemplateError};
+
+    // A needle ending the haystack is found and stays within it
+    let tbs = vec![0x30, 0x04, 0x04, 0x02, 0xAA, 0xBB];
+    let param = init_param(&[0xAA, 0xBB], &tbs, TbsParam::new("VALUE", 0, 2)).unwrap();
+    assert_eq!((param.offset, param.len), (4, 2));
+
+    let template = TbsTemplate::new(tbs.clone(), vec![param]);
+    assert_eq!(
+        template.apply(&[("VALUE", &[0x01, 0x02])]).unwrap(),
+        [0x30, 0x04, 0x04, 0x02, 0x01, 0x02]
+    );
+
+    // A param one byte past the end is rejected instead of panicking
+    let template = TbsTemplate::new(tbs, vec![TbsParam::new("VALUE", 5, 2)]);
+    assert_eq!(
+        template.apply(&[("VALUE", &[0x01, 0x02])]).unwrap_err(),
+        TemplateError::ParamBounds {
+            name: "VALUE",
+            offset: 5,
+            len: 2,
+            tbs_len: 6
+        }
+    );
+}
+
+#[test]
+fn test_gen_init_devid_csr_p256() {
+    use crate::code_gen::CodeGen;
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use der::Decode;
+    use x509_cert::ext::pkix::{KeyUsage, KeyUsages};
+    use x509_cert::request::CertReq;
+
+    // Create a temporary directory for output
+    let temp_dir = std::env::temp_dir();
+    let out_dir = temp_dir.to_str().unwrap();
+
+    // Set up key usage for certificate signing
+    let key_usage = KeyUsage(KeyUsages::KeyCertSign.into());
+
+    // Create the CSR template builder with P-256
+    let bldr = CsrTemplateBuilder::<p256::ecdsa::SigningKey>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .add_basic_constraints_ext(true, 5)
+        .add_key_usage_ext(key_usage);
+
+    // Generate the template with a subject name
+    let template = bldr.tbs_template("Caliptra 2.0 P256 IDevID");
+
+    // ecdsa-with-SHA256
+    let req = CertReq::from_der(template.der()).unwrap();
+    assert_eq!(
+        req.algorithm.oid,
+        const_oid::ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.2")
+    );
+
+    // The uncompressed point is the public key and its hash the subject serialNumber
+    let param = |name: &str| template.params().iter().find(|p| p.name == name).copied();
+    assert_eq!(param("PUBLIC_KEY").unwrap().len, 65);
+    assert_eq!(param("SUBJECT_SN").unwrap().len, 64);
+
+    // Generate code from the template
+    CodeGen::gen_code("InitDevIdCsrTbsP256", template, out_dir);
+}
+
+#[test]
+fn test_gen_p256_spki_parameters() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::keys::SECP256R1_OID;
+    use const_oid::ObjectIdentifier;
+    use der::Decode;
+    use x509_cert::request::CertReq;
+    use x509_cert::Certificate;
+
+    // id-ecPublicKey with the named curve, while the signature omits the parameters
+    let check = |spki: &spki::AlgorithmIdentifierOwned,
+                 signature: &spki::AlgorithmIdentifierOwned| {
+        assert_eq!(spki.oid, ObjectIdentifier::new_unwrap("1.2.840.10045.2.1"));
+        let curve = spki.parameters.as_ref().unwrap();
+        assert_eq!(
+            curve.decode_as::<ObjectIdentifier>().unwrap(),
+            SECP256R1_OID
+        );
+        assert!(signature.parameters.is_none());
+    };
+
+    let csr = CsrTemplateBuilder::<p256::ecdsa::SigningKey>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 P256 IDevID");
+    let req = CertReq::from_der(csr.der()).unwrap();
+    check(&req.info.public_key.algorithm, &req.algorithm);
+
+    let cert = CertTemplateBuilder::<p256::ecdsa::SigningKey>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 P256 LDevID", "Caliptra 2.0 P256 IDevID");
+    let cert = Certificate::from_der(cert.der()).unwrap();
+    check(
+        &cert.tbs_certificate.subject_public_key_info.algorithm,
+        &cert.signature_algorithm,
+    );
+}
+
+#[test]
+fn test_gen_csr_key_usage_opt_in() {
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use ml_dsa::MlDsa87;
+    use x509_cert::ext::pkix::{KeyUsage, KeyUsages};
+
+    let template = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .add_basic_constraints_ext(true, 5)
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    assert_extensions(&template, &[BC, UEID]);
+
+    let template = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .add_basic_constraints_ext(true, 5)
+        .add_key_usage_ext(KeyUsage(KeyUsages::KeyCertSign.into()))
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    assert_extensions(&template, &[BC, KU, UEID]);
+}
+
+#[test]
+fn test_gen_minimal_csr_p256() {
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+
+    // Without extensions the TBS length fits a single length byte
+    let template = CsrTemplateBuilder::<p256::ecdsa::SigningKey>::new().tbs_template("P256");
+    let tbs = template.tbs();
+    assert!(tbs.len() < 256);
+    assert_eq!(tbs[1], 0x81);
+    assert_eq!(tbs.len(), 3 + tbs[2] as usize);
+}
+
+#[test]
+fn test_gen_owned_fwids() {
+    use crate::cert_rustcrypto::{CertTemplateBuilder, Fwid, FwidParam};
+    use crate::measurement::SHA384_OID;
+    use ml_dsa::MlDsa87;
+
+    // FWIDs parsed from a config inside a helper, outliving the strings they are read from
+    fn parse_fwids(config: &[(&'static str, String)]) -> Vec<FwidParam<'static>> {
+        config
+            .iter()
+            .map(|&(name, ref hex_digest)| {
+                let digest: [u8; 48] = hex::decode(hex_digest).unwrap().try_into().unwrap();
+                FwidParam::new(name, Fwid::sha384(digest))
+            })
+            .collect()
+    }
+    let fwids = parse_fwids(&[
+        ("TCB_INFO_RT_TCI", "AB".repeat(48)),
+        ("TCB_INFO_RT_JOURNEY", "CD".repeat(48)),
+    ]);
+    assert_eq!(fwids[0].fwid, Fwid::new(SHA384_OID, vec![0xAB; 48]));
+    assert_eq!(fwids[1].fwid, Fwid::new(SHA384_OID, vec![0xCD; 48]));
+
+    // Placeholders are distinct per index, and from the fill bytes of the digests above
+    let placeholders: Vec<Fwid> = (0..4).map(Fwid::placeholder_sha384).collect();
+    assert_eq!(placeholders[0], Fwid::placeholder_sha384(0));
+    for (i, placeholder) in placeholders.iter().enumerate() {
+        assert_eq!(placeholder.hash_alg, SHA384_OID);
+        assert_eq!(placeholder.digest.len(), 48);
+        assert!(placeholder.digest.windows(2).any(|w| w[0] != w[1]));
+        assert!(placeholders[i + 1..]
+            .iter()
+            .all(|other| other != placeholder));
+    }
+
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .add_rt_dice_tcb_info_ext_owned(0xC4, fwids.clone(), true)
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 RT Alias",
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+        );
+    decode_cert_tbs(&template);
+    assert_extensions(&template, &[UEID, TCB_INFO, SKI, AKI, KU]);
+
+    // The same FWIDs borrowed through the slice-based entry point give the same TBS layout
+    let borrowed = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .add_rt_dice_tcb_info_ext(0xC4, &fwids, true)
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 RT Alias",
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+        );
+    for fwid in fwids.iter() {
+        let param = |template: &tbs::TbsTemplate| {
+            let param = template
+                .params()
+                .iter()
+                .find(|p| p.name == fwid.name)
+                .unwrap();
+            (param.offset, param.len)
+        };
+        assert_eq!(param(&template), param(&borrowed));
+        assert_eq!(param(&template).1, 48);
+    }
+}
+
+#[test]
+fn test_gen_rt_tcb_info_multiple_fwids() {
+    use crate::cert_rustcrypto::{CertTemplateBuilder, Fwid, FwidParam};
+    use const_oid::ObjectIdentifier;
+    use ml_dsa::MlDsa87;
+    use std::borrow::Cow;
+
+    let sha384_oid = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.2");
+    let fwid = |name, digest| FwidParam {
+        name,
+        fwid: Fwid {
+            hash_alg: sha384_oid.clone(),
+            digest: Cow::Borrowed(digest),
+        },
+    };
+    // The first two FWIDs share the same placeholder digest
+    let rt_fwids = [
+        fwid("TCB_INFO_RT_TCI_0", &[0xCD; 48]),
+        fwid("TCB_INFO_RT_TCI_1", &[0xCD; 48]),
+        fwid("TCB_INFO_RT_TCI_2", &[0xAB; 48]),
+    ];
+
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .add_rt_dice_tcb_info_ext(0xC4, &rt_fwids, true)
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 RT Alias",
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+        );
+
+    let der = template.der();
+    let params = template.params_in_der().unwrap();
+    let mut offsets = Vec::new();
+    for fwid in rt_fwids.iter() {
+        let param = params.iter().find(|p| p.name == fwid.name).unwrap();
+        assert_eq!(param.len, 48);
+        assert!(param.offset + param.len <= der.len());
+        assert_eq!(
+            &der[param.offset..param.offset + param.len],
+            fwid.fwid.digest
+        );
+        offsets.push(param.offset);
+    }
+
+    // Each FWID has its own param in encoding order
+    assert!(offsets.windows(2).all(|w| w[0] + 48 <= w[1]));
+}
+
+#[test]
+fn test_fwid_param_in_digest_range() {
+    use crate::cert_rustcrypto::{CertTemplateBuilder, Fwid, FwidParam};
+    use crate::keys::mldsa87_key_from_seed;
+    use crate::measurement::SHA384_OID;
+    use crate::profile::TCB_INFO_OID;
+    use crate::tbs::{fwid_digest_ranges, get_tbs, locate_element};
+    use ml_dsa::MlDsa87;
+
+    let build = |digest: &[u8]| {
+        let rt_fwids = [FwidParam::new(
+            "TCB_INFO_RT_TCI",
+            Fwid::new(SHA384_OID, digest.to_vec()),
+        )];
+        CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::rt_alias_profile(
+            &[0xFF; 17],
+            0xC4,
+            &rt_fwids,
+        )
+        .set_subject_key(mldsa87_key_from_seed(&[0x01; 32]))
+        .set_issuer_key(mldsa87_key_from_seed(&[0x02; 32]))
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 RT Alias",
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+        )
+    };
+
+    // Craft a digest starting with the signature AlgorithmIdentifier of the TBS, followed
+    // by the fixed bytes of the issuer Name
+    let template = build(&[0xCD; 48]);
+    let tbs = get_tbs(template.der()).unwrap();
+    let (algorithm, _) = locate_element(tbs, &[2]);
+    assert_eq!(tbs[algorithm + 2], 0x06);
+    let digest = tbs[algorithm..algorithm + 48].to_vec();
+
+    // The earlier instance of the digest is not taken for the FWID
+    let template = build(&digest);
+    let tbs = get_tbs(template.der()).unwrap();
+    assert_eq!(tbs[algorithm..algorithm + 48], digest);
+    let param = template
+        .params()
+        .iter()
+        .find(|p| p.name == "TCB_INFO_RT_TCI")
+        .unwrap();
+    let range = param.offset..param.offset + param.len;
+    assert!(param.offset > algorithm);
+    assert_eq!(tbs[range.clone()], digest);
+    assert_eq!(fwid_digest_ranges(tbs, TCB_INFO_OID), vec![range]);
+}
+
+#[test]
+fn test_validate_dice_profile() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::profile::{validate_dice_profile, DiceCertKind, ProfileViolation, TCB_INFO_OID};
+    use const_oid::AssociatedOid;
+    use ml_dsa::MlDsa87;
+    use x509_cert::ext::pkix::{KeyUsage, KeyUsages};
+
+    type Csr<'a> = CsrTemplateBuilder<'a, ml_dsa::KeyPair<MlDsa87>>;
+    let violations = |bldr: Csr| {
+        let template = bldr.tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+        validate_dice_profile(template.der(), DiceCertKind::IDevId)
+    };
+    let key_cert_sign = KeyUsage(KeyUsages::KeyCertSign.into());
+
+    // The IDevID preset conforms
+    let template = Csr::idevid_profile(&[0xFF; 17])
+        .validate_as(DiceCertKind::IDevId)
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    assert!(validate_dice_profile(template.der(), DiceCertKind::IDevId).is_empty());
+
+    // Missing UEID
+    let bldr = Csr::new()
+        .add_basic_constraints_ext(true, 5)
+        .add_key_usage_ext(key_cert_sign);
+    assert_eq!(
+        violations(bldr),
+        [ProfileViolation::MissingExtension(profile::UEID_OID)]
+    );
+
+    // Not a CA
+    let bldr = Csr::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .add_basic_constraints_ext(false, 0)
+        .add_key_usage_ext(key_cert_sign);
+    assert_eq!(
+        violations(bldr),
+        [ProfileViolation::CaMismatch { expected: true }]
+    );
+
+    // CA without keyCertSign or any KeyUsage
+    let bldr = Csr::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .add_basic_constraints_ext(true, 5)
+        .add_key_usage_ext(KeyUsage(KeyUsages::DigitalSignature.into()));
+    assert_eq!(
+        violations(bldr),
+        [ProfileViolation::MissingKeyUsage("keyCertSign")]
+    );
+    let bldr = Csr::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .add_basic_constraints_ext(true, 5);
+    assert_eq!(
+        violations(bldr),
+        [ProfileViolation::MissingExtension(KeyUsage::OID)]
+    );
+
+    // An alias certificate must carry TcbInfo
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+            "Caliptra 2.0 MlDsa87 LDevID",
+        );
+    let violations = validate_dice_profile(template.der(), DiceCertKind::AliasCert);
+    assert!(violations.contains(&ProfileViolation::MissingExtension(TCB_INFO_OID)));
+}
+
+#[test]
+#[should_panic(expected = "violates the IDevId DICE profile")]
+fn test_validate_as_fails_generation() {
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::profile::DiceCertKind;
+    use ml_dsa::MlDsa87;
+
+    CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_basic_constraints_ext(true, 5)
+        .validate_as(DiceCertKind::IDevId)
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+}
+
+#[test]
+fn test_gen_code_const_fn() {
+    use crate::code_gen::{CodeGen, CodeGenOptions};
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use ml_dsa::MlDsa87;
+
+    let out_dir = std::env::temp_dir().join("const_template");
+    std::fs::create_dir_all(&out_dir).unwrap();
+
+    let template = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::idevid_profile(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    CodeGen::gen_code_with_options(
+        "ConstTemplateTbs",
+        template,
+        out_dir.to_str().unwrap(),
+        &CodeGenOptions::new().const_fn(),
+    );
+
+    // Compile the generated code together with the const context fixture
+    let code = std::fs::read_to_string(out_dir.join("const_template_tbs.rs")).unwrap();
+    let fixture = include_str!("../tests/fixtures/const_template.rs");
+    let src_path = out_dir.join("const_template_pass.rs");
+    std::fs::write(&src_path, format!("{}\n{}", code, fixture)).unwrap();
+    let status = rustc_command()
+        .args(["--crate-type=lib", "--out-dir"])
+        .arg(&out_dir)
+        .arg(&src_path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+#[test]
+fn test_gen_include() {
+    use crate::code_gen::CodeGen;
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use ml_dsa::MlDsa87;
+
+    let out_dir = std::env::temp_dir().join("include_template");
+    std::fs::create_dir_all(&out_dir).unwrap();
+
+    let template = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::idevid_profile(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    let path = CodeGen::gen_include("IncludeTemplate", template, out_dir.to_str().unwrap());
+    assert_eq!(path, out_dir.join("include_template.rs"));
+    let code = std::fs::read_to_string(&path).unwrap();
+    assert!(!code.contains("use "));
+    assert!(!code.contains("#!"));
+
+    // Compile the fixture including the file from OUT_DIR
+    let status = rustc_command()
+        .args(["--crate-type=lib", "--out-dir"])
+        .arg(&out_dir)
+        .arg(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/include_template.rs"
+        ))
+        .env("OUT_DIR", &out_dir)
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+#[test]
+fn test_gen_verifier() {
+    use crate::code_gen::CodeGen;
+    use crate::tbs::fixed_region_digest;
+
+    let out_dir = std::env::temp_dir().join("verifier");
+    std::fs::create_dir_all(&out_dir).unwrap();
+
+    let template = generate_test_template(TestAlg::MlDsa87, TestScenario::RtAliasCert, 1);
+    let path = CodeGen::gen_verifier("RtAliasMlDsa87", &template, out_dir.to_str().unwrap());
+    assert_eq!(path, out_dir.join("rt_alias_ml_dsa87_verifier.rs"));
+    let code = std::fs::read_to_string(path).unwrap();
+
+    // The algorithm, the param map and the fixed region digest, without the TBS bytes
+    assert!(code.contains("pub struct RtAliasMlDsa87Verifier"));
+    assert!(code.contains(&format!("\"{}\"", template.algorithm_oid())));
+    for p in template.params() {
+        let entry = format!("(\"{}\", {}usize, {}usize)", p.name, p.offset, p.len);
+        assert!(code.contains(&entry), "{}", entry);
+    }
+    let digest = template.fixed_region_digest();
+    let digest: Vec<String> = digest.iter().map(|b| format!("{}u8", b)).collect();
+    assert!(code.contains(&digest.join(", ")));
+    assert!(!code.contains("TBS_TEMPLATE"));
+    assert!(code.len() < template.tbs().len());
+
+    // Patching a param keeps the digest, changing a fixed byte doesn't
+    let public_key = template
+        .params()
+        .iter()
+        .find(|p| p.name == "PUBLIC_KEY")
+        .unwrap();
+    let value = vec![0xAB; public_key.len];
+    let mut tbs = template.apply(&[("PUBLIC_KEY", &value[..])]).unwrap();
+    let expected = template.fixed_region_digest();
+    assert_eq!(fixed_region_digest(&tbs, template.params()), expected);
+    tbs[0] ^= 1;
+    assert_ne!(fixed_region_digest(&tbs, template.params()), expected);
+}
+
+#[test]
+fn test_gen_manifest_provenance() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::code_gen::{CodeGen, CodeGenOptions};
+    use crate::keys::mldsa87_key_from_seed;
+    use crate::provenance::Provenance;
+    use ml_dsa::MlDsa87;
+
+    let seed = [0x01; 32];
+    let build = || {
+        CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+            .set_subject_key(mldsa87_key_from_seed(&seed))
+            .set_issuer_key(mldsa87_key_from_seed(&[0x02; 32]))
+            .record_key_seed(&seed)
+            .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID")
+    };
+    let first = build();
+    let second = build();
+
+    // Two runs with the same seed record the same provenance
+    let provenance: &Provenance = first.provenance().unwrap();
+    assert_eq!(Some(provenance), second.provenance());
+    assert_eq!(provenance.generator, env!("CARGO_PKG_VERSION"));
+    assert_eq!(provenance.key_algorithm, "2.16.840.1.101.3.4.3.19");
+    assert_eq!(provenance.seed, Some(hex::encode(seed)));
+
+    // And write identical manifests
+    let manifests: Vec<Vec<u8>> = [(&first, "manifest_first"), (&second, "manifest_second")]
+        .into_iter()
+        .map(|(template, dir)| {
+            let out_dir = std::env::temp_dir().join(dir);
+            std::fs::create_dir_all(&out_dir).unwrap();
+            let out_path = out_dir.to_str().unwrap();
+            let path = CodeGen::gen_manifest("LocalDevIdCert", template, out_path);
+            assert_eq!(path, out_dir.join("local_dev_id_cert.json"));
+            std::fs::read(path).unwrap()
+        })
+        .collect();
+    assert_eq!(manifests[0], manifests[1]);
+
+    let manifest: serde_json::Value = serde_json::from_slice(&manifests[0]).unwrap();
+    assert_eq!(manifest["kind"], "Cert");
+    assert_eq!(manifest["tbs_len"], first.tbs().len());
+    assert!(manifest["params"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|p| p["name"] == "PUBLIC_KEY"));
+    let recorded = &manifest["provenance"];
+    for field in [
+        "generator",
+        "ml_dsa",
+        "x509_cert",
+        "der",
+        "key_algorithm",
+        "seed",
+    ] {
+        assert!(recorded[field].is_string(), "{}", field);
+    }
+    assert_eq!(recorded["config"]["kind"], "Cert");
+    assert_eq!(recorded["config"]["signing_mode"], "Deterministic");
+
+    // The generated code optionally carries the provenance as a comment block
+    let options = CodeGenOptions::new().provenance_comment();
+    let code = CodeGen::code("LocalDevIdCert", first, &options);
+    assert!(code.contains(&format!("// Seed: {}\n", hex::encode(seed))));
+}
+
+#[test]
+fn test_template_hash() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::code_gen::{CodeGen, CodeGenOptions};
+    use crate::keys::mldsa87_key_from_seed;
+    use crate::tbs::{verify_template_hash, TbsParam, TbsTemplate, TEMPLATE_SCHEMA_VERSION};
+    use ml_dsa::MlDsa87;
+
+    let build = || {
+        CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+            .set_subject_key(mldsa87_key_from_seed(&[0x01; 32]))
+            .set_issuer_key(mldsa87_key_from_seed(&[0x02; 32]))
+            .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID")
+    };
+    let template = build();
+    let hash = template.template_hash();
+
+    // Reproducible regenerations hash the same
+    assert_eq!(build().template_hash(), hash);
+    assert!(verify_template_hash(
+        template.tbs(),
+        template.params(),
+        &hash
+    ));
+
+    // Any template byte or param offset changes the hash
+    let mut tbs = template.tbs().to_vec();
+    tbs[0] ^= 0x01;
+    assert!(!verify_template_hash(&tbs, template.params(), &hash));
+    let mut params = template.params().to_vec();
+    params[0] = TbsParam {
+        offset: params[0].offset + 1,
+        ..params[0]
+    };
+    assert!(!verify_template_hash(template.tbs(), &params, &hash));
+    let moved = TbsTemplate::new(template.tbs().to_vec(), params);
+    assert_ne!(moved.template_hash(), hash);
+
+    let out_dir = std::env::temp_dir().join("template_hash");
+    std::fs::create_dir_all(&out_dir).unwrap();
+    let path = CodeGen::gen_manifest("LocalDevIdCert", &template, out_dir.to_str().unwrap());
+    let manifest: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+    assert_eq!(manifest["schema_version"], TEMPLATE_SCHEMA_VERSION);
+    assert_eq!(manifest["template_hash"], hex::encode(hash));
+
+    let code = CodeGen::code("LocalDevIdCert", build(), &CodeGenOptions::new());
+    assert!(!code.contains("TEMPLATE_HASH"));
+    let options = CodeGenOptions::new().template_hash_const();
+    let code = CodeGen::code("LocalDevIdCert", template, &options);
+    assert!(code.contains("pub const TEMPLATE_SCHEMA_VERSION : u32 = 1u32"));
+    let bytes: String = hash.iter().map(|b| format!("{}u8 , ", b)).collect();
+    assert!(code.contains(&format!(
+        "pub const LOCAL_DEV_ID_CERT_TEMPLATE_HASH : [u8 ; 32] = [{}]",
+        bytes.trim_end()
+    )));
+}
+
+#[test]
+fn test_gen_code_shared() {
+    use crate::cert_rustcrypto::{CertTemplateBuilder, Fwid, FwidParam};
+    use crate::code_gen::{CodeGen, SHARED_GRANULE};
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::keys::mldsa87_key_from_seed;
+    use crate::measurement::SHA384_OID;
+    use ml_dsa::MlDsa87;
+    use std::borrow::Cow;
+    use std::process::Command;
+
+    type Key = ml_dsa::KeyPair<MlDsa87>;
+
+    let out_dir = std::env::temp_dir().join("shared_tbs");
+    std::fs::create_dir_all(&out_dir).unwrap();
+
+    let fwids = [FwidParam {
+        name: "TCB_INFO_RT_TCI",
+        fwid: Fwid {
+            hash_alg: SHA384_OID,
+            digest: Cow::Borrowed(&[0xCD; 48]),
+        },
+    }];
+    let csr = CsrTemplateBuilder::<Key>::idevid_profile(&[0xFF; 17])
+        .set_key(mldsa87_key_from_seed(&[0x01; 32]))
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    let ldevid = CertTemplateBuilder::<Key>::ldevid_profile(&[0xFF; 17])
+        .set_subject_key(mldsa87_key_from_seed(&[0x02; 32]))
+        .set_issuer_key(mldsa87_key_from_seed(&[0x01; 32]))
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    let rt_alias = CertTemplateBuilder::<Key>::rt_alias_profile(&[0xFF; 17], 0xC4, &fwids)
+        .set_subject_key(mldsa87_key_from_seed(&[0x04; 32]))
+        .set_issuer_key(mldsa87_key_from_seed(&[0x03; 32]))
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 RT Alias",
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+        );
+
+    let specs = [
+        ("InitDevIdCsrTbsMlDsa87", &csr),
+        ("LocalDevIdCertTbsMlDsa87", &ldevid),
+        ("RtAliasCertTbsMlDsa87", &rt_alias),
+    ];
+    let report = CodeGen::gen_code_shared(&specs, out_dir.to_str().unwrap());
+    assert_eq!(report.path, out_dir.join("shared_tbs.rs"));
+    assert_eq!(
+        report.total_len,
+        csr.tbs().len() + ldevid.tbs().len() + rt_alias.tbs().len()
+    );
+
+    // The sanitized public keys alone span several identical granules
+    assert!(report.shared_len >= SHARED_GRANULE);
+    assert_eq!(report.shared_len % SHARED_GRANULE, 0);
+    assert!(report.saved > report.shared_len);
+
+    let code = std::fs::read_to_string(&report.path).unwrap();
+    let summary = format!(
+        "{} of {} template bytes saved",
+        report.saved, report.total_len
+    );
+    assert!(code.contains(&summary));
+    assert!(code.contains("pub fn rt_alias_cert_tbs_ml_dsa87_tbs("));
+    assert!(code.contains("pub const LOCAL_DEV_ID_CERT_TBS_ML_DSA87_UEID_OFFSET"));
+
+    // Run the generated tests reconstructing each template
+    let binary = out_dir.join("shared_tbs");
+    let status = rustc_command()
+        .args(["--test", "-A", "warnings", "-o"])
+        .arg(&binary)
+        .arg(&report.path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(Command::new(&binary).status().unwrap().success());
+}
+
+#[test]
+fn test_gen_encoded_ext() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use const_oid::{AssociatedOid, ObjectIdentifier};
+    use der::{asn1::OctetString, Decode, Sequence};
+    use ml_dsa::MlDsa87;
+
+    #[derive(Sequence, Debug, PartialEq)]
+    struct TestExt {
+        version: u8,
+        label: OctetString,
+    }
+
+    impl AssociatedOid for TestExt {
+        const OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.999.1");
+    }
+
+    let value = TestExt {
+        version: 1,
+        label: OctetString::new(b"caliptra".to_vec()).unwrap(),
+    };
+
+    let cert = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_encoded_ext(&value, true)
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    let csr = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_encoded_ext(&value, false)
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+
+    assert_extensions(&csr, &["2.999.1"]);
+    for (template, critical) in [(&cert, true), (&csr, false)] {
+        let ext = profile::der_extensions(template.der())
+            .into_iter()
+            .find(|ext| ext.extn_id == TestExt::OID)
+            .unwrap();
+        assert_eq!(ext.critical, critical);
+        assert_eq!(TestExt::from_der(ext.extn_value.as_bytes()).unwrap(), value);
+    }
+}
+
+#[test]
+fn test_gen_patchable_path_len() {
+    use crate::cert_rustcrypto::{CertTemplateBuilder, PathLen};
+    use crate::code_gen::{CodeGen, CodeGenOptions};
+    use crate::tbs::{TemplateError, BASIC_CONSTRAINTS_PATH_LEN_PARAM};
+    use crate::validate::BuilderError;
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+    use x509_cert::ext::pkix::BasicConstraints;
+
+    type Key = ml_dsa::KeyPair<MlDsa87>;
+
+    let template = CertTemplateBuilder::<Key>::new()
+        .add_basic_constraints_ext_with(true, PathLen::Patchable { max: 7 })
+        .add_ueid_ext(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    let param = template
+        .params()
+        .iter()
+        .find(|p| p.name == BASIC_CONSTRAINTS_PATH_LEN_PARAM)
+        .unwrap();
+    assert_eq!(param.len, 1);
+    assert_eq!(
+        template.param_max(BASIC_CONSTRAINTS_PATH_LEN_PARAM),
+        Some(7)
+    );
+
+    for path_len in [0u8, 5] {
+        let tbs = template
+            .apply(&[(BASIC_CONSTRAINTS_PATH_LEN_PARAM, &[path_len])])
+            .unwrap();
+        let patched = tbs::TbsTemplate::new(tbs, template.params().to_vec());
+        let extensions = decode_cert_tbs(&patched).extensions.unwrap();
+        let ext = extensions
+            .iter()
+            .find(|ext| ext.extn_id.to_string() == BC)
+            .unwrap();
+        let bc = BasicConstraints::from_der(ext.extn_value.as_bytes()).unwrap();
+        assert!(bc.ca);
+        assert_eq!(bc.path_len_constraint, Some(path_len));
+    }
+
+    // Values above the declared maximum are rejected
+    assert_eq!(
+        template.apply(&[(BASIC_CONSTRAINTS_PATH_LEN_PARAM, &[8])]),
+        Err(TemplateError::ValueRange {
+            name: BASIC_CONSTRAINTS_PATH_LEN_PARAM,
+            value: 8,
+            max: 7
+        })
+    );
+
+    let code = CodeGen::code("LocalDevIdCertTbsMlDsa87", template, &CodeGenOptions::new());
+    assert!(code.contains("pub const BASIC_CONSTRAINTS_PATH_LEN_MAX : u8 = 7u8"));
+
+    // A two byte INTEGER content can't be patched with stable DER lengths
+    let err = CertTemplateBuilder::<Key>::new()
+        .add_basic_constraints_ext_with(true, PathLen::Patchable { max: 200 })
+        .try_tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID")
+        .unwrap_err();
+    assert_eq!(err, BuilderError::PatchablePathLength { max: 200 });
+}
+
+#[test]
+fn test_gen_basic_constraints_critical() {
+    use crate::cert_rustcrypto::{CertTemplateBuilder, PathLen};
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::tbs::BASIC_CONSTRAINTS_PATH_LEN_PARAM;
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+    use x509_cert::ext::pkix::BasicConstraints;
+
+    type Key = ml_dsa::KeyPair<MlDsa87>;
+
+    let bc_ext = |template: &tbs::TbsTemplate| {
+        profile::der_extensions(template.der())
+            .into_iter()
+            .find(|ext| ext.extn_id.to_string() == BC)
+            .unwrap()
+    };
+
+    // Critical for a CA, non-critical for a leaf by default
+    let ca = CertTemplateBuilder::<Key>::ldevid_profile(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    assert!(bc_ext(&ca).critical);
+    let leaf = CertTemplateBuilder::<Key>::new()
+        .add_basic_constraints_ext(false, 0)
+        .tbs_template("Caliptra 2.0 MlDsa87 Leaf", "Caliptra 2.0 MlDsa87 LDevID");
+    assert!(!bc_ext(&leaf).critical);
+    let ca_csr = CsrTemplateBuilder::<Key>::idevid_profile(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    assert!(bc_ext(&ca_csr).critical);
+    let leaf_csr = CsrTemplateBuilder::<Key>::new()
+        .add_basic_constraints_ext(false, 0)
+        .tbs_template("Caliptra 2.0 MlDsa87 Leaf");
+    assert!(!bc_ext(&leaf_csr).critical);
+
+    // Overridden criticality
+    let leaf = CertTemplateBuilder::<Key>::new()
+        .add_basic_constraints_ext(false, 0)
+        .set_basic_constraints_critical(true)
+        .tbs_template("Caliptra 2.0 MlDsa87 Leaf", "Caliptra 2.0 MlDsa87 LDevID");
+    assert!(bc_ext(&leaf).critical);
+
+    // The patchable pathLenConstraint is located without the critical BOOLEAN
+    let template = CertTemplateBuilder::<Key>::new()
+        .add_basic_constraints_ext_with(true, PathLen::Patchable { max: 7 })
+        .set_basic_constraints_critical(false)
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    let tbs = template
+        .apply(&[(BASIC_CONSTRAINTS_PATH_LEN_PARAM, &[3])])
+        .unwrap();
+    let patched = tbs::TbsTemplate::new(tbs, template.params().to_vec());
+    let extensions = decode_cert_tbs(&patched).extensions.unwrap();
+    let ext = extensions
+        .iter()
+        .find(|ext| ext.extn_id.to_string() == BC)
+        .unwrap();
+    assert!(!ext.critical);
+    let bc = BasicConstraints::from_der(ext.extn_value.as_bytes()).unwrap();
+    assert_eq!(bc.path_len_constraint, Some(3));
+}
+
+#[test]
+fn test_gen_unique_ids() {
+    use crate::cert_rustcrypto::{
+        CertTemplateBuilder, ISSUER_UNIQUE_ID_PARAM, SUBJECT_UNIQUE_ID_PARAM,
+    };
+    use crate::validate::BuilderError;
+    use ml_dsa::MlDsa87;
+    use x509_cert::certificate::Version;
+
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+        .add_issuer_unique_id(8)
+        .add_subject_unique_id(16)
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    for (name, len) in [(ISSUER_UNIQUE_ID_PARAM, 8), (SUBJECT_UNIQUE_ID_PARAM, 16)] {
+        let param = template.params().iter().find(|p| p.name == name).unwrap();
+        assert_eq!(param.len, len);
+    }
+
+    let issuer_id = [0x11; 8];
+    let subject_id = [0x22; 16];
+    let tbs = template
+        .apply(&[
+            (ISSUER_UNIQUE_ID_PARAM, &issuer_id[..]),
+            (SUBJECT_UNIQUE_ID_PARAM, &subject_id[..]),
+        ])
+        .unwrap();
+    let patched = tbs::TbsTemplate::new(tbs, template.params().to_vec());
+    let decoded = decode_cert_tbs(&patched);
+    assert_eq!(decoded.version, Version::V3);
+    assert_eq!(
+        decoded.issuer_unique_id.unwrap().as_bytes(),
+        Some(&issuer_id[..])
+    );
+    assert_eq!(
+        decoded.subject_unique_id.unwrap().as_bytes(),
+        Some(&subject_id[..])
+    );
+    assert_extensions(&template, &[BC, KU, UEID, SKI, AKI]);
+    assert_eq!(decoded.extensions.unwrap().len(), 5);
+
+    // The subjectUniqueID alone takes the position of the issuerUniqueID
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+        .add_subject_unique_id(4)
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    let tbs = template
+        .apply(&[(SUBJECT_UNIQUE_ID_PARAM, &[0x33; 4])])
+        .unwrap();
+    let decoded = decode_cert_tbs(&tbs::TbsTemplate::new(tbs, template.params().to_vec()));
+    assert!(decoded.issuer_unique_id.is_none());
+    assert_eq!(
+        decoded.subject_unique_id.unwrap().as_bytes(),
+        Some(&[0x33; 4][..])
+    );
+
+    let err = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+        .add_issuer_unique_id(0)
+        .try_tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID")
+        .unwrap_err();
+    assert_eq!(
+        err,
+        BuilderError::EmptyField {
+            field: "issuerUniqueID"
+        }
+    );
+}
+
+#[test]
+fn test_gen_key_usage_high_bits() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use const_oid::AssociatedOid;
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+    use x509_cert::ext::pkix::{KeyUsage, KeyUsages};
+
+    // Sets ending at bit 0, bit 7 and bit 8, with their DER-minimal BIT STRING
+    let cases: [(KeyUsage, &[u8]); 4] = [
+        (
+            KeyUsage(KeyUsages::DigitalSignature.into()),
+            &[0x03, 0x02, 0x07, 0x80],
+        ),
+        (
+            KeyUsage(KeyUsages::EncipherOnly.into()),
+            &[0x03, 0x02, 0x00, 0x01],
+        ),
+        (
+            KeyUsage(KeyUsages::DecipherOnly.into()),
+            &[0x03, 0x03, 0x07, 0x00, 0x80],
+        ),
+        (
+            KeyUsage(KeyUsages::KeyAgreement | KeyUsages::EncipherOnly | KeyUsages::DecipherOnly),
+            &[0x03, 0x03, 0x07, 0x09, 0x80],
+        ),
+    ];
+    for (usage, expected) in cases {
+        let cert = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+            .add_key_usage_ext(usage)
+            .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+        let csr = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+            .add_key_usage_ext(usage)
+            .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+
+        for template in [&cert, &csr] {
+            let ext = profile::der_extensions(template.der())
+                .into_iter()
+                .find(|ext| ext.extn_id == KeyUsage::OID)
+                .unwrap();
+            let bit_string = ext.extn_value.as_bytes();
+            assert_eq!(bit_string, expected);
+            assert!(profile::bit_string_is_minimal(bit_string));
+            assert_eq!(KeyUsage::from_der(bit_string).unwrap(), usage);
+        }
+    }
+
+    // Trailing zero bits and miscounted unused bits are not DER
+    assert!(!profile::bit_string_is_minimal(&[
+        0x03, 0x03, 0x07, 0x80, 0x00
+    ]));
+    assert!(!profile::bit_string_is_minimal(&[0x03, 0x02, 0x00, 0x80]));
+    assert!(!profile::bit_string_is_minimal(&[0x03, 0x02, 0x07, 0x00]));
+    assert!(profile::bit_string_is_minimal(&[0x03, 0x01, 0x00]));
+}
+
+#[test]
+fn test_gen_key_usage_combinations() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::profile::key_usage_bit_string;
+    use const_oid::AssociatedOid;
+    use der::{Decode, Encode};
+    use ml_dsa::MlDsa87;
+    use x509_cert::ext::pkix::{KeyUsage, KeyUsages};
+
+    // Named bits 0 to 8
+    const ALL: [KeyUsages; 9] = [
+        KeyUsages::DigitalSignature,
+        KeyUsages::NonRepudiation,
+        KeyUsages::KeyEncipherment,
+        KeyUsages::DataEncipherment,
+        KeyUsages::KeyAgreement,
+        KeyUsages::KeyCertSign,
+        KeyUsages::CRLSign,
+        KeyUsages::EncipherOnly,
+        KeyUsages::DecipherOnly,
+    ];
+
+    // Every combination encodes as the expected DER-minimal BIT STRING
+    for mask in 1..1u16 << ALL.len() {
+        let usage = ALL
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| (mask >> bit) & 1 == 1)
+            .map(|(_, &usage)| KeyUsage(usage.into()))
+            .reduce(|a, b| KeyUsage(a.0 | b.0))
+            .unwrap();
+        assert_eq!(
+            usage.to_der().unwrap(),
+            key_usage_bit_string(usage),
+            "{:#x}",
+            mask
+        );
+    }
+
+    let cases: [(KeyUsage, &[u8]); 3] = [
+        (
+            KeyUsage(KeyUsages::KeyEncipherment | KeyUsages::CRLSign),
+            &[0x03, 0x02, 0x01, 0x22],
+        ),
+        (
+            KeyUsage(
+                KeyUsages::NonRepudiation | KeyUsages::DataEncipherment | KeyUsages::KeyCertSign,
+            ),
+            &[0x03, 0x02, 0x02, 0x54],
+        ),
+        (
+            KeyUsage(
+                KeyUsages::DigitalSignature
+                    | KeyUsages::KeyEncipherment
+                    | KeyUsages::KeyAgreement
+                    | KeyUsages::CRLSign
+                    | KeyUsages::DecipherOnly,
+            ),
+            &[0x03, 0x03, 0x07, 0xAA, 0x80],
+        ),
+    ];
+    for (usage, expected) in cases {
+        // The configured bits replace the KeyUsage of the DevId profile
+        let cert = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+            .add_key_usage_ext(usage)
+            .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+        let csr = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+            .add_key_usage_ext(usage)
+            .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+
+        for template in [&cert, &csr] {
+            let exts: Vec<_> = profile::der_extensions(template.der())
+                .into_iter()
+                .filter(|ext| ext.extn_id == KeyUsage::OID)
+                .collect();
+            assert_eq!(exts.len(), 1);
+            assert!(exts[0].critical);
+            let bit_string = exts[0].extn_value.as_bytes();
+            assert_eq!(bit_string, expected);
+            assert_eq!(KeyUsage::from_der(bit_string).unwrap(), usage);
+        }
+    }
+}
+
+#[test]
+fn test_gen_logs_subject() {
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use ml_dsa::MlDsa87;
+    use std::sync::Mutex;
+
+    /// Logger recording the debug records of all tests running in the process
+    struct CapturingLogger(Mutex<Vec<String>>);
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.level() <= log::Level::Debug
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                self.0.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: CapturingLogger = CapturingLogger(Mutex::new(Vec::new()));
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Debug);
+
+    CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 Logged IDevID");
+
+    let records = LOGGER.0.lock().unwrap();
+    let subject = "CN=Caliptra 2.0 MlDsa87 Logged IDevID";
+    assert!(records
+        .iter()
+        .any(|r| r.starts_with("Subject: ") && r.contains(subject)));
+}
+
+#[test]
+fn test_gen_dual_alg_fmc_alias_certs() {
+    use crate::cert_rustcrypto::{Fwid, FwidParam};
+    use crate::dual_alg::DualAlgTemplateBuilder;
+    use const_oid::ObjectIdentifier;
+    use ml_dsa::MlDsa87;
+    use std::borrow::Cow;
+
+    let sha384_oid = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.2");
+    let device_fwids = [FwidParam {
+        name: "TCB_INFO_DEVICE_INFO_HASH",
+        fwid: Fwid {
+            hash_alg: sha384_oid.clone(),
+            digest: Cow::Borrowed(&[0xEF; 48]),
+        },
+    }];
+    let fmc_fwids = [FwidParam {
+        name: "TCB_INFO_FMC_TCI",
+        fwid: Fwid {
+            hash_alg: sha384_oid,
+            digest: Cow::Borrowed(&[0xCD; 48]),
+        },
+    }];
+
+    type Builder<'a> =
+        DualAlgTemplateBuilder<'a, p384::ecdsa::SigningKey, ml_dsa::KeyPair<MlDsa87>>;
+    let pair = Builder::fmc_alias_profile(&[0xFF; 17], &device_fwids, &fmc_fwids)
+        .tbs_templates("Caliptra 2.0 FMC Alias", "Caliptra 2.0 LDevID");
+
+    // Only the key and signature algorithm dependent elements differ
+    let elements: Vec<&str> = pair
+        .differences
+        .iter()
+        .map(|d| d.element.as_str())
+        .collect();
+    assert_eq!(elements, ["signature", "subjectPublicKeyInfo"]);
+
+    // The byte ranges lie within their elements, the shorter P-384 key ends first
+    for difference in pair.differences.iter() {
+        for (ranges, (offset, len)) in [
+            (&difference.ecc_ranges, difference.ecc),
+            (&difference.pqc_ranges, difference.pqc),
+        ] {
+            assert!(!ranges.is_empty(), "{}", difference.element);
+            assert!(ranges
+                .iter()
+                .all(|range| range.start >= offset && range.end <= offset + len));
+        }
+    }
+    let spki = &pair.differences[1];
+    assert_eq!(spki.pqc_ranges.last().unwrap().end, spki.pqc.0 + spki.pqc.1);
+
+    // Both certificates have the same params, and the non-key ones have the same value
+    let names = |template: &tbs::TbsTemplate| {
+        let mut names: Vec<&str> = template.params().iter().map(|p| p.name).collect();
+        names.sort();
+        names
+    };
+    assert_eq!(names(&pair.ecc), names(&pair.pqc));
+    let param = |template: &tbs::TbsTemplate, name: &str| {
+        let param = template
+            .params_in_der()
+            .unwrap()
+            .into_iter()
+            .find(|p| p.name == name)
+            .unwrap();
+        template.der()[param.offset..param.offset + param.len].to_vec()
+    };
+    for name in [
+        "UEID",
+        "TCB_INFO_DEVICE_INFO_HASH",
+        "TCB_INFO_FMC_TCI",
+        "tcb_info_fw_svn",
+        "tcb_info_fw_svn_fuses",
+        "tcb_info_flags",
+    ] {
+        assert_eq!(param(&pair.ecc, name), param(&pair.pqc, name), "{}", name);
+    }
+    assert_ne!(
+        param(&pair.ecc, "PUBLIC_KEY").len(),
+        param(&pair.pqc, "PUBLIC_KEY").len()
+    );
+}
+
+#[test]
+fn test_gen_reproducible_cert_der() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::keys::{mldsa87_key_from_seed, SigningMode};
+    use ml_dsa::MlDsa87;
+
+    let gen = || {
+        CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+            .set_subject_key(mldsa87_key_from_seed(&[0x01; 32]))
+            .set_issuer_key(mldsa87_key_from_seed(&[0x02; 32]))
+            .signing_mode(SigningMode::Deterministic)
+            .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID")
+    };
+
+    // The signature is part of the DER, so it must not be randomized
+    assert_eq!(gen().der(), gen().der());
+
+    let gen_csr = || {
+        CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::idevid_profile(&[0xFF; 17])
+            .set_key(mldsa87_key_from_seed(&[0x01; 32]))
+            .signing_mode(SigningMode::Deterministic)
+            .tbs_template("Caliptra 2.0 MlDsa87 IDevID")
+    };
+    assert_eq!(gen_csr().der(), gen_csr().der());
+}
+
+#[test]
+fn test_max_tbs_size_fails_generation() {
+    use crate::cert_rustcrypto::{CertTemplateBuilder, Fwid, FwidParam};
+    use crate::tbs::TemplateError;
+    use crate::validate::BuilderError;
+    use const_oid::ObjectIdentifier;
+    use ml_dsa::MlDsa87;
+    use std::borrow::Cow;
+
+    let sha384_oid = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.2");
+    let fwids = [FwidParam {
+        name: "TCB_INFO_DEVICE_INFO_HASH",
+        fwid: Fwid {
+            hash_alg: sha384_oid,
+            digest: Cow::Borrowed(&[0xEF; 48]),
+        },
+    }];
+
+    // The MultiTcbInfo is the largest extension of the FMC alias certificate
+    let err = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::fmc_alias_profile(
+        &[0xFF; 17],
+        &fwids,
+        &[],
+    )
+    .max_tbs_size(1024)
+    .try_tbs_template(
+        "Caliptra 2.0 MlDsa87 FMC Alias",
+        "Caliptra 2.0 MlDsa87 LDevID",
+    )
+    .unwrap_err();
+    match &err {
+        BuilderError::Template(TemplateError::TbsSize {
+            max, extensions, ..
+        }) => {
+            assert_eq!(*max, 1024);
+            assert_eq!(extensions[0].0, "2.23.133.5.4.5");
+        }
+        _ => panic!("unexpected error {}", err),
+    }
+    assert!(err
+        .to_string()
+        .contains("exceeds the budget of 1024 bytes, extensions: 2.23.133.5.4.5 ("));
+}
+
+#[test]
+fn test_gen_code_tbs_len_const() {
+    use crate::code_gen::{CodeGen, CodeGenOptions};
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use ml_dsa::MlDsa87;
+
+    let template = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::idevid_profile(&[0xFF; 17])
+        .max_tbs_size(4096)
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    let tbs_len = template.tbs().len();
+    let code = CodeGen::code(
+        "InitDevIdCsrTbsMlDsa87",
+        template,
+        &CodeGenOptions::new().tbs_len_const(),
+    );
+    assert!(code.contains("pub const INIT_DEV_ID_CSR_TBS_ML_DSA87_TBS_LEN"));
+    assert!(code.contains(&format!("= {}usize", tbs_len)));
+}
+
+#[test]
+fn test_gen_code_param_crc32_consts() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::code_gen::{CodeGen, CodeGenOptions};
+    use crate::tbs::crc32;
+    use ml_dsa::MlDsa87;
+
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    let expected: Vec<(String, u32)> = template
+        .params()
+        .iter()
+        .map(|p| {
+            let placeholder = &template.tbs()[p.offset..p.offset + p.len];
+            (p.name.to_uppercase(), crc32(placeholder))
+        })
+        .collect();
+
+    let code = CodeGen::code(
+        "LocalDevIdCertTbsMlDsa87",
+        template,
+        &CodeGenOptions::new().param_crc32_consts(),
+    );
+    for (name, crc) in expected {
+        assert!(code.contains(&format!("pub const {}_CRC32 : u32 = {}u32", name, crc)));
+    }
+}
+
+#[test]
+fn test_gen_ueid_variants() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::code_gen::CodeGen;
+    use crate::keys::mldsa87_key_from_seed;
+    use crate::name::key_bytes_hash;
+    use crate::validate::BuilderError;
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+    use signature::Keypair;
+    use x509_cert::Certificate;
+
+    let variants = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+        .set_subject_key(mldsa87_key_from_seed(&[0x02; 32]))
+        .set_issuer_key(mldsa87_key_from_seed(&[0x01; 32]))
+        .tbs_template_variants("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    let names: Vec<&str> = variants.iter().map(|(name, _)| *name).collect();
+    assert_eq!(names, ["WithUeid", "WithoutUeid"]);
+    let (with_ueid, without_ueid) = (&variants[0].1, &variants[1].1);
+    assert!(with_ueid.ranges().is_empty());
+
+    // The variant without UEID parses with recomputed lengths and lacks the extension
+    let cert = Certificate::from_der(without_ueid.der()).unwrap();
+    let extensions = cert.tbs_certificate.extensions.unwrap();
+    assert!(extensions.iter().all(|ext| ext.extn_id.to_string() != UEID));
+    assert_eq!(
+        without_ueid.tbs().len(),
+        with_ueid.tbs().len() - (extensions_len(with_ueid) - extensions_len(without_ueid))
+    );
+    assert!(with_ueid.params().iter().any(|p| p.name == "UEID"));
+    assert!(without_ueid.params().iter().all(|p| p.name != "UEID"));
+    let signature = without_ueid.signature_param().unwrap();
+    assert!(without_ueid.der()[signature.offset..]
+        .iter()
+        .all(|&b| b == 0));
+
+    // The shared params patch the same fields in both variants
+    let key = mldsa87_key_from_seed(&[0x05; 32]);
+    let public_key = key.verifying_key().encode();
+    let subject_sn = key_bytes_hash(&public_key);
+    for (_, template) in variants.iter() {
+        let tbs = template
+            .apply(&[
+                ("PUBLIC_KEY", &public_key[..]),
+                ("SUBJECT_SN", subject_sn.as_bytes()),
+            ])
+            .unwrap();
+        let patched = tbs::TbsTemplate::new(tbs, template.params().to_vec());
+        let decoded = decode_cert_tbs(&patched);
+        assert_eq!(
+            decoded
+                .subject_public_key_info
+                .subject_public_key
+                .raw_bytes(),
+            &public_key[..]
+        );
+        assert_eq!(
+            name::name_serial_number(&decoded.subject).unwrap(),
+            subject_sn.as_bytes()
+        );
+    }
+
+    let out_dir = std::env::temp_dir();
+    let path = CodeGen::gen_variants("LdevidMlDsa87", variants, out_dir.to_str().unwrap());
+    let code = std::fs::read_to_string(path).unwrap();
+    assert!(code.contains("pub struct LdevidMlDsa87WithUeid "));
+    assert!(code.contains("pub struct LdevidMlDsa87WithoutUeid "));
+
+    let err = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .try_tbs_template_variants("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID")
+        .unwrap_err();
+    assert_eq!(
+        err,
+        BuilderError::MissingExtension {
+            field: "UEID variants",
+            extension: "Ueid",
+        }
+    );
+}
+
+/// rustc compiling the generated code in the tests, `RUSTC` if set, with the edition of the
+/// crate
+#[cfg(test)]
+fn rustc_command() -> std::process::Command {
+    let rustc = std::env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let mut command = std::process::Command::new(rustc);
+    command.arg("--edition=2021");
+    command
+}
+
+/// Length of the extensions [3] element of the TBSCertificate of `template`
+#[cfg(test)]
+fn extensions_len(template: &tbs::TbsTemplate) -> usize {
+    tbs::element_children(template.tbs(), 0)
+        .into_iter()
+        .find(|&(offset, _)| template.tbs()[offset] == 0xA3)
+        .map(|(_, len)| len)
+        .unwrap()
+}
+
+#[test]
+fn test_gen_bundle() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::code_gen::CodeGen;
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::keys::mldsa87_key_from_seed;
+    use ml_dsa::MlDsa87;
+
+    let csr_template = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::idevid_profile(&[0xFF; 17])
+        .set_key(mldsa87_key_from_seed(&[0x01; 32]))
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    let cert_template =
+        CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+            .set_subject_key(mldsa87_key_from_seed(&[0x02; 32]))
+            .set_issuer_key(mldsa87_key_from_seed(&[0x01; 32]))
+            .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+
+    let out_dir = std::env::temp_dir();
+    let path = CodeGen::gen_bundle(
+        "IdevidLdevidMlDsa87",
+        csr_template,
+        cert_template,
+        out_dir.to_str().unwrap(),
+    );
+    assert_eq!(path.file_name().unwrap(), "idevid_ldevid_ml_dsa87.rs");
+    let code = std::fs::read_to_string(path).unwrap();
+
+    // One preamble, and each template with its own type and params struct
+    assert_eq!(
+        code.matches("Licensed under the Apache-2.0 license.")
+            .count(),
+        1
+    );
+    let file = syn::parse_file(&code).unwrap();
+    let structs: Vec<String> = file
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            syn::Item::Struct(item) => Some(item.ident.to_string()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        structs,
+        [
+            "IdevidLdevidMlDsa87CsrTbsParams",
+            "IdevidLdevidMlDsa87CsrTbs",
+            "IdevidLdevidMlDsa87CertTbsParams",
+            "IdevidLdevidMlDsa87CertTbs",
+        ]
+    );
+
+    // Both templates carry a UEID param, namespaced by their params struct
+    let ueid_params = file
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            syn::Item::Struct(item) if item.ident.to_string().ends_with("Params") => {
+                Some(&item.fields)
+            }
+            _ => None,
+        })
+        .filter(|fields| {
+            fields
+                .iter()
+                .any(|field| field.ident.as_ref().is_some_and(|ident| ident == "ueid"))
+        })
+        .count();
+    assert_eq!(ueid_params, 2);
+}
+
+#[test]
+fn test_gen_code_link_section_align() {
+    use crate::code_gen::{CodeGen, CodeGenOptions};
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use ml_dsa::MlDsa87;
+    use std::process::Command;
+
+    let template = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::idevid_profile(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    let out_dir = std::env::temp_dir().join("link_section_align");
+    let options = CodeGenOptions::new()
+        .link_section(".rodata.x509_templates")
+        .align(64)
+        .create_dir();
+    let path = CodeGen::gen_code_with_options(
+        "InitDevIdCsrTbsMlDsa87",
+        template,
+        out_dir.to_str().unwrap(),
+        &options,
+    );
+
+    // Compile the generated code with a test module checking the alignment of the static
+    let mut code = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(code.matches("pub struct Aligned").count(), 1);
+    assert!(code.contains("#[link_section = \".rodata.x509_templates\"]"));
+    code += "
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn template_aligned() {
+        let template = &super::INIT_DEV_ID_CSR_TBS_ML_DSA87_TBS_TEMPLATE;
+        assert_eq!(core::mem::align_of_val(template), 64);
+        assert_eq!(template as *const _ as usize % 64, 0);
+        assert_eq!(template.0, super::InitDevIdCsrTbsMlDsa87::TBS_TEMPLATE);
+    }
+}
+";
+    std::fs::write(&path, code).unwrap();
+    let binary = out_dir.join("template_aligned");
+    let status = rustc_command()
+        .args(["--test", "-A", "warnings", "-o"])
+        .arg(&binary)
+        .arg(&path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(Command::new(&binary).status().unwrap().success());
+}
+
+#[test]
+fn test_key_hash_matches_subject_sn() {
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::keys::BuilderKeys;
+    use crate::name::key_hash;
+    use ml_dsa::MlDsa87;
+    use signature::Keypair;
+
+    let key = ml_dsa::KeyPair::<MlDsa87>::key_gen_os();
+    let hash = key_hash(&key.verifying_key());
+
+    let template = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .set_key(key)
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    let param = template
+        .params_in_der()
+        .unwrap()
+        .into_iter()
+        .find(|p| p.name == "SUBJECT_SN")
+        .unwrap();
+    assert_eq!(
+        &template.der()[param.offset..param.offset + param.len],
+        hash.as_bytes()
+    );
+}
+
+#[test]
+fn test_gen_extension_order() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::profile::UEID_OID;
+    use const_oid::{AssociatedOid, ObjectIdentifier};
+    use ml_dsa::MlDsa87;
+    use x509_cert::ext::pkix::{BasicConstraints, KeyUsage};
+
+    let oids = |template: &tbs::TbsTemplate| -> Vec<ObjectIdentifier> {
+        profile::der_extensions(template.der())
+            .iter()
+            .map(|e| e.extn_id)
+            .collect()
+    };
+
+    // Legacy Caliptra layout by default
+    let csr = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::idevid_profile(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    assert_eq!(oids(&csr), [BasicConstraints::OID, KeyUsage::OID, UEID_OID]);
+
+    let csr = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::idevid_profile(&[0xFF; 17])
+        .extension_order(&[UEID_OID, KeyUsage::OID])
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    assert_eq!(oids(&csr), [UEID_OID, KeyUsage::OID, BasicConstraints::OID]);
+
+    // The extensions added by the certificate profile are ordered too
+    let cert = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    let expected: Vec<ObjectIdentifier> = [BC, KU, UEID, SKI, AKI]
+        .iter()
+        .map(|oid| ObjectIdentifier::new_unwrap(oid))
+        .collect();
+    assert_eq!(oids(&cert), expected);
+
+    let cert = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+        .extension_order(&[UEID_OID])
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    assert_eq!(oids(&cert)[0], UEID_OID);
+}
+
+#[test]
+fn test_extension_oids() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::profile::UEID_OID;
+    use const_oid::{AssociatedOid, ObjectIdentifier};
+    use ml_dsa::MlDsa87;
+    use x509_cert::ext::pkix::{
+        AuthorityKeyIdentifier, BasicConstraints, KeyUsage, KeyUsages, SubjectKeyIdentifier,
+    };
+
+    let usage = KeyUsage(KeyUsages::KeyCertSign.into());
+    let csr = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .add_key_usage_ext(usage)
+        .add_basic_constraints_ext(true, 5);
+    assert_eq!(
+        csr.extension_oids(),
+        [BasicConstraints::OID, KeyUsage::OID, UEID_OID]
+    );
+
+    // The reported order is the order of the generated template
+    let csr = csr.canonical_extension_order();
+    let expected = csr.extension_oids();
+    let template = csr.tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    let oids: Vec<ObjectIdentifier> = profile::der_extensions(template.der())
+        .iter()
+        .map(|e| e.extn_id)
+        .collect();
+    assert_eq!(oids, expected);
+
+    let cert = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .add_key_usage_ext(usage)
+        .add_basic_constraints_ext(true, 3);
+    assert_eq!(
+        cert.extension_oids(),
+        [
+            BasicConstraints::OID,
+            KeyUsage::OID,
+            UEID_OID,
+            SubjectKeyIdentifier::OID,
+            AuthorityKeyIdentifier::OID,
+        ]
+    );
+}
+
+#[test]
+fn test_gen_canonical_extension_order() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::keys::mldsa87_key_from_seed;
+    use const_oid::db::rfc5280::ID_KP_CLIENT_AUTH;
+    use const_oid::ObjectIdentifier;
+    use ml_dsa::MlDsa87;
+    use x509_cert::ext::pkix::ExtendedKeyUsage;
+
+    type Builder<'a> = CertTemplateBuilder<'a, ml_dsa::KeyPair<MlDsa87>>;
+
+    let eku = ExtendedKeyUsage(vec![ID_KP_CLIENT_AUTH]);
+    let build = |builder: Builder| {
+        builder
+            .set_subject_key(mldsa87_key_from_seed(&[0x01; 32]))
+            .set_issuer_key(mldsa87_key_from_seed(&[0x02; 32]))
+            .canonical_extension_order()
+            .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID")
+    };
+    let first = build(
+        Builder::ldevid_profile(&[0xFF; 17])
+            .add_ocsp_nocheck_ext()
+            .add_encoded_ext(&eku, false),
+    );
+    let second = build(
+        Builder::ldevid_profile(&[0xFF; 17])
+            .add_encoded_ext(&eku, false)
+            .add_ocsp_nocheck_ext(),
+    );
+    assert_eq!(first.tbs(), second.tbs());
+    let layout = |template: &tbs::TbsTemplate| -> Vec<(&str, usize, usize)> {
+        template
+            .params()
+            .iter()
+            .map(|p| (p.name, p.offset, p.len))
+            .collect()
+    };
+    assert_eq!(layout(&first), layout(&second));
+
+    // The extensions are sorted by the numeric arcs of their OIDs
+    let oids: Vec<ObjectIdentifier> = profile::der_extensions(first.der())
+        .iter()
+        .map(|e| e.extn_id)
+        .collect();
+    assert_eq!(oids.len(), 7);
+    assert!(oids
+        .windows(2)
+        .all(|pair| pair[0].arcs().cmp(pair[1].arcs()).is_lt()));
+
+    // The params are located in the sorted extensions
+    let param = first.params().iter().find(|p| p.name == "UEID").unwrap();
+    assert_eq!(first.tbs()[param.offset - 2..param.offset], [0x04, 17]);
+}
+
+#[test]
+fn test_gen_csr_custom_attribute() {
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::profile::EXTENSION_REQUEST_OID;
+    use const_oid::ObjectIdentifier;
+    use der::{asn1::Utf8StringRef, Decode};
+    use ml_dsa::MlDsa87;
+
+    let device_model = ObjectIdentifier::new_unwrap("2.999.2");
+    let template = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::idevid_profile(&[0xFF; 17])
+        .add_attribute(device_model, &Utf8StringRef::new("Caliptra 2.0").unwrap())
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+
+    let req = x509_cert::request::CertReq::from_der(template.der()).unwrap();
+    let mut oids: Vec<ObjectIdentifier> = req.info.attributes.iter().map(|a| a.oid).collect();
+    oids.sort();
+    let mut expected = vec![EXTENSION_REQUEST_OID, device_model];
+    expected.sort();
+    assert_eq!(oids, expected);
+
+    let attr = req
+        .info
+        .attributes
+        .iter()
+        .find(|a| a.oid == device_model)
+        .unwrap();
+    let value: Utf8StringRef = attr.values.get(0).unwrap().decode_as().unwrap();
+    assert_eq!(value.as_str(), "Caliptra 2.0");
+
+    // The attribute isn't taken for an extension
+    assert_extensions(&template, &[BC, KU, UEID]);
+}
+
+#[test]
+fn test_gen_ocsp_nocheck_ext() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use const_oid::{AssociatedOid, ObjectIdentifier};
+    use der::{asn1::Null, Decode};
+    use ml_dsa::MlDsa87;
+    use x509_cert::ext::pkix::OcspNoCheck;
+
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ocsp_nocheck_ext()
+        .tbs_template("Caliptra 2.0 MlDsa87 OCSP", "Caliptra 2.0 MlDsa87 LDevID");
+
+    let ext = decode_cert_tbs(&template)
+        .extensions
+        .unwrap()
+        .into_iter()
+        .find(|ext| ext.extn_id == OcspNoCheck::OID)
+        .unwrap();
+    assert_eq!(
+        ext.extn_id,
+        ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.48.1.5")
+    );
+    assert!(!ext.critical);
+    assert_eq!(ext.extn_value.as_bytes(), [0x05, 0x00]);
+    assert_eq!(Null::from_der(ext.extn_value.as_bytes()).unwrap(), Null);
+
+    // SEQUENCE { OID, OCTET STRING { NULL } } without the default criticality
+    let ext_der = [
+        0x30, 0x0F, 0x06, 0x09, 0x2B, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x05, 0x04, 0x02,
+        0x05, 0x00,
+    ];
+    assert!(template.tbs().windows(ext_der.len()).any(|w| w == ext_der));
+}
+
+#[test]
+fn test_gen_ecdsa_signature_encoding() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::keys::{sign, BuilderKeys, SignatureEncoding, SigningMode};
+    use der::Decode;
+    use p256::elliptic_curve::scalar::IsHigh;
+    use rand::rngs::OsRng;
+
+    let SignatureEncoding::Der { max_len } = p256::ecdsa::SigningKey::SIGNATURE_ENCODING else {
+        panic!("ECDSA signatures must be DER encoded");
+    };
+
+    let issuer_key = p256::ecdsa::SigningKey::key_gen_os();
+    let subject_key = p256::ecdsa::SigningKey::key_gen_os();
+    let gen = || {
+        let template = CertTemplateBuilder::<p256::ecdsa::SigningKey>::new()
+            .set_subject_key(subject_key.clone())
+            .set_issuer_key(issuer_key.clone())
+            .tbs_template("P256 Leaf", "P256 Issuer");
+        x509_cert::Certificate::from_der(template.der())
+            .unwrap()
+            .signature
+            .raw_bytes()
+            .to_vec()
+    };
+
+    // Ecdsa-Sig-Value of variable length, signed deterministically
+    let signature = gen();
+    assert!(p256::ecdsa::DerSignature::try_from(signature.as_slice()).is_ok());
+    assert!(signature.len() <= max_len);
+    assert_eq!(signature, gen());
+
+    // Low-S form, s <= n/2, half of the raw signatures have a high s
+    let signature = p256::ecdsa::Signature::from_der(&signature).unwrap();
+    assert!(!bool::from(signature.s().is_high()));
+    for i in 0..32_u8 {
+        let signature = sign(&issuer_key, &[i], SigningMode::Deterministic, &mut OsRng);
+        let signature = p256::ecdsa::Signature::from_der(signature.as_bytes()).unwrap();
+        assert!(!bool::from(signature.s().is_high()));
+    }
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_gen_cert_async_signer() {
+    use crate::async_signer::AsyncTemplateSigner;
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::keys::mldsa87_key_from_seed;
+    use crate::validate::BuilderError;
+    use ml_dsa::MlDsa87;
+    use signature::{Keypair, Signer};
+    use spki::EncodePublicKey;
+
+    type Key = ml_dsa::KeyPair<MlDsa87>;
+
+    /// Remote signer holding a local key, flipping a signature byte if `corrupt`
+    struct MockKms(Key, bool);
+
+    #[async_trait::async_trait]
+    impl AsyncTemplateSigner for MockKms {
+        async fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, signature::Error> {
+            let mut signature = self.0.try_sign(msg)?.encode().to_vec();
+            signature[0] ^= self.1 as u8;
+            Ok(signature)
+        }
+
+        fn verifying_key_der(&self) -> Vec<u8> {
+            let spki = self.0.verifying_key().to_public_key_der().unwrap();
+            spki.as_bytes().to_vec()
+        }
+    }
+
+    let ueid = [0xFF; 17];
+    let builder = || {
+        CertTemplateBuilder::<Key>::ldevid_profile(&ueid)
+            .set_subject_key(mldsa87_key_from_seed(&[0x01; 32]))
+    };
+    let sync = builder()
+        .set_issuer_key(mldsa87_key_from_seed(&[0x02; 32]))
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    // The signing future can be spawned on a multi-threaded runtime
+    fn assert_send<T: Send>(value: T) -> T {
+        value
+    }
+    let kms = MockKms(mldsa87_key_from_seed(&[0x02; 32]), false);
+    let remote = assert_send(builder().tbs_template_with_async_signer(
+        "Caliptra 2.0 MlDsa87 LDevID",
+        "Caliptra 2.0 MlDsa87 IDevID",
+        &kms,
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(remote.der(), sync.der());
+    assert_eq!(remote.tbs(), sync.tbs());
+    let offsets = |template: &tbs::TbsTemplate| {
+        template
+            .params()
+            .iter()
+            .map(|p| (p.name, p.offset, p.len))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(offsets(&remote), offsets(&sync));
+
+    // A signature failing verification with the public key of the signer is rejected
+    let kms = MockKms(mldsa87_key_from_seed(&[0x02; 32]), true);
+    let err = builder()
+        .tbs_template_with_async_signer(
+            "Caliptra 2.0 MlDsa87 LDevID",
+            "Caliptra 2.0 MlDsa87 IDevID",
+            &kms,
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(err, BuilderError::Signing { .. }));
+}
+
+#[test]
+fn test_gen_cross_signed_cert() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::keys::{mldsa87_key_from_seed, BuilderKeys, SECP384R1_OID};
+    use crate::tbs::tbs_range;
+    use const_oid::ObjectIdentifier;
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+    use signature::Verifier;
+    use spki::SignatureAlgorithmIdentifier;
+
+    type Subject = ml_dsa::KeyPair<MlDsa87>;
+    type Issuer = p384::ecdsa::SigningKey;
+
+    // ML-DSA-87 subject key certified by an ECDSA P-384 issuer
+    let issuer_key = Issuer::key_gen_os();
+    let template = CertTemplateBuilder::<Subject, Issuer>::cross_signed()
+        .set_subject_key(mldsa87_key_from_seed(&[0x01; 32]))
+        .set_issuer_key(issuer_key.clone())
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 EccP384 IDevID");
+
+    let cert = x509_cert::Certificate::from_der(template.der()).unwrap();
+    let signature_oid = Issuer::SIGNATURE_ALGORITHM_IDENTIFIER.oid;
+    let spki_oid = cert.tbs_certificate.subject_public_key_info.algorithm.oid;
+    assert_eq!(cert.signature_algorithm.oid, signature_oid);
+    assert_eq!(cert.tbs_certificate.signature.oid, signature_oid);
+    assert_eq!(spki_oid, Subject::SIGNATURE_ALGORITHM_IDENTIFIER.oid);
+    assert_ne!(spki_oid, signature_oid);
+
+    // The PUBLIC_KEY param is the ML-DSA-87 key
+    let public_key = template
+        .params()
+        .iter()
+        .find(|p| p.name == "PUBLIC_KEY")
+        .unwrap();
+    assert_eq!(public_key.len, 2592);
+
+    let signature = p384::ecdsa::DerSignature::try_from(cert.signature.raw_bytes()).unwrap();
+    let tbs = &template.der()[tbs_range(template.der()).unwrap()];
+    assert!(issuer_key.verifying_key().verify(tbs, &signature).is_ok());
+
+    // And vice versa, an ECDSA P-384 subject key certified by an ML-DSA-87 issuer
+    let issuer_key = mldsa87_key_from_seed(&[0x02; 32]);
+    let template = CertTemplateBuilder::<Issuer, Subject>::cross_signed()
+        .set_subject_key(Issuer::key_gen_os())
+        .set_issuer_key(issuer_key.clone())
+        .tbs_template("Caliptra 2.0 EccP384 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+
+    let cert = x509_cert::Certificate::from_der(template.der()).unwrap();
+    let signature_oid = Subject::SIGNATURE_ALGORITHM_IDENTIFIER.oid;
+    let spki = &cert.tbs_certificate.subject_public_key_info;
+    assert_eq!(cert.signature_algorithm.oid, signature_oid);
+    assert_eq!(cert.tbs_certificate.signature.oid, signature_oid);
+    assert_eq!(
+        spki.algorithm.oid,
+        ObjectIdentifier::new_unwrap("1.2.840.10045.2.1")
+    );
+    let curve = spki.algorithm.parameters.as_ref().unwrap();
+    assert_eq!(
+        curve.decode_as::<ObjectIdentifier>().unwrap(),
+        SECP384R1_OID
+    );
+
+    // The PUBLIC_KEY param is the uncompressed P-384 point
+    let public_key = template
+        .params()
+        .iter()
+        .find(|p| p.name == "PUBLIC_KEY")
+        .unwrap();
+    assert_eq!(public_key.len, 97);
+
+    let tbs = &template.der()[tbs_range(template.der()).unwrap()];
+    assert!(BuilderKeys::verify(
+        &issuer_key,
+        tbs,
+        cert.signature.raw_bytes()
+    ));
+}
+
+#[test]
+fn test_tbs_digest() {
+    use crate::code_gen::{CodeGen, CodeGenOptions};
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::tbs::{TbsDigest, TemplateError};
+    use ml_dsa::MlDsa87;
+    use sha2::{Digest, Sha384, Sha512};
+
+    let template = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::idevid_profile(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    let ueid = *template.params().iter().find(|p| p.name == "UEID").unwrap();
+
+    // Patch the TBS manually for the cross-check
+    let patched = |value: &[u8]| {
+        let mut tbs = template.tbs().to_vec();
+        tbs[ueid.offset..ueid.offset + ueid.len].copy_from_slice(value);
+        tbs
+    };
+    let ueid_a: &[u8] = &[0xA5; 17];
+    let ueid_b: &[u8] = &[0x5A; 17];
+
+    assert_eq!(
+        template
+            .digest(&[("UEID", ueid_a)], TbsDigest::Sha384)
+            .unwrap(),
+        Sha384::digest(patched(ueid_a)).to_vec()
+    );
+    assert_eq!(
+        template
+            .digest(&[("UEID", ueid_a)], TbsDigest::Sha512)
+            .unwrap(),
+        Sha512::digest(patched(ueid_a)).to_vec()
+    );
+    assert_eq!(
+        template
+            .digests(
+                &[&[("UEID", ueid_a)], &[("UEID", ueid_b)]],
+                TbsDigest::Sha384
+            )
+            .unwrap(),
+        [
+            Sha384::digest(patched(ueid_a)).to_vec(),
+            Sha384::digest(patched(ueid_b)).to_vec()
+        ]
+    );
+    assert_eq!(
+        template.digest(&[("UEID", &[0xA5; 16])], TbsDigest::Sha384),
+        Err(TemplateError::ValueLength {
+            name: "UEID",
+            len: 17,
+            value_len: 16
+        })
+    );
+    assert_eq!(
+        template.digest(&[("VENDOR", &[0xA5; 4])], TbsDigest::Sha384),
+        Err(TemplateError::UnknownParam {
+            name: "VENDOR".to_string()
+        })
+    );
+
+    // The placeholder digest emitted by CodeGen matches the template bytes
+    let expected = Sha512::digest(template.tbs());
+    let code = CodeGen::code(
+        "InitDevIdCsrTbsMlDsa87",
+        template,
+        &CodeGenOptions::new().tbs_digest_const(TbsDigest::Sha512),
+    );
+    let expected: Vec<String> = expected.iter().map(|b| format!("{}u8", b)).collect();
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    assert!(code.contains(&format!(
+        "pubconstTBS_TEMPLATE_SHA512:[u8;64usize]=[{},]",
+        expected.join(",")
+    )));
+}
+
+#[test]
+fn test_tbs_template_patch() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::keys::BuilderKeys;
+    use crate::tbs::TemplateError;
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+    use signature::Keypair;
+    use spki::EncodePublicKey;
+    use std::collections::HashMap;
+
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+
+    // Public key bytes of another key
+    let key = ml_dsa::KeyPair::<MlDsa87>::key_gen_os();
+    let spki_der = key.verifying_key().to_public_key_der().unwrap();
+    let spki = spki::SubjectPublicKeyInfoOwned::from_der(spki_der.as_bytes()).unwrap();
+    let public_key = spki.subject_public_key.raw_bytes().to_vec();
+
+    let mut values: HashMap<&str, Vec<u8>> = HashMap::new();
+    values.insert("UEID", vec![0xA5; 17]);
+    values.insert("PUBLIC_KEY", public_key.clone());
+    values.insert("NOT_BEFORE", b"230101000000Z".to_vec());
+    values.insert("NOT_AFTER", b"491231235959Z".to_vec());
+    let tbs = template.patch(&values).unwrap();
+
+    let tbs = x509_cert::certificate::TbsCertificate::from_der(&tbs).unwrap();
+    assert_eq!(
+        tbs.subject_public_key_info.subject_public_key.raw_bytes(),
+        public_key
+    );
+    let ueid = tbs
+        .extensions
+        .unwrap()
+        .into_iter()
+        .find(|ext| ext.extn_id == crate::profile::UEID_OID)
+        .unwrap();
+    assert!(ueid.extn_value.as_bytes().ends_with(&[0xA5; 17]));
+
+    values.insert("UEID", vec![0xA5; 16]);
+    assert!(matches!(
+        template.patch(&values),
+        Err(TemplateError::ValueLength { name: "UEID", .. })
+    ));
+    values.insert("UEID", vec![0xA5; 17]);
+    values.insert("VENDOR", vec![0xA5; 4]);
+    assert!(matches!(
+        template.patch(&values),
+        Err(TemplateError::UnknownParam { .. })
+    ));
+}
+
+#[test]
+fn test_gen_non_critical_tcb_info() {
+    use crate::cert_rustcrypto::{CertTemplateBuilder, Fwid, FwidParam};
+    use crate::profile::{MULTI_TCB_INFO_OID, TCB_INFO_OID};
+    use const_oid::ObjectIdentifier;
+    use ml_dsa::MlDsa87;
+    use std::borrow::Cow;
+
+    let rt_fwids = [FwidParam {
+        name: "TCB_INFO_RT_TCI",
+        fwid: Fwid {
+            hash_alg: ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.2"),
+            digest: Cow::Borrowed(&[0xCD; 48]),
+        },
+    }];
+
+    let tcb_info_critical = |critical: bool| {
+        let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+            .add_rt_dice_tcb_info_ext(0xC4, &rt_fwids, critical)
+            .tbs_template(
+                "Caliptra 2.0 MlDsa87 RT Alias",
+                "Caliptra 2.0 MlDsa87 FMC Alias",
+            );
+        decode_cert_tbs(&template)
+            .extensions
+            .unwrap()
+            .into_iter()
+            .find(|ext| ext.extn_id == TCB_INFO_OID)
+            .unwrap()
+            .critical
+    };
+
+    assert!(!tcb_info_critical(false));
+    assert!(tcb_info_critical(true));
+
+    let multi_tcb_info_critical = |critical: bool| {
+        let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+            .add_fmc_dice_tcb_info_ext(&[], &rt_fwids, critical)
+            .tbs_template(
+                "Caliptra 2.0 MlDsa87 FMC Alias",
+                "Caliptra 2.0 MlDsa87 LDevID",
+            );
+        decode_cert_tbs(&template)
+            .extensions
+            .unwrap()
+            .into_iter()
+            .find(|ext| ext.extn_id == MULTI_TCB_INFO_OID)
+            .unwrap()
+            .critical
+    };
+
+    assert!(!multi_tcb_info_critical(false));
+    assert!(multi_tcb_info_critical(true));
+}
+
+#[test]
+fn test_gen_seeded_rng_reproducible() {
+    let gen_cert = |seed| generate_test_template(TestAlg::MlDsa87, TestScenario::LdevidCert, seed);
+    let gen_csr = |seed| generate_test_template(TestAlg::MlDsa87, TestScenario::IdevidCsr, seed);
+
+    assert_eq!(gen_cert(1).der(), gen_cert(1).der());
+    assert_eq!(gen_cert(1).tbs(), gen_cert(1).tbs());
+    assert_ne!(gen_cert(1).der(), gen_cert(2).der());
+    assert_eq!(gen_csr(1).der(), gen_csr(1).der());
+    assert_ne!(gen_csr(1).der(), gen_csr(2).der());
+}
+
+#[test]
+fn test_gen_csr_from_mnemonic() {
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::keys::{mldsa87_key_from_seed, seed_from_mnemonic};
+    use ml_dsa::MlDsa87;
+
+    let phrase = "correct horse battery staple";
+    let public_key = |phrase: &str| {
+        let template = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+            .add_ueid_ext(&[0xFF; 17])
+            .tbs_template_from_mnemonic(phrase, "Caliptra 2.0 MlDsa87 IDevID");
+        let param = template
+            .params()
+            .iter()
+            .find(|p| p.name == "PUBLIC_KEY")
+            .copied()
+            .unwrap();
+        let offset = template.tbs_offset() + param.offset;
+        template.der()[offset..offset + param.len].to_vec()
+    };
+
+    // The same phrase, whatever its whitespace, yields the same key
+    assert_eq!(public_key(phrase), public_key(phrase));
+    assert_eq!(
+        public_key(phrase),
+        public_key("  correct horse\tbattery  staple\n")
+    );
+    assert_ne!(
+        public_key(phrase),
+        public_key("Correct horse battery staple")
+    );
+
+    // The key is the FIPS 204 key of the HKDF-SHA256 seed of the phrase
+    let key = mldsa87_key_from_seed(&seed_from_mnemonic(phrase));
+    assert_eq!(public_key(phrase), &key.verifying_key().encode()[..]);
+}
+
+#[test]
+fn test_gen_cert_from_mnemonic() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::keys::{seed_from_mnemonic, BuilderKeys, ECDSA_KEY_SEED_INFO};
+    use p384::ecdsa::SigningKey;
+    use spki::EncodePublicKey;
+
+    let phrase = "correct horse battery staple";
+    let public_key = |phrase: &str| {
+        let template = CertTemplateBuilder::<SigningKey>::ldevid_profile(&[0xFF; 17])
+            .tbs_template_from_mnemonic(
+                phrase,
+                "Caliptra 2.0 P384 LDevID",
+                "Caliptra 2.0 P384 IDevID",
+            );
+        let param = template
+            .params()
+            .iter()
+            .find(|p| p.name == "PUBLIC_KEY")
+            .copied()
+            .unwrap();
+        let offset = template.tbs_offset() + param.offset;
+        template.der()[offset..offset + param.len].to_vec()
+    };
+
+    assert_eq!(public_key(phrase), public_key(phrase));
+    assert_ne!(
+        public_key(phrase),
+        public_key("Correct horse battery staple")
+    );
+
+    // The key is the first HKDF-SHA256 candidate of the seed within the curve order
+    let seed = seed_from_mnemonic(phrase);
+    let key = SigningKey::key_gen_from_seed(&seed);
+    let mut scalar = [0; 48];
+    hkdf::Hkdf::<sha2::Sha256>::new(None, &seed)
+        .expand_multi_info(&[ECDSA_KEY_SEED_INFO, &[0]], &mut scalar)
+        .unwrap();
+    assert_eq!(key.to_bytes().as_slice(), &scalar);
+    let spki = key.verifying_key().to_public_key_der().unwrap();
+    assert!(spki.as_bytes().ends_with(&public_key(phrase)));
+}
+
+/// Check the params and signature algorithm of every scenario signed with `alg`
+#[cfg(test)]
+fn check_test_templates(alg: TestAlg) {
+    use der::Decode;
+    use x509_cert::request::CertReq;
+    use x509_cert::Certificate;
+
+    for scenario in [
+        TestScenario::IdevidCsr,
+        TestScenario::LdevidCert,
+        TestScenario::RtAliasCert,
+    ] {
+        let template = generate_test_template(alg, scenario, 7);
+        assert_eq!(
+            template.der(),
+            generate_test_template(alg, scenario, 7).der(),
+            "{:?} {:?}",
+            alg,
+            scenario
+        );
+
+        let mut names: Vec<&str> = template.params().iter().map(|p| p.name).collect();
+        let mut expected = scenario.param_names().to_vec();
+        names.sort();
+        expected.sort();
+        assert_eq!(names, expected, "{:?} {:?}", alg, scenario);
+
+        let public_key = template.params().iter().find(|p| p.name == "PUBLIC_KEY");
+        assert_eq!(public_key.unwrap().len, alg.public_key_len(), "{:?}", alg);
+
+        let signature_oid = match scenario {
+            TestScenario::IdevidCsr => CertReq::from_der(template.der()).unwrap().algorithm.oid,
+            _ => {
+                Certificate::from_der(template.der())
+                    .unwrap()
+                    .signature_algorithm
+                    .oid
+            }
+        };
+        assert_eq!(
+            signature_oid,
+            alg.signature_oid(),
+            "{:?} {:?}",
+            alg,
+            scenario
+        );
+        assert_eq!(template.algorithm_oid(), signature_oid);
+    }
+}
+
+#[test]
+fn test_gen_test_templates_mldsa87() {
+    check_test_templates(TestAlg::MlDsa87);
+}
+
+#[test]
+fn test_gen_test_templates_hash_mldsa87() {
+    check_test_templates(TestAlg::HashMlDsa87);
+}
+
+#[test]
+fn test_gen_test_templates_ed25519() {
+    check_test_templates(TestAlg::Ed25519);
+}
+
+#[test]
+fn test_gen_test_templates_p256() {
+    check_test_templates(TestAlg::EcdsaP256);
+}
+
+#[test]
+fn test_gen_test_templates_p384() {
+    check_test_templates(TestAlg::EcdsaP384);
+}
+
+#[cfg(feature = "rsa")]
+#[test]
+fn test_gen_test_templates_rsa() {
+    check_test_templates(TestAlg::RsaSha384);
+}
+
+#[test]
+fn test_signing_mode() {
+    use crate::keys::{mldsa87_key_from_seed, sign, BuilderKeys, HashMlDsa87KeyPair, SigningMode};
+    use ml_dsa::MlDsa87;
+    use rand::rngs::OsRng;
+    use spki::SignatureBitStringEncoding;
+
+    let tbs = b"TBS signed twice";
+    let key = mldsa87_key_from_seed(&[0x01; 32]);
+    let pre_hash_key = HashMlDsa87KeyPair::new(mldsa87_key_from_seed(&[0x02; 32]));
+    let raw = |signature: ml_dsa::Signature<MlDsa87>| signature.to_bitstring().unwrap();
+
+    let deterministic = || raw(sign(&key, tbs, SigningMode::Deterministic, &mut OsRng));
+    assert_eq!(deterministic(), deterministic());
+
+    let hedged = || raw(sign(&key, tbs, SigningMode::Hedged, &mut OsRng));
+    let (first, second) = (hedged(), hedged());
+    assert_ne!(first, second);
+    assert!(key.verify(tbs, first.raw_bytes()));
+    assert!(key.verify(tbs, second.raw_bytes()));
+
+    let pre_hash_hedged = || raw(sign(&pre_hash_key, tbs, SigningMode::Hedged, &mut OsRng));
+    let (first, second) = (pre_hash_hedged(), pre_hash_hedged());
+    assert_ne!(first, second);
+    assert!(pre_hash_key.verify(tbs, first.raw_bytes()));
+}
+
+#[test]
+fn test_gen_hedged_cert() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::keys::{mldsa87_key_from_seed, SigningMode};
+    use ml_dsa::MlDsa87;
+
+    let gen = |mode| {
+        CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+            .set_subject_key(mldsa87_key_from_seed(&[0x01; 32]))
+            .set_issuer_key(mldsa87_key_from_seed(&[0x02; 32]))
+            .signing_mode(mode)
+            .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID")
+    };
+
+    let deterministic = gen(SigningMode::Deterministic);
+    let hedged = gen(SigningMode::Hedged);
+    assert_eq!(deterministic.tbs(), hedged.tbs());
+    assert_ne!(hedged.der(), gen(SigningMode::Hedged).der());
+}
+
+#[test]
+fn test_gen_forced_version() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+    use x509_cert::certificate::Version;
+    use x509_cert::Certificate;
+
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+        .version(Version::V3)
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    let cert = Certificate::from_der(template.der()).unwrap();
+    assert_eq!(cert.tbs_certificate.version, Version::V3);
+}
+
+#[test]
+fn test_gen_v1_with_extensions_fails() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::validate::BuilderError;
+    use ml_dsa::MlDsa87;
+    use x509_cert::certificate::Version;
+
+    let err = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+        .version(Version::V1)
+        .try_tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID")
+        .unwrap_err();
+    assert_eq!(
+        err,
+        BuilderError::Version {
+            version: Version::V1,
+            fields: "extensions",
+            required: Version::V3,
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "V1 certificates can't carry extensions, they require V3"
+    );
+}
+
+#[test]
+#[should_panic(expected = "1.3.101.112 keys have no hedged signing mode")]
+fn test_gen_hedged_ed25519_fails() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::keys::{Ed25519KeyPair, SigningMode};
+
+    CertTemplateBuilder::<Ed25519KeyPair>::ldevid_profile(&[0xFF; 17])
+        .signing_mode(SigningMode::Hedged)
+        .tbs_template("Caliptra 2.0 Ed25519 LDevID", "Caliptra 2.0 Ed25519 IDevID");
+}
+
+#[test]
+fn test_gen_placeholder_signature() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+
+    // FIPS 204 Table 2
+    const MLDSA87_SIGNATURE_LEN: usize = 4627;
+
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+        .placeholder_signature()
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+
+    let cert = x509_cert::Certificate::from_der(template.der()).unwrap();
+    let signature = cert.signature.raw_bytes();
+    assert_eq!(signature.len(), MLDSA87_SIGNATURE_LEN);
+    assert!(signature.iter().all(|&b| b == 0));
+
+    let param = template.signature_param().unwrap();
+    assert_eq!(param.len, MLDSA87_SIGNATURE_LEN);
+    assert_eq!(param.offset + param.len, template.der().len());
+    let params = template.params_in_der().unwrap();
+    assert!(params
+        .iter()
+        .any(|p| p.name == "SIGNATURE" && p.offset == param.offset));
+}
+
+#[test]
+fn test_gen_csr_placeholder_signature() {
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::keys::mldsa87_key_from_seed;
+    use crate::name::key_bytes_hash;
+    use der::{Decode, Encode};
+    use ml_dsa::MlDsa87;
+    use signature::{Keypair, Signer, Verifier};
+    use x509_cert::request::CertReq;
+
+    // FIPS 204 Table 2
+    const MLDSA87_SIGNATURE_LEN: usize = 4627;
+
+    let template = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .set_key(mldsa87_key_from_seed(&[0x01; 32]))
+        .placeholder_signature()
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+
+    let req = CertReq::from_der(template.der()).unwrap();
+    assert_eq!(req.signature.raw_bytes().len(), MLDSA87_SIGNATURE_LEN);
+    assert!(req.signature.raw_bytes().iter().all(|&b| b == 0));
+    let signature_param = template.signature_param().unwrap();
+    assert_eq!(signature_param.len, MLDSA87_SIGNATURE_LEN);
+    assert_eq!(
+        signature_param.offset + signature_param.len,
+        template.der().len()
+    );
+
+    // Firmware patches the CertificationRequestInfo of its own key and signs it
+    let key = mldsa87_key_from_seed(&[0x05; 32]);
+    let public_key = key.verifying_key().encode();
+    let subject_sn = key_bytes_hash(&public_key);
+    let info = template
+        .apply(&[
+            ("PUBLIC_KEY", &public_key[..]),
+            ("SUBJECT_SN", subject_sn.as_bytes()),
+        ])
+        .unwrap();
+    let signature = key.sign(&info).encode();
+
+    // The info and the signature are patched in the full DER, whose length is unchanged
+    let mut der = template.der().to_vec();
+    let params = template.params_in_der().unwrap();
+    der[template.tbs_offset()..template.tbs_offset() + info.len()].copy_from_slice(&info);
+    let param = params.iter().find(|p| p.name == "PUBLIC_KEY").unwrap();
+    assert_eq!(der[param.offset..param.offset + param.len], public_key[..]);
+    let param = params.iter().find(|p| p.name == "SIGNATURE").unwrap();
+    der[param.offset..param.offset + param.len].copy_from_slice(&signature);
+
+    let req = CertReq::from_der(&der).unwrap();
+    assert_eq!(req.info.to_der().unwrap(), info);
+    let signature = ml_dsa::Signature::<MlDsa87>::try_from(req.signature.raw_bytes()).unwrap();
+    assert!(key.verifying_key().verify(&info, &signature).is_ok());
+}
+
+#[test]
+fn test_tbs_range() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::tbs::{get_tbs, tbs_range};
+    use ml_dsa::MlDsa87;
+
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+
+    let range = tbs_range(template.der()).unwrap();
+    assert_eq!(range.start, template.tbs_offset());
+    assert_eq!(range.len(), template.tbs().len());
+    assert_eq!(&template.der()[range], get_tbs(template.der()).unwrap());
+}
+
+#[test]
+fn test_get_tbs() {
+    use crate::tbs::{get_tbs, get_tbs_offset, TemplateError};
+
+    // SEQUENCE { SEQUENCE { OCTET STRING }, NULL } with a long form outer length
+    let mut der = vec![0x30, 0x81, 0x85, 0x30, 0x81, 0x80, 0x04, 0x7E];
+    der.extend([0xAA; 0x7E]);
+    der.extend([0x05, 0x00]);
+    assert_eq!(get_tbs_offset(&der), Ok(3));
+    let tbs = get_tbs(&der).unwrap();
+    assert_eq!(tbs.len(), 0x83);
+    assert_eq!(tbs.as_ptr(), der[3..].as_ptr());
+
+    let invalid = |element| Err(TemplateError::InvalidDer { element });
+    assert_eq!(get_tbs(&[]), invalid("signed"));
+    assert_eq!(get_tbs(&[0x04, 0x00]), invalid("signed"));
+    // Truncated DER
+    assert_eq!(get_tbs(&der[..100]), invalid("signed"));
+    // Outer SEQUENCE without a TBS SEQUENCE, or with a TBS overrunning it
+    assert_eq!(get_tbs(&[0x30, 0x02, 0x05, 0x00]), invalid("TBS"));
+    assert_eq!(get_tbs(&[0x30, 0x02, 0x30, 0x05, 0x00]), invalid("TBS"));
+}
+
+#[test]
+fn test_init_param() {
+    use crate::tbs::{init_param, init_repeated_param, TemplateError};
+
+    let haystack = [0x01, 0xAA, 0xBB, 0x02, 0xAA, 0xBB, 0x03, 0xCC];
+    let param = TbsParam::new("VALUE", 0, 2);
+
+    let found = init_param(&[0x03, 0xCC], &haystack, param).unwrap();
+    assert_eq!((found.name, found.offset, found.len), ("VALUE", 6, 2));
+
+    let count_error = |expected, found| {
+        Err(TemplateError::NeedleCount {
+            name: "VALUE",
+            expected,
+            found,
+        })
+    };
+    assert_eq!(
+        init_param(&[0xDD, 0xDD], &haystack, param).map(|p| p.offset),
+        count_error(1, 0)
+    );
+    assert_eq!(
+        init_param(&[0xAA, 0xBB], &haystack, param).map(|p| p.offset),
+        count_error(1, 2)
+    );
+    assert_eq!(
+        init_param(&[0xAA], &haystack, param).map(|p| p.offset),
+        Err(TemplateError::ValueLength {
+            name: "VALUE",
+            len: 2,
+            value_len: 1
+        })
+    );
+
+    // Params sharing a needle take its first instance, as many as it has
+    let first = init_repeated_param(&[0xAA, 0xBB], &haystack, param, 2).unwrap();
+    assert_eq!(first.offset, 1);
+    assert_eq!(
+        init_repeated_param(&[0xAA, 0xBB], &haystack, param, 3).map(|p| p.offset),
+        count_error(3, 2)
+    );
+}
+
+#[test]
+fn test_sanitize() {
+    use crate::tbs::sanitize;
+
+    let mut buf = [0x11; 8];
+    let param = sanitize(TbsParam::new("VALUE", 2, 3), &mut buf);
+    assert_eq!(param.offset, 2);
+    assert_eq!(buf, [0x11, 0x11, 0x5F, 0x5F, 0x5F, 0x11, 0x11, 0x11]);
+
+    // Bytes past the buffer are ignored
+    sanitize(TbsParam::new("VALUE", 6, 4), &mut buf);
+    assert_eq!(buf[6..], [0x5F, 0x5F]);
+}
+
+#[test]
+fn test_signature_algorithms_match() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::keys::HASH_ML_DSA_87_WITH_SHA512_OID;
+    use crate::tbs::{check_signature_algorithms, TemplateError};
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+    use x509_cert::Certificate;
+
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    let cert = Certificate::from_der(template.der()).unwrap();
+    let tbs_signature = &cert.tbs_certificate.signature;
+    assert_eq!(
+        check_signature_algorithms(tbs_signature, &cert.signature_algorithm),
+        Ok(())
+    );
+
+    let mut other = cert.signature_algorithm.clone();
+    other.oid = HASH_ML_DSA_87_WITH_SHA512_OID;
+    assert!(matches!(
+        check_signature_algorithms(tbs_signature, &other),
+        Err(TemplateError::AlgorithmMismatch { .. })
+    ));
+}
+
+#[test]
+fn test_locate_public_key() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::tbs::{locate_public_key, TemplateError};
+    use ml_dsa::MlDsa87;
+
+    type Key = ml_dsa::KeyPair<MlDsa87>;
+
+    let csr = CsrTemplateBuilder::<Key>::idevid_profile(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    let cert = CertTemplateBuilder::<Key>::ldevid_profile(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+
+    // The PUBLIC_KEY param sits in the subjectPublicKey of the SubjectPublicKeyInfo
+    for (template, spki_path) in [(&csr, [2]), (&cert, [6])] {
+        let tbs = &template.der()[template.tbs_offset()..];
+        let param = template
+            .params()
+            .iter()
+            .find(|p| p.name == "PUBLIC_KEY")
+            .unwrap();
+        let public_key = &tbs[param.offset..param.offset + param.len];
+        assert_eq!(
+            locate_public_key(tbs, &spki_path, public_key),
+            Ok(param.offset)
+        );
+
+        // A key the encoder represented differently, e.g. compressed, isn't found
+        let other = &public_key[..public_key.len() / 2];
+        assert_eq!(
+            locate_public_key(tbs, &spki_path, other),
+            Err(TemplateError::PublicKeyNotFound {
+                key_len: other.len()
+            })
+        );
+    }
+}
+
+#[test]
+fn test_stored_keys_chain_templates() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::keys::{mldsa87_key_from_seed, BuilderKeys};
+    use crate::keystore::{KeyStore, KeyStoreError};
+    use der::{Decode, Encode};
+    use ml_dsa::MlDsa87;
+    use signature::Keypair;
+    use spki::EncodePublicKey;
+    use x509_cert::Certificate;
+
+    let dir = std::env::temp_dir().join("stored_keys_chain");
+    let _ = std::fs::remove_dir_all(&dir);
+    let store = KeyStore::new(&dir);
+
+    // Separate builders, as if the templates were generated on different days
+    let ldevid = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+        .with_stored_subject_key(&store, "ldevid")
+        .with_stored_issuer_key(&store, "idevid")
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    let fmc_alias = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+        .with_stored_subject_key(&store, "fmc_alias")
+        .with_stored_issuer_key(&store, "ldevid")
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+            "Caliptra 2.0 MlDsa87 LDevID",
+        );
+
+    // The FMC alias certificate is signed by the LDevID subject key
+    let ldevid = Certificate::from_der(ldevid.der()).unwrap();
+    let fmc_alias = Certificate::from_der(fmc_alias.der()).unwrap();
+    let ldevid_spki = ldevid
+        .tbs_certificate
+        .subject_public_key_info
+        .to_der()
+        .unwrap();
+    let issuer_key = store.load("ldevid").unwrap();
+    let issuer_spki = Keypair::verifying_key(&issuer_key)
+        .to_public_key_der()
+        .unwrap();
+    assert_eq!(ldevid_spki, issuer_spki.as_bytes());
+    assert!(issuer_key.verify(
+        &fmc_alias.tbs_certificate.to_der().unwrap(),
+        fmc_alias.signature.raw_bytes()
+    ));
+
+    // Saved key pairs load back, and missing keys are generated with the builder RNG
+    let key = mldsa87_key_from_seed(&[0x03; 32]);
+    store.save("rt_alias", &key).unwrap();
+    let loaded = store.load("rt_alias").unwrap();
+    assert_eq!(
+        loaded.verifying_key().encode(),
+        key.verifying_key().encode()
+    );
+    let seeded = |name| {
+        CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+            .set_rng(<rand::rngs::StdRng as rand::SeedableRng>::seed_from_u64(1))
+            .with_stored_subject_key(&store, name)
+            .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+        store.load(name).unwrap().verifying_key().encode()
+    };
+    assert_eq!(seeded("seeded_1"), seeded("seeded_2"));
+
+    // Flipped bytes are reported rather than yielding another key
+    let path = store.path("ldevid");
+    let mut data = std::fs::read(&path).unwrap();
+    data[10] ^= 0xFF;
+    std::fs::write(&path, data).unwrap();
+    assert!(matches!(
+        store.load("ldevid"),
+        Err(KeyStoreError::Corrupted {
+            reason: "checksum mismatch",
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_gen_code_formatted() {
+    use crate::cert_rustcrypto::{CertTemplateBuilder, Fwid, FwidParam};
+    use crate::code_gen::CodeGen;
+    use const_oid::ObjectIdentifier;
+    use ml_dsa::MlDsa87;
+    use std::borrow::Cow;
+
+    let sha384_oid = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.2");
+    let fwids = [FwidParam {
+        name: "TCB_INFO_DEVICE_INFO_HASH",
+        fwid: Fwid {
+            hash_alg: sha384_oid,
+            digest: Cow::Borrowed(&[0xEF; 48]),
+        },
+    }];
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::fmc_alias_profile(
+        &[0xFF; 17],
+        &fwids,
+        &[],
+    )
+    .tbs_template(
+        "Caliptra 2.0 MlDsa87 FMC Alias",
+        "Caliptra 2.0 MlDsa87 LDevID",
+    );
+    let tbs = template.tbs().to_vec();
+
+    let out_dir = std::env::temp_dir();
+    let path = CodeGen::gen_code(
+        "FmcAliasCertTbsMlDsa87",
+        template,
+        out_dir.to_str().unwrap(),
+    );
+    let code = std::fs::read_to_string(path).unwrap();
+    assert!(code.lines().all(|line| line.len() <= 100));
+
+    // The template bytes survive the formatting
+    let file = syn::parse_file(&code).unwrap();
+    let tbs_template = file
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            syn::Item::Impl(item) => Some(&item.items),
+            _ => None,
+        })
+        .flatten()
+        .find_map(|item| match item {
+            syn::ImplItem::Const(c) if c.ident == "TBS_TEMPLATE" => Some(&c.expr),
+            _ => None,
+        })
+        .unwrap();
+    let syn::Expr::Array(array) = tbs_template else {
+        panic!("TBS_TEMPLATE is not an array");
+    };
+    let bytes: Vec<u8> = array
+        .elems
+        .iter()
+        .map(|elem| match elem {
+            syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Int(int),
+                ..
+            }) => int.base10_parse().unwrap(),
+            _ => panic!("TBS_TEMPLATE element is not an integer"),
+        })
+        .collect();
+    assert_eq!(bytes, tbs);
+}
+
+#[test]
+fn test_fwid_from_measurement_file() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::measurement::{FwidDigest, SHA384_OID};
+    use ml_dsa::MlDsa87;
+
+    let path = std::env::temp_dir().join("fmc_measurement.hex");
+    std::fs::write(&path, format!("{}\n", hex::encode([0xCD; 48]))).unwrap();
+
+    let fmc = FwidDigest::from_file(&path).unwrap();
+    assert_eq!(fmc.hash_alg, SHA384_OID);
+    assert_eq!(fmc.digest, [0xCD; 48]);
+
+    let fmc_fwids = [fmc.param("TCB_INFO_FMC_TCI")];
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::fmc_alias_profile(
+        &[0xFF; 17],
+        &[],
+        &fmc_fwids,
+    )
+    .tbs_template(
+        "Caliptra 2.0 MlDsa87 FMC Alias",
+        "Caliptra 2.0 MlDsa87 LDevID",
+    );
+    let param = template
+        .params()
+        .iter()
+        .find(|p| p.name == "TCB_INFO_FMC_TCI")
+        .unwrap();
+    assert_eq!(param.len, 48);
+}
+
+#[test]
+fn test_gen_indexed_rt_tcb_info() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::tbs::element_children;
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+    use x509_cert::Certificate;
+
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_indexed_rt_dice_tcb_info_ext(0xC4, &[], Some(2), Some(b"RT_LAYER_2"), true)
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 RT Alias",
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+        );
+
+    let cert = Certificate::from_der(template.der()).unwrap();
+    let extensions = cert.tbs_certificate.extensions.unwrap();
+    let tcb_info = extensions
+        .iter()
+        .find(|ext| ext.extn_id.to_string() == TCB_INFO)
+        .unwrap()
+        .extn_value
+        .as_bytes();
+
+    // DiceTcbInfo fields are [n] IMPLICIT, so each is found by its context-specific tag
+    let field = |tag: u8| {
+        element_children(tcb_info, 0)
+            .into_iter()
+            .find(|&(offset, _)| tcb_info[offset] == tag)
+            .map(|(offset, len)| &tcb_info[offset + 2..offset + len])
+            .unwrap()
+    };
+    // index [5] INTEGER
+    assert_eq!(field(0x85), [2]);
+    // type [9] OCTET STRING
+    assert_eq!(field(0x89), b"RT_LAYER_2");
+}
+
+#[test]
+fn test_gen_rt_tcb_info_version_param() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::tbs::element_children;
+    use crate::validate::BuilderError;
+    use ml_dsa::MlDsa87;
+
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_rt_dice_tcb_info_ext(0xC4, &[], true)
+        .add_rt_tcb_info_version_param(16)
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 RT Alias",
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+        );
+    let param = template
+        .params()
+        .iter()
+        .find(|p| p.name == "TCB_INFO_RT_VERSION")
+        .unwrap();
+    assert_eq!(param.len, 16);
+
+    let version = format!("{:<16}", "1.0.0");
+    let tbs = template
+        .apply(&[("TCB_INFO_RT_VERSION", version.as_bytes())])
+        .unwrap();
+    let patched = tbs::TbsTemplate::new(tbs, template.params().to_vec());
+    let extensions = decode_cert_tbs(&patched).extensions.unwrap();
+    let tcb_info = extensions
+        .iter()
+        .find(|ext| ext.extn_id.to_string() == TCB_INFO)
+        .unwrap()
+        .extn_value
+        .as_bytes();
+
+    // version [2] IMPLICIT UTF8String
+    let (offset, len) = element_children(tcb_info, 0)
+        .into_iter()
+        .find(|&(offset, _)| tcb_info[offset] == 0x82)
+        .unwrap();
+    let decoded = std::str::from_utf8(&tcb_info[offset + 2..offset + len]).unwrap();
+    assert_eq!(decoded, version);
+    assert_eq!(decoded.trim_end(), "1.0.0");
+
+    let err = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_rt_dice_tcb_info_ext(0xC4, &[], true)
+        .add_rt_tcb_info_version_param(0)
+        .try_tbs_template(
+            "Caliptra 2.0 MlDsa87 RT Alias",
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+        )
+        .unwrap_err();
+    assert_eq!(
+        err,
+        BuilderError::EmptyField {
+            field: "TcbInfo version"
+        }
+    );
+
+    // The param belongs to the RT TcbInfo
+    let err = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_rt_tcb_info_version_param(16)
+        .try_tbs_template(
+            "Caliptra 2.0 MlDsa87 RT Alias",
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+        )
+        .unwrap_err();
+    assert!(matches!(err, BuilderError::MissingExtension { .. }));
+}
+
+#[test]
+fn test_gen_rt_tcb_info_vendor_info() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::tbs::element_children;
+    use crate::validate::BuilderError;
+    use ml_dsa::MlDsa87;
+
+    let flags = [0x80, 0x00, 0x00, 0x01];
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_rt_dice_tcb_info_ext(0xC4, &[], true)
+        .add_rt_tcb_info_operational_flags(&flags)
+        .add_rt_tcb_info_vendor_info_param(&[0xA5; 16])
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 RT Alias",
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+        );
+    let param = template
+        .params()
+        .iter()
+        .find(|p| p.name == "TCB_INFO_RT_VENDOR_INFO")
+        .unwrap();
+    assert_eq!(param.len, 16);
+
+    let nonce = [0x3C; 16];
+    let tbs = template
+        .apply(&[("TCB_INFO_RT_VENDOR_INFO", &nonce)])
+        .unwrap();
+    let patched = tbs::TbsTemplate::new(tbs, template.params().to_vec());
+    let extensions = decode_cert_tbs(&patched).extensions.unwrap();
+    let tcb_info = extensions
+        .iter()
+        .find(|ext| ext.extn_id.to_string() == TCB_INFO)
+        .unwrap()
+        .extn_value
+        .as_bytes();
+    let field = |tag: u8| {
+        element_children(tcb_info, 0)
+            .into_iter()
+            .find(|&(offset, _)| tcb_info[offset] == tag)
+            .map(|(offset, len)| &tcb_info[offset + 2..offset + len])
+            .unwrap()
+    };
+    // operationalFlags [7] IMPLICIT BIT STRING
+    assert!(field(0x87).ends_with(&flags));
+    // vendorInfo [8] IMPLICIT OCTET STRING
+    assert_eq!(field(0x88), nonce);
+
+    // The fields belong to the RT TcbInfo
+    let err = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_rt_tcb_info_vendor_info(&nonce)
+        .try_tbs_template(
+            "Caliptra 2.0 MlDsa87 RT Alias",
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+        )
+        .unwrap_err();
+    assert_eq!(
+        err,
+        BuilderError::MissingExtension {
+            field: "vendorInfo",
+            extension: "RT TcbInfo",
+        }
+    );
+}
+
+#[test]
+fn test_gen_fwid_hash_alg_param() {
+    use crate::cert_rustcrypto::{CertTemplateBuilder, Fwid, FwidParam};
+    use crate::measurement::{padded_digest, SHA384_OID, SHA512_OID};
+    use crate::tbs::element_children;
+    use const_oid::ObjectIdentifier;
+    use der::asn1::BitString;
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+    use std::borrow::Cow;
+
+    // Built for the SHA-512 capacity
+    let rt_fwids = [FwidParam {
+        name: "TCB_INFO_RT_TCI",
+        fwid: Fwid {
+            hash_alg: SHA512_OID,
+            digest: Cow::Borrowed(&[0xCD; 64]),
+        },
+    }];
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_rt_dice_tcb_info_ext(0xC4, &rt_fwids, true)
+        .add_fwid_hash_alg_param("TCB_INFO_RT_TCI")
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 RT Alias",
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+        );
+    let param = |name: &str| template.params().iter().find(|p| p.name == name).unwrap();
+    assert_eq!(param("TCB_INFO_RT_TCI_HASH_ALG").len, 9);
+    assert_eq!(param("TCB_INFO_RT_TCI").len, 64);
+
+    let sha384_digest = padded_digest(&[0x12; 48], 64);
+    let cases: [(ObjectIdentifier, &[u8]); 2] =
+        [(SHA512_OID, &[0xAB; 64]), (SHA384_OID, &sha384_digest)];
+    for (hash_alg, digest) in cases {
+        let tbs = template
+            .apply(&[
+                ("TCB_INFO_RT_TCI_HASH_ALG", hash_alg.as_bytes()),
+                ("TCB_INFO_RT_TCI", digest),
+            ])
+            .unwrap();
+        let patched = tbs::TbsTemplate::new(tbs, template.params().to_vec());
+        let extensions = decode_cert_tbs(&patched).extensions.unwrap();
+        let tcb_info = extensions
+            .iter()
+            .find(|ext| ext.extn_id.to_string() == TCB_INFO)
+            .unwrap()
+            .extn_value
+            .as_bytes();
+
+        // fwids [6] IMPLICIT SEQUENCE OF FWID
+        let (fwids, _) = element_children(tcb_info, 0)
+            .into_iter()
+            .find(|&(offset, _)| tcb_info[offset] == 0xA6)
+            .unwrap();
+        let (fwid, _) = element_children(tcb_info, fwids)[0];
+        let fields = element_children(tcb_info, fwid);
+        let bytes = |(offset, len): (usize, usize)| &tcb_info[offset..offset + len];
+        assert_eq!(
+            ObjectIdentifier::from_der(bytes(fields[0])).unwrap(),
+            hash_alg
+        );
+        let decoded = BitString::from_der(bytes(fields[1])).unwrap();
+        assert_eq!(decoded.raw_bytes(), digest);
+    }
+    assert_eq!(sha384_digest[..48], [0x12; 48]);
+    assert_eq!(sha384_digest[48..], [0; 16]);
+}
+
+#[test]
+fn test_gen_fwid_hash_alg_param_candidates() {
+    use crate::cert_rustcrypto::{CertTemplateBuilder, Fwid, FwidParam};
+    use crate::measurement::{SHA256_OID, SHA384_OID, SHA512_OID};
+    use crate::validate::BuilderError;
+    use const_oid::ObjectIdentifier;
+    use ml_dsa::MlDsa87;
+    use std::borrow::Cow;
+
+    const SHA1_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.14.3.2.26");
+
+    let rt_fwids = [FwidParam {
+        name: "TCB_INFO_RT_TCI",
+        fwid: Fwid {
+            hash_alg: SHA384_OID,
+            digest: Cow::Borrowed(&[0xCD; 48]),
+        },
+    }];
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_rt_dice_tcb_info_ext(0xC4, &rt_fwids, true)
+        .add_fwid_hash_alg_param_with("TCB_INFO_RT_TCI", &[SHA256_OID, SHA512_OID])
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 RT Alias",
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+        );
+    let param = |name: &str| *template.params().iter().find(|p| p.name == name).unwrap();
+
+    // The param covers the content of the OBJECT IDENTIFIER preceding the digest BIT STRING
+    let hash_alg = param("TCB_INFO_RT_TCI_HASH_ALG");
+    assert_eq!(hash_alg.len, SHA384_OID.as_bytes().len());
+    let offset = template.tbs_offset() + hash_alg.offset;
+    let der = template.der();
+    assert_eq!(der[offset - 2..offset], [0x06, 0x09]);
+    assert_eq!(&der[offset..offset + hash_alg.len], SHA384_OID.as_bytes());
+    assert_eq!(der[offset + 9..offset + 12], [0x03, 0x31, 0x00]);
+    assert_eq!(param("TCB_INFO_RT_TCI").offset, hash_alg.offset + 12);
+
+    // A candidate of another length would shift the digest
+    let err = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_rt_dice_tcb_info_ext(0xC4, &rt_fwids, true)
+        .add_fwid_hash_alg_param_with("TCB_INFO_RT_TCI", &[SHA512_OID, SHA1_OID])
+        .try_tbs_template(
+            "Caliptra 2.0 MlDsa87 RT Alias",
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+        )
+        .unwrap_err();
+    assert_eq!(
+        err,
+        BuilderError::HashAlgLength {
+            fwid: "TCB_INFO_RT_TCI",
+            hash_alg: SHA1_OID,
+            len: 5,
+            expected: 9,
+        }
+    );
+
+    // The param must name an FWID of the template
+    let err = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_rt_dice_tcb_info_ext(0xC4, &rt_fwids, true)
+        .add_fwid_hash_alg_param("TCB_INFO_FMC_TCI")
+        .try_tbs_template(
+            "Caliptra 2.0 MlDsa87 RT Alias",
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+        )
+        .unwrap_err();
+    assert_eq!(
+        err,
+        BuilderError::UnknownFwid {
+            fwid: "TCB_INFO_FMC_TCI"
+        }
+    );
+}
+
+#[test]
+fn test_gen_debug_artifacts() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::keys::mldsa87_key_from_seed;
+    use crate::validate::BuilderError;
+    use ml_dsa::MlDsa87;
+
+    let dir = std::env::temp_dir().join("debug_artifacts");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let gen = |dir: Option<&std::path::Path>| {
+        let builder = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+            .set_subject_key(mldsa87_key_from_seed(&[0x01; 32]))
+            .set_issuer_key(mldsa87_key_from_seed(&[0x02; 32]));
+        let builder = match dir {
+            Some(dir) => builder.debug_artifacts(dir),
+            None => builder,
+        };
+        builder.tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID")
+    };
+    let template = gen(Some(&dir));
+    assert_eq!(template.tbs(), gen(None).tbs());
+
+    let prefix = "caliptra_2_0_mldsa87_ldevid";
+    let read = |suffix: &str| std::fs::read(dir.join(format!("{}{}", prefix, suffix))).unwrap();
+    assert_eq!(read(".der"), template.der());
+    let tbs = read("_tbs.der");
+    let sanitized = read("_tbs_sanitized.der");
+    assert_eq!(sanitized, template.tbs());
+    let params = String::from_utf8(read("_params.txt")).unwrap();
+    assert_eq!(params.lines().count(), template.params().len());
+
+    // Sanitization only rewrites the param ranges
+    assert_eq!(tbs.len(), sanitized.len());
+    let in_param = |i: usize| {
+        template
+            .params()
+            .iter()
+            .any(|p| (p.offset..p.offset + p.len).contains(&i))
+    };
+    assert_ne!(tbs, sanitized);
+    assert!(tbs
+        .iter()
+        .zip(&sanitized)
+        .enumerate()
+        .all(|(i, (a, b))| a == b || in_param(i)));
+
+    CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::idevid_profile(&[0xFF; 17])
+        .debug_artifacts(&dir)
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    assert!(dir.join("caliptra_2_0_mldsa87_idevid_params.txt").exists());
+
+    // The artifacts can't be written below a file
+    let file = dir.join("caliptra_2_0_mldsa87_idevid.der");
+    let err = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::idevid_profile(&[0xFF; 17])
+        .debug_artifacts(&file)
+        .try_tbs_template("Caliptra 2.0 MlDsa87 IDevID")
+        .unwrap_err();
+    assert!(matches!(err, BuilderError::DebugArtifacts { ref dir, .. } if *dir == file));
+}
+
+#[test]
+fn test_gen_subject_alt_name_ip_and_other_name() {
+    use crate::cert_rustcrypto::{CertTemplateBuilder, SanEntry};
+    use const_oid::{AssociatedOid, ObjectIdentifier};
+    use der::asn1::OctetString;
+    use der::{Decode, Encode};
+    use ml_dsa::MlDsa87;
+    use std::net::{IpAddr, Ipv4Addr};
+    use x509_cert::ext::pkix::{name::GeneralName, SubjectAltName};
+
+    // Hardware identifier of a private arc, carried as an OCTET STRING
+    let hw_id_oid = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.55555.1");
+    let hw_id = OctetString::new([0xA5; 16]).unwrap().to_der().unwrap();
+
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_subject_alt_name_ext(&[
+            SanEntry::Ip(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))),
+            SanEntry::OtherName {
+                type_id: hw_id_oid,
+                value: hw_id.clone(),
+                param: Some("HW_ID"),
+            },
+        ])
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+
+    let tbs = decode_cert_tbs(&template);
+    let ext = tbs
+        .extensions
+        .unwrap()
+        .into_iter()
+        .find(|ext| ext.extn_id == SubjectAltName::OID)
+        .unwrap();
+    let san = SubjectAltName::from_der(ext.extn_value.as_bytes()).unwrap();
+    assert_eq!(san.0.len(), 2);
+    let GeneralName::IpAddress(ip) = &san.0[0] else {
+        panic!("Unexpected {:?}", san.0[0]);
+    };
+    assert_eq!(ip.as_bytes(), [192, 0, 2, 1]);
+    let GeneralName::OtherName(other_name) = &san.0[1] else {
+        panic!("Unexpected {:?}", san.0[1]);
+    };
+    assert_eq!(other_name.type_id, hw_id_oid);
+    assert_eq!(other_name.value.to_der().unwrap(), hw_id);
+
+    let param = template
+        .params()
+        .iter()
+        .find(|p| p.name == "HW_ID")
+        .unwrap();
+    assert_eq!(param.len, 16);
+}
+
+#[test]
+fn test_gen_permanent_identifier_with_hardware_module_name() {
+    use crate::cert_rustcrypto::{CertTemplateBuilder, SanEntry, PERMANENT_IDENTIFIER_OID};
+    use const_oid::{AssociatedOid, ObjectIdentifier};
+    use der::asn1::OctetString;
+    use der::{Decode, Encode, Sequence};
+    use ml_dsa::MlDsa87;
+    use x509_cert::ext::pkix::{name::GeneralName, SubjectAltName};
+
+    #[derive(Sequence, Debug)]
+    struct HardwareModuleName {
+        hw_type: ObjectIdentifier,
+        hw_serial_num: OctetString,
+    }
+
+    #[derive(Sequence, Debug)]
+    struct PermanentIdentifier {
+        #[asn1(optional = "true")]
+        identifier_value: Option<String>,
+        #[asn1(optional = "true")]
+        assigner: Option<ObjectIdentifier>,
+    }
+
+    let hw_module_name_oid = ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.8.4");
+    let assigner = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.55555.2");
+    let hw_module_name = HardwareModuleName {
+        hw_type: ObjectIdentifier::new_unwrap("1.3.6.1.4.1.55555.1"),
+        hw_serial_num: OctetString::new([0xA5; 16]).unwrap(),
+    };
+
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_subject_alt_name_ext(&[SanEntry::OtherName {
+            type_id: hw_module_name_oid,
+            value: hw_module_name.to_der().unwrap(),
+            param: None,
+        }])
+        .add_permanent_identifier_ext(Some(assigner), 24)
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+
+    // The placeholder identifierValue sits at the param offset
+    let param = template
+        .params()
+        .iter()
+        .find(|p| p.name == "PERMANENT_ID")
+        .unwrap();
+    assert_eq!(param.len, 24);
+    let offset = template.tbs_offset() + param.offset;
+    assert_eq!(&template.der()[offset..offset + param.len], [b'#'; 24]);
+
+    // A single SubjectAltName carries both otherNames, with the patched permanent ID
+    let permanent_id = b"DEVICE-0123456789ABCDEF0";
+    let tbs = template
+        .apply(&[("PERMANENT_ID", permanent_id.as_slice())])
+        .unwrap();
+    let patched = tbs::TbsTemplate::new(tbs, template.params().to_vec());
+    let extensions = decode_cert_tbs(&patched).extensions.unwrap();
+    let mut sans = extensions
+        .iter()
+        .filter(|ext| ext.extn_id == SubjectAltName::OID);
+    let san = SubjectAltName::from_der(sans.next().unwrap().extn_value.as_bytes()).unwrap();
+    assert!(sans.next().is_none());
+    assert_eq!(san.0.len(), 2);
+
+    let GeneralName::OtherName(other_name) = &san.0[0] else {
+        panic!("Unexpected {:?}", san.0[0]);
+    };
+    assert_eq!(other_name.type_id, hw_module_name_oid);
+    let decoded = HardwareModuleName::from_der(&other_name.value.to_der().unwrap()).unwrap();
+    assert_eq!(decoded.hw_serial_num, hw_module_name.hw_serial_num);
+
+    let GeneralName::OtherName(other_name) = &san.0[1] else {
+        panic!("Unexpected {:?}", san.0[1]);
+    };
+    assert_eq!(other_name.type_id, PERMANENT_IDENTIFIER_OID);
+    let decoded = PermanentIdentifier::from_der(&other_name.value.to_der().unwrap()).unwrap();
+    assert_eq!(decoded.identifier_value.unwrap().as_bytes(), permanent_id);
+    assert_eq!(decoded.assigner, Some(assigner));
+}
+
+#[test]
+fn test_inspect_fmc_alias_template() {
+    use crate::cert_rustcrypto::{CertTemplateBuilder, Fwid, FwidParam};
+    use crate::inspect::inspect_tbs;
+    use const_oid::{AssociatedOid, ObjectIdentifier};
+    use der::Sequence;
+    use ml_dsa::MlDsa87;
+    use std::borrow::Cow;
+
+    /// Extension of an OID without a friendly name
+    #[derive(Sequence)]
+    struct UnknownExt {
+        version: u8,
+    }
+
+    impl AssociatedOid for UnknownExt {
+        const OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.999.3");
+    }
+
+    let sha384_oid = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.2");
+    let device_fwids = [FwidParam {
+        name: "TCB_INFO_DEVICE_INFO_HASH",
+        fwid: Fwid {
+            hash_alg: sha384_oid,
+            digest: Cow::Borrowed(&[0xEF; 48]),
+        },
+    }];
+    let fmc_fwids = [FwidParam {
+        name: "TCB_INFO_FMC_TCI",
+        fwid: Fwid {
+            hash_alg: sha384_oid,
+            digest: Cow::Borrowed(&[0xCD; 48]),
+        },
+    }];
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::fmc_alias_profile(
+        &[0xFF; 17],
+        &device_fwids,
+        &fmc_fwids,
+    )
+    .add_encoded_ext(&UnknownExt { version: 1 }, false)
+    .tbs_template(
+        "Caliptra 2.0 MlDsa87 FMC Alias",
+        "Caliptra 2.0 MlDsa87 LDevID",
+    );
+
+    let report = inspect_tbs(template.tbs(), template.params());
+    assert_eq!(report.kind, "TBSCertificate");
+    assert!(report.unassigned.is_empty());
+
+    let field = report.field_of_param("TCB_INFO_FMC_TCI").unwrap();
+    assert_eq!(
+        field.name,
+        "extension tcg-dice-MultiTcbInfo (2.23.133.5.4.5)"
+    );
+    let field = report.field_of_param("SERIAL_NUMBER").unwrap();
+    assert_eq!(field.name, "serialNumber");
+    let field = report.field_of_param("NOT_BEFORE").unwrap();
+    assert_eq!(field.name, "validity.notBefore");
+    assert!(report
+        .field("signature id-ml-dsa-87 (2.16.840.1.101.3.4.3.19)")
+        .is_some());
+
+    // Unknown extensions are shown by OID
+    assert!(report.field("extension 2.999.3").is_some());
+    assert!(report.to_string().contains("TCB_INFO_FMC_TCI"));
+}
+
+#[test]
+fn test_strip_extension_range() {
+    use crate::cert_rustcrypto::{CertTemplateBuilder, Fwid, FwidParam};
+    use crate::code_gen::{CodeGen, CodeGenOptions};
+    use crate::profile::TCB_INFO_OID;
+    use const_oid::ObjectIdentifier;
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+    use std::borrow::Cow;
+    use x509_cert::certificate::TbsCertificate;
+
+    let sha384_oid = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.2");
+    let rt_fwids = [FwidParam {
+        name: "TCB_INFO_RT_TCI",
+        fwid: Fwid {
+            hash_alg: sha384_oid,
+            digest: Cow::Borrowed(&[0xCD; 48]),
+        },
+    }];
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .add_ueid_ext(&[0xFF; 17])
+        .add_rt_dice_tcb_info_ext(0xC4, &rt_fwids, true)
+        .add_extension_range_param(TCB_INFO_OID, "EXT_TCB_INFO_RANGE")
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 RT Alias",
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+        );
+    let range = template.ranges()[0];
+    assert_eq!(range.name, "EXT_TCB_INFO_RANGE");
+
+    // Strip the TcbInfo once firmware applied the params
+    let values: [(&str, &[u8]); 3] = [
+        ("NOT_BEFORE", b"230101000000Z"),
+        ("NOT_AFTER", b"491231235959Z"),
+        ("TCB_INFO_RT_TCI", &[0xAB; 48]),
+    ];
+    let applied = template.apply(&values).unwrap();
+    let stripped = template.strip(&applied, "EXT_TCB_INFO_RANGE").unwrap();
+    assert!(stripped.len() <= applied.len() - range.len);
+
+    let tbs = TbsCertificate::from_der(&stripped).unwrap();
+    let oids: Vec<_> = tbs
+        .extensions
+        .unwrap()
+        .iter()
+        .map(|ext| ext.extn_id.to_string())
+        .collect();
+    assert!(oids.iter().any(|oid| oid == UEID));
+    assert!(oids.iter().all(|oid| oid != TCB_INFO));
+
+    // The generated code embeds the stripped layout
+    let code = CodeGen::code(
+        "RtAliasCertTbsMlDsa87",
+        template,
+        &CodeGenOptions::default(),
+    );
+    let code = code.replace(' ', "");
+    assert!(code.contains("pubfnstrip_ext_tcb_info_range(&self)"));
+    assert!(code.contains(&format!(
+        "EXT_TCB_INFO_RANGE_STRIPPED_LEN:usize={}usize",
+        stripped.len()
+    )));
+}
+
+#[test]
+fn test_parameterized_len() {
+    use crate::cert_rustcrypto::{CertTemplateBuilder, Fwid, FwidParam};
+    use const_oid::ObjectIdentifier;
+    use ml_dsa::MlDsa87;
+    use std::borrow::Cow;
+
+    let sha384_oid = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.2");
+    let rt_fwids = [FwidParam {
+        name: "TCB_INFO_RT_TCI",
+        fwid: Fwid {
+            hash_alg: sha384_oid,
+            digest: Cow::Borrowed(&[0xCD; 48]),
+        },
+    }];
+    let bldr = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::rt_alias_profile(
+        &[0xFF; 17],
+        0xC4,
+        &rt_fwids,
+    );
+    let template = bldr.tbs_template(
+        "Caliptra 2.0 MlDsa87 RT Alias",
+        "Caliptra 2.0 MlDsa87 FMC Alias",
+    );
+
+    // The params of the RT alias template don't overlap
+    let params_len: usize = template.params().iter().map(|p| p.len).sum();
+    assert_eq!(template.parameterized_len(), params_len);
+    assert_eq!(
+        template.parameterized_len() + template.fixed_len(),
+        template.tbs().len()
+    );
+
+    // Overlapping bytes are counted once
+    let overlapping = tbs::TbsTemplate::new(
+        vec![0; 16],
+        vec![
+            tbs::TbsParam::new("A", 0, 8),
+            tbs::TbsParam::new("B", 4, 8),
+            tbs::TbsParam::new("C", 14, 2),
+        ],
+    );
+    assert_eq!(overlapping.parameterized_len(), 14);
+    assert_eq!(overlapping.fixed_len(), 2);
+}
+
+#[test]
+fn test_validate_ueid_length() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::validate::BuilderError;
+    use ml_dsa::MlDsa87;
+
+    type Key = ml_dsa::KeyPair<MlDsa87>;
+
+    for ueid in [&[][..], &[0xFF; 40][..]] {
+        let err = CsrTemplateBuilder::<Key>::new()
+            .add_ueid_ext(ueid)
+            .try_tbs_template("Caliptra 2.0 MlDsa87 IDevID")
+            .unwrap_err();
+        assert_eq!(err, BuilderError::UeidLength { len: ueid.len() });
+
+        let err = CertTemplateBuilder::<Key>::new()
+            .add_ueid_ext(ueid)
+            .try_tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID")
+            .unwrap_err();
+        assert_eq!(err, BuilderError::UeidLength { len: ueid.len() });
+    }
+}
+
+#[test]
+fn test_gen_ueid_int() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::validate::BuilderError;
+    use ml_dsa::MlDsa87;
+    use num_bigint::BigUint;
+
+    type Key = ml_dsa::KeyPair<MlDsa87>;
+
+    // The integer only needs 2 bytes, the UEID keeps its 15 leading zero bytes
+    let value = BigUint::from(0x01ABu32);
+    let mut expected = vec![0u8; 15];
+    expected.extend_from_slice(&[0x01, 0xAB]);
+
+    let template = CsrTemplateBuilder::<Key>::new()
+        .add_ueid_int(&value, 17)
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    let param = template
+        .params_in_der()
+        .unwrap()
+        .into_iter()
+        .find(|p| p.name == "UEID")
+        .unwrap();
+    assert_eq!(param.len, 17);
+    assert_eq!(
+        &template.der()[param.offset..param.offset + param.len],
+        expected
+    );
+
+    let err = CertTemplateBuilder::<Key>::new()
+        .add_ueid_int(&BigUint::from(0x010000u32), 2)
+        .try_tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID")
+        .unwrap_err();
+    assert_eq!(err, BuilderError::UeidValue { width: 2 });
+}
+
+#[test]
+fn test_template_kind() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::tbs::TemplateKind;
+    use der::{Decode, Encode};
+    use ml_dsa::MlDsa87;
+    use x509_cert::certificate::{TbsCertificate, Version};
+
+    type Key = ml_dsa::KeyPair<MlDsa87>;
+
+    let csr = CsrTemplateBuilder::<Key>::idevid_profile(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    assert_eq!(csr.kind(), TemplateKind::Csr);
+    let cert = CertTemplateBuilder::<Key>::ldevid_profile(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    assert_eq!(cert.kind(), TemplateKind::Cert);
+
+    // Templates built from a TBS detect their kind
+    let patched = tbs::TbsTemplate::new(cert.tbs().to_vec(), cert.params().to_vec());
+    assert_eq!(patched.kind(), TemplateKind::Cert);
+    let patched = tbs::TbsTemplate::new(csr.tbs().to_vec(), csr.params().to_vec());
+    assert_eq!(patched.kind(), TemplateKind::Csr);
+
+    // A v1 TBSCertificate starts with its serialNumber, like a CSR with its version
+    let mut v1 = TbsCertificate::from_der(cert.tbs()).unwrap();
+    v1.version = Version::V1;
+    v1.extensions = None;
+    let v1 = tbs::TbsTemplate::new(v1.to_der().unwrap(), Vec::new());
+    assert_eq!(v1.kind(), TemplateKind::Cert);
+}
+
+#[test]
+fn test_validate_path_len() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::validate::BuilderError;
+    use ml_dsa::MlDsa87;
+
+    type Key = ml_dsa::KeyPair<MlDsa87>;
+
+    // 300 used to wrap to 44
+    let err = CsrTemplateBuilder::<Key>::new()
+        .add_basic_constraints_ext(true, 300)
+        .try_tbs_template("Caliptra 2.0 MlDsa87 IDevID")
+        .unwrap_err();
+    assert_eq!(err, BuilderError::PathLength { path_len: 300 });
+
+    let err = CertTemplateBuilder::<Key>::new()
+        .add_basic_constraints_ext(true, 256)
+        .try_tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID")
+        .unwrap_err();
+    assert_eq!(err, BuilderError::PathLength { path_len: 256 });
+}
+
+#[test]
+fn test_validate_fwid_digest() {
+    use crate::cert_rustcrypto::{CertTemplateBuilder, Fwid, FwidParam};
+    use crate::measurement::{SHA384_OID, SHA3_256_OID, SHA3_384_OID};
+    use crate::validate::BuilderError;
+    use const_oid::ObjectIdentifier;
+    use ml_dsa::MlDsa87;
+    use std::borrow::Cow;
+
+    let try_rt_template = |hash_alg: ObjectIdentifier, digest: &[u8]| {
+        let fwids = [FwidParam {
+            name: "TCB_INFO_RT_TCI",
+            fwid: Fwid {
+                hash_alg,
+                digest: Cow::Borrowed(digest),
+            },
+        }];
+        CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+            .add_rt_dice_tcb_info_ext(0xC4, &fwids, true)
+            .try_tbs_template(
+                "Caliptra 2.0 MlDsa87 RT Alias",
+                "Caliptra 2.0 MlDsa87 FMC Alias",
+            )
+            .map(|_| ())
+    };
+
+    assert_eq!(
+        try_rt_template(SHA384_OID, &[]),
+        Err(BuilderError::EmptyDigest {
+            fwid: "TCB_INFO_RT_TCI"
+        })
+    );
+    assert_eq!(
+        try_rt_template(SHA384_OID, &[0xCD; 32]),
+        Err(BuilderError::DigestLength {
+            fwid: "TCB_INFO_RT_TCI",
+            hash_alg: SHA384_OID,
+            len: 32,
+            expected: 48,
+        })
+    );
+
+    // SHA-3 digests are checked too, unknown algorithms only require a digest
+    assert_eq!(try_rt_template(SHA3_384_OID, &[0xCD; 48]), Ok(()));
+    assert_eq!(
+        try_rt_template(SHA3_256_OID, &[0xCD; 48]),
+        Err(BuilderError::DigestLength {
+            fwid: "TCB_INFO_RT_TCI",
+            hash_alg: SHA3_256_OID,
+            len: 48,
+            expected: 32,
+        })
+    );
+    let unknown = ObjectIdentifier::new_unwrap("1.2.3.4");
+    assert_eq!(try_rt_template(unknown, &[0xCD; 20]), Ok(()));
+}
+
+#[test]
+fn test_validate_fwid_name() {
+    use crate::cert_rustcrypto::{CertTemplateBuilder, Fwid, FwidParam};
+    use crate::measurement::SHA384_OID;
+    use crate::validate::BuilderError;
+    use ml_dsa::MlDsa87;
+    use std::borrow::Cow;
+
+    for name in ["TCB-INFO-FMC", "1ST_FMC_TCI", "_", ""] {
+        let fwids = [FwidParam {
+            name,
+            fwid: Fwid {
+                hash_alg: SHA384_OID,
+                digest: Cow::Borrowed(&[0xCD; 48]),
+            },
+        }];
+        let err = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::fmc_alias_profile(
+            &[0xFF; 17],
+            &[],
+            &fwids,
+        )
+        .try_tbs_template(
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+            "Caliptra 2.0 MlDsa87 LDevID",
+        )
+        .unwrap_err();
+        assert_eq!(err, BuilderError::FwidName { fwid: name });
+    }
+}
+
+#[test]
+fn test_validate_cn_length() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::validate::{BuilderError, MAX_CN_LEN};
+    use ml_dsa::MlDsa87;
+
+    type Key = ml_dsa::KeyPair<MlDsa87>;
+
+    // A CN pushing the RDN over a single byte DER length
+    let long_cn = "C".repeat(MAX_CN_LEN + 1);
+    let err = CsrTemplateBuilder::<Key>::new()
+        .try_tbs_template(&long_cn)
+        .unwrap_err();
+    assert_eq!(
+        err,
+        BuilderError::CnLength {
+            name: "Subject CN",
+            len: MAX_CN_LEN + 1
+        }
+    );
+
+    let err = CertTemplateBuilder::<Key>::new()
+        .try_tbs_template("Caliptra 2.0 MlDsa87 LDevID", "")
+        .unwrap_err();
+    assert_eq!(
+        err,
+        BuilderError::CnLength {
+            name: "Issuer CN",
+            len: 0
+        }
+    );
+
+    // Reservations are checked too
+    let err = CertTemplateBuilder::<Key>::new()
+        .add_subject_cn_param(MAX_CN_LEN + 1)
+        .try_tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID")
+        .unwrap_err();
+    assert_eq!(
+        err,
+        BuilderError::CnLength {
+            name: "Subject CN reservation",
+            len: MAX_CN_LEN + 1
+        }
+    );
+
+    // The longest CN keeps single byte lengths, its RDN SET of 127 bytes starts with the SET,
+    // SEQUENCE, OID and string headers
+    let cn = "C".repeat(MAX_CN_LEN);
+    let template = CsrTemplateBuilder::<Key>::new()
+        .try_tbs_template(&cn)
+        .unwrap();
+    let tbs = template.tbs();
+    let offset = tbs
+        .windows(cn.len())
+        .position(|window| window == cn.as_bytes())
+        .unwrap();
+    assert_eq!(tbs[offset - 11..offset - 9], [0x31, 0x7F]);
+}
+
+#[test]
+fn test_template_from_cert_der() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::keys::mldsa87_key_from_seed;
+    use crate::tbs::{TbsTemplate, TemplateError, TemplateKind};
+    use ml_dsa::MlDsa87;
+    use signature::Keypair;
+
+    type Key = ml_dsa::KeyPair<MlDsa87>;
+
+    let ueid = [0xFF; 17];
+    let subject_key = mldsa87_key_from_seed(&[0x01; 32]);
+    let public_key = subject_key.verifying_key().encode();
+    let template = CertTemplateBuilder::<Key>::ldevid_profile(&ueid)
+        .set_subject_key(subject_key)
+        .set_issuer_key(mldsa87_key_from_seed(&[0x02; 32]))
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+
+    // Feeding the signed certificate back finds the params of the builder
+    let imported = TbsTemplate::from_cert_der(
+        template.der(),
+        &[("UEID", &ueid[..]), ("PUBLIC_KEY", &public_key[..])],
+    )
+    .unwrap();
+    assert_eq!(imported.kind(), TemplateKind::Cert);
+    assert_eq!(imported.der(), template.der());
+    for param in imported.params() {
+        let expected = template
+            .params()
+            .iter()
+            .find(|p| p.name == param.name)
+            .unwrap();
+        assert_eq!((param.offset, param.len), (expected.offset, expected.len));
+        assert!(imported.tbs()[param.offset..param.offset + param.len]
+            .iter()
+            .all(|&b| b == 0x5F));
+    }
+
+    let err = TbsTemplate::from_cert_der(template.der(), &[("UEID", &[0xEE; 17][..])]);
+    assert_eq!(
+        err.err(),
+        Some(TemplateError::NeedleCount {
+            name: "UEID",
+            expected: 1,
+            found: 0
+        })
+    );
+}
+
+#[test]
+fn test_template_add_param() {
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::keys::mldsa87_key_from_seed;
+    use crate::tbs::{TbsTemplate, TemplateError};
+    use ml_dsa::MlDsa87;
+
+    type Key = ml_dsa::KeyPair<MlDsa87>;
+
+    let subject_cn = "Caliptra 2.0 MlDsa87 IDevID";
+    let ueid = [0xFF; 17];
+    let template = CsrTemplateBuilder::<Key>::idevid_profile(&ueid)
+        .set_key(mldsa87_key_from_seed(&[0x01; 32]))
+        .tbs_template(subject_cn);
+    let mut imported = TbsTemplate::from_cert_der(template.der(), &[("UEID", &ueid[..])]).unwrap();
+
+    // The subject CN of the loaded template becomes a param
+    let offset = imported
+        .tbs()
+        .windows(subject_cn.len())
+        .position(|window| window == subject_cn.as_bytes())
+        .unwrap();
+    imported
+        .add_param("SUBJECT_CN", subject_cn.as_bytes())
+        .unwrap();
+    let param = imported.params().last().unwrap();
+    assert_eq!(
+        (param.name, param.offset, param.len),
+        ("SUBJECT_CN", offset, subject_cn.len())
+    );
+    assert!(imported.tbs()[param.offset..param.offset + param.len]
+        .iter()
+        .all(|&b| b == 0x5F));
+
+    // The sanitized value is no longer found
+    let err = imported.add_param("CN", subject_cn.as_bytes());
+    assert!(matches!(
+        err,
+        Err(TemplateError::NeedleCount { found: 0, .. })
+    ));
+
+    // A needle straddling the placeholder of an existing param is rejected
+    let ueid_param = imported.params()[0];
+    let needle = imported.tbs()[ueid_param.offset - 2..ueid_param.offset + 2].to_vec();
+    assert_eq!(
+        imported.add_param("UEID_HEADER", &needle),
+        Err(TemplateError::ParamOverlap {
+            name: "UEID_HEADER",
+            other: "UEID"
+        })
+    );
+    assert_eq!(imported.params().len(), 2);
+}
+
+#[test]
+fn test_der_round_trip_check() {
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::tbs::{check_der_reencoding, check_der_round_trip, TemplateError};
+    use ml_dsa::MlDsa87;
+    use x509_cert::request::CertReq;
+    use x509_cert::Certificate;
+
+    // The generated templates round trip, as checked during generation
+    let cert = generate_test_template(TestAlg::MlDsa87, TestScenario::LdevidCert, 1);
+    assert_eq!(check_der_round_trip::<Certificate>(cert.der()), Ok(()));
+    let csr = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::idevid_profile(&[0xFF; 17])
+        .skip_der_round_trip_check()
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    assert_eq!(check_der_round_trip::<CertReq>(csr.der()), Ok(()));
+
+    // SEQUENCE { INTEGER 5 } with a non-minimal INTEGER, re-encoded without the leading zero
+    let non_canonical = [0x30, 0x04, 0x02, 0x02, 0x00, 0x05];
+    let reencoded = [0x30, 0x03, 0x02, 0x01, 0x05];
+    let err = check_der_reencoding(&non_canonical, &reencoded).unwrap_err();
+    assert_eq!(
+        err,
+        TemplateError::DerDrift {
+            offset: 1,
+            der: non_canonical.to_vec(),
+            reencoded: reencoded.to_vec(),
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "DER re-encoding differs at offset 1: 300402020005 re-encoded as 3003020105"
+    );
+
+    // The same drift in the serialNumber of a certificate, which keeps its length. A
+    // leading 0x00 followed by a byte below 0x80 is non-minimal whatever the serial is.
+    let serial_number = cert
+        .params()
+        .iter()
+        .find(|p| p.name == "SERIAL_NUMBER")
+        .unwrap();
+    let serial_offset = cert.tbs_offset() + serial_number.offset;
+    let mut der = cert.der().to_vec();
+    der[serial_offset..serial_offset + 2].copy_from_slice(&[0x00, 0x01]);
+    assert!(check_der_round_trip::<Certificate>(&der).is_err());
+}
+
+#[test]
+fn test_verify_patched() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::tbs::{get_tbs, PatchViolation};
+    use ml_dsa::MlDsa87;
+
+    type Key = ml_dsa::KeyPair<MlDsa87>;
+
+    let template = CertTemplateBuilder::<Key>::ldevid_profile(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    let ueid = *template.params().iter().find(|p| p.name == "UEID").unwrap();
+    let ueid_range = ueid.offset..ueid.offset + ueid.len;
+
+    // The signed TBS holds the generation-time values of all the params
+    let patched = get_tbs(template.der()).unwrap().to_vec();
+    assert_eq!(template.verify_patched(&patched), Ok(()));
+
+    // UEID left with its placeholder
+    let mut unpatched = patched.clone();
+    unpatched[ueid_range.clone()].copy_from_slice(&template.tbs()[ueid_range.clone()]);
+    assert_eq!(
+        template.verify_patched(&unpatched),
+        Err(vec![PatchViolation::UnpatchedParam { name: "UEID" }])
+    );
+
+    // UEID written 2 bytes early, over its OCTET STRING header
+    let mut shifted = unpatched.clone();
+    shifted[ueid.offset - 2..ueid.offset - 2 + ueid.len].fill(0xFF);
+    let violations = template.verify_patched(&shifted).unwrap_err();
+    assert_eq!(
+        violations[0],
+        PatchViolation::FixedBytes {
+            offset: ueid.offset - 2,
+            len: 2
+        }
+    );
+    assert!(matches!(
+        violations.last(),
+        Some(PatchViolation::InvalidDer { .. })
+    ));
+
+    let truncated = &patched[..patched.len() - 1];
+    assert_eq!(
+        template.verify_patched(truncated),
+        Err(vec![PatchViolation::Length {
+            len: patched.len() - 1,
+            expected: patched.len()
+        }])
+    );
+}
+
+#[test]
+fn test_pkcs7_bundle() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::keys::mldsa87_key_from_seed;
+    use crate::pkcs7::{bundle_certs, certs_only_bundle};
+    use ml_dsa::MlDsa87;
+
+    type Key = ml_dsa::KeyPair<MlDsa87>;
+
+    let ldevid = CertTemplateBuilder::<Key>::ldevid_profile(&[0xFF; 17])
+        .set_subject_key(mldsa87_key_from_seed(&[0x01; 32]))
+        .set_issuer_key(mldsa87_key_from_seed(&[0x02; 32]))
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    let fmc_alias = CertTemplateBuilder::<Key>::fmc_alias_profile(&[0xFF; 17], &[], &[])
+        .set_subject_key(mldsa87_key_from_seed(&[0x03; 32]))
+        .set_issuer_key(mldsa87_key_from_seed(&[0x01; 32]))
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+            "Caliptra 2.0 MlDsa87 LDevID",
+        );
+
+    let bundle = certs_only_bundle(&[ldevid.der(), fmc_alias.der()]).unwrap();
+    let mut certs = bundle_certs(&bundle).unwrap();
+    certs.sort();
+    let mut expected = vec![ldevid.der().to_vec(), fmc_alias.der().to_vec()];
+    expected.sort();
+    assert_eq!(certs, expected);
+
+    // Not a certificate
+    assert!(certs_only_bundle(&[ldevid.tbs()]).is_err());
+}
+
+#[test]
+fn test_name_der() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::keys::mldsa87_key_from_seed;
+    use ml_dsa::MlDsa87;
+
+    type Key = ml_dsa::KeyPair<MlDsa87>;
+
+    let ldevid = CertTemplateBuilder::<Key>::ldevid_profile(&[0xFF; 17])
+        .set_subject_key(mldsa87_key_from_seed(&[0x01; 32]))
+        .set_issuer_key(mldsa87_key_from_seed(&[0x02; 32]))
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    let fmc_alias = CertTemplateBuilder::<Key>::fmc_alias_profile(&[0xFF; 17], &[], &[])
+        .set_subject_key(mldsa87_key_from_seed(&[0x03; 32]))
+        .set_issuer_key(mldsa87_key_from_seed(&[0x01; 32]))
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+            "Caliptra 2.0 MlDsa87 LDevID",
+        );
+
+    // The child is issued by the parent
+    assert_eq!(fmc_alias.issuer_der(), Some(ldevid.subject_der()));
+    assert_ne!(fmc_alias.subject_der(), ldevid.subject_der());
+    assert_eq!(ldevid.subject_der()[0], 0x30);
+
+    // A CSR has a subject only, encoded like that of its certificate
+    let csr = CsrTemplateBuilder::<Key>::new()
+        .set_key(mldsa87_key_from_seed(&[0x01; 32]))
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID");
+    assert_eq!(csr.issuer_der(), None);
+    assert_eq!(csr.subject_der(), ldevid.subject_der());
+}
+
+#[test]
+fn test_size_report() {
+    use crate::cert_rustcrypto::{CertTemplateBuilder, Fwid, FwidParam};
+    use crate::code_gen::{CodeGen, CodeGenOptions};
+    use crate::measurement::SHA384_OID;
+    use ml_dsa::MlDsa87;
+    use std::borrow::Cow;
+
+    let fwid = |name, digest| FwidParam {
+        name,
+        fwid: Fwid {
+            hash_alg: SHA384_OID,
+            digest: Cow::Borrowed(digest),
+        },
+    };
+    let device_fwids = [fwid("TCB_INFO_DEVICE_INFO_HASH", &[0xEF; 48])];
+    let fmc_fwids = [fwid("TCB_INFO_FMC_TCI", &[0xCD; 48])];
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::fmc_alias_profile(
+        &[0xFF; 17],
+        &device_fwids,
+        &fmc_fwids,
+    )
+    .tbs_template(
+        "Caliptra 2.0 MlDsa87 FMC Alias",
+        "Caliptra 2.0 MlDsa87 LDevID",
+    );
+
+    let size_report = report::generate(&[("FmcAlias", &template)]);
+    let sizes = &size_report.templates[0];
+    assert_eq!(sizes.kind, "TBSCertificate");
+    assert_eq!(sizes.tbs_len, template.tbs().len());
+    assert_eq!(sizes.extensions.len(), 6);
+
+    // The fields, the extensions and their headers make up the TBS
+    let fields_len: usize = sizes.fields.iter().map(|f| f.len).sum();
+    let exts_len: usize = sizes.extensions.iter().map(|e| e.len).sum();
+    assert_eq!(
+        sizes.extensions_overhead + exts_len,
+        extensions_len(&template)
+    );
+    assert_eq!(
+        sizes.tbs_header_len + fields_len + sizes.extensions_overhead + exts_len,
+        sizes.tbs_len
+    );
+
+    // ML-DSA signatures are of a fixed length
+    assert_eq!(sizes.max_signed_len, Some(template.der().len()));
+
+    let markdown = size_report.to_markdown();
+    assert!(markdown.contains("## FmcAlias"));
+    let ueid = template.params().iter().find(|p| p.name == "UEID").unwrap();
+    assert!(markdown.contains(&format!("| UEID | {} | 17 |", ueid.offset)));
+    let csv = size_report.to_csv();
+    assert_eq!(
+        csv.lines().count(),
+        1 + 1 + sizes.fields.len() + sizes.extensions.len() + sizes.params.len() + 1
+    );
+
+    let out_dir = std::env::temp_dir().join("size_report");
+    std::fs::create_dir_all(&out_dir).unwrap();
+    let path = CodeGen::gen_size_report(
+        &[("FmcAlias", &template)],
+        out_dir.to_str().unwrap(),
+        &CodeGenOptions::new(),
+    );
+    assert_eq!(path, out_dir.join("SIZES.md"));
+    assert_eq!(std::fs::read_to_string(path).unwrap(), markdown);
+}
+
+#[test]
+fn test_gen_batch_size_report() {
+    use crate::code_gen::{CodeGen, CodeGenOptions};
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use ml_dsa::MlDsa87;
+
+    let out_dir = std::env::temp_dir().join("batch");
+    let _ = std::fs::remove_dir_all(&out_dir);
+
+    let options = CodeGenOptions::new().create_dir();
+    let specs = vec![
+        (
+            "InitDevIdCsrTbsMlDsa87",
+            CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::idevid_profile(&[0xFF; 17])
+                .tbs_template("Caliptra 2.0 MlDsa87 IDevID"),
+        ),
+        (
+            "UeidCsrTbsMlDsa87",
+            CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+                .add_ueid_ext(&[0xFF; 17])
+                .tbs_template("Caliptra 2.0 MlDsa87 IDevID"),
+        ),
+    ];
+    let path = CodeGen::gen_batch(specs, out_dir.to_str().unwrap(), &options);
+    assert_eq!(path, out_dir.join("mod.rs"));
+
+    // The report lists every template of the batch, next to its code
+    let sizes = std::fs::read_to_string(out_dir.join("SIZES.md")).unwrap();
+    for (type_name, file_name) in [
+        ("InitDevIdCsrTbsMlDsa87", "init_dev_id_csr_tbs_ml_dsa87.rs"),
+        ("UeidCsrTbsMlDsa87", "ueid_csr_tbs_ml_dsa87.rs"),
+    ] {
+        assert!(sizes.contains(&format!("## {}", type_name)));
+        assert!(out_dir.join(file_name).exists());
+    }
+}
+
+#[test]
+fn test_code_size_estimate() {
+    use crate::cert_rustcrypto::{CertTemplateBuilder, Fwid, FwidParam};
+    use crate::code_gen::{CodeGen, CodeGenOptions};
+    use crate::keys::mldsa87_key_from_seed;
+    use crate::measurement::SHA384_OID;
+    use ml_dsa::MlDsa87;
+    use std::borrow::Cow;
+
+    let fwids = [FwidParam {
+        name: "TCB_INFO_RT_TCI",
+        fwid: Fwid {
+            hash_alg: SHA384_OID,
+            digest: Cow::Borrowed(&[0xCD; 48]),
+        },
+    }];
+    let template = CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::rt_alias_profile(
+        &[0xFF; 17],
+        0xC4,
+        &fwids,
+    )
+    .set_subject_key(mldsa87_key_from_seed(&[0x04; 32]))
+    .set_issuer_key(mldsa87_key_from_seed(&[0x03; 32]))
+    .tbs_template(
+        "Caliptra 2.0 MlDsa87 RT Alias",
+        "Caliptra 2.0 MlDsa87 FMC Alias",
+    );
+    let estimate = template.code_size_estimate();
+    assert_eq!(estimate.param_records, template.params().len());
+
+    let code = CodeGen::code("RtAliasCertTbsMlDsa87", template, &CodeGenOptions::new());
+    let array = code.split("const TBS_TEMPLATE :").nth(1).unwrap();
+    let array = &array[array.find("= [").unwrap() + 3..];
+    let array = &array[..array.find(']').unwrap()];
+    let emitted = array.split(',').filter(|b| !b.trim().is_empty()).count();
+    assert_eq!(estimate.tbs_len, emitted);
+    assert_eq!(
+        code.matches("_OFFSET : usize").count(),
+        estimate.param_records
+    );
+}
+
+#[test]
+fn test_template_diff() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::keys::mldsa87_key_from_seed;
+    use ml_dsa::MlDsa87;
+
+    let ldevid = |ueid: &[u8]| {
+        CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(ueid)
+            .set_subject_key(mldsa87_key_from_seed(&[0x01; 32]))
+            .set_issuer_key(mldsa87_key_from_seed(&[0x02; 32]))
+            .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID")
+    };
+    let template = ldevid(&[0xFF; 17]);
+    assert!(template.diff(&ldevid(&[0xFF; 17])).is_empty());
+
+    let diff = template.diff(&ldevid(&[0xFF; 20]));
+    assert_eq!(diff.lens, (template.tbs().len(), template.tbs().len() + 3));
+    assert!(diff.added.is_empty() && diff.removed.is_empty());
+    assert!(!diff.byte_ranges.is_empty());
+    assert_eq!(
+        diff.byte_ranges.last().unwrap().end,
+        template.tbs().len() + 3
+    );
+
+    // The longer UEID grows its param and shifts the params of the following extensions,
+    // while the subjectPublicKeyInfo preceding the extensions stays in place
+    let ueid = diff.change("UEID").unwrap();
+    assert_eq!((ueid.before.len, ueid.after.len), (17, 20));
+    for name in ["SUBJECT_KEY_ID", "AUTHORITY_KEY_ID"] {
+        let change = diff.change(name).unwrap();
+        assert_eq!(change.after.offset, change.before.offset + 3, "{}", name);
+        assert_eq!(change.after.len, change.before.len, "{}", name);
+    }
+    assert!(diff.change("PUBLIC_KEY").is_none());
+    assert_eq!(diff.changed.len(), 3);
+    assert!(diff.to_string().contains("~ UEID at offset"));
+}
+
+#[test]
+fn test_gen_code_tbs_words() {
+    use crate::code_gen::{tbs_words, CodeGen, CodeGenOptions};
+
+    assert_eq!(
+        tbs_words(&[0x01, 0x02, 0x03, 0x04, 0x05]),
+        [0x04030201, 0x00000005]
+    );
+
+    let template = generate_test_template(TestAlg::MlDsa87, TestScenario::IdevidCsr, 1);
+    let tbs = template.tbs().to_vec();
+    let options = CodeGenOptions::new().tbs_words_const();
+    let code = CodeGen::code("InitDevIdCsrTbsMlDsa87", template, &options);
+    assert!(code.contains(&format!(
+        "pub const TBS_TEMPLATE_WORDS_LEN : usize = {}usize",
+        tbs.len().div_ceil(4)
+    )));
+
+    let array = code.split("const TBS_TEMPLATE_WORDS :").nth(1).unwrap();
+    let array = &array[array.find("= [").unwrap() + 3..];
+    let array = &array[..array.find(']').unwrap()];
+    let mut bytes: Vec<u8> = array
+        .split(',')
+        .map(str::trim)
+        .filter(|word| !word.is_empty())
+        .flat_map(|word| {
+            let word: u32 = word.strip_suffix("u32").unwrap().parse().unwrap();
+            word.to_le_bytes()
+        })
+        .collect();
+    assert!(bytes[tbs.len()..].iter().all(|&b| b == 0));
+    bytes.truncate(tbs.len());
+    assert_eq!(bytes, tbs);
+}
+
+#[test]
+fn test_template_algorithm_oid() {
+    use crate::tbs::TbsTemplate;
+    use const_oid::ObjectIdentifier;
+
+    let mldsa87 = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.3.19");
+    for scenario in [TestScenario::IdevidCsr, TestScenario::LdevidCert] {
+        let template = generate_test_template(TestAlg::MlDsa87, scenario, 1);
+        assert_eq!(template.algorithm_oid(), mldsa87);
+
+        // Read from the signatureAlgorithm of templates not built by a builder
+        let imported = TbsTemplate::from_cert_der(template.der(), &[]).unwrap();
+        assert_eq!(imported.algorithm_oid(), mldsa87);
+    }
+}
+
+#[test]
+fn test_gen_code_full_der() {
+    use crate::code_gen::{CodeGen, CodeGenOptions};
+    use crate::tbs::get_tbs;
+
+    for scenario in [TestScenario::IdevidCsr, TestScenario::LdevidCert] {
+        let template = generate_test_template(TestAlg::MlDsa87, scenario, 1);
+        let tbs = template.tbs().to_vec();
+        let der = template.template_der().unwrap();
+        assert_eq!(der.len(), template.der().len());
+        assert_eq!(get_tbs(&der).unwrap(), tbs);
+
+        let options = CodeGenOptions::new().full_der_const();
+        let code = CodeGen::code("TestTbs", template, &options);
+        let array = code.split("const FULL_DER :").nth(1).unwrap();
+        let array = &array[array.find("= [").unwrap() + 3..];
+        let array = &array[..array.find(']').unwrap()];
+        let emitted: Vec<u8> = array
+            .split(',')
+            .map(str::trim)
+            .filter(|b| !b.is_empty())
+            .map(|b| b.strip_suffix("u8").unwrap().parse().unwrap())
+            .collect();
+        assert_eq!(emitted, der);
+        assert_eq!(get_tbs(&emitted).unwrap(), tbs);
+    }
+}
+
+#[test]
+fn test_gen_code_split_tbs() {
+    use crate::code_gen::{CodeGen, CodeGenOptions};
+
+    let template = generate_test_template(TestAlg::MlDsa87, TestScenario::LdevidCert, 1);
+    let tbs = template.tbs().to_vec();
+    let chunk_len = 1024;
+    let options = CodeGenOptions::new().split_tbs(chunk_len);
+    let code = CodeGen::code("TestTbs", template, &options);
+    syn::parse_file(&code).unwrap();
+    assert!(!code.contains("TBS_TEMPLATE_LEN] = ["));
+
+    let count = tbs.len().div_ceil(chunk_len);
+    assert!(count > 1);
+    let chunks = format!("TBS_TEMPLATE_CHUNKS:[&'static[u8];{}usize]", count);
+    assert!(code.replace(' ', "").contains(&chunks));
+    let mut joined = Vec::new();
+    for i in 0..count {
+        let array = code
+            .split(&format!("const TBS_TEMPLATE_CHUNK_{} :", i))
+            .nth(1)
+            .unwrap();
+        let array = &array[array.find("= [").unwrap() + 3..];
+        let array = &array[..array.find(']').unwrap()];
+        let chunk: Vec<u8> = array
+            .split(',')
+            .map(str::trim)
+            .filter(|b| !b.is_empty())
+            .map(|b| b.strip_suffix("u8").unwrap().parse().unwrap())
+            .collect();
+        assert!(chunk.len() <= chunk_len);
+        joined.extend(chunk);
+    }
+    assert!(!code.contains(&format!("TBS_TEMPLATE_CHUNK_{} :", count)));
+    assert_eq!(joined, tbs);
+}
+
+#[test]
+fn test_gen_code_sorted_params() {
+    use crate::code_gen::{CodeGen, CodeGenOptions};
+
+    let template = generate_test_template(TestAlg::MlDsa87, TestScenario::RtAliasCert, 1);
+    let sorted: Vec<tbs::TbsParam> = template.sorted_params().into_iter().copied().collect();
+    assert_eq!(sorted.len(), template.params().len());
+    assert!(sorted.windows(2).all(|w| w[0].offset <= w[1].offset));
+    for param in template.params() {
+        assert!(sorted.iter().any(|p| p.name == param.name));
+    }
+
+    // The offset consts are emitted in the order of the TBS
+    let options = CodeGenOptions::new().sorted_params();
+    let code = CodeGen::code("RtAliasCertTbsMlDsa87", template, &options);
+    let positions: Vec<usize> = sorted
+        .iter()
+        .map(|p| {
+            let name = format!("const {}_OFFSET : usize", p.name.to_uppercase());
+            code.find(&name).unwrap()
+        })
+        .collect();
+    assert!(positions.windows(2).all(|w| w[0] < w[1]));
+}
+
+#[test]
+fn test_gen_code_metadata() {
+    use crate::code_gen::{CodeGen, CodeGenOptions};
+
+    let (alg, scenario) = (TestAlg::MlDsa87, TestScenario::IdevidCsr);
+    let template = generate_test_template(alg, scenario, 1);
+    let default_code = CodeGen::code("TestTbs", template, &CodeGenOptions::default());
+    assert!(!default_code.contains("METADATA"));
+
+    let template = generate_test_template(alg, scenario, 1);
+    let options = CodeGenOptions::new()
+        .metadata("algorithm", &format!("{:?}", alg))
+        .metadata("scenario", &format!("{:?}", scenario))
+        .metadata("ueid", &hex::encode([0xFF; 17]));
+    let code = CodeGen::code("TestTbs", template, &options).replace(' ', "");
+    assert!(code.contains("pubconstMETADATA_ALGORITHM:&'staticstr=\"MlDsa87\";"));
+    assert!(code.contains("pubconstMETADATA_SCENARIO:&'staticstr=\"IdevidCsr\";"));
+    assert!(code.contains(&format!(
+        "pubconstMETADATA_UEID:&'staticstr=\"{}\";",
+        "ff".repeat(17)
+    )));
+    assert!(code.contains(
+        "pubconstMETADATA:[(&'staticstr,&'staticstr);3usize]=[(\"algorithm\",\"MlDsa87\"),"
+    ));
+}
+
+#[test]
+fn test_runtime_assemble_cert() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::code_gen::{CodeGen, CodeGenOptions};
+    use crate::keys::{mldsa87_key_from_seed, BuilderKeys};
+    use crate::runtime::{
+        assemble_cert, generalized_time, mask_serial_number, set_serial_number, signed_len,
+        utc_time, write_param, RuntimeError, Time,
+    };
+    use crate::tbs::{get_tbs, SERIAL_NUMBER_PARAM};
+    use der::{Decode, Encode};
+    use ml_dsa::MlDsa87;
+    use x509_cert::Certificate;
+
+    let build = |seed: &[u8; 32]| {
+        CertTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::ldevid_profile(&[0xFF; 17])
+            .set_subject_key(mldsa87_key_from_seed(seed))
+            .set_issuer_key(mldsa87_key_from_seed(&[0x02; 32]))
+            .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID")
+    };
+    let template = build(&[0x01; 32]);
+    // Values of the template of another subject key, with the same layout
+    let other = build(&[0x03; 32]);
+    let mut expected = get_tbs(other.der()).unwrap().to_vec();
+
+    let not_before = Time {
+        year: 2023,
+        month: 1,
+        day: 1,
+        hour: 0,
+        minute: 0,
+        second: 0,
+    };
+    let not_after = Time {
+        year: 2049,
+        month: 12,
+        day: 31,
+        hour: 23,
+        minute: 59,
+        second: 59,
+    };
+    let mut tbs = template.tbs().to_vec();
+    for param in template.params() {
+        let range = param.offset..param.offset + param.len;
+        let written = match param.name {
+            "NOT_BEFORE" => write_param(
+                &mut tbs,
+                param.offset,
+                param.len,
+                &utc_time(&not_before).unwrap(),
+            ),
+            "NOT_AFTER" => write_param(
+                &mut tbs,
+                param.offset,
+                param.len,
+                &utc_time(&not_after).unwrap(),
+            ),
+            SERIAL_NUMBER_PARAM => {
+                set_serial_number(&mut tbs[range.clone()], &expected[range.clone()]);
+                mask_serial_number(&mut expected[range]);
+                Ok(())
+            }
+            _ => write_param(&mut tbs, param.offset, param.len, &expected[range]),
+        };
+        written.unwrap();
+    }
+    assert_eq!(tbs, expected);
+
+    // Sign the patched TBS and assemble the certificate
+    let issuer_key = mldsa87_key_from_seed(&[0x02; 32]);
+    let signature: ml_dsa::Signature<MlDsa87> = signature::Signer::sign(&issuer_key, &tbs);
+    let signature = signature.encode();
+    let signature_algorithm = Certificate::from_der(template.der())
+        .unwrap()
+        .signature_algorithm
+        .to_der()
+        .unwrap();
+    let mut cert = vec![0; signed_len(tbs.len(), signature_algorithm.len(), signature.len())];
+    let len = assemble_cert(&tbs, &signature_algorithm, &signature, &mut cert).unwrap();
+    assert_eq!(len, cert.len());
+    let decoded = Certificate::from_der(&cert).unwrap();
+    assert_eq!(decoded.to_der().unwrap(), cert);
+    assert_eq!(get_tbs(&cert).unwrap(), tbs);
+    assert!(issuer_key.verify(&tbs, decoded.signature.raw_bytes()));
+    assert_eq!(
+        assemble_cert(&tbs, &signature_algorithm, &signature, &mut cert[..len - 1]),
+        Err(RuntimeError::BufferTooSmall)
+    );
+
+    // Bounds and range checks
+    let tbs_len = tbs.len();
+    assert_eq!(
+        write_param(&mut tbs, 0, 2, &[0; 3]),
+        Err(RuntimeError::ValueLength)
+    );
+    assert_eq!(
+        write_param(&mut tbs, tbs_len - 1, 2, &[0; 2]),
+        Err(RuntimeError::ParamBounds)
+    );
+    assert_eq!(
+        write_param(&mut tbs, usize::MAX, 2, &[0; 2]),
+        Err(RuntimeError::ParamBounds)
+    );
+    assert_eq!(&generalized_time(&not_after).unwrap(), b"20491231235959Z");
+    let leap_day = Time {
+        year: 2100,
+        month: 2,
+        day: 29,
+        ..not_before
+    };
+    assert_eq!(generalized_time(&leap_day), Err(RuntimeError::InvalidTime));
+    let after_2049 = Time {
+        year: 2050,
+        ..not_before
+    };
+    assert_eq!(utc_time(&after_2049), Err(RuntimeError::InvalidTime));
+
+    // Generated code calls the runtime helpers instead of copies of them
+    let options = CodeGenOptions::new().runtime_path("caliptra_x509::runtime");
+    let code = CodeGen::code("LocalDevIdCertTbsMlDsa87", template, &options);
+    assert!(code.contains("caliptra_x509 :: runtime :: apply_slice ::"));
+    assert!(code.contains("caliptra_x509 :: runtime :: set_serial_number (field , serial)"));
+    assert!(!code.contains("fn apply_slice"));
+}
+
+#[test]
+fn test_placeholder_fill() {
+    use crate::cert_rustcrypto::CertTemplateBuilder;
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::tbs::PlaceholderFill;
+    use ml_dsa::MlDsa87;
+
+    type Key = ml_dsa::KeyPair<MlDsa87>;
+
+    let offsets = |template: &tbs::TbsTemplate| -> Vec<(&'static str, usize, usize)> {
+        template
+            .params()
+            .iter()
+            .map(|p| (p.name, p.offset, p.len))
+            .collect()
+    };
+    let region = |template: &tbs::TbsTemplate, name: &str| -> Vec<u8> {
+        let p = template.params().iter().find(|p| p.name == name).unwrap();
+        template.tbs()[p.offset..p.offset + p.len].to_vec()
+    };
+
+    let sanitized = CsrTemplateBuilder::<Key>::idevid_profile(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    let filled = CsrTemplateBuilder::<Key>::idevid_profile(&[0xFF; 17])
+        .placeholder_fill(PlaceholderFill::Byte(0xAA))
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    assert_eq!(region(&sanitized, "UEID"), [0x5F; 17]);
+    assert_eq!(region(&filled, "UEID"), [0xAA; 17]);
+    assert_eq!(offsets(&filled), offsets(&sanitized));
+
+    let counted = CertTemplateBuilder::<Key>::ldevid_profile(&[0xFF; 17])
+        .placeholder_fill(PlaceholderFill::Counter)
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    assert_eq!(region(&counted, "UEID"), (0..17).collect::<Vec<u8>>());
+
+    // The unsanitized params keep their bytes whatever the fill
+    let csr = |fill: PlaceholderFill| {
+        CsrTemplateBuilder::<Key>::new()
+            .set_key(crate::keys::mldsa87_key_from_seed(&[0x01; 32]))
+            .add_ueid_ext(&[0xFF; 17])
+            .add_spki_algorithm_param()
+            .add_subject_param(160)
+            .placeholder_fill(fill)
+            .tbs_template("Caliptra 2.0 MlDsa87 IDevID")
+    };
+    let (sanitized, filled) = (
+        csr(PlaceholderFill::Sanitized),
+        csr(PlaceholderFill::Byte(0xAA)),
+    );
+    for name in ["SPKI_ALGORITHM", "SUBJECT"] {
+        assert_eq!(region(&filled, name), region(&sanitized, name));
+        assert!(region(&filled, name).iter().any(|&b| b != 0xAA));
+    }
+    assert_eq!(region(&filled, "UEID"), [0xAA; 17]);
+}
+
+#[cfg(feature = "host-verify")]
+#[test]
+fn test_csr_resign_and_verify() {
+    use crate::csr_rustcrypto::CsrTemplateBuilder;
+    use crate::keys::mldsa87_key_from_seed;
+    use crate::name::key_hash;
+    use crate::tbs::get_tbs;
+    use der::Decode;
+    use ml_dsa::MlDsa87;
+    use signature::Keypair;
+    use x509_cert::request::CertReq;
+
+    let template = CsrTemplateBuilder::<ml_dsa::KeyPair<MlDsa87>>::new()
+        .set_key(mldsa87_key_from_seed(&[0x01; 32]))
+        .add_ueid_ext(&[0xFF; 17])
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+
+    // Fresh key, patched into the template then signing it
+    let key = mldsa87_key_from_seed(&[0x02; 32]);
+    let der = template.resign_and_verify(&key).unwrap();
+    let csr = CertReq::from_der(&der).unwrap();
+    assert_eq!(
+        csr.info.public_key.subject_public_key.raw_bytes(),
+        key.verifying_key().encode().as_slice()
+    );
+    let subject = csr.info.subject.to_string();
+    assert!(subject.contains(&key_hash(&key.verifying_key())));
+
+    // Same layout as the template
+    assert_eq!(get_tbs(&der).unwrap().len(), template.tbs().len());
+    assert_eq!(der.len(), template.der().len());
+}
@@ -0,0 +1,157 @@
+/*++
+
+Licensed under the Apache-2.0 license.
+
+File Name:
+
+    measurement.rs
+
+Abstract:
+
+    File contains the FWID digests read from the measurement files of the build.
+
+--*/
+
+use crate::cert_rustcrypto::{Fwid, FwidParam};
+use const_oid::ObjectIdentifier;
+use std::borrow::Cow;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// id-sha256
+pub const SHA256_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.1");
+
+/// id-sha384
+pub const SHA384_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.2");
+
+/// id-sha512
+pub const SHA512_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.3");
+
+/// id-sha3-256
+pub const SHA3_256_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.8");
+
+/// id-sha3-384
+pub const SHA3_384_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.9");
+
+/// id-sha3-512
+pub const SHA3_512_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.10");
+
+/// Digest length of the hash algorithm `hash_alg`, if it is known
+pub fn digest_len(hash_alg: ObjectIdentifier) -> Option<usize> {
+    match hash_alg {
+        oid if oid == SHA256_OID || oid == SHA3_256_OID => Some(32),
+        oid if oid == SHA384_OID || oid == SHA3_384_OID => Some(48),
+        oid if oid == SHA512_OID || oid == SHA3_512_OID => Some(64),
+        _ => None,
+    }
+}
+
+/// Measurement file error
+#[derive(Debug)]
+pub enum MeasurementError {
+    /// Measurement file that couldn't be read
+    Io { path: PathBuf, err: std::io::Error },
+    /// Digest whose length doesn't identify a hash algorithm
+    DigestLength { path: PathBuf, len: usize },
+}
+
+impl fmt::Display for MeasurementError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, err } => write!(f, "Measurement file {}: {}", path.display(), err),
+            Self::DigestLength { path, len } => write!(
+                f,
+                "Digest of {} bytes in {} is not SHA-256, SHA-384 or SHA-512",
+                len,
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MeasurementError {}
+
+/// FWID digest owned by the caller, to build `Fwid` and `FwidParam` values borrowing it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FwidDigest {
+    pub hash_alg: ObjectIdentifier,
+    pub digest: Vec<u8>,
+}
+
+impl FwidDigest {
+    /// Read the digest of the measurement file at `path`, inferring the hash algorithm from
+    /// its length
+    ///
+    /// The file holds either the hex encoded digest, surrounding whitespace being ignored,
+    /// or the raw digest bytes.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, MeasurementError> {
+        let path = path.as_ref();
+        let digest = read_digest(path)?;
+        let hash_alg = match digest.len() {
+            32 => SHA256_OID,
+            48 => SHA384_OID,
+            64 => SHA512_OID,
+            len => {
+                return Err(MeasurementError::DigestLength {
+                    path: path.to_path_buf(),
+                    len,
+                })
+            }
+        };
+        Ok(Self { hash_alg, digest })
+    }
+
+    /// Read the digest of the measurement file at `path`, computed with `hash_alg`
+    pub fn from_file_with_alg(
+        path: impl AsRef<Path>,
+        hash_alg: ObjectIdentifier,
+    ) -> Result<Self, MeasurementError> {
+        let digest = read_digest(path.as_ref())?;
+        Ok(Self { hash_alg, digest })
+    }
+
+    pub fn fwid(&self) -> Fwid<'_> {
+        Fwid {
+            hash_alg: self.hash_alg,
+            digest: Cow::Borrowed(&self.digest),
+        }
+    }
+
+    /// FWID param `name`, with the digest as its generation-time value
+    pub fn param(&self, name: &'static str) -> FwidParam<'_> {
+        FwidParam {
+            name,
+            fwid: self.fwid(),
+        }
+    }
+}
+
+/// Value of a FWID digest param of `len` bytes holding the shorter `digest`
+///
+/// Templates whose hash algorithm is chosen at runtime reserve the longest digest. A shorter
+/// digest fills the start of the param and the remaining bytes are zero; verifiers read as
+/// many bytes as the patched hashAlg produces.
+pub fn padded_digest(digest: &[u8], len: usize) -> Vec<u8> {
+    if digest.len() > len {
+        panic!(
+            "Digest of {} bytes doesn't fit the FWID param of {} bytes",
+            digest.len(),
+            len
+        );
+    }
+    let mut value = digest.to_vec();
+    value.resize(len, 0);
+    value
+}
+
+fn read_digest(path: &Path) -> Result<Vec<u8>, MeasurementError> {
+    let data = std::fs::read(path).map_err(|err| MeasurementError::Io {
+        path: path.to_path_buf(),
+        err,
+    })?;
+    let text = data.trim_ascii();
+    match hex::decode(text) {
+        Ok(digest) if !text.is_empty() => Ok(digest),
+        _ => Ok(data),
+    }
+}
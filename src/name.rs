@@ -0,0 +1,201 @@
+/*++
+
+Licensed under the Apache-2.0 license.
+
+File Name:
+
+    name.rs
+
+Abstract:
+
+    File contains construction of the subject and issuer names used in X509 templates.
+
+--*/
+
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+use const_oid::ObjectIdentifier;
+use sha2::{Digest, Sha256, Sha384};
+use spki::EncodePublicKey;
+use x509_cert::der::{Decode, Encode};
+use x509_cert::name::Name;
+
+use crate::tbs::TbsTemplate;
+use crate::validate::BuilderError;
+
+/// id-at-commonName
+const COMMON_NAME_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.4.3");
+
+/// id-at-serialNumber
+const SERIAL_NUMBER_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.4.5");
+
+/// Sentinel padding the subject CN placeholder
+pub const SUBJECT_CN_SENTINEL: char = '*';
+
+/// Sentinel padding the issuer CN placeholder
+pub const ISSUER_CN_SENTINEL: char = '^';
+
+/// Pad `cn` with `sentinel` to a placeholder of exactly `len` characters
+///
+/// Distinct sentinels keep the placeholders of different names from matching each other
+/// during needle search, so `cn` must be shorter than `len`, see
+/// `validate::check_cn_reservation`.
+pub fn cn_placeholder(cn: &str, sentinel: char, len: usize) -> String {
+    if cn.len() >= len {
+        panic!(
+            "CN '{}' does not fit the reserved length of {} bytes",
+            cn, len
+        );
+    }
+    if !cn.is_ascii() {
+        panic!("CN '{}' placeholder must be ASCII", cn);
+    }
+    let mut placeholder = cn.to_string();
+    placeholder.extend(std::iter::repeat(sentinel).take(len - cn.len()));
+    placeholder
+}
+
+/// Compute the serialNumber attribute of the Name of the owner of `verifying_key`
+///
+/// Use it to reproduce the issuer serialNumber of a child template without re-implementing
+/// the derivation.
+pub fn key_hash(verifying_key: &impl EncodePublicKey) -> String {
+    let der = verifying_key.to_public_key_der().unwrap();
+    let spki: spki::SubjectPublicKeyInfo<der::asn1::Any, der::asn1::BitString> =
+        spki::SubjectPublicKeyInfo::from_der(der.as_bytes()).unwrap();
+    key_bytes_hash(spki.subject_public_key.as_bytes().unwrap())
+}
+
+/// Compute the serialNumber attribute from the public key bytes of a SubjectPublicKeyInfo,
+/// the uppercase hex SHA-256 digest of the key
+pub fn key_bytes_hash(public_key: &[u8]) -> String {
+    key_bytes_hash_with(SnHash::Sha256, public_key)
+}
+
+/// Digest of the public key held by the serialNumber attribute of the Names
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SnHash {
+    /// 64 hex characters
+    #[default]
+    Sha256,
+    /// 96 hex characters
+    Sha384,
+}
+
+/// Compute the serialNumber attribute from the public key bytes of a SubjectPublicKeyInfo,
+/// the uppercase hex `hash` digest of the key
+pub fn key_bytes_hash_with(hash: SnHash, public_key: &[u8]) -> String {
+    let digest = match hash {
+        SnHash::Sha256 => Sha256::digest(public_key).to_vec(),
+        SnHash::Sha384 => Sha384::digest(public_key).to_vec(),
+    };
+    hex::encode(digest).to_uppercase()
+}
+
+/// Subject Name of the templates, see `format_name`
+pub const DEFAULT_SUBJECT_FORMAT: &str = "CN={cn},serialNumber={sn}";
+
+/// Subject Name of the templates without a serialNumber RDN, see `subject_format`
+pub const CN_ONLY_SUBJECT_FORMAT: &str = "CN={cn}";
+
+/// Select the subject format of a builder set to `format`
+///
+/// Without `serial_number_rdn`, the default format is replaced by `CN_ONLY_SUBJECT_FORMAT`
+/// and a custom format must not hold `{sn}`.
+pub fn subject_format(format: &str, serial_number_rdn: bool) -> &str {
+    match (format, serial_number_rdn) {
+        (DEFAULT_SUBJECT_FORMAT, false) => CN_ONLY_SUBJECT_FORMAT,
+        (format, false) if format.contains("{sn}") => panic!(
+            "Subject format '{}' holds the {{sn}} of the omitted serialNumber RDN",
+            format
+        ),
+        (format, _) => format,
+    }
+}
+
+/// Build the Name of the RFC 4514 string `format`, substituting `{cn}` and `{sn}` with the
+/// CommonName and serialNumber values
+///
+/// The format may repeat attribute types, e.g. `OU=A,OU=B,CN={cn},serialNumber={sn}`.
+pub fn format_name(format: &str, cn: &str, serial: &str) -> Name {
+    let name = format.replace("{cn}", cn).replace("{sn}", serial);
+    match Name::from_str(&name) {
+        Ok(name) => name,
+        Err(err) => panic!("Invalid subject name '{}': {}", name, err),
+    }
+}
+
+/// Build the `CN=<cn>,serialNumber=<serial>` name
+pub fn cn_sn_name(cn: &str, serial: &str) -> Name {
+    format_name(DEFAULT_SUBJECT_FORMAT, cn, serial)
+}
+
+/// Build the `CN=<cn>,serialNumber=<serial>` name with an encoding of exactly `len` bytes
+///
+/// The CN is padded with trailing spaces to fill the reservation, so firmware can substitute
+/// any name of the same encoded length without shifting the offsets of other params. Each
+/// padding step may grow the encoding by more than one byte, e.g. when a DER length moves
+/// to its long form, so a `len` skipped by the padding or shorter than the unpadded Name is
+/// reported as `BuilderError::SubjectLength` with the closest reachable lengths.
+pub fn reserved_cn_sn_name(cn: &str, serial: &str, len: usize) -> Result<Name, BuilderError> {
+    reserved_name(DEFAULT_SUBJECT_FORMAT, cn, serial, len)
+}
+
+/// Build the Name of `format`, see `format_name`, with an encoding of exactly `len` bytes
+/// by padding the CN as `reserved_cn_sn_name` does
+pub fn reserved_name(
+    format: &str,
+    cn: &str,
+    serial: &str,
+    len: usize,
+) -> Result<Name, BuilderError> {
+    let mut cn = cn.to_string();
+    let mut below = None;
+    loop {
+        let name = format_name(format, &cn, serial);
+        let name_len = name.to_der().unwrap().len();
+        match name_len.cmp(&len) {
+            Ordering::Equal => return Ok(name),
+            Ordering::Less => {
+                below = Some(name_len);
+                // Escape the space so it isn't dropped as insignificant trailing whitespace
+                cn.push_str("\\ ");
+            }
+            Ordering::Greater => {
+                return Err(BuilderError::SubjectLength {
+                    len,
+                    below,
+                    above: name_len,
+                })
+            }
+        }
+    }
+}
+
+/// Retrieve the subject Name of a certificate or CSR template
+pub fn template_subject(template: &TbsTemplate) -> Name {
+    if let Ok(cert) = x509_cert::Certificate::from_der(template.der()) {
+        return cert.tbs_certificate.subject;
+    }
+    match x509_cert::request::CertReq::from_der(template.der()) {
+        Ok(req) => req.info.subject,
+        Err(_) => panic!("Template is neither a certificate nor a CSR"),
+    }
+}
+
+/// Retrieve the value of the serialNumber attribute of `name`
+pub fn name_serial_number(name: &Name) -> Option<Vec<u8>> {
+    name.iter_rdn()
+        .flat_map(|rdn| rdn.iter())
+        .find(|attr| attr.oid == SERIAL_NUMBER_OID)
+        .map(|attr| attr.value.value().to_vec())
+}
+
+/// Retrieve the value of the commonName attribute of `name`
+pub fn name_common_name(name: &Name) -> Option<Vec<u8>> {
+    name.iter_rdn()
+        .flat_map(|rdn| rdn.iter())
+        .find(|attr| attr.oid == COMMON_NAME_OID)
+        .map(|attr| attr.value.value().to_vec())
+}
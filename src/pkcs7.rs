@@ -0,0 +1,75 @@
+/*++
+
+Licensed under the Apache-2.0 license.
+
+File Name:
+
+    pkcs7.rs
+
+Abstract:
+
+    File contains the degenerate PKCS#7 bundle distributing generated certificates.
+
+--*/
+
+use cms::cert::CertificateChoices;
+use cms::content_info::{CmsVersion, ContentInfo};
+use cms::signed_data::{CertificateSet, EncapsulatedContentInfo, SignedData, SignerInfos};
+use const_oid::ObjectIdentifier;
+use der::asn1::{Any, SetOfVec};
+use der::{Decode, Encode, ErrorKind};
+use x509_cert::Certificate;
+
+/// id-data
+pub const DATA_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.7.1");
+
+/// id-signedData
+pub const SIGNED_DATA_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.7.2");
+
+/// Wrap the certificate DERs `certs`, e.g. a DICE chain, in a certs-only CMS SignedData
+/// without signers, the .p7b expected by some verifiers
+///
+/// DER sorts the certificates SET OF, so the bundle doesn't keep the order of `certs`.
+pub fn certs_only_bundle(certs: &[&[u8]]) -> Result<Vec<u8>, der::Error> {
+    let certs = certs
+        .iter()
+        .map(|der| Ok(CertificateChoices::Certificate(Certificate::from_der(der)?)))
+        .collect::<Result<Vec<_>, der::Error>>()?;
+    let signed_data = SignedData {
+        version: CmsVersion::V1,
+        digest_algorithms: SetOfVec::new(),
+        encap_content_info: EncapsulatedContentInfo {
+            econtent_type: DATA_OID,
+            econtent: None,
+        },
+        certificates: Some(CertificateSet(SetOfVec::try_from(certs)?)),
+        crls: None,
+        signer_infos: SignerInfos(SetOfVec::new()),
+    };
+    ContentInfo {
+        content_type: SIGNED_DATA_OID,
+        content: Any::encode_from(&signed_data)?,
+    }
+    .to_der()
+}
+
+/// Retrieve the certificate DERs of the CMS SignedData `bundle`, in the order of the bundle
+pub fn bundle_certs(bundle: &[u8]) -> Result<Vec<Vec<u8>>, der::Error> {
+    let content_info = ContentInfo::from_der(bundle)?;
+    if content_info.content_type != SIGNED_DATA_OID {
+        return Err(ErrorKind::OidUnknown {
+            oid: content_info.content_type,
+        }
+        .into());
+    }
+    let signed_data: SignedData = content_info.content.decode_as()?;
+    signed_data
+        .certificates
+        .iter()
+        .flat_map(|set| set.0.iter())
+        .filter_map(|choice| match choice {
+            CertificateChoices::Certificate(cert) => Some(cert.to_der()),
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,152 @@
+/*++
+
+Licensed under the Apache-2.0 license.
+
+File Name:
+
+    placeholder.rs
+
+Abstract:
+
+    File contains the allocation of distinct generation-time values of the byte params, so
+    their needles resolve unambiguously.
+
+--*/
+
+use crate::cert_rustcrypto::{Fwid, FwidParam};
+use crate::measurement::digest_len;
+use crate::tbs::{get_tbs, TbsTemplate, TemplateError};
+use const_oid::ObjectIdentifier;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+
+/// Allocator of distinct placeholder patterns for the params of the templates
+///
+/// Patterns are derived from the param name and an allocation counter, so the same sequence
+/// of allocations yields the same templates. A pattern is never contained in another one.
+/// Pass the allocator to `with_placeholder_allocator` of the builders, which then allocate
+/// the needles of their own params from it, e.g. the serial number and the key identifiers,
+/// and check that no pattern occurs in the TBS outside of its param.
+#[derive(Clone, Debug, Default)]
+pub struct PlaceholderAllocator {
+    counter: u32,
+    placeholders: Vec<(&'static str, Vec<u8>)>,
+}
+
+impl PlaceholderAllocator {
+    /// Create an instance of `PlaceholderAllocator` without allocated patterns
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allocate a pattern of `len` bytes for the param `name`
+    pub fn allocate(&mut self, name: &'static str, len: usize) -> Vec<u8> {
+        self.allocate_with(name, len, |_| {})
+    }
+
+    /// Allocate a pattern of `len` bytes for the param `name`, brought to the encoding of its
+    /// field by `encode`, e.g. `alphanumeric` for a CN
+    pub fn allocate_with(
+        &mut self,
+        name: &'static str,
+        len: usize,
+        encode: impl Fn(&mut [u8]),
+    ) -> Vec<u8> {
+        if len == 0 {
+            panic!("Placeholder of {} requires a non-zero length", name);
+        }
+        let placeholder = loop {
+            let mut candidate = pattern(name, self.counter, len);
+            encode(&mut candidate);
+            self.counter += 1;
+            let distinct = self.placeholders.iter().all(|(_, placeholder)| {
+                !contains(placeholder, &candidate) && !contains(&candidate, placeholder)
+            });
+            if distinct {
+                break candidate;
+            }
+        };
+        self.placeholders.push((name, placeholder.clone()));
+        placeholder
+    }
+
+    /// Record the `value` of the param `name` that can't be a pattern, e.g. a validity time,
+    /// so it is checked like the patterns and the later allocations are distinct from it
+    pub fn register(&mut self, name: &'static str, value: &[u8]) {
+        self.placeholders.push((name, value.to_vec()));
+    }
+
+    /// Allocate the digest of the FWID `name` of the hash algorithm `hash_alg`
+    ///
+    /// Panics if the digest length of `hash_alg` is unknown.
+    pub fn fwid(&mut self, name: &'static str, hash_alg: ObjectIdentifier) -> FwidParam<'static> {
+        let Some(len) = digest_len(hash_alg) else {
+            panic!(
+                "Unknown digest length of the FWID {} hash algorithm {}",
+                name, hash_alg
+            );
+        };
+        FwidParam {
+            name,
+            fwid: Fwid {
+                hash_alg,
+                digest: Cow::Owned(self.allocate(name, len)),
+            },
+        }
+    }
+
+    /// Retrieve the allocated patterns and registered values with the names of their params
+    pub fn placeholders(&self) -> &[(&'static str, Vec<u8>)] {
+        &self.placeholders
+    }
+
+    /// Check that each allocated pattern only occurs in the TBS of `template` at a param of
+    /// its name
+    pub fn check(&self, template: &TbsTemplate) -> Result<(), TemplateError> {
+        let tbs = get_tbs(template.der())?;
+        for &(name, ref placeholder) in self.placeholders.iter() {
+            let len = placeholder.len();
+            for (offset, window) in tbs.windows(len).enumerate() {
+                if window != &placeholder[..] {
+                    continue;
+                }
+                let own = template
+                    .params()
+                    .iter()
+                    .any(|p| p.name == name && p.offset == offset && p.len == len);
+                if !own {
+                    return Err(TemplateError::PlaceholderCollision { name, offset });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Map the bytes of `placeholder` to ASCII letters and digits, e.g. for a CN
+pub fn alphanumeric(placeholder: &mut [u8]) {
+    const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+    for byte in placeholder.iter_mut() {
+        *byte = CHARSET[*byte as usize % CHARSET.len()];
+    }
+}
+
+/// Expand the SHA-256 digests of `name`, `counter` and a block index to `len` bytes
+fn pattern(name: &str, counter: u32, len: usize) -> Vec<u8> {
+    (0_u32..)
+        .flat_map(|block| {
+            Sha256::new()
+                .chain_update(name.as_bytes())
+                .chain_update(counter.to_be_bytes())
+                .chain_update(block.to_be_bytes())
+                .finalize()
+        })
+        .take(len)
+        .collect()
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
@@ -0,0 +1,352 @@
+/*++
+
+Licensed under the Apache-2.0 license.
+
+File Name:
+
+    profile.rs
+
+Abstract:
+
+    File contains validation of X509 templates against the TCG DICE certificate profile.
+
+--*/
+
+use std::fmt;
+
+use const_oid::{AssociatedOid, ObjectIdentifier};
+use der::Decode;
+use num_bigint::BigUint;
+use x509_cert::ext::pkix::{
+    AuthorityKeyIdentifier, BasicConstraints, KeyUsage, SubjectKeyIdentifier,
+};
+use x509_cert::ext::{Extension, Extensions};
+
+/// tcg-dice-Ueid
+pub const UEID_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.23.133.5.4.4");
+
+/// Encode the integer UEID `value` as `width` big-endian bytes, keeping the leading zeros
+///
+/// Returns `None` if `value` doesn't fit `width` bytes.
+pub fn ueid_int_bytes(value: &BigUint, width: usize) -> Option<Vec<u8>> {
+    let bytes = value.to_bytes_be();
+    // Zero encodes as a single zero byte
+    let bytes = if bytes == [0] { Vec::new() } else { bytes };
+    if bytes.len() > width {
+        return None;
+    }
+    let mut ueid = vec![0; width - bytes.len()];
+    ueid.extend_from_slice(&bytes);
+    Some(ueid)
+}
+
+/// tcg-dice-TcbInfo
+pub const TCB_INFO_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.23.133.5.4.1");
+
+/// tcg-dice-MultiTcbInfo
+pub const MULTI_TCB_INFO_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.23.133.5.4.5");
+
+/// tcg-dice-kp-attestLoc, the ExtendedKeyUsage of keys attesting local measurements
+pub const TCG_DICE_KP_ATTEST_LOC_OID: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("2.23.133.5.4.100.9");
+
+/// anyExtendedKeyUsage, the ExtendedKeyUsage key purpose not restricting the key
+pub const ANY_EXTENDED_KEY_USAGE_OID: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("2.5.29.37.0");
+
+/// pkcs-9-at-extensionRequest
+pub const EXTENSION_REQUEST_OID: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.14");
+
+/// Extension order of the legacy Caliptra templates
+pub const CALIPTRA_EXTENSION_ORDER: [ObjectIdentifier; 7] = [
+    BasicConstraints::OID,
+    KeyUsage::OID,
+    UEID_OID,
+    MULTI_TCB_INFO_OID,
+    TCB_INFO_OID,
+    SubjectKeyIdentifier::OID,
+    AuthorityKeyIdentifier::OID,
+];
+
+/// Sort `extensions` by the position of their OID in `order`
+///
+/// Extensions missing from `order` are moved after the others in insertion order. Returns
+/// whether the order of the extensions changed.
+pub fn order_extensions(extensions: &mut [Extension], order: &[ObjectIdentifier]) -> bool {
+    let position = |ext: &Extension| {
+        order
+            .iter()
+            .position(|oid| *oid == ext.extn_id)
+            .unwrap_or(order.len())
+    };
+    if extensions
+        .windows(2)
+        .all(|pair| position(&pair[0]) <= position(&pair[1]))
+    {
+        return false;
+    }
+    extensions.sort_by_key(position);
+    true
+}
+
+/// Sort `extensions` by OID, comparing the arcs numerically, for a canonical order
+/// independent of the order they were added in
+///
+/// Returns whether the order of the extensions changed.
+pub fn sort_extensions_by_oid(extensions: &mut [Extension]) -> bool {
+    let mut order: Vec<ObjectIdentifier> = extensions.iter().map(|ext| ext.extn_id).collect();
+    order.sort_by(|a, b| a.arcs().cmp(b.arcs()));
+    order_extensions(extensions, &order)
+}
+
+/// Sort extension `oids` as `order_extensions` orders extensions, or by OID if `canonical`
+pub fn order_extension_oids(
+    oids: &mut [ObjectIdentifier],
+    order: &[ObjectIdentifier],
+    canonical: bool,
+) {
+    if canonical {
+        oids.sort_by(|a, b| a.arcs().cmp(b.arcs()));
+    } else {
+        oids.sort_by_key(|oid| order.iter().position(|o| o == oid).unwrap_or(order.len()));
+    }
+}
+
+/// Kind of certificate in a DICE chain
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiceCertKind {
+    /// Initial device identity, a CA without TcbInfo
+    IDevId,
+    /// Locally significant device identity, a CA without TcbInfo
+    LDevId,
+    /// Alias certificate of a DICE layer, a CA carrying the TcbInfo of the layer
+    AliasCert,
+    /// End entity certificate carrying the TcbInfo of the attested layer
+    Leaf,
+}
+
+impl DiceCertKind {
+    fn is_ca(self) -> bool {
+        self != Self::Leaf
+    }
+}
+
+/// Violation of the DICE certificate profile
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProfileViolation {
+    /// A required extension is missing
+    MissingExtension(ObjectIdentifier),
+    /// An extension is not allowed for the kind of certificate
+    ForbiddenExtension(ObjectIdentifier),
+    /// An extension is present more than once
+    DuplicateExtension(ObjectIdentifier),
+    /// An extension must be marked critical
+    NonCritical(ObjectIdentifier),
+    /// An extension value can't be decoded
+    Undecodable(ObjectIdentifier),
+    /// The BasicConstraints cA flag doesn't match the kind of certificate
+    CaMismatch { expected: bool },
+    /// A required KeyUsage bit is clear
+    MissingKeyUsage(&'static str),
+    /// A KeyUsage bit is set that is not allowed for the kind of certificate
+    ForbiddenKeyUsage(&'static str),
+}
+
+impl fmt::Display for ProfileViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingExtension(oid) => write!(f, "missing extension {}", oid),
+            Self::ForbiddenExtension(oid) => write!(f, "forbidden extension {}", oid),
+            Self::DuplicateExtension(oid) => write!(f, "duplicate extension {}", oid),
+            Self::NonCritical(oid) => write!(f, "extension {} must be critical", oid),
+            Self::Undecodable(oid) => write!(f, "extension {} can't be decoded", oid),
+            Self::CaMismatch { expected } => write!(f, "BasicConstraints cA must be {}", expected),
+            Self::MissingKeyUsage(usage) => write!(f, "KeyUsage {} must be set", usage),
+            Self::ForbiddenKeyUsage(usage) => write!(f, "KeyUsage {} must be clear", usage),
+        }
+    }
+}
+
+/// Retrieve the extensions of a DER encoded certificate or CSR
+///
+/// CSR extensions are taken from the extensionRequest attribute.
+pub fn der_extensions(der: &[u8]) -> Vec<Extension> {
+    if let Ok(cert) = x509_cert::Certificate::from_der(der) {
+        return cert.tbs_certificate.extensions.unwrap_or_default();
+    }
+    match x509_cert::request::CertReq::from_der(der) {
+        Ok(req) => req
+            .info
+            .attributes
+            .iter()
+            .filter(|attr| attr.oid == EXTENSION_REQUEST_OID)
+            .flat_map(|attr| attr.values.iter())
+            .filter_map(|value| value.decode_as::<Extensions>().ok())
+            .flatten()
+            .collect(),
+        Err(_) => panic!("DER is neither a certificate nor a CSR"),
+    }
+}
+
+/// Validate a DER encoded certificate or CSR against the DICE profile of `kind`
+///
+/// Checks the required and forbidden extensions, their criticality, the KeyUsage bits and
+/// the BasicConstraints, returning all violations found.
+pub fn validate_dice_profile(der: &[u8], kind: DiceCertKind) -> Vec<ProfileViolation> {
+    let extensions = der_extensions(der);
+    let find = |oid: ObjectIdentifier| extensions.iter().find(|ext| ext.extn_id == oid);
+    let mut violations = Vec::new();
+
+    for (i, ext) in extensions.iter().enumerate() {
+        let violation = ProfileViolation::DuplicateExtension(ext.extn_id);
+        if extensions[..i].iter().any(|e| e.extn_id == ext.extn_id)
+            && !violations.contains(&violation)
+        {
+            violations.push(violation);
+        }
+    }
+
+    if find(UEID_OID).is_none() {
+        violations.push(ProfileViolation::MissingExtension(UEID_OID));
+    }
+
+    // Alias and leaf certificates attest their layer, device identities don't
+    let tcb_infos: Vec<&Extension> = [TCB_INFO_OID, MULTI_TCB_INFO_OID]
+        .into_iter()
+        .filter_map(find)
+        .collect();
+    match kind {
+        DiceCertKind::AliasCert | DiceCertKind::Leaf if tcb_infos.is_empty() => {
+            violations.push(ProfileViolation::MissingExtension(TCB_INFO_OID));
+        }
+        DiceCertKind::IDevId | DiceCertKind::LDevId => {
+            for ext in tcb_infos.iter() {
+                violations.push(ProfileViolation::ForbiddenExtension(ext.extn_id));
+            }
+        }
+        _ => (),
+    }
+    for ext in tcb_infos.iter().filter(|ext| !ext.critical) {
+        violations.push(ProfileViolation::NonCritical(ext.extn_id));
+    }
+
+    let ca = kind.is_ca();
+    match find(BasicConstraints::OID) {
+        Some(ext) => match BasicConstraints::from_der(ext.extn_value.as_bytes()) {
+            Ok(bc) => {
+                // RFC 5280 only requires the BasicConstraints of a CA to be critical
+                if bc.ca && !ext.critical {
+                    violations.push(ProfileViolation::NonCritical(ext.extn_id));
+                }
+                if bc.ca != ca {
+                    violations.push(ProfileViolation::CaMismatch { expected: ca });
+                }
+            }
+            Err(_) => violations.push(ProfileViolation::Undecodable(ext.extn_id)),
+        },
+        None if ca => violations.push(ProfileViolation::MissingExtension(BasicConstraints::OID)),
+        None => (),
+    }
+
+    match find(KeyUsage::OID) {
+        Some(ext) => {
+            if !ext.critical {
+                violations.push(ProfileViolation::NonCritical(ext.extn_id));
+            }
+            match KeyUsage::from_der(ext.extn_value.as_bytes()) {
+                Ok(key_usage) => {
+                    if ca && !key_usage.key_cert_sign() {
+                        violations.push(ProfileViolation::MissingKeyUsage("keyCertSign"));
+                    }
+                    if !ca && key_usage.key_cert_sign() {
+                        violations.push(ProfileViolation::ForbiddenKeyUsage("keyCertSign"));
+                    }
+                    if !ca && !key_usage.digital_signature() {
+                        violations.push(ProfileViolation::MissingKeyUsage("digitalSignature"));
+                    }
+                }
+                Err(_) => violations.push(ProfileViolation::Undecodable(ext.extn_id)),
+            }
+        }
+        None => violations.push(ProfileViolation::MissingExtension(KeyUsage::OID)),
+    }
+
+    violations
+}
+
+/// Fail template generation if `der` violates the DICE profile of `kind`
+pub fn check_dice_profile(der: &[u8], kind: DiceCertKind) {
+    let violations = validate_dice_profile(der, kind);
+    if !violations.is_empty() {
+        let violations: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+        panic!(
+            "Template violates the {:?} DICE profile: {}",
+            kind,
+            violations.join(", ")
+        );
+    }
+}
+
+/// Whether the BIT STRING TLV `der` is DER-minimal: trailing zero bits are truncated and
+/// the unused-bits octet counts exactly the bits left over in the last content byte
+pub fn bit_string_is_minimal(der: &[u8]) -> bool {
+    let [0x03, len, unused, content @ ..] = der else {
+        return false;
+    };
+    if *len as usize != content.len() + 1 || *unused > 7 {
+        return false;
+    }
+    match content.last() {
+        None => *unused == 0,
+        Some(&last) => last != 0 && last.trailing_zeros() == *unused as u32,
+    }
+}
+
+/// DER-minimal BIT STRING TLV of the KeyUsage `usage`
+///
+/// Named bit n is the bit 7 - n % 8 of content byte n / 8, and the content ends with the
+/// byte of the highest set bit.
+pub fn key_usage_bit_string(usage: KeyUsage) -> Vec<u8> {
+    let bits = usage.0.bits();
+    let mut content: Vec<u8> = Vec::new();
+    for bit in (0..16).filter(|bit| bits & (1 << bit) != 0) {
+        content.resize(bit / 8 + 1, 0);
+        content[bit / 8] |= 0x80 >> (bit % 8);
+    }
+    let unused = content.last().map_or(0, |last| last.trailing_zeros() as u8);
+    let mut der = vec![0x03, content.len() as u8 + 1, unused];
+    der.extend(content);
+    der
+}
+
+/// Fail template generation if the KeyUsage BIT STRING of `der` is not DER-minimal, or
+/// doesn't encode exactly the configured `usage` bits
+///
+/// KeyUsage is a 9 bit named bit list: up to encipherOnly (bit 7) the content is one byte,
+/// decipherOnly (bit 8) alone takes a second byte with 7 unused bits. The encoding length
+/// depends on the bits, which is why KeyUsage is not a patchable param.
+pub fn check_key_usage_encoding(der: &[u8], usage: Option<KeyUsage>) {
+    let Some(ext) = der_extensions(der)
+        .into_iter()
+        .find(|ext| ext.extn_id == KeyUsage::OID)
+    else {
+        if usage.is_some() {
+            panic!("KeyUsage extension is missing");
+        }
+        return;
+    };
+    let bit_string = ext.extn_value.as_bytes();
+    if !bit_string_is_minimal(bit_string) {
+        panic!("KeyUsage BIT STRING {:02X?} is not DER-minimal", bit_string);
+    }
+    if let Some(usage) = usage {
+        let expected = key_usage_bit_string(usage);
+        if bit_string != expected.as_slice() {
+            panic!(
+                "KeyUsage BIT STRING {:02X?} instead of the configured {:02X?}",
+                bit_string, expected
+            );
+        }
+    }
+}
@@ -0,0 +1,114 @@
+/*++
+
+Licensed under the Apache-2.0 license.
+
+File Name:
+
+    provenance.rs
+
+Abstract:
+
+    File contains the provenance record of the generated templates.
+
+--*/
+
+use serde_json::json;
+use std::fmt;
+
+/// Record of the generator, dependencies and inputs producing a template
+///
+/// Only stable data is recorded, no timestamps or paths, so regenerating a template with the
+/// same inputs and crate versions yields the same provenance and reproducible builds hold.
+///
+/// The dependency versions come from the Cargo.lock of the build. Without one, e.g. in a
+/// fresh checkout, they fall back to the Cargo.toml dependency, "git <rev>" for a pinned git
+/// dependency or "req <requirement>", and to "unknown" otherwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    /// Version of the generator crate
+    pub generator: &'static str,
+    /// Locked version of the `ml-dsa` crate
+    pub ml_dsa: &'static str,
+    /// Locked version of the `x509-cert` crate
+    pub x509_cert: &'static str,
+    /// Locked version of the `der` crate
+    pub der: &'static str,
+    /// OID of the signature algorithm of the key
+    pub key_algorithm: String,
+    /// Hex seed of the key, only recorded for deterministic signing
+    pub seed: Option<String>,
+    /// Builder configuration summary, in insertion order
+    pub config: Vec<(String, String)>,
+}
+
+impl Provenance {
+    /// Create the provenance of a template signed with `key_algorithm`
+    pub fn new(key_algorithm: impl fmt::Display) -> Self {
+        Self {
+            generator: env!("CARGO_PKG_VERSION"),
+            ml_dsa: env!("PROVENANCE_ML_DSA_VERSION"),
+            x509_cert: env!("PROVENANCE_X509_CERT_VERSION"),
+            der: env!("PROVENANCE_DER_VERSION"),
+            key_algorithm: key_algorithm.to_string(),
+            seed: None,
+            config: Vec::new(),
+        }
+    }
+
+    /// Record the seed the key was generated from
+    pub fn with_seed(mut self, seed: &[u8]) -> Self {
+        self.seed = Some(hex::encode(seed));
+        self
+    }
+
+    /// Record the builder setting `key`
+    pub fn with_config(mut self, key: &str, value: impl fmt::Display) -> Self {
+        self.config.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Serialize the provenance for the JSON manifest
+    pub fn to_json(&self) -> serde_json::Value {
+        let config: serde_json::Map<String, serde_json::Value> = self
+            .config
+            .iter()
+            .map(|(key, value)| (key.clone(), json!(value)))
+            .collect();
+        json!({
+            "generator": self.generator,
+            "ml_dsa": self.ml_dsa,
+            "x509_cert": self.x509_cert,
+            "der": self.der,
+            "key_algorithm": self.key_algorithm,
+            "seed": self.seed,
+            "config": config,
+        })
+    }
+
+    /// Render the provenance as a `//` comment block for the generated code
+    pub fn comment(&self) -> String {
+        self.to_string()
+            .lines()
+            .map(|line| format!("// {}\n", line))
+            .collect()
+    }
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Generator: test-rustcrypto-mldsa {}", self.generator)?;
+        writeln!(
+            f,
+            "Dependencies: ml-dsa {}, x509-cert {}, der {}",
+            self.ml_dsa, self.x509_cert, self.der
+        )?;
+        writeln!(f, "Key algorithm: {}", self.key_algorithm)?;
+        if let Some(seed) = &self.seed {
+            writeln!(f, "Seed: {}", seed)?;
+        }
+        for (key, value) in self.config.iter() {
+            writeln!(f, "{}: {}", key, value)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+/*++
+
+Licensed under the Apache-2.0 license.
+
+File Name:
+
+    report.rs
+
+Abstract:
+
+    File contains the size report of templates, listing the sizes of their fields,
+    extensions and params and of the signed certificate or CSR for firmware memory planning.
+
+--*/
+
+use crate::inspect::{inspect_tbs, InspectedField};
+use crate::keys::{BuilderKeys, SignatureEncoding};
+use crate::tbs::{der_header, der_lengths, element_children, TbsParam, TbsTemplate};
+use const_oid::ObjectIdentifier;
+use der::Decode;
+use spki::{AlgorithmIdentifierOwned, SignatureAlgorithmIdentifier};
+use std::fmt::Write;
+
+/// Sizes of a template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateSizes {
+    pub name: String,
+    /// "TBSCertificate" or "CertificationRequestInfo"
+    pub kind: &'static str,
+    pub tbs_len: usize,
+    /// Length of the header of the TBS SEQUENCE
+    pub tbs_header_len: usize,
+    /// Top-level fields of the TBS other than the element holding the extensions
+    pub fields: Vec<InspectedField>,
+    /// Extensions, each named after its extnID
+    pub extensions: Vec<InspectedField>,
+    /// Bytes of the element holding the extensions outside of the extensions, e.g. the
+    /// headers of the [3] EXPLICIT SEQUENCE OF a certificate
+    pub extensions_overhead: usize,
+    pub params: Vec<TbsParam>,
+    /// Longest signed certificate or CSR, with the longest signature of its algorithm, or
+    /// `None` if the template has no signed DER
+    pub max_signed_len: Option<usize>,
+}
+
+/// Sizes of a set of templates
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeReport {
+    pub templates: Vec<TemplateSizes>,
+}
+
+/// Compute the sizes of the named `templates` by walking their DER
+pub fn generate(templates: &[(&str, &TbsTemplate)]) -> SizeReport {
+    SizeReport {
+        templates: templates
+            .iter()
+            .map(|(name, template)| template_sizes(name, template))
+            .collect(),
+    }
+}
+
+fn template_sizes(name: &str, template: &TbsTemplate) -> TemplateSizes {
+    let tbs = template.tbs();
+    let inspection = inspect_tbs(tbs, template.params());
+    let (tbs_header_len, _) = der_lengths(tbs, 0);
+
+    let (containers, fields): (Vec<_>, Vec<_>) = inspection
+        .fields
+        .iter()
+        .filter(|f| f.depth == 0)
+        .cloned()
+        .partition(|f| f.name == "extensions" || f.name == "attributes");
+    let extensions: Vec<InspectedField> = inspection
+        .fields
+        .iter()
+        .filter(|f| f.name.starts_with("extension "))
+        .map(|f| InspectedField {
+            name: f.name["extension ".len()..].to_string(),
+            ..f.clone()
+        })
+        .collect();
+    let containers_len: usize = containers.iter().map(|f| f.len).sum();
+    let extensions_len: usize = extensions.iter().map(|f| f.len).sum();
+
+    TemplateSizes {
+        name: name.to_string(),
+        kind: inspection.kind,
+        tbs_len: tbs.len(),
+        tbs_header_len,
+        fields,
+        extensions,
+        extensions_overhead: containers_len - extensions_len,
+        params: template.params().to_vec(),
+        max_signed_len: max_signed_len(template),
+    }
+}
+
+/// Length of the signed DER of `template` with the longest signature of its algorithm
+///
+/// Certificate and CertReq: TBS, signatureAlgorithm, signature BIT STRING
+fn max_signed_len(template: &TbsTemplate) -> Option<usize> {
+    let der = template.der();
+    if der.is_empty() {
+        return None;
+    }
+    let children = element_children(der, 0);
+    let (algorithm, algorithm_len) = children[1];
+    let (signature, _) = children[2];
+    let algorithm =
+        AlgorithmIdentifierOwned::from_der(&der[algorithm..algorithm + algorithm_len]).ok()?;
+    // The BIT STRING content starts with its unused bits byte
+    let (_, signature_len) = der_lengths(der, signature);
+    let bit_string_len = max_signature_len(algorithm.oid, signature_len - 1) + 1;
+
+    let content_len = template.tbs().len()
+        + algorithm_len
+        + der_header(0x03, bit_string_len).len()
+        + bit_string_len;
+    Some(der_header(0x30, content_len).len() + content_len)
+}
+
+/// Longest signature of the signatureAlgorithm `oid`, given the `len` bytes of the
+/// generated signature
+///
+/// The signatures of the key types encoding them as DER are of variable length, the others
+/// are all of the generated length.
+fn max_signature_len(oid: ObjectIdentifier, len: usize) -> usize {
+    let encoding = match oid {
+        oid if oid == p256::ecdsa::SigningKey::SIGNATURE_ALGORITHM_IDENTIFIER.oid => {
+            p256::ecdsa::SigningKey::SIGNATURE_ENCODING
+        }
+        oid if oid == p384::ecdsa::SigningKey::SIGNATURE_ALGORITHM_IDENTIFIER.oid => {
+            p384::ecdsa::SigningKey::SIGNATURE_ENCODING
+        }
+        _ => SignatureEncoding::Fixed,
+    };
+    match encoding {
+        SignatureEncoding::Der { max_len } => max_len,
+        SignatureEncoding::Fixed => len,
+    }
+}
+
+impl SizeReport {
+    /// Render the report as Markdown, a section of tables per template
+    pub fn to_markdown(&self) -> String {
+        let mut md = String::from("# Template sizes\n");
+        for t in self.templates.iter() {
+            writeln!(md, "\n## {}\n", t.name).unwrap();
+            writeln!(md, "{} of {} bytes.", t.kind, t.tbs_len).unwrap();
+            if let Some(len) = t.max_signed_len {
+                writeln!(md, "Signed DER of at most {} bytes.", len).unwrap();
+            }
+            let tables = [("Field", &t.fields), ("Extension", &t.extensions)];
+            for (title, fields) in tables {
+                writeln!(md, "\n| {} | Offset | Size |\n|---|---:|---:|", title).unwrap();
+                for f in fields.iter() {
+                    writeln!(md, "| {} | {} | {} |", f.name, f.offset, f.len).unwrap();
+                }
+            }
+            writeln!(md, "\n| Param | Offset | Size |\n|---|---:|---:|").unwrap();
+            for p in t.params.iter() {
+                writeln!(md, "| {} | {} | {} |", p.name, p.offset, p.len).unwrap();
+            }
+        }
+        md
+    }
+
+    /// Render the report as CSV, a row per template, field, extension and param
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("template,item,name,offset,size\n");
+        for t in self.templates.iter() {
+            writeln!(csv, "{},tbs,{},0,{}", t.name, t.kind, t.tbs_len).unwrap();
+            for f in t.fields.iter() {
+                writeln!(csv, "{},field,{},{},{}", t.name, f.name, f.offset, f.len).unwrap();
+            }
+            for f in t.extensions.iter() {
+                writeln!(
+                    csv,
+                    "{},extension,{},{},{}",
+                    t.name, f.name, f.offset, f.len
+                )
+                .unwrap();
+            }
+            for p in t.params.iter() {
+                writeln!(csv, "{},param,{},{},{}", t.name, p.name, p.offset, p.len).unwrap();
+            }
+            if let Some(len) = t.max_signed_len {
+                writeln!(csv, "{},signed,,0,{}", t.name, len).unwrap();
+            }
+        }
+        csv
+    }
+}
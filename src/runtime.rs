@@ -0,0 +1,222 @@
+/*++
+
+Licensed under the Apache-2.0 license.
+
+File Name:
+
+    runtime.rs
+
+Abstract:
+
+    File contains the firmware-side helpers patching the generated templates and assembling
+    the signed certificates. Only depends on core and never allocates, so no_std firmware
+    can vendor or re-export it and point the generated code at it with
+    `CodeGenOptions::runtime_path`. tests/runtime_no_std.rs builds it in a no_std crate to
+    keep it that way.
+
+--*/
+
+use core::fmt;
+
+/// Firmware-side template error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuntimeError {
+    /// Param range extending past the end of the TBS
+    ParamBounds,
+    /// Value whose length differs from the length of its param
+    ValueLength,
+    /// Date or time outside of the range of its encoding
+    InvalidTime,
+    /// Output buffer too small for the signed DER
+    BufferTooSmall,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ParamBounds => write!(f, "Param extends past the end of the TBS"),
+            Self::ValueLength => write!(f, "Value length differs from the param length"),
+            Self::InvalidTime => write!(f, "Time outside of the range of its encoding"),
+            Self::BufferTooSmall => write!(f, "Buffer too small for the signed DER"),
+        }
+    }
+}
+
+/// Write `value` to the param of `len` bytes at `offset` of `tbs`
+pub fn write_param(
+    tbs: &mut [u8],
+    offset: usize,
+    len: usize,
+    value: &[u8],
+) -> Result<(), RuntimeError> {
+    if value.len() != len {
+        return Err(RuntimeError::ValueLength);
+    }
+    let end = offset.checked_add(len).ok_or(RuntimeError::ParamBounds)?;
+    let field = tbs.get_mut(offset..end).ok_or(RuntimeError::ParamBounds)?;
+    field.copy_from_slice(value);
+    Ok(())
+}
+
+/// Write `value` to the param at the generation-time `OFFSET` of `tbs`
+#[inline(always)]
+pub fn apply_slice<const OFFSET: usize, const LEN: usize>(tbs: &mut [u8], value: &[u8; LEN]) {
+    tbs[OFFSET..OFFSET + LEN].copy_from_slice(value);
+}
+
+/// Force the top nibble of the first byte of the serialNumber content `serial` to 0x4
+///
+/// Any value then encodes a positive INTEGER with no redundant leading zero in the width
+/// reserved by the template.
+pub fn mask_serial_number(serial: &mut [u8]) {
+    if let Some(first) = serial.first_mut() {
+        *first = 0x40 | (*first & 0x0F);
+    }
+}
+
+/// Write `serial` to the SERIAL_NUMBER param `field` and mask it, see `mask_serial_number`
+pub fn set_serial_number(field: &mut [u8], serial: &[u8]) {
+    field.copy_from_slice(serial);
+    mask_serial_number(field);
+}
+
+/// Calendar date and time of a Validity param, in UTC
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Time {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl Time {
+    fn is_valid(&self) -> bool {
+        let leap = self.year % 4 == 0 && (self.year % 100 != 0 || self.year % 400 == 0);
+        let days = match self.month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 if leap => 29,
+            2 => 28,
+            _ => return false,
+        };
+        (1..=days).contains(&self.day) && self.hour < 24 && self.minute < 60 && self.second < 60
+    }
+
+    /// Write the two digits of the month, day, hour, minute and second to `out`
+    fn write_digits(&self, out: &mut [u8]) {
+        for (i, value) in [self.month, self.day, self.hour, self.minute, self.second]
+            .into_iter()
+            .enumerate()
+        {
+            out[2 * i] = b'0' + value / 10;
+            out[2 * i + 1] = b'0' + value % 10;
+        }
+    }
+}
+
+/// Format `time` as the YYYYMMDDHHMMSSZ content of a 15 byte GeneralizedTime param
+pub fn generalized_time(time: &Time) -> Result<[u8; 15], RuntimeError> {
+    if time.year > 9999 || !time.is_valid() {
+        return Err(RuntimeError::InvalidTime);
+    }
+    let mut out = [0u8; 15];
+    let mut year = time.year;
+    for digit in out[..4].iter_mut().rev() {
+        *digit = b'0' + (year % 10) as u8;
+        year /= 10;
+    }
+    time.write_digits(&mut out[4..14]);
+    out[14] = b'Z';
+    Ok(out)
+}
+
+/// Format `time` as the YYMMDDHHMMSSZ content of a 13 byte UTCTime param, for the years
+/// 1950 to 2049 of RFC 5280
+pub fn utc_time(time: &Time) -> Result<[u8; 13], RuntimeError> {
+    if !(1950..=2049).contains(&time.year) || !time.is_valid() {
+        return Err(RuntimeError::InvalidTime);
+    }
+    let mut out = [0u8; 13];
+    let year = (time.year % 100) as u8;
+    out[0] = b'0' + year / 10;
+    out[1] = b'0' + year % 10;
+    time.write_digits(&mut out[2..12]);
+    out[12] = b'Z';
+    Ok(out)
+}
+
+/// Length of the DER length octets of a content of `len` bytes
+const fn der_len_size(len: usize) -> usize {
+    match len {
+        0..=0x7F => 1,
+        0x80..=0xFF => 2,
+        0x100..=0xFFFF => 3,
+        0x1_0000..=0xFF_FFFF => 4,
+        _ => 5,
+    }
+}
+
+/// Write the tag and the DER length of a content of `len` bytes to `out`, returning the
+/// number of bytes written
+fn write_header(out: &mut [u8], tag: u8, len: usize) -> usize {
+    out[0] = tag;
+    let size = der_len_size(len);
+    if size == 1 {
+        out[1] = len as u8;
+    } else {
+        out[1] = 0x80 | (size - 1) as u8;
+        for (i, byte) in out[2..size + 1].iter_mut().enumerate() {
+            *byte = (len >> (8 * (size - 2 - i))) as u8;
+        }
+    }
+    size + 1
+}
+
+/// Length of the content of the signed SEQUENCE
+const fn content_len(
+    tbs_len: usize,
+    signature_algorithm_len: usize,
+    signature_len: usize,
+) -> usize {
+    let bit_string_len = 1 + signature_len;
+    tbs_len + signature_algorithm_len + 1 + der_len_size(bit_string_len) + bit_string_len
+}
+
+/// Length of the signed DER assembled by `assemble_cert`
+pub const fn signed_len(
+    tbs_len: usize,
+    signature_algorithm_len: usize,
+    signature_len: usize,
+) -> usize {
+    let content_len = content_len(tbs_len, signature_algorithm_len, signature_len);
+    1 + der_len_size(content_len) + content_len
+}
+
+/// Assemble the certificate, or CSR, SEQUENCE of the patched `tbs`, the DER
+/// `signature_algorithm` AlgorithmIdentifier and the `signature` BIT STRING content to
+/// `out`, returning its length
+///
+/// Size `out` with `signed_len`, e.g. for the fixed signature length of the algorithm.
+pub fn assemble_cert(
+    tbs: &[u8],
+    signature_algorithm: &[u8],
+    signature: &[u8],
+    out: &mut [u8],
+) -> Result<usize, RuntimeError> {
+    let len = signed_len(tbs.len(), signature_algorithm.len(), signature.len());
+    let out = out.get_mut(..len).ok_or(RuntimeError::BufferTooSmall)?;
+    let content_len = content_len(tbs.len(), signature_algorithm.len(), signature.len());
+
+    let mut offset = write_header(out, 0x30, content_len);
+    for part in [tbs, signature_algorithm] {
+        out[offset..offset + part.len()].copy_from_slice(part);
+        offset += part.len();
+    }
+    offset += write_header(&mut out[offset..], 0x03, 1 + signature.len());
+    // No unused bits
+    out[offset] = 0;
+    out[offset + 1..].copy_from_slice(signature);
+    Ok(len)
+}
@@ -12,10 +12,409 @@ Abstract:
 
 --*/
 
+#[cfg(feature = "host-verify")]
+use crate::keys::BuilderKeys;
+use crate::profile::der_extensions;
+use crate::provenance::Provenance;
+use crate::runtime::mask_serial_number;
+#[cfg(feature = "host-verify")]
+use crate::runtime::{assemble_cert, signed_len};
+use const_oid::ObjectIdentifier;
+use der::{Decode, Encode};
 use hex::ToHex;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Template generation error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    /// Param with an empty name or a zero length
+    InvalidParam { name: &'static str, len: usize },
+    /// Value for a param missing from the template
+    UnknownParam { name: String },
+    /// Value whose length differs from the length of its param
+    ValueLength {
+        name: &'static str,
+        len: usize,
+        value_len: usize,
+    },
+    /// TBSCertificate signature AlgorithmIdentifier differing from the outer
+    /// signatureAlgorithm, both DER encoded
+    AlgorithmMismatch { tbs: Vec<u8>, outer: Vec<u8> },
+    /// Value of a single byte param above its declared maximum
+    ValueRange {
+        name: &'static str,
+        value: u8,
+        max: u8,
+    },
+    /// Public key of `key_len` bytes differing from the subjectPublicKey content of the TBS,
+    /// e.g. a key the encoder represents differently
+    PublicKeyNotFound { key_len: usize },
+    /// DER whose `element` SEQUENCE is missing, truncated or of an unsupported length
+    InvalidDer { element: &'static str },
+    /// Needle of a param found `found` times in the haystack instead of `expected` times
+    NeedleCount {
+        name: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    /// Param extending past the end of a TBS of `tbs_len` bytes
+    ParamBounds {
+        name: &'static str,
+        offset: usize,
+        len: usize,
+        tbs_len: usize,
+    },
+    /// Allocated placeholder of the param `name` found at `offset` outside of its param
+    PlaceholderCollision { name: &'static str, offset: usize },
+    /// Param whose range overlaps the range of the param `other` of the template
+    ParamOverlap {
+        name: &'static str,
+        other: &'static str,
+    },
+    /// DER differing from its decoded re-encoding from `offset` on, with the bytes
+    /// surrounding the difference in both encodings
+    DerDrift {
+        offset: usize,
+        der: Vec<u8>,
+        reencoded: Vec<u8>,
+    },
+    /// Signature over the patched TBS rejected by the verifying key
+    SignatureMismatch,
+    /// TBS of `len` bytes exceeding the budget of `max` bytes, with the OID and encoded
+    /// length of each extension, largest first
+    TbsSize {
+        len: usize,
+        max: usize,
+        extensions: Vec<(String, usize)>,
+    },
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidParam { name, len } => {
+                write!(f, "Invalid param '{}' with length {}", name, len)
+            }
+            Self::UnknownParam { name } => write!(f, "Unknown param '{}'", name),
+            Self::ValueLength {
+                name,
+                len,
+                value_len,
+            } => write!(
+                f,
+                "Value of {} bytes for param '{}' of length {}",
+                value_len, name, len
+            ),
+            Self::AlgorithmMismatch { tbs, outer } => write!(
+                f,
+                "TBSCertificate signature {} differs from signatureAlgorithm {}",
+                tbs.encode_hex_upper::<String>(),
+                outer.encode_hex_upper::<String>()
+            ),
+            Self::ValueRange { name, value, max } => write!(
+                f,
+                "Value {} for param '{}' exceeds its maximum {}",
+                value, name, max
+            ),
+            Self::PublicKeyNotFound { key_len } => write!(
+                f,
+                "Public key of {} bytes not found in the subjectPublicKey of the TBS",
+                key_len
+            ),
+            Self::InvalidDer { element } => write!(f, "Invalid DER {} SEQUENCE", element),
+            Self::NeedleCount {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Found {} instances of the needle of param '{}', expected {}",
+                found, name, expected
+            ),
+            Self::ParamBounds {
+                name,
+                offset,
+                len,
+                tbs_len,
+            } => write!(
+                f,
+                "Param '{}' at offset {} with length {} exceeds the TBS of {} bytes",
+                name, offset, len, tbs_len
+            ),
+            Self::PlaceholderCollision { name, offset } => write!(
+                f,
+                "Placeholder of param '{}' found at offset {} outside of its param",
+                name, offset
+            ),
+            Self::ParamOverlap { name, other } => {
+                write!(f, "Param '{}' overlaps param '{}'", name, other)
+            }
+            Self::DerDrift {
+                offset,
+                der,
+                reencoded,
+            } => write!(
+                f,
+                "DER re-encoding differs at offset {}: {} re-encoded as {}",
+                offset,
+                der.encode_hex_upper::<String>(),
+                reencoded.encode_hex_upper::<String>()
+            ),
+            Self::SignatureMismatch => {
+                write!(f, "Signature over the patched TBS fails verification")
+            }
+            Self::TbsSize {
+                len,
+                max,
+                extensions,
+            } => {
+                let extensions: Vec<String> = extensions
+                    .iter()
+                    .map(|(oid, len)| format!("{} ({} bytes)", oid, len))
+                    .collect();
+                write!(
+                    f,
+                    "TBS of {} bytes exceeds the budget of {} bytes, extensions: {}",
+                    len,
+                    max,
+                    extensions.join(", ")
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// Inconsistency of a TBS patched by firmware with its template, see
+/// `TbsTemplate::verify_patched`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchViolation {
+    /// Patched TBS of `len` bytes instead of the `expected` bytes of the template
+    Length { len: usize, expected: usize },
+    /// `len` bytes at `offset` outside of the params differing from the template, e.g. a
+    /// param written at the wrong offset
+    FixedBytes { offset: usize, len: usize },
+    /// Param still holding its placeholder bytes
+    UnpatchedParam { name: &'static str },
+    /// Patched TBS that doesn't decode as the structure of its template kind
+    InvalidDer { err: der::Error },
+}
+
+impl fmt::Display for PatchViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Length { len, expected } => write!(
+                f,
+                "Patched TBS of {} bytes, the template is {} bytes",
+                len, expected
+            ),
+            Self::FixedBytes { offset, len } => write!(
+                f,
+                "{} bytes at offset {} outside of the params differ from the template",
+                len, offset
+            ),
+            Self::UnpatchedParam { name } => {
+                write!(f, "Param '{}' holds its placeholder bytes", name)
+            }
+            Self::InvalidDer { err } => write!(f, "Patched TBS is not valid DER: {}", err),
+        }
+    }
+}
+
+/// Size of the code generated for a template, see `TbsTemplate::code_size_estimate`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CodeSizeEstimate {
+    /// Length of the emitted `TBS_TEMPLATE` byte array
+    pub tbs_len: usize,
+    /// Number of param records, each emitted as an offset and a length const
+    pub param_records: usize,
+}
+
+/// Param present in two templates at different offsets or with different lengths, see
+/// `TemplateDiff`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParamChange {
+    pub before: TbsParam,
+    pub after: TbsParam,
+}
+
+/// Difference between two templates, see `TbsTemplate::diff`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TemplateDiff {
+    /// TBS lengths of the template and of the other template
+    pub lens: (usize, usize),
+    /// Ranges of differing bytes of the sanitized TBSs compared at the same offsets, with
+    /// the tail of the longer TBS as the last range
+    ///
+    /// The bytes are not aligned, so an insertion or removal marks every byte after it as
+    /// differing; use the param changes to locate the shift.
+    pub byte_ranges: Vec<Range<usize>>,
+    /// Params only in the other template
+    pub added: Vec<TbsParam>,
+    /// Params only in the template
+    pub removed: Vec<TbsParam>,
+    /// Params of both templates which moved or changed length
+    pub changed: Vec<ParamChange>,
+}
+
+impl TemplateDiff {
+    /// Whether the templates have identical TBSs and params
+    pub fn is_empty(&self) -> bool {
+        self.byte_ranges.is_empty()
+            && self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+    }
+
+    /// Change of the param `name`, the first one for repeated names
+    pub fn change(&self, name: &str) -> Option<&ParamChange> {
+        self.changed.iter().find(|c| c.before.name == name)
+    }
+}
+
+impl fmt::Display for TemplateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "TBS of {} -> {} bytes", self.lens.0, self.lens.1)?;
+        for range in &self.byte_ranges {
+            writeln!(f, "  bytes {}..{} differ", range.start, range.end)?;
+        }
+        for param in &self.added {
+            writeln!(
+                f,
+                "  + {} at offset {}, {} bytes",
+                param.name, param.offset, param.len
+            )?;
+        }
+        for param in &self.removed {
+            writeln!(
+                f,
+                "  - {} at offset {}, {} bytes",
+                param.name, param.offset, param.len
+            )?;
+        }
+        for ParamChange { before, after } in &self.changed {
+            writeln!(
+                f,
+                "  ~ {} at offset {} -> {}, {} -> {} bytes",
+                before.name, before.offset, after.offset, before.len, after.len
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Hash algorithm of a TBS digest
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TbsDigest {
+    Sha384,
+    Sha512,
+}
+
+impl TbsDigest {
+    /// Compute the digest of `data`
+    pub fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Sha384 => Sha384::digest(data).to_vec(),
+            Self::Sha512 => Sha512::digest(data).to_vec(),
+        }
+    }
+
+    /// Name of the algorithm, as used in generated identifiers
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sha384 => "SHA384",
+            Self::Sha512 => "SHA512",
+        }
+    }
+}
+
+/// CRC-32 (IEEE 802.3) of `data`, the reflected 0x04C11DB7 polynomial with inverted input
+/// and output
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..u8::BITS {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// Version of the format of the generated templates, incremented on each incompatible
+/// change of their layout
+pub const TEMPLATE_SCHEMA_VERSION: u32 = 1;
+
+/// Compute the SHA-256 content hash of the template `tbs` and its `params`
+///
+/// The hash covers the schema version, the template bytes and the name, offset and length
+/// of each param, so firmware built against the offsets of one template can detect the bytes
+/// of another.
+pub fn template_hash(tbs: &[u8], params: &[TbsParam]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(TEMPLATE_SCHEMA_VERSION.to_be_bytes());
+    hasher.update((tbs.len() as u64).to_be_bytes());
+    hasher.update(tbs);
+    for param in params {
+        hasher.update((param.name.len() as u64).to_be_bytes());
+        hasher.update(param.name.as_bytes());
+        hasher.update((param.offset as u64).to_be_bytes());
+        hasher.update((param.len as u64).to_be_bytes());
+    }
+    hasher.finalize().into()
+}
+
+/// Check that the template `tbs` and its `params` match the `expected` content hash, e.g.
+/// the `<NAME>_TEMPLATE_HASH` const firmware was compiled against
+pub fn verify_template_hash(tbs: &[u8], params: &[TbsParam], expected: &[u8; 32]) -> bool {
+    template_hash(tbs, params) == *expected
+}
+
+/// SHA-256 of the bytes of `tbs` outside of the `params`, concatenated in order
+///
+/// Firmware only writes the params, so a verifier recomputing the digest over a patched TBS
+/// of the template length finds the digest of the template, see
+/// `TbsTemplate::fixed_region_digest`.
+pub fn fixed_region_digest(tbs: &[u8], params: &[TbsParam]) -> [u8; 32] {
+    let mut covered = vec![false; tbs.len()];
+    for param in params {
+        covered[param.offset..param.offset + param.len].fill(true);
+    }
+    let fixed: Vec<u8> = tbs
+        .iter()
+        .zip(covered)
+        .filter(|&(_, covered)| !covered)
+        .map(|(&byte, _)| byte)
+        .collect();
+    Sha256::digest(&fixed).into()
+}
+
+/// Maximum length of a param, the largest DER length supported by the templates
+pub const MAX_PARAM_LEN: usize = 0xFFFF;
+
+/// Param name built at runtime, e.g. from the name of an FWID
+///
+/// Params are named by `&'static str`, so each distinct name is allocated once and shared by
+/// every template registering it.
+pub fn param_name(name: String) -> &'static str {
+    static NAMES: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+    let mut names = NAMES.lock().unwrap();
+    if let Some(&interned) = names.get(name.as_str()) {
+        return interned;
+    }
+    let interned: &'static str = Box::leak(name.into_boxed_str());
+    names.insert(interned);
+    interned
+}
 
 /// Template parameter
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct TbsParam {
     pub name: &'static str,
     pub offset: usize,
@@ -24,24 +423,369 @@ pub struct TbsParam {
 
 impl TbsParam {
     /// Create an instance `TbsParam`
+    ///
+    /// Panics if the param is invalid, see `try_new`.
     pub fn new(name: &'static str, offset: usize, len: usize) -> Self {
-        Self { name, offset, len }
+        match Self::try_new(name, offset, len) {
+            Ok(param) => param,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Create an instance `TbsParam`, rejecting empty names, zero lengths and lengths above
+    /// `MAX_PARAM_LEN`
+    pub fn try_new(name: &'static str, offset: usize, len: usize) -> Result<Self, TemplateError> {
+        if name.is_empty() || len == 0 || len > MAX_PARAM_LEN {
+            return Err(TemplateError::InvalidParam { name, len });
+        }
+        Ok(Self { name, offset, len })
+    }
+}
+
+/// Placeholder bytes filling the params of a template
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PlaceholderFill {
+    /// The 0x5F bytes written by `sanitize`
+    #[default]
+    Sanitized,
+    /// A single byte, e.g. 0xAA to spot unpatched regions in a dump
+    Byte(u8),
+    /// Bytes counting up from 0 in each param, wrapping at 256
+    Counter,
+}
+
+impl PlaceholderFill {
+    /// Fill the `placeholder` bytes of a param
+    pub fn fill(self, placeholder: &mut [u8]) {
+        for (i, byte) in placeholder.iter_mut().enumerate() {
+            *byte = self.byte(i);
+        }
+    }
+
+    /// Placeholder byte at the index `i` of a param
+    fn byte(self, i: usize) -> u8 {
+        match self {
+            Self::Sanitized => 0x5F,
+            Self::Byte(byte) => byte,
+            Self::Counter => i as u8,
+        }
+    }
+}
+
+/// Structure a TBS template was extracted from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TemplateKind {
+    /// CertificationRequestInfo of a CertReq
+    Csr,
+    /// TBSCertificate of a Certificate
+    Cert,
+}
+
+impl TemplateKind {
+    /// Detect the kind of `tbs` from its leading elements
+    ///
+    /// A TBSCertificate starts with its [0] EXPLICIT version, or for a v1 certificate with
+    /// the serialNumber followed by the signature AlgorithmIdentifier, a SEQUENCE starting
+    /// with an OBJECT IDENTIFIER. The version of a CertificationRequestInfo is followed by the
+    /// subject Name, a SEQUENCE of SETs. Anything else, including TBS that aren't DER, is
+    /// taken for a CSR.
+    pub fn detect(tbs: &[u8]) -> Self {
+        // Header and content lengths of the element at `offset`
+        let lengths = |offset: usize| -> Option<(usize, usize)> {
+            match *tbs.get(offset + 1)? {
+                len @ 0..=0x7F => Some((2, len as usize)),
+                0x81 => Some((3, *tbs.get(offset + 2)? as usize)),
+                0x82 => {
+                    let len = tbs.get(offset + 2..offset + 4)?;
+                    Some((4, u16::from_be_bytes([len[0], len[1]]) as usize))
+                }
+                _ => None,
+            }
+        };
+
+        if tbs.first() != Some(&0x30) {
+            return Self::Csr;
+        }
+        let Some((header_len, _)) = lengths(0) else {
+            return Self::Csr;
+        };
+        match tbs.get(header_len) {
+            Some(0xA0) => return Self::Cert,
+            Some(0x02) => (),
+            _ => return Self::Csr,
+        }
+        let Some((integer_header_len, integer_len)) = lengths(header_len) else {
+            return Self::Csr;
+        };
+        let second = header_len + integer_header_len + integer_len;
+        let Some((second_header_len, _)) = lengths(second) else {
+            return Self::Csr;
+        };
+        match (tbs.get(second), tbs.get(second + second_header_len)) {
+            (Some(0x30), Some(0x06)) => Self::Cert,
+            _ => Self::Csr,
+        }
     }
 }
 
 /// Template
 pub struct TbsTemplate {
+    kind: TemplateKind,
     buf: Vec<u8>,
     params: Vec<TbsParam>,
+    tbs_offset: usize,
+    der: Vec<u8>,
+    signature_param: Option<TbsParam>,
+    ranges: Vec<TbsParam>,
+    param_max: Vec<(&'static str, u8)>,
+    provenance: Option<Provenance>,
+    algorithm_oid: Option<ObjectIdentifier>,
 }
 
 impl TbsTemplate {
-    /// Create an instance of `TbsTemplate`
+    /// Create an instance of `TbsTemplate`, detecting its kind from the TBS structure
     pub fn new(template: Vec<u8>, params: Vec<TbsParam>) -> Self {
         Self {
+            kind: TemplateKind::detect(&template),
             buf: template,
             params,
+            tbs_offset: 0,
+            der: Vec::new(),
+            signature_param: None,
+            ranges: Vec::new(),
+            param_max: Vec::new(),
+            provenance: None,
+            algorithm_oid: None,
+        }
+    }
+
+    /// Template the TBS of the signed certificate or CSR `der`, e.g. produced by another
+    /// tool, locating each param of `param_specs` by its needle
+    ///
+    /// Like the builders, needles are searched longest first and sanitized once located,
+    /// so a needle contained in a longer one isn't matched within it. Each needle must occur
+    /// exactly once, the kind of the template is detected from the TBS.
+    pub fn from_cert_der(
+        der: &[u8],
+        param_specs: &[(&'static str, &[u8])],
+    ) -> Result<Self, TemplateError> {
+        let tbs_offset = get_tbs_offset(der)?;
+        let mut tbs = get_tbs(der)?.to_vec();
+
+        let mut specs = param_specs.to_vec();
+        specs.sort_by(|a, b| a.1.len().cmp(&b.1.len()).reverse());
+        let params = specs
+            .iter()
+            .map(|&(name, needle)| {
+                let param = TbsParam::try_new(name, 0, needle.len())?;
+                Ok(sanitize(init_param(needle, &tbs, param)?, &mut tbs))
+            })
+            .collect::<Result<_, TemplateError>>()?;
+
+        Ok(Self::new(tbs, params)
+            .with_tbs_offset(tbs_offset)
+            .with_der(der.to_vec()))
+    }
+
+    /// Set the kind of the template, as known by the builder generating it
+    pub fn with_kind(mut self, kind: TemplateKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Retrieve whether the template is the TBS of a CSR or of a certificate
+    pub fn kind(&self) -> TemplateKind {
+        self.kind
+    }
+
+    /// Set the record of the generator and inputs producing the template
+    pub fn with_provenance(mut self, provenance: Provenance) -> Self {
+        self.provenance = Some(provenance);
+        self
+    }
+
+    /// Retrieve the record of the generator and inputs producing the template
+    pub fn provenance(&self) -> Option<&Provenance> {
+        self.provenance.as_ref()
+    }
+
+    /// Set the OID of the signature algorithm of the builder key signing the template
+    pub fn with_algorithm_oid(mut self, oid: ObjectIdentifier) -> Self {
+        self.algorithm_oid = Some(oid);
+        self
+    }
+
+    /// Retrieve the OID of the signature algorithm the template was built with, which is
+    /// also the algorithm of the subject key of CSRs and self-issued certificates
+    ///
+    /// Templates not generated by a builder report the signatureAlgorithm of their DER.
+    pub fn algorithm_oid(&self) -> ObjectIdentifier {
+        if let Some(oid) = self.algorithm_oid {
+            return oid;
         }
+        // The signatureAlgorithm follows the TBS
+        let algorithm = tbs_range(&self.der)
+            .and_then(|range| {
+                let (header_len, len) =
+                    sequence_lengths(&self.der, range.end, "signatureAlgorithm")?;
+                Ok(&self.der[range.end..range.end + header_len + len])
+            })
+            .unwrap_or_else(|err| panic!("Template without algorithm OID: {}", err));
+        spki::AlgorithmIdentifierOwned::from_der(algorithm)
+            .unwrap()
+            .oid
+    }
+
+    /// Set the offset of the TBS within the full DER it was extracted from
+    pub fn with_tbs_offset(mut self, tbs_offset: usize) -> Self {
+        self.tbs_offset = tbs_offset;
+        self
+    }
+
+    /// Retrieve the offset of the TBS within the full DER
+    pub fn tbs_offset(&self) -> usize {
+        self.tbs_offset
+    }
+
+    /// Set the signed DER the template was extracted from
+    pub fn with_der(mut self, der: Vec<u8>) -> Self {
+        self.der = der;
+        self
+    }
+
+    /// Retrieve the signed DER holding the generation-time placeholder values
+    pub fn der(&self) -> &[u8] {
+        &self.der
+    }
+
+    /// Retrieve the signed DER with its TBS replaced by the template bytes
+    ///
+    /// The signature remains that of the generation-time values, a placeholder that doesn't
+    /// verify over the template bytes.
+    pub fn template_der(&self) -> Result<Vec<u8>, TemplateError> {
+        let mut der = self.der.clone();
+        der[tbs_range(&self.der)?].copy_from_slice(&self.buf);
+        Ok(der)
+    }
+
+    /// Set the placeholder signature param, with its offset relative to the full DER
+    pub fn with_signature_param(mut self, param: TbsParam) -> Self {
+        self.signature_param = Some(param);
+        self
+    }
+
+    /// Retrieve the placeholder signature param, with its offset relative to the full DER
+    pub fn signature_param(&self) -> Option<TbsParam> {
+        self.signature_param
+    }
+
+    /// Set the ranges of the TBS that firmware may strip, see `strip`
+    pub fn with_ranges(mut self, ranges: Vec<TbsParam>) -> Self {
+        self.ranges = ranges;
+        self
+    }
+
+    /// Retrieve the ranges of whole extensions that firmware may strip
+    ///
+    /// Unlike params, ranges are not sanitized nor applied, and may contain params.
+    pub fn ranges(&self) -> &[TbsParam] {
+        &self.ranges
+    }
+
+    /// Retrieve a copy of the applied `tbs` without the extension of the range `name`, with
+    /// the lengths of the enclosing elements renormalized, see `strip_range`
+    pub fn strip(&self, tbs: &[u8], name: &str) -> Result<Vec<u8>, TemplateError> {
+        match self.ranges.iter().find(|r| r.name == name) {
+            Some(range) => Ok(strip_range(tbs, *range)),
+            None => Err(TemplateError::UnknownParam {
+                name: name.to_string(),
+            }),
+        }
+    }
+
+    /// Retrieve the template without the extension of the range `name`
+    ///
+    /// The TBS is that of `strip` and the params keep their semantics at the offsets of the
+    /// stripped TBS, the params within the range are dropped. The generation-time signature
+    /// doesn't cover the stripped TBS, so the DER is rebuilt with the signature zeroed and
+    /// registered as the "SIGNATURE" param.
+    pub fn without_range(&self, name: &str) -> Result<TbsTemplate, TemplateError> {
+        let Some(&range) = self.ranges.iter().find(|r| r.name == name) else {
+            return Err(TemplateError::UnknownParam {
+                name: name.to_string(),
+            });
+        };
+        let segments = strip_segments(&self.buf, range);
+        let remap = |params: &[TbsParam]| -> Vec<TbsParam> {
+            params
+                .iter()
+                .filter(|p| p.name != name)
+                .filter_map(|p| {
+                    let offset = stripped_offset(&segments, p.offset)?;
+                    Some(TbsParam { offset, ..*p })
+                })
+                .collect()
+        };
+        let mut template = TbsTemplate::new(strip_range(&self.buf, range), remap(&self.params))
+            .with_kind(self.kind)
+            .with_ranges(remap(&self.ranges));
+        template.param_max = self.param_max.clone();
+        template.provenance = self.provenance.clone();
+        template.algorithm_oid = self.algorithm_oid;
+        if self.der.is_empty() {
+            return Ok(template);
+        }
+
+        // Certificate: tbsCertificate, signatureAlgorithm, signatureValue
+        let tbs_end = self.tbs_offset + self.buf.len();
+        let tbs = strip_range(&self.der[self.tbs_offset..tbs_end], range);
+        let trailer = &self.der[tbs_end..];
+        let mut der = der_header(self.der[0], tbs.len() + trailer.len());
+        let tbs_offset = der.len();
+        der.extend_from_slice(&tbs);
+        der.extend_from_slice(trailer);
+        let (signature, _) = element_children(&der, 0)[2];
+        let (header_len, len) = der_lengths(&der, signature);
+        // The BIT STRING content starts with its unused bits byte
+        let param = TbsParam::new("SIGNATURE", signature + header_len + 1, len - 1);
+        der[param.offset..param.offset + param.len].fill(0);
+        Ok(template
+            .with_tbs_offset(tbs_offset)
+            .with_der(der)
+            .with_signature_param(param))
+    }
+
+    /// Set the maximum value of the single byte param `name`, checked by `apply`
+    pub fn with_param_max(mut self, name: &'static str, max: u8) -> Self {
+        self.param_max.push((name, max));
+        self
+    }
+
+    /// Retrieve the maximum value of the single byte param `name`, if declared
+    pub fn param_max(&self, name: &str) -> Option<u8> {
+        self.param_max
+            .iter()
+            .find(|(param, _)| *param == name)
+            .map(|&(_, max)| max)
+    }
+
+    /// Retrieve template parameters with offsets relative to the full DER, followed by the
+    /// placeholder signature param if any
+    ///
+    /// The offset of the TBS is derived from the DER, so this returns `None` for templates
+    /// without a DER, e.g. created with `new` alone.
+    pub fn params_in_der(&self) -> Option<Vec<TbsParam>> {
+        let tbs_offset = get_tbs_offset(&self.der).ok()?;
+        Some(
+            self.params
+                .iter()
+                .map(|p| TbsParam {
+                    offset: p.offset + tbs_offset,
+                    ..*p
+                })
+                .chain(self.signature_param)
+                .collect(),
+        )
     }
 
     /// Retrieve template blob
@@ -53,75 +797,984 @@ impl TbsTemplate {
     pub fn params(&self) -> &[TbsParam] {
         &self.params
     }
+
+    /// Compute the digest of the TBS bytes outside of the params, see `fixed_region_digest`
+    pub fn fixed_region_digest(&self) -> [u8; 32] {
+        fixed_region_digest(&self.buf, &self.params)
+    }
+
+    /// Compute the content hash of the template, see `template_hash`
+    pub fn template_hash(&self) -> [u8; 32] {
+        template_hash(&self.buf, &self.params)
+    }
+
+    /// Add the param `name` for the value `needle` of the stored TBS, e.g. to parametrize a
+    /// field of a loaded template
+    ///
+    /// The needle must occur exactly once in the TBS, outside the existing params.
+    pub fn add_param(&mut self, name: &'static str, needle: &[u8]) -> Result<(), TemplateError> {
+        let param = init_param(needle, &self.buf, TbsParam::try_new(name, 0, needle.len())?)?;
+        let range = param.offset..param.offset + param.len;
+        if let Some(other) = self
+            .params
+            .iter()
+            .find(|p| p.offset < range.end && range.start < p.offset + p.len)
+        {
+            return Err(TemplateError::ParamOverlap {
+                name,
+                other: other.name,
+            });
+        }
+        let param = sanitize(param, &mut self.buf);
+        self.params.push(param);
+        Ok(())
+    }
+
+    /// Retrieve the encoded subject Name of the TBS, holding the placeholders of its params
+    pub fn subject_der(&self) -> &[u8] {
+        match self.kind {
+            // CertificationRequestInfo: version, subject
+            TemplateKind::Csr => self.tbs_element(1),
+            TemplateKind::Cert => self.tbs_cert_element(5),
+        }
+    }
+
+    /// Retrieve the encoded issuer Name of a certificate TBS, holding the placeholders of its
+    /// params, or `None` for a CSR
+    pub fn issuer_der(&self) -> Option<&[u8]> {
+        (self.kind == TemplateKind::Cert).then(|| self.tbs_cert_element(3))
+    }
+
+    /// Full TLV of the child at `index` of the TBSCertificate, counting the [0] EXPLICIT
+    /// version even if it is omitted
+    fn tbs_cert_element(&self, index: usize) -> &[u8] {
+        let (version, _) = element_children(&self.buf, 0)[0];
+        match self.buf[version] {
+            0xA0 => self.tbs_element(index),
+            _ => self.tbs_element(index - 1),
+        }
+    }
+
+    /// Full TLV of the child at `index` of the TBS
+    fn tbs_element(&self, index: usize) -> &[u8] {
+        let (offset, len) = locate_element(&self.buf, &[index]);
+        &self.buf[offset..offset + len]
+    }
+
+    /// CRC-32 of the sanitized placeholder bytes of `param`, for firmware to check the
+    /// region it is about to patch
+    pub fn param_crc32(&self, param: &TbsParam) -> u32 {
+        crc32(&self.buf[param.offset..param.offset + param.len])
+    }
+
+    /// Retrieve the params in ascending offset order, for firmware patching the TBS front to
+    /// back
+    ///
+    /// Params at the same offset keep their registration order.
+    pub fn sorted_params(&self) -> Vec<&TbsParam> {
+        let mut params: Vec<&TbsParam> = self.params.iter().collect();
+        params.sort_by_key(|p| p.offset);
+        params
+    }
+
+    /// Number of TBS bytes covered by params, which firmware writes at runtime
+    ///
+    /// Bytes covered by overlapping params are counted once.
+    pub fn parameterized_len(&self) -> usize {
+        let mut ranges: Vec<(usize, usize)> = self
+            .params
+            .iter()
+            .map(|p| (p.offset, p.offset + p.len))
+            .collect();
+        ranges.sort();
+
+        let mut len = 0;
+        let mut covered_end = 0;
+        for (start, end) in ranges {
+            let start = start.max(covered_end);
+            if end > start {
+                len += end - start;
+                covered_end = end;
+            }
+        }
+        len
+    }
+
+    /// Number of TBS bytes not covered by any param
+    pub fn fixed_len(&self) -> usize {
+        self.buf.len() - self.parameterized_len()
+    }
+
+    /// Size of the code `CodeGen` emits for the template, without generating it
+    pub fn code_size_estimate(&self) -> CodeSizeEstimate {
+        CodeSizeEstimate {
+            tbs_len: self.buf.len(),
+            param_records: self.params.len(),
+        }
+    }
+
+    /// Compare the template with `other`, e.g. generated after a builder change
+    ///
+    /// The sanitized TBSs are compared byte by byte, so differences in param values aren't
+    /// reported. Params are matched by name, repeated names in order of their offsets.
+    pub fn diff(&self, other: &TbsTemplate) -> TemplateDiff {
+        let (tbs, other_tbs) = (self.tbs(), other.tbs());
+        let byte_ranges = byte_ranges(tbs, other_tbs);
+
+        let sorted = |params: &[TbsParam]| {
+            let mut params = params.to_vec();
+            params.sort_by_key(|p| p.offset);
+            params
+        };
+        let mut added = sorted(&other.params);
+        let mut removed = Vec::new();
+        let mut changed = Vec::new();
+        for before in sorted(&self.params) {
+            match added.iter().position(|p| p.name == before.name) {
+                Some(index) => {
+                    let after = added.remove(index);
+                    if after != before {
+                        changed.push(ParamChange { before, after });
+                    }
+                }
+                None => removed.push(before),
+            }
+        }
+
+        TemplateDiff {
+            lens: (tbs.len(), other_tbs.len()),
+            byte_ranges,
+            added,
+            removed,
+            changed,
+        }
+    }
+
+    /// Retrieve a copy of the TBS with the `values` of the named params applied
+    ///
+    /// Params without a value keep their placeholder bytes. The certificate serial number
+    /// value follows the convention of `encode_serial_number`, and values of params with a
+    /// declared maximum are checked against it.
+    pub fn apply(&self, values: &[(&str, &[u8])]) -> Result<Vec<u8>, TemplateError> {
+        let mut tbs = self.buf.clone();
+        for (name, value) in values {
+            let mut params = self.params.iter().filter(|p| p.name == *name).peekable();
+            if params.peek().is_none() {
+                return Err(TemplateError::UnknownParam {
+                    name: name.to_string(),
+                });
+            }
+            for param in params {
+                check_param_bounds(*param, tbs.len())?;
+                if param.len != value.len() {
+                    return Err(TemplateError::ValueLength {
+                        name: param.name,
+                        len: param.len,
+                        value_len: value.len(),
+                    });
+                }
+                if let Some(max) = self.param_max(param.name) {
+                    if value[0] > max {
+                        return Err(TemplateError::ValueRange {
+                            name: param.name,
+                            value: value[0],
+                            max,
+                        });
+                    }
+                }
+                let field = &mut tbs[param.offset..param.offset + param.len];
+                field.copy_from_slice(value);
+                if self.kind == TemplateKind::Cert && param.name == SERIAL_NUMBER_PARAM {
+                    encode_serial_number(field);
+                }
+            }
+        }
+        Ok(tbs)
+    }
+
+    /// Check the TBS `patched` by firmware against the template
+    ///
+    /// The patched TBS must have the length of the template, match it outside of the params,
+    /// overwrite the placeholder of each param and decode as a TBSCertificate or a
+    /// CertificationRequestInfo, depending on the kind of the template. A param whose value
+    /// equals its placeholder is reported as unpatched. All the violations are returned,
+    /// except on a length mismatch which makes the other checks meaningless.
+    pub fn verify_patched(&self, patched: &[u8]) -> Result<(), Vec<PatchViolation>> {
+        if patched.len() != self.buf.len() {
+            return Err(vec![PatchViolation::Length {
+                len: patched.len(),
+                expected: self.buf.len(),
+            }]);
+        }
+
+        let mut violations = Vec::new();
+        let mut covered = vec![false; self.buf.len()];
+        for param in self.params.iter() {
+            let range = param.offset..param.offset + param.len;
+            covered[range.clone()].fill(true);
+            if patched[range.clone()] == self.buf[range] {
+                violations.push(PatchViolation::UnpatchedParam { name: param.name });
+            }
+        }
+
+        // Report each run of modified fixed bytes once
+        let mut modified = (0..patched.len())
+            .filter(|&i| !covered[i] && patched[i] != self.buf[i])
+            .peekable();
+        while let Some(offset) = modified.next() {
+            let mut len = 1;
+            while modified.next_if_eq(&(offset + len)).is_some() {
+                len += 1;
+            }
+            violations.push(PatchViolation::FixedBytes { offset, len });
+        }
+
+        let decoded = match self.kind {
+            TemplateKind::Cert => x509_cert::TbsCertificate::from_der(patched).map(|_| ()),
+            TemplateKind::Csr => x509_cert::request::CertReqInfo::from_der(patched).map(|_| ()),
+        };
+        if let Err(err) = decoded {
+            violations.push(PatchViolation::InvalidDer { err });
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    /// Retrieve a copy of the TBS with the `values` of the named params written at their
+    /// offsets, as firmware does before signing
+    pub fn patch(&self, values: &HashMap<&str, Vec<u8>>) -> Result<Vec<u8>, TemplateError> {
+        let values: Vec<(&str, &[u8])> = values
+            .iter()
+            .map(|(name, value)| (*name, value.as_slice()))
+            .collect();
+        self.apply(&values)
+    }
+
+    /// Patch the template with the public key of `key`, sign the patched TBS with `key` and
+    /// retrieve the assembled DER once the signature verifies
+    ///
+    /// Simulates the firmware flow on the host to catch offset bugs. The PUBLIC_KEY param, and
+    /// the SUBJECT_SN param if present, receive the values derived from `key` while the other
+    /// params keep their generation-time values. The result must decode as a certificate or a
+    /// CSR, depending on the kind of the template.
+    #[cfg(feature = "host-verify")]
+    pub fn resign_and_verify<Key>(&self, key: &Key) -> Result<Vec<u8>, TemplateError>
+    where
+        Key: BuilderKeys + signature::Keypair + signature::Signer<Key::Signature>,
+        Key::VerifyingKey: spki::EncodePublicKey,
+    {
+        use spki::{EncodePublicKey, SignatureBitStringEncoding};
+
+        let pk_der = key.verifying_key().to_public_key_der().unwrap();
+        let spki: spki::SubjectPublicKeyInfo<der::asn1::Any, der::asn1::BitString> =
+            spki::SubjectPublicKeyInfo::from_der(pk_der.as_bytes()).unwrap();
+        let pk_bytes = spki.subject_public_key.as_bytes().unwrap();
+        let key_hash = crate::name::key_bytes_hash(pk_bytes);
+
+        let range = tbs_range(&self.der)?;
+        let generated = &self.der[range.clone()];
+        let values: Vec<(&str, &[u8])> = self
+            .params
+            .iter()
+            .map(|param| match param.name {
+                "PUBLIC_KEY" => (param.name, pk_bytes),
+                "SUBJECT_SN" => (param.name, key_hash.as_bytes()),
+                _ => (
+                    param.name,
+                    &generated[param.offset..param.offset + param.len],
+                ),
+            })
+            .collect();
+        let tbs = self.apply(&values)?;
+
+        let signature = key.sign(&tbs).to_bitstring().unwrap();
+        let signature = signature.raw_bytes();
+        if !key.verify(&tbs, signature) {
+            return Err(TemplateError::SignatureMismatch);
+        }
+
+        let (header_len, len) = sequence_lengths(&self.der, range.end, "signatureAlgorithm")?;
+        let signature_algorithm = &self.der[range.end..range.end + header_len + len];
+        let mut der = vec![0; signed_len(tbs.len(), signature_algorithm.len(), signature.len())];
+        assemble_cert(&tbs, signature_algorithm, signature, &mut der).unwrap();
+
+        match self.kind {
+            TemplateKind::Cert => check_der_round_trip::<x509_cert::Certificate>(&der)?,
+            TemplateKind::Csr => check_der_round_trip::<x509_cert::request::CertReq>(&der)?,
+        }
+        Ok(der)
+    }
+
+    /// Compute the `digest` of the TBS with the `values` of the named params applied
+    pub fn digest(
+        &self,
+        values: &[(&str, &[u8])],
+        digest: TbsDigest,
+    ) -> Result<Vec<u8>, TemplateError> {
+        Ok(digest.digest(&self.apply(values)?))
+    }
+
+    /// Compute the `digest` of the TBS for each set of param values of `value_sets`
+    pub fn digests(
+        &self,
+        value_sets: &[&[(&str, &[u8])]],
+        digest: TbsDigest,
+    ) -> Result<Vec<Vec<u8>>, TemplateError> {
+        value_sets
+            .iter()
+            .map(|values| self.digest(values, digest))
+            .collect()
+    }
+
+    /// Write the intermediate artifacts of the template to `dir`, with file names prefixed
+    /// by `name`
+    ///
+    /// * `<name>.der` - Full signed DER
+    /// * `<name>_tbs.der` - TBS before sanitization
+    /// * `<name>_tbs_sanitized.der` - TBS of the template
+    /// * `<name>_params.txt` - Name, TBS offset, DER offset and length of each param
+    pub fn write_debug_artifacts(&self, dir: &Path, name: &str) -> std::io::Result<()> {
+        let mut params = String::new();
+        for param in self.params.iter() {
+            params += &format!(
+                "{} offset={} der_offset={} len={}\n",
+                param.name,
+                param.offset,
+                param.offset + self.tbs_offset,
+                param.len
+            );
+        }
+        if let Some(param) = self.signature_param {
+            params += &format!(
+                "{} der_offset={} len={}\n",
+                param.name, param.offset, param.len
+            );
+        }
+
+        let artifacts = [
+            (format!("{}.der", name), self.der.clone()),
+            (
+                format!("{}_tbs.der", name),
+                get_tbs(&self.der).unwrap().to_vec(),
+            ),
+            (format!("{}_tbs_sanitized.der", name), self.buf.clone()),
+            (format!("{}_params.txt", name), params.into_bytes()),
+        ];
+        std::fs::create_dir_all(dir)?;
+        for (file_name, data) in artifacts {
+            std::fs::write(dir.join(file_name), data)?;
+        }
+        Ok(())
+    }
+}
+
+/// File name prefix of the debug artifacts of the template of `subject_cn`
+pub fn artifact_name(subject_cn: &str) -> String {
+    subject_cn
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' => c,
+            'A'..='Z' => c.to_ascii_lowercase(),
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Check that the TBSCertificate `tbs_signature` AlgorithmIdentifier is byte-identical to the
+/// outer `signature_algorithm`, as required by RFC 5280 §4.1.2.3
+pub fn check_signature_algorithms<Params: Encode>(
+    tbs_signature: &spki::AlgorithmIdentifier<Params>,
+    signature_algorithm: &spki::AlgorithmIdentifier<Params>,
+) -> Result<(), TemplateError> {
+    let tbs = tbs_signature.to_der().unwrap();
+    let outer = signature_algorithm.to_der().unwrap();
+    if tbs != outer {
+        return Err(TemplateError::AlgorithmMismatch { tbs, outer });
+    }
+    Ok(())
+}
+
+/// Name of the param of the certificate serial number
+pub const SERIAL_NUMBER_PARAM: &str = "SERIAL_NUMBER";
+
+/// Name of the param of the BasicConstraints pathLenConstraint INTEGER content
+pub const BASIC_CONSTRAINTS_PATH_LEN_PARAM: &str = "BASIC_CONSTRAINTS_PATH_LEN";
+
+/// Apply the serial number convention to `serial`, the content octets of the serialNumber
+/// INTEGER
+///
+/// The top nibble of the first byte is forced to 0x4, as `derive_serial_number` does, so
+/// any value patched into the reserved width encodes a positive INTEGER with no redundant
+/// leading zero. Values with the MSB set or leading zeros are therefore never rejected, at
+/// the cost of the four top bits.
+pub fn encode_serial_number(serial: &mut [u8]) {
+    mask_serial_number(serial);
+}
+
+/// Fail template generation if the serial number `param` doesn't span the whole content of
+/// the serialNumber INTEGER of `tbs`, so the width reserved by the placeholder holds any
+/// serial following `encode_serial_number`
+pub fn check_serial_number_param(tbs: &[u8], param: TbsParam) {
+    // TBSCertificate: version, serialNumber, ...
+    let (offset, len) = locate_element(tbs, &[1]);
+    let (header_len, content_len) = der_lengths(tbs, offset);
+    if tbs[offset] != 0x02
+        || param.offset != offset + header_len
+        || param.len != content_len
+        || param.len > 20
+    {
+        panic!(
+            "{} param at offset {} with length {} is not the content of the serialNumber \
+             INTEGER at offset {} with length {}",
+            param.name, param.offset, param.len, offset, len
+        );
+    }
 }
 
-/// Retrieve the TBS from DER encoded vector
+/// Fail template generation if the TBS of `der` is larger than `max_tbs_size` bytes
 ///
-/// Note: Rust OpenSSL binding is missing the extensions to retrieve TBS portion of the X509
-/// artifact
-pub fn get_tbs(der: Vec<u8>) -> Vec<u8> {
-    if der[0] != 0x30 {
-        panic!("Invalid DER start tag");
+/// The `TemplateError::TbsSize` lists the size of each extension, largest first, as the
+/// extensions are usually the part of the TBS that can be trimmed.
+pub fn check_tbs_size(der: &[u8], max_tbs_size: usize) -> Result<(), TemplateError> {
+    let tbs_len = tbs_range(der)?.len();
+    if tbs_len <= max_tbs_size {
+        return Ok(());
     }
 
-    let der_len_offset = 1;
+    let mut extensions: Vec<(String, usize)> = der_extensions(der)
+        .iter()
+        .map(|ext| (ext.extn_id.to_string(), ext.to_der().unwrap().len()))
+        .collect();
+    extensions.sort_by(|a, b| b.1.cmp(&a.1));
+    Err(TemplateError::TbsSize {
+        len: tbs_len,
+        max: max_tbs_size,
+        extensions,
+    })
+}
+
+/// Bytes on each side of the first difference reported by `check_der_reencoding`
+const DRIFT_CONTEXT: usize = 8;
+
+/// Check that `reencoded`, the re-encoding of the decoded `der`, gives back the same bytes
+///
+/// Decode-modify-reencode is only safe if the encoding is canonical. This catches e.g.
+/// non-minimal INTEGERs, encoded default values or unsorted SETs accepted by the decoder.
+pub fn check_der_reencoding(der: &[u8], reencoded: &[u8]) -> Result<(), TemplateError> {
+    if der == reencoded {
+        return Ok(());
+    }
+    let offset = der
+        .iter()
+        .zip(reencoded)
+        .position(|(a, b)| a != b)
+        .unwrap_or(der.len().min(reencoded.len()));
+    let context = |bytes: &[u8]| {
+        let end = (offset + DRIFT_CONTEXT).min(bytes.len());
+        bytes[offset.saturating_sub(DRIFT_CONTEXT)..end].to_vec()
+    };
+    Err(TemplateError::DerDrift {
+        offset,
+        der: context(der),
+        reencoded: context(reencoded),
+    })
+}
+
+/// Decode `der` as `T` and check that re-encoding it gives back the same bytes
+///
+/// DER the decoder rejects is reported as `TemplateError::InvalidDer`.
+pub fn check_der_round_trip<'a, T: Decode<'a> + Encode>(
+    der: &'a [u8],
+) -> Result<(), TemplateError> {
+    let decoded = T::from_der(der).map_err(|_| TemplateError::InvalidDer { element: "decoded" })?;
+    check_der_reencoding(der, &decoded.to_der().unwrap())
+}
+
+/// Retrieve the header length and content length of the SEQUENCE `element` at `offset` of
+/// `der`, checking it fits `der`
+fn sequence_lengths(
+    der: &[u8],
+    offset: usize,
+    element: &'static str,
+) -> Result<(usize, usize), TemplateError> {
+    let invalid = TemplateError::InvalidDer { element };
+    let header_len = match der.get(offset..offset + 2) {
+        Some([0x30, 0..=0x7F]) => 2,
+        Some([0x30, 0x81]) => 3,
+        Some([0x30, 0x82]) => 4,
+        _ => return Err(invalid),
+    };
+    if der.len() < offset + header_len {
+        return Err(invalid);
+    }
+    let (header_len, len) = der_lengths(der, offset);
+    if der.len() < offset + header_len + len {
+        return Err(invalid);
+    }
+    Ok((header_len, len))
+}
+
+/// Retrieve the offset of the TBS within the DER encoded certificate or CSR `der`
+///
+/// ```
+/// use test_rustcrypto_mldsa::tbs::get_tbs_offset;
+///
+/// // SEQUENCE { SEQUENCE { INTEGER 1 } }
+/// let der = [0x30, 0x05, 0x30, 0x03, 0x02, 0x01, 0x01];
+/// assert_eq!(get_tbs_offset(&der), Ok(2));
+/// ```
+pub fn get_tbs_offset(der: &[u8]) -> Result<usize, TemplateError> {
+    let (header_len, _) = sequence_lengths(der, 0, "signed")?;
+    Ok(header_len)
+}
+
+/// Borrow the TBS of the DER encoded certificate or CSR `der`
+///
+/// ```
+/// use test_rustcrypto_mldsa::tbs::get_tbs;
+///
+/// // SEQUENCE { SEQUENCE { INTEGER 1 }, NULL }
+/// let der = [0x30, 0x07, 0x30, 0x03, 0x02, 0x01, 0x01, 0x05, 0x00];
+/// assert_eq!(get_tbs(&der), Ok(&der[2..7]));
+/// assert!(get_tbs(&der[..4]).is_err());
+/// ```
+pub fn get_tbs(der: &[u8]) -> Result<&[u8], TemplateError> {
+    Ok(&der[tbs_range(der)?])
+}
+
+/// Retrieve the range of the TBS in DER encoded `der`
+///
+/// Copying the range out of the signed DER avoids cloning the whole DER first, which
+/// matters for algorithms with large keys and signatures.
+pub fn tbs_range(der: &[u8]) -> Result<Range<usize>, TemplateError> {
+    let tbs_offset = get_tbs_offset(der)?;
+    let (header_len, len) = sequence_lengths(der, tbs_offset, "TBS")?;
+    Ok(tbs_offset..tbs_offset + header_len + len)
+}
 
-    let tbs_offset = match der[der_len_offset] {
-        0..=0x7F => der_len_offset + 1,
-        0x81 => der_len_offset + 2,
-        0x82 => der_len_offset + 3,
+/// Retrieve the header length and content length of the DER element at `offset`
+pub(crate) fn der_lengths(der: &[u8], offset: usize) -> (usize, usize) {
+    let len_offset = offset + 1;
+    match der[len_offset] {
+        len @ 0..=0x7F => (2, len as usize),
+        0x81 => (3, der[len_offset + 1] as usize),
+        0x82 => (
+            4,
+            ((der[len_offset + 1] as usize) << u8::BITS) | der[len_offset + 2] as usize,
+        ),
         _ => panic!("Unsupported DER Length"),
+    }
+}
+
+/// Locate a nested DER element structurally rather than by needle search
+///
+/// Each entry of `path` is the index of a child within the preceding constructed element,
+/// starting from the outer element at offset 0. Returns the offset and length of the full
+/// TLV of the located element.
+pub fn locate_element(der: &[u8], path: &[usize]) -> (usize, usize) {
+    let tlv_len = |offset: usize| {
+        let (header_len, len) = der_lengths(der, offset);
+        header_len + len
     };
 
-    if der[tbs_offset] != 0x30 {
-        panic!("Invalid TBS start tag");
+    let mut offset = 0;
+    let mut len = tlv_len(offset);
+    for &index in path {
+        let (header_len, _) = der_lengths(der, offset);
+        let end = offset + len;
+        let mut child = offset + header_len;
+        for _ in 0..index {
+            child += tlv_len(child);
+        }
+        if child >= end {
+            panic!("DER element {} not found in path {:?}", index, path);
+        }
+        offset = child;
+        len = tlv_len(child);
+    }
+    (offset, len)
+}
+
+/// Locate the `public_key` bytes in the subjectPublicKey BIT STRING of the
+/// SubjectPublicKeyInfo at `spki_path` of `tbs`
+///
+/// The PUBLIC_KEY param is the BIT STRING content after its unused bits byte. Returns the
+/// offset of the key, or `TemplateError::PublicKeyNotFound` if the encoded key differs.
+pub fn locate_public_key(
+    tbs: &[u8],
+    spki_path: &[usize],
+    public_key: &[u8],
+) -> Result<usize, TemplateError> {
+    let mut path = spki_path.to_vec();
+    path.push(1);
+    let (offset, len) = locate_element(tbs, &path);
+    let (header_len, _) = der_lengths(tbs, offset);
+    let key_offset = offset + header_len + 1;
+    if tbs[offset] != 0x03 || tbs.get(key_offset..offset + len) != Some(public_key) {
+        return Err(TemplateError::PublicKeyNotFound {
+            key_len: public_key.len(),
+        });
+    }
+    Ok(key_offset)
+}
+
+/// List the offset and length of the full TLV of each child of the constructed DER element
+/// at `offset`
+pub fn element_children(der: &[u8], offset: usize) -> Vec<(usize, usize)> {
+    let (header_len, len) = der_lengths(der, offset);
+    let end = offset + header_len + len;
+    let mut children = Vec::new();
+    let mut child = offset + header_len;
+    while child < end {
+        let (child_header_len, child_len) = der_lengths(der, child);
+        children.push((child, child_header_len + child_len));
+        child += child_header_len + child_len;
+    }
+    children
+}
+
+/// List the content ranges of the FWID digests nested in the Extension of `oid` in the
+/// TBSCertificate `tbs`, e.g. a DiceTcbInfo or DiceTcbInfoSeq
+///
+/// A FWID is a SEQUENCE of a hashAlg OBJECT IDENTIFIER and a digest string, the unused
+/// bits byte of a BIT STRING digest is excluded from its range. Returns no range without
+/// the extension.
+pub fn fwid_digest_ranges(tbs: &[u8], oid: ObjectIdentifier) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    if let Some((extension, _)) = locate_extension(tbs, oid) {
+        // The extnValue OCTET STRING ends the Extension and wraps the DER of its value
+        let (value, value_len) = *element_children(tbs, extension).last().unwrap();
+        let (header_len, _) = der_lengths(tbs, value);
+        collect_fwid_digests(tbs, value + header_len..value + value_len, &mut ranges);
     }
+    ranges
+}
 
-    let tbs_len_offset = tbs_offset + 1;
-    let tbs_len = match der[tbs_len_offset] {
-        0..=0x7F => der[tbs_len_offset] as usize + 2,
-        0x81 => (der[tbs_len_offset + 1]) as usize + 3,
-        0x82 => {
-            (((der[tbs_len_offset + 1]) as usize) << u8::BITS)
-                | (((der[tbs_len_offset + 2]) as usize) + 4)
+/// Append the content ranges of the FWID digests of the DER elements in `elements` to
+/// `ranges`, descending into the constructed elements
+fn collect_fwid_digests(der: &[u8], elements: Range<usize>, ranges: &mut Vec<Range<usize>>) {
+    let mut offset = elements.start;
+    while offset < elements.end {
+        let (header_len, len) = der_lengths(der, offset);
+        let content = offset + header_len..offset + header_len + len;
+        if der[offset] & 0x20 != 0 {
+            let children = element_children(der, offset);
+            match children.as_slice() {
+                [(hash_alg, _), (digest, digest_len)]
+                    if der[offset] == 0x30
+                        && der[*hash_alg] == 0x06
+                        && matches!(der[*digest], 0x03 | 0x04) =>
+                {
+                    let (digest_header_len, _) = der_lengths(der, *digest);
+                    let unused_bits_len = usize::from(der[*digest] == 0x03);
+                    ranges.push(digest + digest_header_len + unused_bits_len..digest + digest_len);
+                }
+                _ => collect_fwid_digests(der, content.clone(), ranges),
+            }
         }
-        _ => panic!("Invalid DER Length"),
-    };
+        offset = content.end;
+    }
+}
+
+/// Ranges of the differing bytes of `bytes` and `other` compared at the same offsets, with
+/// the tail of the longer one as the last range
+///
+/// No alignment is attempted: an insertion or removal marks the whole tail after it as
+/// differing.
+pub fn byte_ranges(bytes: &[u8], other: &[u8]) -> Vec<Range<usize>> {
+    let common_len = bytes.len().min(other.len());
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for offset in (0..common_len).filter(|&i| bytes[i] != other[i]) {
+        match ranges.last_mut() {
+            Some(range) if range.end == offset => range.end += 1,
+            _ => ranges.push(offset..offset + 1),
+        }
+    }
+    if bytes.len() != other.len() {
+        ranges.push(common_len..bytes.len().max(other.len()));
+    }
+    ranges
+}
+
+/// List the content ranges of the SVNs of the TcbInfos nested in the Extension of `oid` in
+/// the TBSCertificate `tbs`, excluding the 0x01 marker byte of their fixed width encoding
+///
+/// The svn [3] IMPLICIT INTEGER is the only TcbInfo field with the tag 0x83. Returns no
+/// range without the extension.
+pub fn tcb_info_svn_ranges(tbs: &[u8], oid: ObjectIdentifier) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    if let Some((extension, _)) = locate_extension(tbs, oid) {
+        let (value, value_len) = *element_children(tbs, extension).last().unwrap();
+        let (header_len, _) = der_lengths(tbs, value);
+        collect_svns(tbs, value + header_len..value + value_len, &mut ranges);
+    }
+    ranges
+}
 
-    der[tbs_offset..tbs_offset + tbs_len].to_vec()
+/// Append the content ranges of the SVNs of the DER elements in `elements` to `ranges`,
+/// descending into the SEQUENCEs
+fn collect_svns(der: &[u8], elements: Range<usize>, ranges: &mut Vec<Range<usize>>) {
+    let mut offset = elements.start;
+    while offset < elements.end {
+        let (header_len, len) = der_lengths(der, offset);
+        let content = offset + header_len..offset + header_len + len;
+        match der[offset] {
+            0x30 => collect_svns(der, content.clone(), ranges),
+            0x83 if len > 1 => ranges.push(content.start + 1..content.end),
+            _ => (),
+        }
+        offset = content.end;
+    }
 }
 
-/// Initialize template parameter with its offset
-pub fn init_param(needle: &[u8], haystack: &[u8], param: TbsParam) -> TbsParam {
-    assert_eq!(needle.len(), param.len);
-    eprintln!("{}", param.name);
-    // Throw an error if there are multiple instances of our "needle"
-    // This could lead to incorrect offsets in the cert template
-    if haystack.windows(param.len).filter(|w| *w == needle).count() > 1 {
-        panic!(
-            "Multiple instances of needle '{}' with value\n\n{}\n\nin haystack\n\n{}",
-            param.name,
-            needle.encode_hex::<String>(),
-            haystack.encode_hex::<String>()
+/// Locate the full TLV of the Extension of `oid` in the TBSCertificate `tbs`
+pub fn locate_extension(tbs: &[u8], oid: ObjectIdentifier) -> Option<(usize, usize)> {
+    // extensions [3] EXPLICIT SEQUENCE OF Extension
+    let (extensions, _) = element_children(tbs, 0)
+        .into_iter()
+        .find(|&(offset, _)| tbs[offset] == 0xA3)?;
+    let (extensions, _) = element_children(tbs, extensions)[0];
+    element_children(tbs, extensions)
+        .into_iter()
+        .find(|&extension| {
+            let (offset, len) = element_children(tbs, extension.0)[0];
+            ObjectIdentifier::from_der(&tbs[offset..offset + len]).is_ok_and(|o| o == oid)
+        })
+}
+
+/// Piece of a TBS rebuilt without a range
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum StripSegment {
+    /// Bytes at the offset and of the length in the original TBS
+    Copy(usize, usize),
+    /// Renormalized header of an enclosing element
+    Header(Vec<u8>),
+}
+
+/// DER header of an element of `tag` with `len` content bytes
+pub(crate) fn der_header(tag: u8, len: usize) -> Vec<u8> {
+    match len {
+        0..=0x7F => vec![tag, len as u8],
+        0x80..=0xFF => vec![tag, 0x81, len as u8],
+        0x100..=0xFFFF => vec![tag, 0x82, (len >> u8::BITS) as u8, len as u8],
+        _ => panic!("Unsupported DER Length"),
+    }
+}
+
+/// Layout of the TBSCertificate `tbs` without the Extension `range`
+///
+/// The extensions [3] element is dropped with its last Extension, as RFC 5280 requires at
+/// least one Extension when present.
+pub(crate) fn strip_segments(tbs: &[u8], range: TbsParam) -> Vec<StripSegment> {
+    let extensions = element_children(tbs, 0)
+        .into_iter()
+        .find(|&(offset, len)| {
+            tbs[offset] == 0xA3 && (offset..offset + len).contains(&range.offset)
+        })
+        .unwrap_or_else(|| panic!("Range {} is not an Extension", range.name));
+    let sequence = element_children(tbs, extensions.0)[0];
+    let (tbs_header_len, tbs_len) = der_lengths(tbs, 0);
+    let tbs_end = tbs_header_len + tbs_len;
+
+    let mut segments = Vec::new();
+    if element_children(tbs, sequence.0).len() == 1 {
+        segments.push(StripSegment::Header(der_header(
+            tbs[0],
+            tbs_len - extensions.1,
+        )));
+        segments.push(StripSegment::Copy(
+            tbs_header_len,
+            extensions.0 - tbs_header_len,
+        ));
+        let extensions_end = extensions.0 + extensions.1;
+        segments.push(StripSegment::Copy(extensions_end, tbs_end - extensions_end));
+    } else {
+        let (sequence_header_len, sequence_len) = der_lengths(tbs, sequence.0);
+        let sequence_header = der_header(tbs[sequence.0], sequence_len - range.len);
+        let extensions_header = der_header(
+            tbs[extensions.0],
+            sequence_header.len() + sequence_len - range.len,
         );
+        let removed = extensions.1
+            - (extensions_header.len() + sequence_header.len() + sequence_len - range.len);
+        segments.push(StripSegment::Header(der_header(tbs[0], tbs_len - removed)));
+        segments.push(StripSegment::Copy(
+            tbs_header_len,
+            extensions.0 - tbs_header_len,
+        ));
+        segments.push(StripSegment::Header(extensions_header));
+        segments.push(StripSegment::Header(sequence_header));
+        let sequence_content = sequence.0 + sequence_header_len;
+        segments.push(StripSegment::Copy(
+            sequence_content,
+            range.offset - sequence_content,
+        ));
+        let range_end = range.offset + range.len;
+        segments.push(StripSegment::Copy(range_end, tbs_end - range_end));
     }
-    let pos = haystack.windows(param.len).position(|w| w == needle);
+    segments
+}
+
+/// Offset in the TBS rebuilt from `segments` of the byte at `offset` of the original TBS,
+/// `None` if the byte was stripped
+fn stripped_offset(segments: &[StripSegment], offset: usize) -> Option<usize> {
+    let mut stripped = 0;
+    for segment in segments {
+        match segment {
+            StripSegment::Copy(start, len) if (*start..start + len).contains(&offset) => {
+                return Some(stripped + offset - start);
+            }
+            StripSegment::Copy(_, len) => stripped += len,
+            StripSegment::Header(header) => stripped += header.len(),
+        }
+    }
+    None
+}
 
+/// Remove the Extension `range` from the TBSCertificate `tbs` and renormalize the lengths of
+/// the Extensions, the extensions [3] element and the TBS
+pub fn strip_range(tbs: &[u8], range: TbsParam) -> Vec<u8> {
+    let mut stripped = Vec::new();
+    for segment in strip_segments(tbs, range) {
+        match segment {
+            StripSegment::Copy(offset, len) => {
+                stripped.extend_from_slice(&tbs[offset..offset + len])
+            }
+            StripSegment::Header(header) => stripped.extend_from_slice(&header),
+        }
+    }
+    stripped
+}
+
+/// Check that `param` lies within a TBS of `tbs_len` bytes
+pub fn check_param_bounds(param: TbsParam, tbs_len: usize) -> Result<(), TemplateError> {
+    match param.offset.checked_add(param.len) {
+        Some(end) if end <= tbs_len => Ok(()),
+        _ => Err(TemplateError::ParamBounds {
+            name: param.name,
+            offset: param.offset,
+            len: param.len,
+            tbs_len,
+        }),
+    }
+}
+
+/// Initialize template parameter with the offset of its `needle`, the generation-time value
+/// of the param, which must occur exactly once in `haystack`
+///
+/// ```
+/// use test_rustcrypto_mldsa::tbs::{init_param, TemplateError};
+/// use test_rustcrypto_mldsa::TbsParam;
+///
+/// let tbs = [0x30, 0x06, 0x04, 0x04, 0xAA, 0xBB, 0xCC, 0xDD];
+/// let param = init_param(&[0xBB, 0xCC], &tbs, TbsParam::new("VALUE", 0, 2)).unwrap();
+/// assert_eq!(param.offset, 5);
+///
+/// let missing = init_param(&[0xEE, 0xEE], &tbs, TbsParam::new("VALUE", 0, 2));
+/// assert!(matches!(missing, Err(TemplateError::NeedleCount { found: 0, .. })));
+/// ```
+pub fn init_param(
+    needle: &[u8],
+    haystack: &[u8],
+    param: TbsParam,
+) -> Result<TbsParam, TemplateError> {
+    init_repeated_param(needle, haystack, param, 1)
+}
+
+/// Initialize template parameter with the offset of the first of `count` instances of its
+/// needle
+///
+/// Params sharing a needle are initialized in order, each sanitized before the next so the
+/// remaining instances move to the front.
+pub fn init_repeated_param(
+    needle: &[u8],
+    haystack: &[u8],
+    param: TbsParam,
+    count: usize,
+) -> Result<TbsParam, TemplateError> {
+    init_repeated_param_in(needle, haystack, param, count, &[0..haystack.len()])
+}
+
+/// Initialize template parameter with the offset of the first of `count` instances of its
+/// needle lying within one of the `regions` of the haystack
+///
+/// Bytes equal to the needle outside of the regions are ignored, e.g. to only match a FWID
+/// digest in the digests of `fwid_digest_ranges`.
+pub fn init_repeated_param_in(
+    needle: &[u8],
+    haystack: &[u8],
+    param: TbsParam,
+    count: usize,
+    regions: &[Range<usize>],
+) -> Result<TbsParam, TemplateError> {
+    // An empty needle would match at every offset
+    TbsParam::try_new(param.name, param.offset, param.len)?;
+    if needle.len() != param.len {
+        return Err(TemplateError::ValueLength {
+            name: param.name,
+            len: param.len,
+            value_len: needle.len(),
+        });
+    }
+    log::trace!("Locating param {}", param.name);
+    // More instances of the needle than params sharing it would lead to incorrect offsets in
+    // the template. Count the instances and find the first in a single pass over the haystack.
+    let mut positions = regions.iter().flat_map(|region| {
+        let end = (region.end.min(haystack.len()) + 1).saturating_sub(param.len);
+        (region.start..end).filter(|&offset| &haystack[offset..offset + param.len] == needle)
+    });
+    let pos = positions.next();
+    let instances = pos.map_or(0, |_| 1 + positions.count());
     match pos {
-        Some(offset) => TbsParam { offset, ..param },
-        None => panic!(
-            "Could not find needle '{}' with value\n\n{}\n\nin haystack\n\n{}",
-            param.name,
-            needle.encode_hex::<String>(),
-            haystack.encode_hex::<String>()
-        ),
+        Some(offset) if instances == count => {
+            let param = TbsParam { offset, ..param };
+            check_param_bounds(param, haystack.len())?;
+            Ok(param)
+        }
+        _ => {
+            log::debug!(
+                "Needle of param '{}' with value\n\n{}\n\nin haystack\n\n{}",
+                param.name,
+                needle.encode_hex::<String>(),
+                haystack.encode_hex::<String>()
+            );
+            Err(TemplateError::NeedleCount {
+                name: param.name,
+                expected: count,
+                found: instances,
+            })
+        }
     }
 }
 
-/// Sanitize the TBS buffer for the specified parameter
+/// Sanitize the bytes of `param` in the TBS `buf`, so the template doesn't leak the
+/// generation-time values
+///
+/// ```
+/// use test_rustcrypto_mldsa::tbs::sanitize;
+/// use test_rustcrypto_mldsa::TbsParam;
+///
+/// let mut tbs = [0x04, 0x02, 0xAA, 0xBB];
+/// sanitize(TbsParam::new("VALUE", 2, 2), &mut tbs);
+/// assert_eq!(tbs, [0x04, 0x02, 0x5F, 0x5F]);
+/// ```
 pub fn sanitize(param: TbsParam, buf: &mut [u8]) -> TbsParam {
-    for byte in buf.iter_mut().skip(param.offset).take(param.len) {
-        *byte = 0x5F;
+    sanitize_with(param, buf, PlaceholderFill::Sanitized)
+}
+
+/// Sanitize the `param` of `buf` with the placeholder bytes of `fill` instead of 0x5F
+pub fn sanitize_with(param: TbsParam, buf: &mut [u8], fill: PlaceholderFill) -> TbsParam {
+    for (i, byte) in buf
+        .iter_mut()
+        .skip(param.offset)
+        .take(param.len)
+        .enumerate()
+    {
+        *byte = fill.byte(i);
     }
     param
 }
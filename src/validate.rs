@@ -0,0 +1,390 @@
+/*++
+
+Licensed under the Apache-2.0 license.
+
+File Name:
+
+    validate.rs
+
+Abstract:
+
+    File contains the validation of the template builder inputs.
+
+--*/
+
+use crate::cert_rustcrypto::FwidParam;
+use crate::measurement::digest_len;
+use crate::profile::ANY_EXTENDED_KEY_USAGE_OID;
+use crate::tbs::TemplateError;
+use const_oid::ObjectIdentifier;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use x509_cert::certificate::Version;
+
+/// Lengths of a UEID, up to the 33 bytes of a TCG DICE random UEID
+pub const UEID_LEN: RangeInclusive<usize> = 1..=33;
+
+/// Longest CommonName keeping its RelativeDistinguishedName SET under 128 bytes
+///
+/// The SET holds a SEQUENCE of the 5 byte id-at-commonName OID and the string, so the CN
+/// content fits 127 - 2 - 5 - 2 bytes with single byte DER lengths.
+pub const MAX_CN_LEN: usize = 118;
+
+/// Length of the smallest opaque subject placeholder, a Name of a single CommonName
+/// character
+///
+/// The Name, RelativeDistinguishedName SET and AttributeTypeAndValue SEQUENCE headers take
+/// 2 bytes each, followed by the 5 byte id-at-commonName OID and the 3 byte string.
+pub const MIN_OPAQUE_SUBJECT_LEN: usize = 14;
+
+/// Longest serialNumber INTEGER content, RFC 5280 §4.1.2.2
+pub const MAX_SERIAL_NUMBER_LEN: usize = 20;
+
+/// Largest patchable pathLenConstraint, whose INTEGER content is a single byte
+pub const MAX_PATCHABLE_PATH_LEN: u8 = 127;
+
+/// Template builder input error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuilderError {
+    /// UEID whose length is outside `UEID_LEN`
+    UeidLength { len: usize },
+    /// Integer UEID that doesn't fit its `width` bytes
+    UeidValue { width: usize },
+    /// BasicConstraints pathLenConstraint that doesn't fit the INTEGER (0..255) of the
+    /// template
+    PathLength { path_len: u32 },
+    /// Patchable pathLenConstraint maximum above `MAX_PATCHABLE_PATH_LEN`
+    PatchablePathLength { max: u8 },
+    /// FWID with an empty digest
+    EmptyDigest { fwid: &'static str },
+    /// FWID digest whose length differs from the digest of its hash algorithm
+    DigestLength {
+        fwid: &'static str,
+        hash_alg: ObjectIdentifier,
+        len: usize,
+        expected: usize,
+    },
+    /// Candidate hashAlg OID of the FWID param whose `len` DER content bytes differ from the
+    /// `expected` length of the generation-time OID
+    HashAlgLength {
+        fwid: &'static str,
+        hash_alg: ObjectIdentifier,
+        len: usize,
+        expected: usize,
+    },
+    /// hashAlg param registered for a name matching no FWID of the template
+    UnknownFwid { fwid: &'static str },
+    /// FWID name that isn't a Rust identifier, so it can't name a generated constant
+    FwidName { fwid: &'static str },
+    /// ExtendedKeyUsage without key purposes
+    EmptyExtendedKeyUsage,
+    /// anyExtendedKeyUsage listed along `others` specific key purposes
+    AnyExtendedKeyUsage { others: usize },
+    /// CommonName, or its reservation, longer than `MAX_CN_LEN` or empty
+    CnLength { name: &'static str, len: usize },
+    /// CommonName of `cn_len` bytes not shorter than its reservation of `len` bytes, whose
+    /// placeholder would then lack the sentinel telling it apart from the other CN
+    CnReservation {
+        name: &'static str,
+        cn_len: usize,
+        len: usize,
+    },
+    /// Opaque subject reservation shorter than `MIN_OPAQUE_SUBJECT_LEN`
+    OpaqueSubjectLength { len: usize },
+    /// Subject reservation of `len` bytes skipped by padding the CN, between the reachable
+    /// lengths `below`, unless the unpadded Name is already longer, and `above`
+    SubjectLength {
+        len: usize,
+        below: Option<usize>,
+        above: usize,
+    },
+    /// Serial number content longer than `MAX_SERIAL_NUMBER_LEN` or empty
+    SerialNumberLength { len: usize },
+    /// Issuer CN of a child template differing from the CN of its parent subject
+    IssuerCn { cn: String, parent_cn: String },
+    /// Builder input `first` that can't be combined with `second`
+    Conflict {
+        first: &'static str,
+        second: &'static str,
+    },
+    /// DPE leaf certificate without TCIs
+    NoDpeTci,
+    /// Placeholder `field` reserved with a zero length
+    EmptyField { field: &'static str },
+    /// `field` set without the `extension` it belongs to
+    MissingExtension {
+        field: &'static str,
+        extension: &'static str,
+    },
+    /// Forced certificate `version` lacking the `fields` of the TBS, which require the
+    /// `required` version
+    Version {
+        version: Version,
+        fields: &'static str,
+        required: Version,
+    },
+    /// Generated TBS that can't be templated
+    Template(TemplateError),
+    /// Debug artifacts that can't be written to `dir`
+    DebugArtifacts { dir: PathBuf, reason: String },
+    /// Remote signer failing to sign the TBS
+    Signing { reason: String },
+}
+
+impl fmt::Display for BuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UeidLength { len } => write!(
+                f,
+                "UEID of {} bytes, expected {} to {} bytes",
+                len,
+                UEID_LEN.start(),
+                UEID_LEN.end()
+            ),
+            Self::UeidValue { width } => write!(f, "UEID value doesn't fit {} bytes", width),
+            Self::PathLength { path_len } => {
+                write!(f, "pathLenConstraint {} exceeds {}", path_len, u8::MAX)
+            }
+            Self::PatchablePathLength { max } => write!(
+                f,
+                "Patchable pathLenConstraint maximum {} exceeds {}",
+                max, MAX_PATCHABLE_PATH_LEN
+            ),
+            Self::EmptyDigest { fwid } => write!(f, "FWID '{}' has an empty digest", fwid),
+            Self::DigestLength {
+                fwid,
+                hash_alg,
+                len,
+                expected,
+            } => write!(
+                f,
+                "FWID '{}' digest of {} bytes, {} digests are {} bytes",
+                fwid, len, hash_alg, expected
+            ),
+            Self::HashAlgLength {
+                fwid,
+                hash_alg,
+                len,
+                expected,
+            } => write!(
+                f,
+                "FWID '{}' hashAlg candidate {} of {} bytes, the template OID is {} bytes",
+                fwid, hash_alg, len, expected
+            ),
+            Self::UnknownFwid { fwid } => {
+                write!(f, "No FWID '{}' for the {}_HASH_ALG param", fwid, fwid)
+            }
+            Self::FwidName { fwid } => {
+                write!(f, "FWID name '{}' is not a Rust identifier", fwid)
+            }
+            Self::EmptyExtendedKeyUsage => write!(f, "ExtendedKeyUsage without key purposes"),
+            Self::AnyExtendedKeyUsage { others } => write!(
+                f,
+                "anyExtendedKeyUsage combined with {} other key purposes",
+                others
+            ),
+            Self::CnLength { name, len } => write!(
+                f,
+                "{} of {} bytes, expected 1 to {} bytes",
+                name, len, MAX_CN_LEN
+            ),
+            Self::CnReservation { name, cn_len, len } => write!(
+                f,
+                "{} of {} bytes must be shorter than its reservation of {} bytes",
+                name, cn_len, len
+            ),
+            Self::OpaqueSubjectLength { len } => write!(
+                f,
+                "Opaque subject of {} bytes, a minimal Name takes {} bytes",
+                len, MIN_OPAQUE_SUBJECT_LEN
+            ),
+            Self::SubjectLength {
+                len,
+                below: Some(below),
+                above,
+            } => write!(
+                f,
+                "Subject of {} bytes can't be reached by padding the CN, the closest reachable \
+                 lengths are {} and {} bytes",
+                len, below, above
+            ),
+            Self::SubjectLength {
+                len,
+                below: None,
+                above,
+            } => write!(
+                f,
+                "Subject of {} bytes is shorter than the unpadded Name of {} bytes",
+                len, above
+            ),
+            Self::SerialNumberLength { len } => write!(
+                f,
+                "Serial number of {} bytes, expected 1 to {} bytes",
+                len, MAX_SERIAL_NUMBER_LEN
+            ),
+            Self::IssuerCn { cn, parent_cn } => write!(
+                f,
+                "Issuer CN '{}' of a child template differs from the parent CN '{}'",
+                cn, parent_cn
+            ),
+            Self::Conflict { first, second } => {
+                write!(f, "{} can't be combined with {}", first, second)
+            }
+            Self::NoDpeTci => write!(f, "DPE leaf certificate requires at least one TCI"),
+            Self::EmptyField { field } => write!(f, "{} requires a non-zero length", field),
+            Self::MissingExtension { field, extension } => {
+                write!(f, "{} requires the {} extension", field, extension)
+            }
+            Self::Version {
+                version,
+                fields,
+                required,
+            } => write!(
+                f,
+                "{:?} certificates can't carry {}, they require {:?}",
+                version, fields, required
+            ),
+            Self::Template(err) => err.fmt(f),
+            Self::DebugArtifacts { dir, reason } => write!(
+                f,
+                "Failed to write the debug artifacts to {}: {}",
+                dir.display(),
+                reason
+            ),
+            Self::Signing { reason } => write!(f, "Failed to sign the TBS: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for BuilderError {}
+
+impl From<TemplateError> for BuilderError {
+    fn from(err: TemplateError) -> Self {
+        Self::Template(err)
+    }
+}
+
+pub fn check_ueid(ueid: &[u8]) -> Result<(), BuilderError> {
+    if !UEID_LEN.contains(&ueid.len()) {
+        return Err(BuilderError::UeidLength { len: ueid.len() });
+    }
+    Ok(())
+}
+
+/// Check the key purposes of an ExtendedKeyUsage
+///
+/// RFC 5280 requires at least one key purpose. anyExtendedKeyUsage lifts the restriction of
+/// the other key purposes, so it must be the only one.
+pub fn check_extended_key_usage(usages: &[ObjectIdentifier]) -> Result<(), BuilderError> {
+    if usages.is_empty() {
+        return Err(BuilderError::EmptyExtendedKeyUsage);
+    }
+    if usages.contains(&ANY_EXTENDED_KEY_USAGE_OID) && usages.len() > 1 {
+        return Err(BuilderError::AnyExtendedKeyUsage {
+            others: usages.len() - 1,
+        });
+    }
+    Ok(())
+}
+
+/// Convert `path_len` to the pathLenConstraint of a BasicConstraints extension
+pub fn check_path_len(path_len: u32) -> Result<u8, BuilderError> {
+    u8::try_from(path_len).map_err(|_| BuilderError::PathLength { path_len })
+}
+
+/// Check the FWID name and digest
+///
+/// The digest length of SHA-2 and SHA-3 FWIDs must match the algorithm, other algorithms
+/// only require a digest.
+pub fn check_fwid(fwid: &FwidParam) -> Result<(), BuilderError> {
+    if !is_identifier(fwid.name) {
+        return Err(BuilderError::FwidName { fwid: fwid.name });
+    }
+    let len = fwid.fwid.digest.len();
+    if len == 0 {
+        return Err(BuilderError::EmptyDigest { fwid: fwid.name });
+    }
+    let Some(expected) = digest_len(fwid.fwid.hash_alg) else {
+        return Ok(());
+    };
+    if len != expected {
+        return Err(BuilderError::DigestLength {
+            fwid: fwid.name,
+            hash_alg: fwid.fwid.hash_alg,
+            len,
+            expected,
+        });
+    }
+    Ok(())
+}
+
+/// Check that the hashAlg `candidates` of the FWID param `fwid` encode to the length of the
+/// generation-time `hash_alg`, so patching them doesn't shift the following fields
+pub fn check_hash_alg_candidates(
+    fwid: &'static str,
+    hash_alg: ObjectIdentifier,
+    candidates: &[ObjectIdentifier],
+) -> Result<(), BuilderError> {
+    let expected = hash_alg.as_bytes().len();
+    for &candidate in candidates {
+        let len = candidate.as_bytes().len();
+        if len != expected {
+            return Err(BuilderError::HashAlgLength {
+                fwid,
+                hash_alg: candidate,
+                len,
+                expected,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Check the `len` bytes of the CommonName `name`, e.g. "Subject CN"
+pub fn check_cn_len(name: &'static str, len: usize) -> Result<(), BuilderError> {
+    if len == 0 || len > MAX_CN_LEN {
+        return Err(BuilderError::CnLength { name, len });
+    }
+    Ok(())
+}
+
+/// Check that the CommonName `cn` of `name` is shorter than its reservation of `len` bytes,
+/// so its placeholder ends with at least one sentinel
+pub fn check_cn_reservation(name: &'static str, cn: &str, len: usize) -> Result<(), BuilderError> {
+    if cn.len() >= len {
+        return Err(BuilderError::CnReservation {
+            name,
+            cn_len: cn.len(),
+            len,
+        });
+    }
+    Ok(())
+}
+
+/// Check the `len` bytes reserved for an opaque subject Name
+pub fn check_opaque_subject_len(len: usize) -> Result<(), BuilderError> {
+    if len < MIN_OPAQUE_SUBJECT_LEN {
+        return Err(BuilderError::OpaqueSubjectLength { len });
+    }
+    Ok(())
+}
+
+/// Check the `len` content octets of a serialNumber INTEGER
+pub fn check_serial_number_len(len: usize) -> Result<(), BuilderError> {
+    if len == 0 || len > MAX_SERIAL_NUMBER_LEN {
+        return Err(BuilderError::SerialNumberLength { len });
+    }
+    Ok(())
+}
+
+/// ASCII Rust identifier other than "_"
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() => {}
+        Some('_') if name.len() > 1 => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
@@ -0,0 +1,85 @@
+#!/usr/bin/env python3
+# Licensed under the Apache-2.0 license.
+#
+# Generate the ML-DSA-87 vector files of this directory with the OpenSSL 3.5 command line
+# tool, in the layout of the ACVP-Server internalProjection.json files. Run from this
+# directory; the seeds are derived from fixed labels so the output is reproducible.
+
+import hashlib, json, os, re, subprocess, tempfile
+
+D = tempfile.mkdtemp()
+
+def run(*args, data=None):
+    return subprocess.run(args, check=True, capture_output=True, input=data).stdout
+
+def seed_of(label):
+    return hashlib.sha256(label.encode()).digest()
+
+def key(seed):
+    pem = os.path.join(D, seed.hex() + ".pem")
+    run("openssl", "genpkey", "-algorithm", "ML-DSA-87", "-pkeyopt", "hexseed:" + seed.hex(), "-out", pem)
+    text = run("openssl", "pkey", "-in", pem, "-text", "-noout").decode()
+    fields = {}
+    for name, body in re.findall(r"^(\w+):\n((?:    .*\n)+)", text, re.M):
+        fields[name] = bytes.fromhex(re.sub(r"[\s:]", "", body))
+    pub = os.path.join(D, seed.hex() + ".pub")
+    run("openssl", "pkey", "-in", pem, "-pubout", "-out", pub)
+    assert len(fields["priv"]) == 4896 and len(fields["pub"]) == 2592
+    return pem, pub, fields["pub"], fields["priv"]
+
+def sign(pem, msg, ctx):
+    m = os.path.join(D, "m")
+    open(m, "wb").write(msg)
+    opts = ["-pkeyopt", "deterministic:1"]
+    if ctx:
+        opts += ["-pkeyopt", "hexcontext-string:" + ctx.hex()]
+    return run("openssl", "pkeyutl", "-sign", "-rawin", "-inkey", pem, "-in", m, *opts)
+
+def verify(pub, msg, ctx, sig):
+    m, s = os.path.join(D, "m"), os.path.join(D, "s")
+    open(m, "wb").write(msg)
+    open(s, "wb").write(sig)
+    opts = ["-pkeyopt", "hexcontext-string:" + ctx.hex()] if ctx else []
+    r = subprocess.run(["openssl", "pkeyutl", "-verify", "-rawin", "-pubin", "-inkey", pub, "-in", m, "-sigfile", s, *opts], capture_output=True)
+    return r.returncode == 0
+
+def write(name, groups):
+    with open(name, "w") as f:
+        json.dump({"vsId": 0, "algorithm": "ML-DSA", "mode": name.split("-")[2], "revision": "FIPS204", "testGroups": groups}, f, indent=2)
+        f.write("\n")
+
+keygen = []
+for i in range(1, 6):
+    seed = seed_of("keyGen %d" % i)
+    _, _, pk, sk = key(seed)
+    keygen.append({"tcId": i, "seed": seed.hex().upper(), "pk": pk.hex().upper(), "sk": sk.hex().upper()})
+write("ML-DSA-keyGen-FIPS204.json", [{"tgId": 1, "testType": "AFT", "parameterSet": "ML-DSA-87", "tests": keygen}])
+
+cases = [
+    (b"\x00", b""),
+    (b"abc", b""),
+    (hashlib.sha512(b"message").digest() * 8, b""),
+    (b"Caliptra 2.0 MlDsa87 LDevID", bytes(range(16))),
+    (bytes(range(256)) * 3, bytes([0xA5]) * 255),
+]
+siggen, sigver = [], []
+tc = 0
+for i, (msg, ctx) in enumerate(cases, 1):
+    pem, pub, pk, sk = key(seed_of("sigGen %d" % i))
+    sig = sign(pem, msg, ctx)
+    assert sig == sign(pem, msg, ctx) and verify(pub, msg, ctx, sig)
+    siggen.append({"tcId": i, "sk": sk.hex().upper(), "message": msg.hex().upper(), "context": ctx.hex().upper(), "signature": sig.hex().upper()})
+
+    bad_sig = bytearray(sig); bad_sig[100] ^= 1
+    variants = [
+        (True, msg, ctx, sig, "valid"),
+        (False, msg + b"\x00", ctx, sig, "modified message"),
+        (False, msg, ctx + b"\x01", sig, "modified context"),
+        (False, msg, ctx, bytes(bad_sig), "modified signature"),
+    ]
+    for passed, m, c, s, reason in variants:
+        assert verify(pub, m, c, s) == passed
+        tc += 1
+        sigver.append({"tcId": tc, "testPassed": passed, "reason": reason, "pk": pk.hex().upper(), "message": m.hex().upper(), "context": c.hex().upper(), "signature": s.hex().upper()})
+write("ML-DSA-sigGen-FIPS204.json", [{"tgId": 1, "testType": "AFT", "parameterSet": "ML-DSA-87", "deterministic": True, "signatureInterface": "external", "preHash": "pure", "externalMu": False, "tests": siggen}])
+write("ML-DSA-sigVer-FIPS204.json", [{"tgId": 1, "testType": "AFT", "parameterSet": "ML-DSA-87", "signatureInterface": "external", "preHash": "pure", "externalMu": False, "tests": sigver}])
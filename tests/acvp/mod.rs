@@ -0,0 +1,155 @@
+/*++
+
+Licensed under the Apache-2.0 license.
+
+File Name:
+
+    mod.rs
+
+Abstract:
+
+    File contains the loader of the ACVP ML-DSA vector files.
+
+--*/
+
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Parameter set of the test groups the harness runs
+pub const PARAMETER_SET: &str = "ML-DSA-87";
+
+/// Directory of the checked-in vector files
+pub fn vector_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("acvp")
+}
+
+/// Vector file, the internalProjection.json of an ACVP-Server gen-val directory
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VectorFile<Group> {
+    pub test_groups: Vec<Group>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyGenGroup {
+    pub tg_id: u32,
+    pub parameter_set: String,
+    pub tests: Vec<KeyGenTest>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyGenTest {
+    pub tc_id: u32,
+    #[serde(with = "hex::serde")]
+    pub seed: Vec<u8>,
+    #[serde(with = "hex::serde")]
+    pub pk: Vec<u8>,
+    #[serde(with = "hex::serde")]
+    pub sk: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SigGenGroup {
+    pub tg_id: u32,
+    pub parameter_set: String,
+    pub deterministic: bool,
+    #[serde(default)]
+    pub signature_interface: Option<String>,
+    #[serde(default)]
+    pub pre_hash: Option<String>,
+    #[serde(default)]
+    pub external_mu: Option<bool>,
+    pub tests: Vec<SigGenTest>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SigGenTest {
+    pub tc_id: u32,
+    #[serde(with = "hex::serde")]
+    pub sk: Vec<u8>,
+    #[serde(with = "hex::serde")]
+    pub message: Vec<u8>,
+    #[serde(default, with = "optional_hex")]
+    pub context: Option<Vec<u8>>,
+    #[serde(with = "hex::serde")]
+    pub signature: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SigVerGroup {
+    pub tg_id: u32,
+    pub parameter_set: String,
+    #[serde(default)]
+    pub signature_interface: Option<String>,
+    #[serde(default)]
+    pub pre_hash: Option<String>,
+    #[serde(default)]
+    pub external_mu: Option<bool>,
+    pub tests: Vec<SigVerTest>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SigVerTest {
+    pub tc_id: u32,
+    pub test_passed: bool,
+    #[serde(with = "hex::serde")]
+    pub pk: Vec<u8>,
+    #[serde(with = "hex::serde")]
+    pub message: Vec<u8>,
+    #[serde(default, with = "optional_hex")]
+    pub context: Option<Vec<u8>>,
+    #[serde(with = "hex::serde")]
+    pub signature: Vec<u8>,
+}
+
+/// Groups signing the message itself with the pure ML-DSA of FIPS 204 Algorithm 2, the
+/// variant used by the builders
+pub fn is_pure_external(
+    signature_interface: &Option<String>,
+    pre_hash: &Option<String>,
+    external_mu: Option<bool>,
+) -> bool {
+    signature_interface.as_deref().unwrap_or("external") == "external"
+        && pre_hash.as_deref().unwrap_or("pure") == "pure"
+        && !external_mu.unwrap_or(false)
+}
+
+/// Load the vector file `file_name`
+///
+/// A missing file fails the test, so the known-answer tests can't silently pass without
+/// their vectors.
+pub fn load<Group: for<'de> Deserialize<'de>>(file_name: &str) -> VectorFile<Group> {
+    let path = vector_dir().join(file_name);
+    let data = std::fs::read_to_string(&path).unwrap_or_else(|err| {
+        panic!(
+            "Failed to read the ACVP vectors {}, see tests/acvp/README.md: {}",
+            path.display(),
+            err
+        )
+    });
+    serde_json::from_str(&data)
+        .unwrap_or_else(|err| panic!("Failed to parse {}: {}", path.display(), err))
+}
+
+mod optional_hex {
+    use serde::{Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<u8>>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(value) => hex::decode(value)
+                .map(Some)
+                .map_err(serde::de::Error::custom),
+            None => Ok(None),
+        }
+    }
+}
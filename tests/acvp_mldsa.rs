@@ -0,0 +1,146 @@
+/*++
+
+Licensed under the Apache-2.0 license.
+
+File Name:
+
+    acvp_mldsa.rs
+
+Abstract:
+
+    ACVP known-answer tests of the ML-DSA-87 key generation, signing and verification used
+    by the template builders.
+
+--*/
+
+mod acvp;
+
+use acvp::{is_pure_external, load, KeyGenGroup, SigGenGroup, SigVerGroup, PARAMETER_SET};
+use ml_dsa::{EncodedSignature, EncodedSigningKey, EncodedVerifyingKey, MlDsa87};
+use rand::rngs::OsRng;
+use test_rustcrypto_mldsa::keys::{mldsa87_key_from_seed, sign, SigningMode};
+use test_rustcrypto_mldsa::BuilderKeys;
+
+/// Message signed with the generated keys through the `BuilderKeys` abstraction
+const MESSAGE: &[u8] = b"ACVP ML-DSA-87 builder signing";
+
+fn signing_key(sk: &[u8], tc_id: u32) -> ml_dsa::SigningKey<MlDsa87> {
+    let sk = EncodedSigningKey::<MlDsa87>::try_from(sk)
+        .unwrap_or_else(|_| panic!("tcId {}: bad sk length", tc_id));
+    ml_dsa::SigningKey::decode(&sk)
+}
+
+#[test]
+fn test_acvp_keygen() {
+    let file = load::<KeyGenGroup>("ML-DSA-keyGen-FIPS204.json");
+    let groups = file
+        .test_groups
+        .iter()
+        .filter(|g| g.parameter_set == PARAMETER_SET);
+    for group in groups {
+        for test in group.tests.iter() {
+            let tc_id = test.tc_id;
+            let seed: [u8; 32] = test.seed.as_slice().try_into().unwrap();
+            let key = mldsa87_key_from_seed(&seed);
+            assert_eq!(
+                key.verifying_key().encode().as_slice(),
+                test.pk,
+                "tgId {} tcId {}: pk",
+                group.tg_id,
+                tc_id
+            );
+            assert_eq!(
+                key.signing_key().encode().as_slice(),
+                test.sk,
+                "tgId {} tcId {}: sk",
+                group.tg_id,
+                tc_id
+            );
+
+            // The builder signer matches the expected signing key
+            let signature = sign(&key, MESSAGE, SigningMode::Deterministic, &mut OsRng);
+            let expected = signing_key(&test.sk, tc_id)
+                .sign_deterministic(MESSAGE, &[])
+                .unwrap();
+            assert_eq!(
+                signature.encode(),
+                expected.encode(),
+                "tcId {}: signature",
+                tc_id
+            );
+            assert!(
+                BuilderKeys::verify(&key, MESSAGE, signature.encode().as_slice()),
+                "tcId {}: verification",
+                tc_id
+            );
+        }
+    }
+}
+
+#[test]
+fn test_acvp_siggen() {
+    let file = load::<SigGenGroup>("ML-DSA-sigGen-FIPS204.json");
+    let groups = file.test_groups.iter().filter(|g| {
+        g.parameter_set == PARAMETER_SET
+            && g.deterministic
+            && is_pure_external(&g.signature_interface, &g.pre_hash, g.external_mu)
+    });
+    for group in groups {
+        for test in group.tests.iter() {
+            let tc_id = test.tc_id;
+            let key = signing_key(&test.sk, tc_id);
+            let context = test.context.as_deref().unwrap_or_default();
+            let signature = key
+                .sign_deterministic(&test.message, context)
+                .unwrap_or_else(|_| panic!("tgId {} tcId {}: signing", group.tg_id, tc_id));
+            assert_eq!(
+                signature.encode().as_slice(),
+                test.signature,
+                "tgId {} tcId {}: signature",
+                group.tg_id,
+                tc_id
+            );
+
+            // The builders sign with the signature::Signer interface and an empty context
+            if context.is_empty() {
+                let signature: ml_dsa::Signature<MlDsa87> =
+                    signature::Signer::sign(&key, &test.message);
+                assert_eq!(
+                    signature.encode().as_slice(),
+                    test.signature,
+                    "tcId {}: Signer",
+                    tc_id
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_acvp_sigver() {
+    let file = load::<SigVerGroup>("ML-DSA-sigVer-FIPS204.json");
+    let groups = file.test_groups.iter().filter(|g| {
+        g.parameter_set == PARAMETER_SET
+            && is_pure_external(&g.signature_interface, &g.pre_hash, g.external_mu)
+    });
+    for group in groups {
+        for test in group.tests.iter() {
+            let tc_id = test.tc_id;
+            let pk = EncodedVerifyingKey::<MlDsa87>::try_from(test.pk.as_slice())
+                .unwrap_or_else(|_| panic!("tcId {}: bad pk length", tc_id));
+            let key = ml_dsa::VerifyingKey::<MlDsa87>::decode(&pk);
+            let context = test.context.as_deref().unwrap_or_default();
+            let valid = EncodedSignature::<MlDsa87>::try_from(test.signature.as_slice())
+                .ok()
+                .and_then(|signature| ml_dsa::Signature::decode(&signature))
+                .is_some_and(|signature| {
+                    key.verify_with_context(&test.message, context, &signature)
+                });
+            assert_eq!(
+                valid, test.test_passed,
+                "tgId {} tcId {}: verification",
+                group.tg_id, tc_id
+            );
+        }
+    }
+}
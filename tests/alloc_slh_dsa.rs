@@ -0,0 +1,86 @@
+/*++
+
+Licensed under the Apache-2.0 license.
+
+File Name:
+
+    alloc_slh_dsa.rs
+
+Abstract:
+
+    File contains the peak allocation test of the param pipeline on an SLH-DSA template.
+
+--*/
+
+use rand::{rngs::StdRng, SeedableRng};
+use signature::Keypair;
+use spki::EncodePublicKey;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use test_rustcrypto_mldsa::keys::SlhDsaSha2_128fKeyPair;
+use test_rustcrypto_mldsa::tbs::{init_param, tbs_range};
+use test_rustcrypto_mldsa::{BuilderKeys, CertTemplateBuilder, TbsParam};
+
+/// System allocator tracking the live and the peak allocated bytes
+struct PeakAlloc;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for PeakAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let live = LIVE.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK.fetch_max(live, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: PeakAlloc = PeakAlloc;
+
+/// Peak bytes allocated by `f` on top of those live when it starts
+fn peak_allocation<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let start = LIVE.load(Ordering::Relaxed);
+    PEAK.store(start, Ordering::Relaxed);
+    let value = f();
+    (value, PEAK.load(Ordering::Relaxed) - start)
+}
+
+#[test]
+fn test_slh_dsa_param_pipeline_peak_allocation() {
+    let key = SlhDsaSha2_128fKeyPair::key_gen(&mut StdRng::seed_from_u64(1));
+    let spki = key.verifying_key().to_public_key_der().unwrap();
+    let public_key = &spki.as_bytes()[spki.as_bytes().len() - 32..];
+    let template = CertTemplateBuilder::<SlhDsaSha2_128fKeyPair>::ldevid_profile(&[0xFF; 17])
+        .set_subject_key(key)
+        .tbs_template("Caliptra 2.0 SlhDsa LDevID", "Caliptra 2.0 SlhDsa IDevID");
+    let der = template.der();
+
+    // Extract the TBS and locate a param as the builders do after signing
+    let (param, peak) = peak_allocation(|| {
+        let tbs = der[tbs_range(der).unwrap()].to_vec();
+        init_param(
+            public_key,
+            &tbs,
+            TbsParam::new("PUBLIC_KEY", 0, public_key.len()),
+        )
+        .unwrap()
+    });
+    let tbs = &der[tbs_range(der).unwrap()];
+    assert_eq!(&tbs[param.offset..param.offset + param.len], public_key);
+
+    // Only the TBS is copied, not the DER carrying the 17088 byte signature
+    assert!(der.len() > 4 * tbs.len());
+    assert!(
+        peak < 2 * tbs.len(),
+        "peak allocation of {} bytes for a {} byte TBS in a {} byte DER",
+        peak,
+        tbs.len(),
+        der.len()
+    );
+}
@@ -0,0 +1,114 @@
+/*++
+
+Licensed under the Apache-2.0 license.
+
+File Name:
+
+    fault_injection.rs
+
+Abstract:
+
+    Negative-path tests corrupting the DER generated by the builders before the TBS and the
+    params are extracted. Run with --features fault-injection.
+
+--*/
+
+use ml_dsa::MlDsa87;
+use signature::{Keypair, Signer};
+use test_rustcrypto_mldsa::keys::mldsa87_key_from_seed;
+use test_rustcrypto_mldsa::name::key_bytes_hash;
+use test_rustcrypto_mldsa::tbs::{tbs_range, TemplateError};
+use test_rustcrypto_mldsa::{BuilderError, CertTemplateBuilder, CsrTemplateBuilder};
+
+type Key = ml_dsa::KeyPair<MlDsa87>;
+
+const SUBJECT_SEED: [u8; 32] = [0x01; 32];
+const ISSUER_SEED: [u8; 32] = [0x02; 32];
+
+fn cert_builder() -> CertTemplateBuilder<'static, Key> {
+    CertTemplateBuilder::<Key>::new()
+        .set_subject_key(mldsa87_key_from_seed(&SUBJECT_SEED))
+        .set_issuer_key(mldsa87_key_from_seed(&ISSUER_SEED))
+}
+
+fn csr_builder() -> CsrTemplateBuilder<'static, Key> {
+    CsrTemplateBuilder::<Key>::new().set_key(mldsa87_key_from_seed(&SUBJECT_SEED))
+}
+
+fn flip_signature_byte(der: &mut Vec<u8>) {
+    // The signature BIT STRING content ends the DER
+    *der.last_mut().unwrap() ^= 0x01;
+}
+
+fn truncate(der: &mut Vec<u8>) {
+    der.truncate(der.len() - 1);
+}
+
+/// Erase the SUBJECT_SN needle of the subject key and sign the TBS again with `seed`, so
+/// the DER stays valid and only the param search fails
+fn erase_subject_sn(der: &mut Vec<u8>, seed: &[u8; 32]) {
+    let subject_key = mldsa87_key_from_seed(&SUBJECT_SEED);
+    let subject_sn = key_bytes_hash(&subject_key.verifying_key().encode());
+    let offset = der
+        .windows(subject_sn.len())
+        .position(|window| window == subject_sn.as_bytes())
+        .unwrap();
+    der[offset..offset + subject_sn.len()].fill(b'0');
+
+    let signature = mldsa87_key_from_seed(seed).sign(&der[tbs_range(der).unwrap()]);
+    let signature = signature.encode();
+    let offset = der.len() - signature.len();
+    der[offset..].copy_from_slice(&signature);
+}
+
+#[test]
+#[should_panic(expected = "Certificate signature verification failed")]
+fn test_cert_flipped_signature() {
+    cert_builder()
+        .with_post_build_mutation(flip_signature_byte)
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+}
+
+#[test]
+#[should_panic(expected = "CSR signature verification failed")]
+fn test_csr_flipped_signature() {
+    csr_builder()
+        .with_post_build_mutation(flip_signature_byte)
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+}
+
+#[test]
+fn test_truncated_der() {
+    let expected = BuilderError::Template(TemplateError::InvalidDer { element: "signed" });
+
+    let cert = cert_builder()
+        .with_post_build_mutation(truncate)
+        .try_tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    assert_eq!(cert.err(), Some(expected.clone()));
+
+    let csr = csr_builder()
+        .with_post_build_mutation(truncate)
+        .try_tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    assert_eq!(csr.err(), Some(expected));
+}
+
+#[test]
+fn test_erased_needle() {
+    let expected = BuilderError::Template(TemplateError::NeedleCount {
+        name: "SUBJECT_SN",
+        expected: 1,
+        found: 0,
+    });
+
+    let cert = cert_builder()
+        .with_post_build_mutation(|der| erase_subject_sn(der, &ISSUER_SEED))
+        .try_tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    let err = cert.err().unwrap();
+    assert_eq!(err, expected);
+    assert!(err.to_string().contains("SUBJECT_SN"));
+
+    let csr = csr_builder()
+        .with_post_build_mutation(|der| erase_subject_sn(der, &SUBJECT_SEED))
+        .try_tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    assert_eq!(csr.err(), Some(expected));
+}
@@ -0,0 +1,6 @@
+// Compile-pass fixture appended to the code generated for `ConstTemplateTbs`: the template
+// must be constructible and readable in const contexts.
+
+pub const TEMPLATE: ConstTemplateTbs = get_const_template_tbs();
+
+const _: () = assert!(TEMPLATE.tbs().len() == ConstTemplateTbs::TBS_TEMPLATE_LEN);
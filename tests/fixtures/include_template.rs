@@ -0,0 +1,12 @@
+// Compile-pass fixture including the file generated by `CodeGen::gen_include` for
+// `IncludeTemplate` from `OUT_DIR`, next to items of its own.
+
+include!(concat!(env!("OUT_DIR"), "/include_template.rs"));
+
+pub const TBS_LEN: usize = INCLUDE_TEMPLATE_TBS_TEMPLATE.len();
+
+const _: () = assert!(TBS_LEN == IncludeTemplate::TBS_TEMPLATE_LEN);
+
+pub fn template(params: &IncludeTemplateParams) -> IncludeTemplate {
+    IncludeTemplate::new(params)
+}
@@ -0,0 +1,151 @@
+/*++
+
+Licensed under the Apache-2.0 license.
+
+File Name:
+
+    golden.rs
+
+Abstract:
+
+    Golden-file tests of the code generated for the Caliptra ML-DSA-87 templates.
+
+    Run with UPDATE_GOLDEN=1 to regenerate tests/fixtures/golden after an intended change
+    of the generated code.
+
+--*/
+
+use const_oid::ObjectIdentifier;
+use convert_case::{Case, Casing};
+use ml_dsa::MlDsa87;
+use std::borrow::Cow;
+use std::path::Path;
+use test_rustcrypto_mldsa::keys::mldsa87_key_from_seed;
+use test_rustcrypto_mldsa::{
+    CertTemplateBuilder, CodeGen, CodeGenOptions, CsrTemplateBuilder, Fwid, FwidParam, TbsTemplate,
+};
+
+const SHA384_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.2");
+
+const UEID: [u8; 17] = [0xFF; 17];
+
+/// Seeds of the keys of each DICE layer
+const IDEVID_SEED: [u8; 32] = [0x01; 32];
+const LDEVID_SEED: [u8; 32] = [0x02; 32];
+const FMC_ALIAS_SEED: [u8; 32] = [0x03; 32];
+const RT_ALIAS_SEED: [u8; 32] = [0x04; 32];
+
+type Key = ml_dsa::KeyPair<MlDsa87>;
+
+/// Compare the code generated for `template` with the golden file of `type_name`, or
+/// rewrite the golden file if UPDATE_GOLDEN is set
+fn check_golden(type_name: &str, template: TbsTemplate) {
+    let code = CodeGen::formatted_code(type_name, template, &CodeGenOptions::default());
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join("golden")
+        .join(format!("{}.rs", type_name.to_case(Case::Snake)));
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, code).unwrap();
+        return;
+    }
+
+    let golden = std::fs::read_to_string(&path).unwrap_or_else(|err| {
+        panic!(
+            "Failed to read {}: {}, run with UPDATE_GOLDEN=1 to create it",
+            path.display(),
+            err
+        )
+    });
+    if code != golden {
+        let line = code
+            .lines()
+            .zip(golden.lines())
+            .position(|(generated, golden)| generated != golden)
+            .unwrap_or_else(|| code.lines().count().min(golden.lines().count()));
+        panic!(
+            "Generated code of {} drifted from {} at line {}:\n  generated: {:?}\n  golden:    {:?}\n\
+             Review the change and run with UPDATE_GOLDEN=1 to accept it",
+            type_name,
+            path.display(),
+            line + 1,
+            code.lines().nth(line).unwrap_or_default(),
+            golden.lines().nth(line).unwrap_or_default(),
+        );
+    }
+}
+
+#[test]
+fn test_golden_init_devid_csr() {
+    let template = CsrTemplateBuilder::<Key>::idevid_profile(&UEID)
+        .set_key(mldsa87_key_from_seed(&IDEVID_SEED))
+        .tbs_template("Caliptra 2.0 MlDsa87 IDevID");
+    check_golden("InitDevIdCsrTbsMlDsa87", template);
+}
+
+#[test]
+fn test_golden_fmc_alias_csr() {
+    let template = CsrTemplateBuilder::<Key>::idevid_profile(&UEID)
+        .set_key(mldsa87_key_from_seed(&FMC_ALIAS_SEED))
+        .tbs_template("Caliptra 2.0 MlDsa87 FMC Alias");
+    check_golden("FmcAliasTbsMlDsa87", template);
+}
+
+#[test]
+fn test_golden_local_devid_cert() {
+    let template = CertTemplateBuilder::<Key>::ldevid_profile(&UEID)
+        .set_subject_key(mldsa87_key_from_seed(&LDEVID_SEED))
+        .set_issuer_key(mldsa87_key_from_seed(&IDEVID_SEED))
+        .tbs_template("Caliptra 2.0 MlDsa87 LDevID", "Caliptra 2.0 MlDsa87 IDevID");
+    check_golden("LocalDevIdCertTbsMlDsa87", template);
+}
+
+#[test]
+fn test_golden_fmc_alias_cert() {
+    let device_fwids = [FwidParam {
+        name: "TCB_INFO_DEVICE_INFO_HASH",
+        fwid: Fwid {
+            hash_alg: SHA384_OID,
+            digest: Cow::Borrowed(&[0xEF; 48]),
+        },
+    }];
+    let fmc_fwids = [FwidParam {
+        name: "TCB_INFO_FMC_TCI",
+        fwid: Fwid {
+            hash_alg: SHA384_OID,
+            digest: Cow::Borrowed(&[0xCD; 48]),
+        },
+    }];
+
+    let template = CertTemplateBuilder::<Key>::fmc_alias_profile(&UEID, &device_fwids, &fmc_fwids)
+        .set_subject_key(mldsa87_key_from_seed(&FMC_ALIAS_SEED))
+        .set_issuer_key(mldsa87_key_from_seed(&LDEVID_SEED))
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+            "Caliptra 2.0 MlDsa87 LDevID",
+        );
+    check_golden("FmcAliasCertTbsMlDsa87", template);
+}
+
+#[test]
+fn test_golden_rt_alias_cert() {
+    let rt_fwids = [FwidParam {
+        name: "TCB_INFO_RT_TCI",
+        fwid: Fwid {
+            hash_alg: SHA384_OID,
+            digest: Cow::Borrowed(&[0xCD; 48]),
+        },
+    }];
+
+    let template = CertTemplateBuilder::<Key>::rt_alias_profile(&UEID, 0xC4, &rt_fwids)
+        .set_subject_key(mldsa87_key_from_seed(&RT_ALIAS_SEED))
+        .set_issuer_key(mldsa87_key_from_seed(&FMC_ALIAS_SEED))
+        .tbs_template(
+            "Caliptra 2.0 MlDsa87 RT Alias",
+            "Caliptra 2.0 MlDsa87 FMC Alias",
+        );
+    check_golden("RtAliasCertTbsMlDsa87", template);
+}
@@ -0,0 +1,50 @@
+/*++
+
+Licensed under the Apache-2.0 license.
+
+File Name:
+
+    runtime_no_std.rs
+
+Abstract:
+
+    Builds the runtime module in a no_std crate without alloc, so the tests fail to compile
+    once the module depends on more than core.
+
+--*/
+
+#![no_std]
+
+#[path = "../src/runtime.rs"]
+#[allow(dead_code)]
+mod runtime;
+
+use runtime::{assemble_cert, set_serial_number, signed_len, utc_time, Time};
+
+#[test]
+fn test_runtime_no_std() {
+    let time = Time {
+        year: 2025,
+        month: 1,
+        day: 1,
+        hour: 0,
+        minute: 0,
+        second: 0,
+    };
+    assert_eq!(&utc_time(&time).unwrap(), b"250101000000Z");
+
+    let mut serial = [0u8; 2];
+    set_serial_number(&mut serial, &[0xFF, 0xFF]);
+    assert_eq!(serial, [0x4F, 0xFF]);
+
+    // Empty TBS and AlgorithmIdentifier SEQUENCEs signed by a 2 byte signature
+    let mut out = [0u8; signed_len(2, 2, 2)];
+    assert_eq!(
+        assemble_cert(&[0x30, 0x00], &[0x30, 0x00], &[0xAA; 2], &mut out),
+        Ok(out.len())
+    );
+    assert_eq!(
+        out,
+        [0x30, 0x09, 0x30, 0x00, 0x30, 0x00, 0x03, 0x03, 0x00, 0xAA, 0xAA]
+    );
+}